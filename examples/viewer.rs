@@ -0,0 +1,141 @@
+//! A minimal image viewer on the photos1 framework, without the photo
+//! editing parts:
+//!
+//!     cargo run --example viewer --no-default-features -- image.png
+
+use std::path::PathBuf;
+
+use glam::f32::Mat4;
+
+use photos1::{
+    async_trait,
+    run_app,
+    App,
+    BufBufWrite,
+    Cmd,
+    Error,
+    ImageId,
+    InitCtx,
+    RenderCtx,
+    Result,
+    SwapCtx,
+    GRAY,
+};
+
+const VERTEX : &str = "
+#version 100
+precision lowp float;
+
+attribute vec2 position;
+attribute vec2 texcoord;
+
+varying vec2 uv;
+
+uniform mat4 matrix;
+
+void main() {
+    gl_Position = matrix * vec4(position, 0., 1.);
+    uv = texcoord;
+}
+";
+
+const FRAGMENT : &str = "
+#version 100
+precision lowp float;
+
+varying lowp vec2 uv;
+
+uniform sampler2D texture;
+
+void main() {
+    gl_FragColor = texture2D(texture, uv);
+}
+";
+
+fn main() {
+    run_app::<Viewer>();
+}
+
+struct Viewer;
+
+#[derive(Debug)]
+enum Model {
+    Empty,
+    Loaded(image::RgbaImage),
+    Shown(ImageId),
+}
+
+#[derive(Debug)]
+struct LocalModel {
+    program : glium::Program,
+}
+
+#[async_trait]
+impl App for Viewer {
+    type Model = Model;
+    type LocalModel = LocalModel;
+    type Msg = PathBuf;
+    type Error = Error;
+
+    fn name() -> &'static str {
+        "viewer"
+    }
+
+    fn init(ctx : &mut InitCtx) -> Result<(Self, LocalModel, Model, Cmd<PathBuf>)> {
+        let program = photos1::cached_program(ctx.display, "viewer", VERTEX, FRAGMENT)?;
+
+        let cmd = match std::env::args_os().nth(1) {
+            Some(path) => Cmd::msg(PathBuf::from(path)),
+            None => Cmd::none(),
+        };
+
+        Ok((Viewer, LocalModel{ program }, Model::Empty, cmd))
+    }
+
+    fn render(&self,
+              ctx : &mut RenderCtx,
+              local_model : &mut LocalModel,
+              model : &mut Model) -> Cmd<PathBuf>
+    {
+        ctx.clear_color(GRAY);
+
+        if let Model::Loaded(img) = model {
+            *model = match ctx.add_image(img.clone()) {
+                Ok(img_id) => Model::Shown(img_id),
+                Err(err) => {
+                    println!("could not show the image: {}", err);
+                    Model::Empty
+                },
+            };
+        }
+
+        if let Model::Shown(img_id) = model {
+            let res = ctx.draw_image_screen(
+                *img_id,
+                &Mat4::IDENTITY,
+                &local_model.program,
+                glium::uniforms::EmptyUniforms,
+            );
+            if let Err(err) = res {
+                println!("could not draw the image: {}", err);
+            }
+        }
+
+        Cmd::none()
+    }
+
+    fn swap(&self, ctx : &mut SwapCtx, old : &mut Model, _new : &mut Model) {
+        if let Model::Shown(img_id) = old {
+            ctx.delete_image(*img_id);
+        }
+    }
+
+    async fn update(&'static self, model : &BufBufWrite<Model>, path : PathBuf) -> Result<Cmd<PathBuf>> {
+        let byt = tokio::fs::read(&path).await?;
+        let img = image::load_from_memory(&byt)?.to_rgba8();
+
+        model.set_next(Model::Loaded(img));
+
+        Ok(Cmd::none())
+    }
+}
@@ -0,0 +1,8 @@
+// monitor profiles, including the tone curve tables
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data : &[u8]| {
+    let _ = photos1::DisplayProfile::parse(data);
+});
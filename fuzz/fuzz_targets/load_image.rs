@@ -0,0 +1,19 @@
+// what Photo::new and Thumb::new do with a file's bytes
+#![no_main]
+
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+use photos1::{
+    DecodeLimits,
+    Formats,
+};
+
+fuzz_target!(|data : &[u8]| {
+    // without an extension, so only the signatures pick the decoder
+    let formats = Formats::builtin();
+    if let Ok(img) = formats.decode(data, Path::new("fuzz"), &DecodeLimits::default()) {
+        let _ = img.thumbnail(64, 64).into_rgba8();
+        let _ = img.into_rgba8();
+    }
+});
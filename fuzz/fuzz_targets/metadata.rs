@@ -0,0 +1,24 @@
+// exif from any file, and the dates in sidecars
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/metadata.rs"]
+mod metadata;
+
+use metadata::{
+    DateTime,
+    Metadata,
+};
+
+fuzz_target!(|data : &[u8]| {
+    if let Some(date) = Metadata::read(data).date {
+        let _ = date.to_system_time();
+    }
+
+    if let Some(date) = DateTime::parse_iso(&String::from_utf8_lossy(data)) {
+        let _ = date.to_system_time();
+        let _ = date.month_name();
+    }
+});
@@ -0,0 +1,19 @@
+// the json google takeout keeps next to each photo
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// importers uses the other two through crate::
+#[allow(dead_code)]
+#[path = "../../src/metadata.rs"]
+mod metadata;
+#[allow(dead_code)]
+#[path = "../../src/xmp.rs"]
+mod xmp;
+#[allow(dead_code)]
+#[path = "../../src/importers.rs"]
+mod importers;
+
+fuzz_target!(|data : &[u8]| {
+    let _ = importers::parse_takeout(data);
+});
@@ -0,0 +1,12 @@
+// lightroom, darktable and apple photos sidecars
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/xmp.rs"]
+mod xmp;
+
+fuzz_target!(|data : &[u8]| {
+    let _ = xmp::Sidecar::parse(&String::from_utf8_lossy(data));
+});
@@ -0,0 +1,12 @@
+#!/bin/sh
+# runs every target over the inputs in regressions/<target>/ once, so a
+# crash that was fixed stays fixed. from the repository root:
+#
+#     sh fuzz/regress.sh
+set -e
+
+cd "$(dirname "$0")/.."
+for dir in fuzz/regressions/*/; do
+    target=$(basename "$dir")
+    cargo +nightly fuzz run "$target" "$dir" -- -runs=0
+done
@@ -0,0 +1,100 @@
+// music for slideshows, behind the audio feature. rodio's output stream
+// isn't Send, so this lives on the render thread with the slideshow
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::Duration;
+
+use rodio::{
+    Decoder,
+    OutputStream,
+    Sink,
+};
+
+use photos1::Result;
+
+use crate::manifest;
+
+fn audio_error(err : impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
+}
+
+pub struct Music {
+    // the sink goes quiet if this is dropped
+    _stream : OutputStream,
+    sink : Sink,
+    pub volume : f32,
+    pub muted : bool,
+    // how far into the fade out and how long it is
+    fade : Option<(Duration, Duration)>,
+}
+
+impl std::fmt::Debug for Music {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Music")
+            .field("volume", &self.volume)
+            .field("muted", &self.muted)
+            .field("fade", &self.fade)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Music {
+    /// Plays a file, or the files in an m3u playlist one after the other.
+    pub fn open(path : &Path) -> Result<Self> {
+        let files : Vec<PathBuf> = if manifest::is_manifest_path(path) {
+            manifest::paths(&std::fs::read(path)?, path)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let (stream, handle) = OutputStream::try_default().map_err(audio_error)?;
+        let sink = Sink::try_new(&handle).map_err(audio_error)?;
+
+        for file in files {
+            let reader = BufReader::new(File::open(&file)?);
+            sink.append(Decoder::new(reader).map_err(audio_error)?);
+        }
+
+        Ok(Music {
+            _stream : stream,
+            sink,
+            volume : 1.0,
+            muted : false,
+            fade : None,
+        })
+    }
+
+    pub fn fade_out(&mut self, duration : Duration) {
+        if self.fade.is_none() {
+            self.fade = Some((Duration::ZERO, duration));
+        }
+    }
+
+    pub fn fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    // called every frame to apply the volume and move the fade along
+    pub fn update(&mut self, dt : Duration) {
+        let fade = match &mut self.fade {
+            Some((elapsed, total)) => {
+                *elapsed += dt;
+                1.0 - (elapsed.as_secs_f32() / total.as_secs_f32().max(0.001)).min(1.0)
+            },
+            None => 1.0,
+        };
+
+        let volume = if self.muted { 0.0 } else { self.volume * fade };
+        self.sink.set_volume(volume);
+    }
+
+    /// Out of songs, or faded all the way out.
+    pub fn done(&self) -> bool {
+        self.sink.empty() || matches!(self.fade, Some((elapsed, total)) if elapsed >= total)
+    }
+}
@@ -32,23 +32,22 @@ impl App for TestApp {
         "test app!"
     }
 
-    fn init(ctx : &mut InitCtx, _msgs : &mut Vec<()>) -> (TestApp, TestAppLocal, ()) {
+    fn init(ctx : &mut InitCtx) -> Result<(TestApp, TestAppLocal, (), Cmd<()>)> {
         let image = image::load(std::io::Cursor::new(&include_bytes!("../test0.png")[..]),
-            image::ImageFormat::Png).unwrap().to_rgba8();
+            image::ImageFormat::Png)?.to_rgba8();
 
-        let image_id = ctx.add_image(image);
-        let effects_shader = EffectsShader::new(ctx.display);
+        let image_id = ctx.add_image(image)?;
+        let effects_shader = EffectsShader::new(ctx.display)?;
         let trans = Mat4::IDENTITY;
 
-        (TestApp(), TestAppLocal{effects_shader, trans, image_id}, ())
+        Ok((TestApp(), TestAppLocal{effects_shader, trans, image_id}, (), Cmd::none()))
 
     }
 
     fn render(&self,
               ctx : &mut RenderCtx,
               local_model : &mut TestAppLocal,
-              _model : &mut (),
-              _msgs : &mut Vec<Self::Msg>)
+              _model : &mut ()) -> Cmd<()>
     {
         let TestAppLocal{
             effects_shader,
@@ -144,10 +143,16 @@ impl App for TestApp {
 
             ctx.clear_color(GRAY);
 
-            effects_shader.draw_image_screen(ctx, *image_id, &trans, &Default::default()).unwrap();
+            if let Err(err) = effects_shader.draw_image_screen(ctx, *image_id, &trans, &Default::default()) {
+                println!("could not draw: {}", err);
+            }
+
+            Cmd::none()
     }
 
     fn swap(&self, _ctx : &mut SwapCtx, _old : &mut (), _new : &mut ()) {}
-    async fn update(&'static self, _model : &BufBufWrite<()>, _msg : ()) -> Result<()> { Ok(()) }
+    async fn update(&'static self, _model : &BufBufWrite<()>, _msg : ()) -> Result<Cmd<()>> {
+        Ok(Cmd::none())
+    }
 }
 
@@ -0,0 +1,174 @@
+// text over the photo while it's presented, fullscreen or in a slideshow,
+// made from a template that's filled in with what's known about the photo
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::metadata::DateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptionPosition {
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptionSize {
+    Small,
+    Large,
+}
+
+/// What's known about the photo on screen, for its caption.
+#[derive(Debug, Default)]
+pub struct CaptionFields<'a> {
+    /// The caption written for the photo, or its file name without the
+    /// extension
+    pub title : &'a str,
+    pub date : Option<DateTime>,
+    /// Reverse geocoded from its gps
+    pub location : Option<&'a str>,
+    /// The file name
+    pub name : &'a str,
+}
+
+/// Text over each photo, made from a template. Kept in the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Captions {
+    pub show : bool,
+    /// {title}, {date}, {location}, and {name} are replaced with the
+    /// photo's, lines where all of them are missing are left out
+    pub template : String,
+    pub position : CaptionPosition,
+    pub size : CaptionSize,
+    /// A dark band behind the text, to read it over bright photos
+    pub background : bool,
+}
+
+impl Default for Captions {
+    fn default() -> Self {
+        Captions {
+            show : false,
+            template : "{title}\n{date}  {location}".to_string(),
+            position : CaptionPosition::Bottom,
+            size : CaptionSize::Large,
+            background : true,
+        }
+    }
+}
+
+// from the window's edge
+const MARGIN : f32 = 32.0;
+
+// fills in the line's placeholders in one pass, so a value with braces in
+// it isn't filled in again. None if it had placeholders and all of them were
+// empty
+fn fill(line : &str, values : &[(&str, &str)]) -> Option<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    let mut used = false;
+    let mut empty = true;
+
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let value = rest.find('}').and_then(|close| {
+            values.iter()
+                .find(|(key, _)| *key == &rest[1..close])
+                .map(|(_, value)| (close, *value))
+        });
+        match value {
+            Some((close, value)) => {
+                used = true;
+                empty &= value.is_empty();
+                out.push_str(value);
+                rest = &rest[close + 1..];
+            },
+            // not one of ours, kept as it is
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    out.push_str(rest);
+
+    if used && empty {
+        None
+    } else {
+        Some(out.trim().to_string())
+    }
+}
+
+impl Captions {
+    pub fn text(&self, fields : &CaptionFields) -> String {
+        let date = fields.date.map(|d| format!("{:04}-{:02}-{:02}", d.year, d.month, d.day)).unwrap_or_default();
+        let values = [
+            ("title", fields.title.trim()),
+            ("date", date.as_str()),
+            ("location", fields.location.unwrap_or_default()),
+            ("name", fields.name),
+        ];
+
+        let lines : Vec<String> = self.template.lines()
+            .filter_map(|line| fill(line, &values))
+            .collect();
+
+        lines.join("\n").trim().to_string()
+    }
+
+    /// Over everything else, centered at the top or bottom of the window.
+    pub fn paint(&self, ctx : &egui::CtxRef, text : &str) {
+        if text.is_empty() {
+            return
+        }
+
+        let (align, offset) = match self.position {
+            CaptionPosition::Top => (egui::Align2::CENTER_TOP, [0.0, MARGIN]),
+            CaptionPosition::Bottom => (egui::Align2::CENTER_BOTTOM, [0.0, -MARGIN]),
+        };
+        let style = match self.size {
+            CaptionSize::Small => egui::TextStyle::Body,
+            // the biggest font egui has
+            CaptionSize::Large => egui::TextStyle::Heading,
+        };
+
+        egui::Area::new("caption")
+            .order(egui::Order::Foreground)
+            .anchor(align, offset)
+            .interactable(false)
+            .show(ctx, |ui| {
+                let frame = egui::Frame{
+                    margin : egui::vec2(12.0, 8.0),
+                    corner_radius : 4.0,
+                    fill : if self.background { egui::Color32::from_black_alpha(160) } else { egui::Color32::TRANSPARENT },
+                    ..egui::Frame::none()
+                };
+                frame.show(ui, |ui| {
+                    ui.add(egui::Label::new(text).text_style(style).text_color(egui::Color32::WHITE));
+                });
+            });
+    }
+
+    pub fn ui(&mut self, ui : &mut egui::Ui) {
+        ui.checkbox(&mut self.show, "Show captions while presenting");
+        if !self.show {
+            return
+        }
+
+        ui.label("{title}, {date}, {location}, and {name} are filled in:");
+        ui.text_edit_multiline(&mut self.template);
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.position, CaptionPosition::Bottom, "bottom");
+            ui.radio_value(&mut self.position, CaptionPosition::Top, "top");
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.size, CaptionSize::Small, "small");
+            ui.radio_value(&mut self.size, CaptionSize::Large, "large");
+        });
+        ui.checkbox(&mut self.background, "Dark band behind the text");
+    }
+}
@@ -0,0 +1,86 @@
+// images on the OS clipboard, egui only copies and pastes text. on X11 what's
+// copied is served by arboard's clipboard, so one is kept open for as long
+// as the app runs. every call blocks, run them on a worker
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use image::RgbaImage;
+
+use photos1::MutexExt;
+
+use crate::web;
+
+pub enum Pasted {
+    Image(RgbaImage),
+    /// A file or folder that exists
+    Path(PathBuf),
+    /// An http(s) link
+    Url(String),
+}
+
+#[derive(Default)]
+pub struct Clipboard {
+    // opened the first time it's used
+    inner : Mutex<Option<arboard::Clipboard>>,
+}
+
+fn to_io(err : arboard::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, format!("clipboard: {}", err))
+}
+
+impl Clipboard {
+    fn with<T, F>(&self, f : F) -> std::io::Result<T>
+    where F : FnOnce(&mut arboard::Clipboard) -> Result<T, arboard::Error>
+    {
+        let mut inner = self.inner.lock_or_recover();
+        let clipboard = match inner.as_mut() {
+            Some(clipboard) => clipboard,
+            None => inner.insert(arboard::Clipboard::new().map_err(to_io)?),
+        };
+        f(clipboard).map_err(to_io)
+    }
+
+    pub fn copy_image(&self, img : &RgbaImage) -> std::io::Result<()> {
+        self.with(|clipboard| clipboard.set_image(arboard::ImageData{
+            width : img.width() as usize,
+            height : img.height() as usize,
+            bytes : Cow::Borrowed(img.as_raw()),
+        }))
+    }
+
+    /// An image if there is one, or text naming a file or folder, like a
+    /// path or a file:// url copied from a file manager, or a link. None
+    /// for anything else.
+    pub fn paste(&self) -> std::io::Result<Option<Pasted>> {
+        // an error opening it is said, after that nothing to paste is None
+        self.with(|_| Ok(()))?;
+
+        if let Ok(image) = self.with(|clipboard| clipboard.get_image()) {
+            let (width, height) = (image.width as u32, image.height as u32);
+            if let Some(img) = RgbaImage::from_raw(width, height, image.bytes.into_owned()) {
+                return Ok(Some(Pasted::Image(img)))
+            }
+        }
+
+        // empty or not text
+        let text = match self.with(|clipboard| clipboard.get_text()) {
+            Ok(text) => text,
+            Err(_) => return Ok(None),
+        };
+
+        // the first line, file managers copy one per file
+        let line = text.lines().next().unwrap_or_default().trim();
+        if web::is_url(line) {
+            return Ok(Some(Pasted::Url(line.to_string())))
+        }
+
+        let path = PathBuf::from(line.strip_prefix("file://").unwrap_or(line));
+        if !line.is_empty() && path.exists() {
+            Ok(Some(Pasted::Path(path)))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use glium::glutin::event_loop::EventLoopProxy;
+use tokio::runtime::{
+    Handle,
+    Runtime,
+};
+
+use crate::wake::LoopEvent;
+
+enum Effect<M> {
+    Msg(M),
+    Future(Pin<Box<dyn Future<Output = M> + Send>>),
+    Every(Duration, Box<dyn FnMut() -> Option<M> + Send>),
+}
+
+/// Effects for the framework to run, returned from `App::init`,
+/// `App::render`, and `App::update`. Messages from commands are sent like
+/// any other, through `App::priority` and `App::coalesce` to `App::update`.
+///
+/// ```ignore
+/// // reload in 5 seconds
+/// Cmd::after(Duration::from_secs(5), Msg::Reload)
+/// ```
+pub struct Cmd<M> {
+    effects : Vec<Effect<M>>,
+}
+
+impl<M> Default for Cmd<M> {
+    fn default() -> Self {
+        Cmd::none()
+    }
+}
+
+impl<M> From<Vec<M>> for Cmd<M> {
+    fn from(msgs : Vec<M>) -> Self {
+        Cmd {
+            effects : msgs.into_iter().map(Effect::Msg).collect(),
+        }
+    }
+}
+
+impl<M> std::fmt::Debug for Cmd<M> {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cmd({} effects)", self.effects.len())
+    }
+}
+
+impl<M> Cmd<M> {
+    pub fn none() -> Self {
+        Cmd {
+            effects : Vec::new(),
+        }
+    }
+
+    /// Sends msg.
+    pub fn msg(msg : M) -> Self {
+        Cmd {
+            effects : vec![Effect::Msg(msg)],
+        }
+    }
+
+    /// All of the commands, in order.
+    pub fn batch<I : IntoIterator<Item = Cmd<M>>>(cmds : I) -> Self {
+        Cmd {
+            effects : cmds.into_iter().flat_map(|c| c.effects).collect(),
+        }
+    }
+
+    /// This command followed by other.
+    pub fn and(mut self, other : Cmd<M>) -> Self {
+        self.effects.extend(other.effects);
+        self
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+impl<M : Send + 'static> Cmd<M> {
+    /// Runs fut on the worker runtime and sends its output.
+    pub fn perform<F>(fut : F) -> Self
+    where
+        F : Future<Output = M> + Send + 'static,
+    {
+        Cmd {
+            effects : vec![Effect::Future(Box::pin(fut))],
+        }
+    }
+
+    /// Sends msg once after delay.
+    pub fn after(delay : Duration, msg : M) -> Self {
+        Self::perform(async move {
+            tokio::time::sleep(delay).await;
+            msg
+        })
+    }
+
+    /// Calls f every period and sends what it returns, until it returns
+    /// None. Good for polling, like watching a directory.
+    pub fn every<F>(period : Duration, f : F) -> Self
+    where
+        F : FnMut() -> Option<M> + Send + 'static,
+    {
+        Cmd {
+            effects : vec![Effect::Every(period, Box::new(f))],
+        }
+    }
+
+    // messages go straight to send, the rest are spawned on handle and send
+    // through proxy, which wakes up the render thread
+    crate fn run<F>(self, mut send : F, handle : &Handle, proxy : &EventLoopProxy<LoopEvent<M>>)
+    where
+        F : FnMut(M),
+    {
+        for effect in self.effects {
+            match effect {
+                Effect::Msg(msg) => send(msg),
+                Effect::Future(fut) => {
+                    let proxy = proxy.clone();
+                    handle.spawn(async move {
+                        // the window closed
+                        let _ = proxy.send_event(LoopEvent::Msg(fut.await));
+                    });
+                },
+                Effect::Every(period, mut f) => {
+                    let proxy = proxy.clone();
+                    handle.spawn(async move {
+                        loop {
+                            tokio::time::sleep(period).await;
+
+                            let msg = match f() {
+                                Some(msg) => msg,
+                                None => break,
+                            };
+
+                            if proxy.send_event(LoopEvent::Msg(msg)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                },
+            }
+        }
+    }
+
+    // without an event loop, for testing::Harness. messages and the outputs
+    // of futures, run to completion on rt one after the other, go to send
+    // in order. the functions from every are returned for the harness to
+    // call when it likes instead of on a timer
+    crate fn run_blocking<F>(self, mut send : F, rt : &Runtime) -> Vec<Box<dyn FnMut() -> Option<M> + Send>>
+    where
+        F : FnMut(M),
+    {
+        let mut polls = Vec::new();
+        for effect in self.effects {
+            match effect {
+                Effect::Msg(msg) => send(msg),
+                Effect::Future(fut) => send(rt.block_on(fut)),
+                Effect::Every(_, f) => polls.push(f),
+            }
+        }
+
+        polls
+    }
+}
@@ -0,0 +1,142 @@
+// a gallery's thumbnails on pages of a PDF, a grid of them with their file
+// names and captions under them, like the sheets printed from film. the
+// thumbnails are the gallery's, so they're only as sharp as it shows them
+
+use image::RgbaImage;
+
+use crate::pdf::{
+    Page,
+    Pdf,
+};
+use crate::print::PAPERS;
+
+const MARGIN : f32 = 36.;
+// around each thumbnail in its cell
+const PADDING : f32 = 6.;
+const TITLE_SIZE : f32 = 10.;
+const NAME_SIZE : f32 = 7.;
+const CAPTION_SIZE : f32 = 6.;
+// lines are this much taller than their text
+const LEADING : f32 = 1.3;
+const JPEG_QUALITY : u8 = 85;
+
+/// One photo of the sheet.
+pub struct Cell {
+    pub name : String,
+    pub caption : Option<String>,
+    pub image : RgbaImage,
+}
+
+impl std::fmt::Debug for Cell {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cell")
+            .field("name", &self.name)
+            .field("size", &self.image.dimensions())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SheetLayout {
+    /// In PAPERS, always portrait
+    pub paper : usize,
+    pub columns : u32,
+    pub rows : u32,
+    pub captions : bool,
+}
+
+impl Default for SheetLayout {
+    fn default() -> Self {
+        SheetLayout {
+            paper : 0,
+            columns : 4,
+            rows : 5,
+            captions : true,
+        }
+    }
+}
+
+impl SheetLayout {
+    pub fn per_page(&self) -> usize {
+        (self.columns.max(1) * self.rows.max(1)) as usize
+    }
+
+    /// The pages, with title and page numbers at the top of each. Slow,
+    /// run it off the render thread.
+    pub fn pdf(&self, title : &str, cells : &[Cell]) -> image::ImageResult<Vec<u8>> {
+        let (page_w, page_h) = PAPERS[self.paper.min(PAPERS.len() - 1)].points();
+        let (columns, rows) = (self.columns.max(1), self.rows.max(1));
+
+        let header = TITLE_SIZE * LEADING * 2.;
+        let cell_w = (page_w - 2. * MARGIN) / columns as f32;
+        let cell_h = (page_h - 2. * MARGIN - header) / rows as f32;
+        let text_h = NAME_SIZE * LEADING + if self.captions { CAPTION_SIZE * LEADING } else { 0. };
+        let (image_w, image_h) = (cell_w - 2. * PADDING, (cell_h - 2. * PADDING - text_h).max(1.));
+
+        let mut pdf = Pdf::default();
+        let pages = ((cells.len() + self.per_page() - 1) / self.per_page()).max(1);
+
+        for (number, chunk) in cells.chunks(self.per_page()).enumerate() {
+            let mut page = Page::new(page_w, page_h);
+            let top = page_h - MARGIN - TITLE_SIZE;
+            page.text(MARGIN, top, TITLE_SIZE, page_w - 2. * MARGIN - 60., title);
+            page.text(page_w - MARGIN - 50., top, TITLE_SIZE, 50., &format!("{} / {}", number + 1, pages));
+
+            for (idx, cell) in chunk.iter().enumerate() {
+                let (column, row) = (idx as u32 % columns, idx as u32 / columns);
+                let left = MARGIN + column as f32 * cell_w + PADDING;
+                // the cell's top, rows go down the page
+                let cell_top = page_h - MARGIN - header - row as f32 * cell_h - PADDING;
+
+                let (width, height) = cell.image.dimensions();
+                let scale = (image_w / width.max(1) as f32).min(image_h / height.max(1) as f32);
+                let (w, h) = (width as f32 * scale, height as f32 * scale);
+                // centered above the text
+                let x = left + (image_w - w) / 2.;
+                let y = cell_top - image_h + (image_h - h) / 2.;
+
+                let rgb = image::DynamicImage::ImageRgba8(cell.image.clone()).to_rgb8();
+                let mut jpeg = Vec::new();
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+                    &rgb,
+                    rgb.width(),
+                    rgb.height(),
+                    image::ColorType::Rgb8,
+                )?;
+                let image = pdf.add_jpeg(&jpeg, rgb.width(), rgb.height());
+                page.image(image, [x, y, w, h]);
+
+                let name_y = cell_top - image_h - NAME_SIZE * LEADING;
+                page.text(left, name_y, NAME_SIZE, image_w, &cell.name);
+                if let (true, Some(caption)) = (self.captions, cell.caption.as_ref()) {
+                    page.text(left, name_y - CAPTION_SIZE * LEADING, CAPTION_SIZE, image_w, caption);
+                }
+            }
+
+            pdf.add_page(page);
+        }
+
+        Ok(pdf.to_bytes())
+    }
+
+    /// The paper, the grid, and whether the captions are printed.
+    pub fn show(&mut self, ui : &mut egui::Ui) {
+        let paper = &mut self.paper;
+        egui::ComboBox::from_label("Paper")
+            .selected_text(PAPERS.get(*paper).map_or("", |p| p.name))
+            .show_ui(ui, |ui| {
+                for (idx, p) in PAPERS.iter().enumerate() {
+                    ui.selectable_value(paper, idx, p.name);
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.columns).clamp_range(1..=12));
+            ui.label("across,");
+            ui.add(egui::DragValue::new(&mut self.rows).clamp_range(1..=16));
+            ui.label("down");
+        });
+
+        ui.checkbox(&mut self.captions, "Captions under the file names");
+    }
+}
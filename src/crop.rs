@@ -0,0 +1,136 @@
+// crop suggestions from edge energy, the crop of a given aspect ratio which
+// keeps the most detail is probably the one with the subject in it
+
+/// Aspect ratios offered as suggestions, width over height.
+pub const ASPECTS : &[(&str, f32)] = &[
+    ("1:1", 1.0),
+    ("4:5", 4.0 / 5.0),
+    ("3:2", 3.0 / 2.0),
+    ("16:9", 16.0 / 9.0),
+];
+
+// the longer side of the copy the energy is computed on
+const SIZE : u32 = 128;
+
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub name : &'static str,
+    /// x, y, width, height as fractions of the image size, like Effects::crop
+    pub crop : [f32; 4],
+}
+
+/// The largest crop for each of ASPECTS, placed where the sum of the
+/// gradient magnitude is highest.
+pub fn suggest(img : &image::RgbaImage) -> Vec<Suggestion> {
+    let energy = match Energy::new(img) {
+        Some(energy) => energy,
+        None => return Vec::new(),
+    };
+
+    ASPECTS.iter().map(|&(name, target)| {
+        Suggestion{
+            name,
+            crop : energy.best(target),
+        }
+    }).collect()
+}
+
+/// Like suggest, for any aspect ratio. The whole image for an empty one.
+pub fn best(img : &image::RgbaImage, aspect : f32) -> [f32; 4] {
+    match Energy::new(img) {
+        Some(energy) => energy.best(aspect),
+        None => [0.0, 0.0, 1.0, 1.0],
+    }
+}
+
+// summed area table of the energy of a small copy of the image
+struct Energy {
+    table : Vec<f64>,
+    w : usize,
+    h : usize,
+    // of the full image
+    aspect : f32,
+}
+
+impl Energy {
+    fn new(img : &image::RgbaImage) -> Option<Self> {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return None
+        }
+
+        let scale = SIZE as f32 / width.max(height) as f32;
+        let w = ((width as f32 * scale).round() as u32).max(2);
+        let h = ((height as f32 * scale).round() as u32).max(2);
+        let small = image::imageops::resize(img, w, h, image::imageops::FilterType::Triangle);
+
+        let luma = |x, y| {
+            let p = small.get_pixel(x, y).0;
+            (p[0] as f32 * 0.299 + p[1] as f32 * 0.587 + p[2] as f32 * 0.114) / 255.0
+        };
+
+        // with a row and column of zeros so sums don't need bounds checks
+        let (w, h) = (w as usize, h as usize);
+        let mut table = vec![0f64; (w + 1) * (h + 1)];
+        for y in 0..h {
+            for x in 0..w {
+                let (xi, yi) = (x as u32, y as u32);
+                let dx = luma((xi + 1).min(w as u32 - 1), yi) - luma(xi.saturating_sub(1), yi);
+                let dy = luma(xi, (yi + 1).min(h as u32 - 1)) - luma(xi, yi.saturating_sub(1));
+                let energy = (dx.abs() + dy.abs()) as f64;
+
+                table[(y + 1) * (w + 1) + x + 1] = energy
+                    + table[y * (w + 1) + x + 1]
+                    + table[(y + 1) * (w + 1) + x]
+                    - table[y * (w + 1) + x];
+            }
+        }
+
+        Some(Energy {
+            table,
+            w,
+            h,
+            aspect : width as f32 / height as f32,
+        })
+    }
+
+    fn sum(&self, x : usize, y : usize, cw : usize, ch : usize) -> f64 {
+        let (table, w) = (&self.table, self.w);
+        table[(y + ch) * (w + 1) + x + cw]
+            - table[y * (w + 1) + x + cw]
+            - table[(y + ch) * (w + 1) + x]
+            + table[y * (w + 1) + x]
+    }
+
+    // the largest crop of the target aspect with the most energy in it
+    fn best(&self, target : f32) -> [f32; 4] {
+        let (w, h) = (self.w, self.h);
+
+        // fractions of the image size
+        let (fw, fh) = if target < self.aspect {
+            (target / self.aspect, 1.0)
+        } else {
+            (1.0, self.aspect / target)
+        };
+
+        let cw = ((fw * w as f32).round() as usize).clamp(1, w);
+        let ch = ((fh * h as f32).round() as usize).clamp(1, h);
+
+        let mut best = (0, 0, f64::MIN);
+        for y in 0..=(h - ch) {
+            for x in 0..=(w - cw) {
+                let energy = self.sum(x, y, cw, ch);
+                if energy > best.2 {
+                    best = (x, y, energy);
+                }
+            }
+        }
+
+        [
+            (best.0 as f32 / w as f32).min(1.0 - fw),
+            (best.1 as f32 / h as f32).min(1.0 - fh),
+            fw,
+            fh,
+        ]
+    }
+}
@@ -0,0 +1,78 @@
+// images are decoded whole, so a file that says it's 100000x100000 would
+// take 40GB before anything could stop it. the size is read from the header
+// first and checked against the limits, see Formats::decode
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// The biggest files and images `Formats::decode` opens. Photos from the largest
+/// cameras and panoramas stitched from them fit in the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecodeLimits {
+    /// Width times height
+    pub max_pixels : u64,
+    /// In bytes
+    pub max_file_size : u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            // a gigabyte decoded
+            max_pixels : 250_000_000,
+            max_file_size : 512 * 1024 * 1024,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// For when the user asked for a file anyway.
+    pub fn unlimited() -> Self {
+        DecodeLimits {
+            max_pixels : u64::MAX,
+            max_file_size : u64::MAX,
+        }
+    }
+
+    /// Checks a file's size before it's read.
+    pub fn check_file_size(&self, size : u64) -> std::result::Result<(), LimitError> {
+        if size > self.max_file_size {
+            return Err(LimitError::FileSize{size, max : self.max_file_size})
+        }
+        Ok(())
+    }
+
+    pub fn check_dimensions(&self, width : u32, height : u32) -> std::result::Result<(), LimitError> {
+        if width as u64 * height as u64 > self.max_pixels {
+            return Err(LimitError::Pixels{width, height, max : self.max_pixels})
+        }
+        Ok(())
+    }
+}
+
+/// A file over the `DecodeLimits`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    FileSize{size : u64, max : u64},
+    Pixels{width : u32, height : u32, max : u64},
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MB : u64 = 1024 * 1024;
+
+        match self {
+            LimitError::FileSize{size, max} => write!(
+                f, "the file is {} MB, over the limit of {} MB",
+                size / MB, max / MB,
+            ),
+            LimitError::Pixels{width, height, max} => write!(
+                f, "the image is {}x{}, over the limit of {} megapixels",
+                width, height, max / 1_000_000,
+            ),
+        }
+    }
+}
@@ -1,67 +1,623 @@
-use std::sync::{
-    Arc,
-    Weak,
-    Mutex,
-    MutexGuard,
+// the model the render thread draws, and the writers which change it from
+// the workers. a writer either replaces the whole model (set_next) or sends
+// a patch for it, and both are applied by the render thread between frames
+// (BufBuf::swap), so a frame never sees half a change and the workers never
+// wait on a frame.
+//
+// it's a triple buffer without locks: the render thread owns the model on
+// screen, a writer owns the one it's making, and the one in between waits
+// in an atomic slot, replaced by newer ones. patches wait on an atomic
+// stack. neither side ever waits for the other, however long a worker takes
+
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicPtr,
+    AtomicU64,
+    Ordering,
 };
 
+use crate::Panic;
+use crate::Waker;
+
+/// A change to the model, run on the render thread, see `BufBufWrite::patch`.
+pub type Patch<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Which model a patch is for, from `BufBufWrite::set_next` or
+/// `BufBufWrite::current`. Patches for a model that has been replaced are
+/// dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Generation(u64);
+
+/// Why `BufBufWrite::with` didn't run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Missed {
+    /// The model was replaced by set_next
+    Replaced,
+    /// The model doesn't have the part anymore, like when its screen was
+    /// changed to another kind
+    Missing,
+}
+
+// no generation is retired
+const NONE_RETIRED : u64 = u64::MAX;
+
+struct Next<T> {
+    generation : u64,
+    model : T,
+}
+
+struct Node<T> {
+    generation : u64,
+    patch : Patch<T>,
+    // pushed before this one
+    below : *mut Node<T>,
+}
+
+struct Shared<T> {
+    // of the newest model, given out by set_next
+    generation : AtomicU64,
+    // a Box<Next<T>> or null
+    next : AtomicPtr<Next<T>>,
+    // a stack of Box<Node<T>>, newest on top
+    patches : AtomicPtr<Node<T>>,
+    // the last generation that was missing a part, see patch_in
+    retired : Arc<AtomicU64>,
+    _model : PhantomData<T>,
+}
+
+// the models and patches only move from the writers to the render thread,
+// like through a Mutex
+unsafe impl<T : Send> Send for Shared<T> {}
+unsafe impl<T : Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn take_next(&self) -> Option<Box<Next<T>>> {
+        let next = self.next.swap(ptr::null_mut(), Ordering::AcqRel);
+        if next.is_null() {
+            None
+        } else {
+            // only this thread has it now
+            Some(unsafe { Box::from_raw(next) })
+        }
+    }
+
+    // oldest first
+    fn take_patches(&self) -> Vec<Box<Node<T>>> {
+        let mut top = self.patches.swap(ptr::null_mut(), Ordering::AcqRel);
+        let mut patches = Vec::new();
+        while !top.is_null() {
+            let node = unsafe { Box::from_raw(top) };
+            top = node.below;
+            patches.push(node);
+        }
+
+        patches.reverse();
+        patches
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        self.take_next();
+        self.take_patches();
+    }
+}
+
 pub struct BufBuf<T> {
-    current : Arc<Mutex<T>>,
-    next : Arc<Mutex<Option<Arc<Mutex<T>>>>>,
+    current : T,
+    generation : u64,
+    // applied so far, for Stats
+    swaps : u64,
+    patches : u64,
+    // patches for a model that isn't in the slot yet, see set_next
+    early : Vec<Box<Node<T>>>,
+    shared : Arc<Shared<T>>,
+    waker : Waker,
 }
 
 impl<T> BufBuf<T> {
     pub fn new(v : T) -> Self {
         BufBuf{
-            current : Arc::new(Mutex::new(v)),
-            next : Arc::new(Mutex::new(None)),
+            current : v,
+            generation : 0,
+            swaps : 0,
+            patches : 0,
+            early : Vec::new(),
+            shared : Arc::new(Shared{
+                generation : AtomicU64::new(0),
+                next : AtomicPtr::new(ptr::null_mut()),
+                patches : AtomicPtr::new(ptr::null_mut()),
+                retired : Arc::new(AtomicU64::new(NONE_RETIRED)),
+                _model : PhantomData,
+            }),
+            waker : Waker::default(),
         }
     }
 
-    pub fn lock(&self) -> MutexGuard<T> {
-        self.current.lock().unwrap()
+    /// Writers made after this wake the render thread with it.
+    pub fn set_waker(&mut self, waker : Waker) {
+        self.waker = waker;
+    }
+
+    /// The model on screen, only the render thread has it.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.current
+    }
+
+    /// Of the model on screen.
+    pub fn generation(&self) -> Generation {
+        Generation(self.generation)
+    }
+
+    /// How many times set_next replaced the model so far.
+    pub fn swap_count(&self) -> u64 {
+        self.swaps
+    }
+
+    /// How many patches were applied so far.
+    pub fn patch_count(&self) -> u64 {
+        self.patches
+    }
+
+    /// Gives the model on screen a new generation, as if set_next replaced
+    /// it with itself, for changes the render thread makes that its workers
+    /// shouldn't fill in anymore. Patches for the old one are dropped and
+    /// `is_current` is false for it, and so is a set_next still in the slot.
+    pub fn renew(&mut self) -> Generation {
+        self.generation = self.shared.generation.fetch_add(1, Ordering::AcqRel) + 1;
+        Generation(self.generation)
     }
 
     pub fn new_write(&self) -> BufBufWrite<T> {
         BufBufWrite{
-            next: Arc::clone(&self.next),
+            shared : Arc::clone(&self.shared),
+            waker : self.waker.clone(),
         }
     }
 
-    // the first param is the old value and second param is the new value
-    pub fn swap<F : FnOnce(&mut T, &mut T)>(&mut self, f : F) {
-        let new_opt = self.next.lock().unwrap().take();
+    // the first param is the old value and second param is the new value,
+    // called when set_next was. the patches are applied after, to the new
+    // value. returns the panics of the patches, which are skipped
+    pub fn swap<F : FnOnce(&mut T, &mut T)>(&mut self, f : F) -> Vec<Panic> {
+        // one set_next can lose the race for the slot to a newer one, but
+        // still get it after
+        let next = self.shared.take_next().filter(|next| next.generation > self.generation);
+
+        if let Some(next) = next {
+            let Next{generation, model : mut new} = *next;
+            std::mem::swap(&mut new, &mut self.current);
+            self.generation = generation;
+            self.swaps += 1;
+            let mut old = new;
+            f(&mut old, &mut self.current);
+        }
+
+        let mut patches = std::mem::take(&mut self.early);
+        patches.extend(self.shared.take_patches());
 
-        match new_opt {
-            None => {},
-            Some(mut new) => {
-                std::mem::swap(&mut new, &mut self.current);
-                let old = new;
-                f(&mut old.lock().unwrap(), &mut self.current.lock().unwrap());
+        let mut panics = Vec::new();
+        for node in patches {
+            if node.generation == self.generation {
+                self.patches += 1;
+                // a worker's bug shouldn't take the render thread with it.
+                // the model is left as the patch left it
+                let current = &mut self.current;
+                let patch = node.patch;
+                let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || patch(current)));
+                if let Err(payload) = res {
+                    panics.push(Panic::new("applying a change to the model", payload));
+                }
+            } else if node.generation > self.generation {
+                // the generation was handed out but its model isn't in the
+                // slot yet
+                self.early.push(node);
             }
+            // the others were for a model that's gone
         }
+
+        panics
     }
 }
 
 pub struct BufBufWrite<T> {
-    next : Arc<Mutex<Option<Arc<Mutex<T>>>>>,
+    shared : Arc<Shared<T>>,
+    waker : Waker,
 }
 
 impl<T> Clone for BufBufWrite<T> {
     fn clone(&self) -> BufBufWrite<T> {
         BufBufWrite{
-            next : Arc::clone(&self.next),
+            shared : Arc::clone(&self.shared),
+            waker : self.waker.clone(),
         }
     }
 }
 
 
 impl<T> BufBufWrite<T> {
-    pub fn set_next(&self, v : T) -> Weak<Mutex<T>> {
-        let next = Arc::new(Mutex::new(v));
-        let ret = Arc::downgrade(&next);
-        *self.next.lock().unwrap() = Some(next);
-        ret
+    /// Replaces the model at the next swap, patches for the one before are
+    /// dropped.
+    pub fn set_next(&self, v : T) -> Generation {
+        let generation = self.shared.generation.fetch_add(1, Ordering::AcqRel) + 1;
+        let mut next = Box::into_raw(Box::new(Next{
+            generation,
+            model : v,
+        }));
+        let mut next_generation = generation;
+
+        // the newest of what's in the slot and this one stays. whatever a
+        // swap pushes out belongs to this thread, what it puts in doesn't
+        // anymore
+        loop {
+            let replaced = self.shared.next.swap(next, Ordering::AcqRel);
+            if replaced.is_null() {
+                break
+            }
+
+            let replaced = unsafe { Box::from_raw(replaced) };
+            if replaced.generation < next_generation {
+                break
+            }
+
+            // a newer one got in first, put it back
+            next_generation = replaced.generation;
+            next = Box::into_raw(replaced);
+        }
+
+        self.waker.wake();
+        Generation(generation)
+    }
+
+    /// Asks for a frame, without changing the model.
+    pub fn wake(&self) {
+        self.waker.wake()
+    }
+
+    /// The model that will be rendered next, either the pending one from
+    /// set_next or the one on screen.
+    pub fn current(&self) -> Generation {
+        Generation(self.shared.generation.load(Ordering::Acquire))
+    }
+
+    /// Whether generation hasn't been replaced or retired, for workers to
+    /// stop filling in a screen nobody will see.
+    pub fn is_current(&self, generation : Generation) -> bool {
+        self.current() == generation &&
+            self.shared.retired.load(Ordering::Acquire) != generation.0
+    }
+
+    fn push(&self, generation : u64, patch : Patch<T>) {
+        let node = Box::into_raw(Box::new(Node{
+            generation,
+            patch,
+            below : ptr::null_mut(),
+        }));
+
+        let mut top = self.shared.patches.load(Ordering::Acquire);
+        loop {
+            // not shared until the exchange succeeds
+            unsafe { (*node).below = top };
+            match self.shared.patches.compare_exchange_weak(top, node, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(newer) => top = newer,
+            }
+        }
+
+        self.waker.wake();
+    }
+
+    /// Runs f on the model at the next swap, if it's still generation.
+    /// Returns false, without queueing it, if it's been replaced or retired
+    /// already.
+    pub fn patch<F>(&self, generation : Generation, f : F) -> bool
+    where F : FnOnce(&mut T) + Send + 'static
+    {
+        if !self.is_current(generation) {
+            return false
+        }
+
+        self.push(generation.0, Box::new(f));
+        true
+    }
+
+    /// Runs f on the part of the model select picks, like one kind of
+    /// screen, at the next swap. If the part is gone by then generation is
+    /// retired, `is_current` is false for it from then on so its workers
+    /// stop. Returns false like patch.
+    pub fn patch_in<P, S, F>(&self, generation : Generation, select : S, f : F) -> bool
+    where
+        P : ?Sized,
+        S : FnOnce(&mut T) -> Option<&mut P> + Send + 'static,
+        F : FnOnce(&mut P) + Send + 'static,
+    {
+        let retired = Arc::clone(&self.shared.retired);
+        self.patch(generation, move |model| match select(model) {
+            Some(part) => f(part),
+            None => retired.store(generation.0, Ordering::Release),
+        })
+    }
+
+    /// What f returns for the part of the model select picks, at the next
+    /// swap. Unlike patch_in a missing part doesn't retire generation, it's
+    /// fine to look for a part that might not be there.
+    pub async fn with<P, S, F, R>(&self, generation : Generation, select : S, f : F) -> Result<R, Missed>
+    where
+        P : ?Sized,
+        S : FnOnce(&mut T) -> Option<&mut P> + Send + 'static,
+        F : FnOnce(&mut P) -> R + Send + 'static,
+        R : Send + 'static,
+    {
+        let res = self.read(generation, move |model| select(model).map(f)).await;
+
+        match res {
+            Some(Some(out)) => Ok(out),
+            Some(None) => Err(Missed::Missing),
+            None => Err(Missed::Replaced),
+        }
+    }
+
+    /// Runs f on whichever model is rendered next, for changes to any
+    /// screen, like a file that was removed.
+    pub fn patch_current<F>(&self, f : F)
+    where F : FnOnce(&mut T) + Send + 'static
+    {
+        self.push(self.current().0, Box::new(f));
+    }
+
+    /// What f returns for the model, at the next swap. None if generation
+    /// was replaced before then.
+    pub async fn read<F, R>(&self, generation : Generation, f : F) -> Option<R>
+    where
+        F : FnOnce(&mut T) -> R + Send + 'static,
+        R : Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let queued = self.patch(generation, move |model| {
+            let _ = tx.send(f(model));
+        });
+
+        if !queued {
+            return None
+        }
+        rx.await.ok()
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{
+        Context,
+        Poll,
+        RawWaker,
+        RawWakerVTable,
+    };
+
+    // polls fut once, it's polled again by hand after a swap
+    fn poll<F : Future + Unpin>(fut : &mut F) -> Poll<F::Output> {
+        fn raw() -> RawWaker {
+            fn clone(_ : *const ()) -> RawWaker { raw() }
+            fn noop(_ : *const ()) {}
+            static VTABLE : RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { std::task::Waker::from_raw(raw()) };
+        Pin::new(fut).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn set_next_newest_wins() {
+        let mut buf = BufBuf::new(0);
+        let write = buf.new_write();
+
+        let first = write.set_next(1);
+        let second = write.set_next(2);
+        assert!(second > first);
+        assert_eq!(write.current(), second);
+
+        let mut swapped = Vec::new();
+        buf.swap(|old, new| swapped.push((*old, *new)));
+        assert_eq!(*buf.get_mut(), 2);
+        assert_eq!(buf.generation(), second);
+        // the one replaced in the slot was never on screen
+        assert_eq!(swapped, [(0, 2)]);
+        assert_eq!(buf.swap_count(), 1);
+
+        // nothing new, nothing swapped
+        buf.swap(|_, _| panic!("swapped without a set_next"));
+        assert_eq!(*buf.get_mut(), 2);
+    }
+
+    #[test]
+    fn patches_in_order_after_set_next() {
+        let mut buf = BufBuf::new(Vec::new());
+        let write = buf.new_write();
+
+        let old = buf.generation();
+        assert!(write.patch(old, |v : &mut Vec<u32>| v.push(1)));
+        let new = write.set_next(vec![10]);
+        // queued before the model it was for was replaced
+        assert!(!write.is_current(old));
+        assert!(!write.patch(old, |v| v.push(2)));
+        assert!(write.patch(new, |v| v.push(11)));
+        assert!(write.patch(new, |v| v.push(12)));
+        write.patch_current(|v| v.push(13));
+
+        assert!(buf.swap(|_, _| {}).is_empty());
+        assert_eq!(*buf.get_mut(), [10, 11, 12, 13]);
+        assert_eq!(buf.patch_count(), 3);
+    }
+
+    #[test]
+    fn patch_panics_are_skipped() {
+        let mut buf = BufBuf::new(0);
+        let write = buf.new_write();
+        let generation = buf.generation();
+
+        write.patch(generation, |n| *n += 1);
+        write.patch(generation, |_| panic!("a worker's bug"));
+        write.patch(generation, |n| *n += 10);
+
+        assert_eq!(buf.swap(|_, _| {}).len(), 1);
+        assert_eq!(*buf.get_mut(), 11);
+    }
+
+    #[test]
+    fn patch_in_retires_missing_parts() {
+        let mut buf = BufBuf::new(None::<u32>);
+        let write = buf.new_write();
+        let generation = buf.generation();
+
+        assert!(write.patch_in(generation, |model : &mut Option<u32>| model.as_mut(), |n| *n += 1));
+        buf.swap(|_, _| {});
+        assert!(!write.is_current(generation));
+        assert!(!write.patch(generation, |model| *model = Some(1)));
+        assert_eq!(*buf.get_mut(), None);
+    }
+
+    #[test]
+    fn renew_drops_old_patches() {
+        let mut buf = BufBuf::new(0);
+        let write = buf.new_write();
+        let old = buf.generation();
+
+        assert!(write.patch(old, |n| *n += 1));
+        let pending = write.set_next(5);
+        let new = buf.renew();
+        assert!(new > pending);
+        assert!(!write.is_current(old));
+        assert!(!write.patch(old, |n| *n += 10));
+        assert!(write.patch(new, |n| *n += 100));
+
+        buf.swap(|_, _| panic!("swapped in a model older than the one on screen"));
+        assert_eq!(*buf.get_mut(), 100);
+        assert_eq!(buf.generation(), new);
+    }
+
+    #[test]
+    fn read_sees_earlier_patches() {
+        let mut buf = BufBuf::new(0);
+        let write = buf.new_write();
+        let generation = buf.generation();
+
+        write.patch(generation, |n| *n += 1);
+        let mut read = Box::pin(write.read(generation, |n| *n));
+        // queued on the first poll, answered at the swap
+        assert!(poll(&mut read).is_pending());
+        write.patch(generation, |n| *n += 10);
+        assert!(poll(&mut read).is_pending());
+
+        buf.swap(|_, _| {});
+        assert_eq!(poll(&mut read), Poll::Ready(Some(1)));
+        assert_eq!(*buf.get_mut(), 11);
+    }
+
+    #[test]
+    fn read_of_replaced_model() {
+        let mut buf = BufBuf::new(0);
+        let write = buf.new_write();
+        let generation = buf.generation();
+
+        let mut read = Box::pin(write.read(generation, |n| *n));
+        assert!(poll(&mut read).is_pending());
+        write.set_next(5);
+        buf.swap(|_, _| {});
+        assert_eq!(poll(&mut read), Poll::Ready(None));
+
+        // already replaced, nothing is queued
+        let mut read = Box::pin(write.read(generation, |n| *n));
+        assert_eq!(poll(&mut read), Poll::Ready(None));
+
+        let mut with = Box::pin(write.with(buf.generation(), |n : &mut i32| (*n > 10).then(|| n), |n| *n));
+        assert!(poll(&mut with).is_pending());
+        buf.swap(|_, _| {});
+        assert_eq!(poll(&mut with), Poll::Ready(Err(Missed::Missing)));
+    }
+
+    #[test]
+    fn concurrent_patches() {
+        const THREADS : u64 = 8;
+        const PATCHES : u64 = 2000;
+
+        let mut buf = BufBuf::new(0u64);
+        let generation = buf.generation();
+
+        let writers : Vec<_> = (0..THREADS)
+            .map(|_| {
+                let write = buf.new_write();
+                std::thread::spawn(move || {
+                    for _ in 0..PATCHES {
+                        assert!(write.patch(generation, |n| *n += 1));
+                    }
+                })
+            })
+            .collect();
+
+        // swapped while they're being pushed
+        while buf.patch_count() < THREADS * PATCHES {
+            buf.swap(|_, _| {});
+            std::thread::yield_now();
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        buf.swap(|_, _| {});
+        assert_eq!(*buf.get_mut(), THREADS * PATCHES);
+        assert_eq!(buf.patch_count(), THREADS * PATCHES);
+    }
+
+    #[test]
+    fn concurrent_set_next() {
+        const THREADS : u64 = 8;
+        const MODELS : u64 = 500;
+
+        // the value is what a writer set, checked against its generation
+        let mut buf = BufBuf::new(0u64);
+
+        let writers : Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let write = buf.new_write();
+                std::thread::spawn(move || {
+                    (0..MODELS)
+                        .map(|idx| {
+                            let value = thread * MODELS + idx + 1;
+                            (write.set_next(value), value)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut last = buf.generation();
+        let mut seen = Vec::new();
+        for _ in 0..1000 {
+            buf.swap(|_, _| {});
+            // never goes back to an older model
+            assert!(buf.generation() >= last);
+            last = buf.generation();
+            seen.push((last, *buf.get_mut()));
+            std::thread::yield_now();
+        }
+
+        let set : Vec<(Generation, u64)> = writers.into_iter()
+            .flat_map(|writer| writer.join().unwrap())
+            .collect();
+
+        buf.swap(|_, _| {});
+        let newest = set.iter().max().unwrap();
+        assert_eq!(buf.generation(), newest.0);
+        assert_eq!(*buf.get_mut(), newest.1);
+
+        // every model on screen is the one set with its generation
+        for (generation, value) in seen.into_iter().filter(|(g, _)| *g != Generation(0)) {
+            assert!(set.contains(&(generation, value)));
+        }
+    }
+}
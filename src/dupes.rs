@@ -0,0 +1,61 @@
+// near duplicate detection with difference hashes,
+// http://www.hackerfactor.com/blog/index.php?/archives/529-Kind-of-Like-That.html
+
+/// Hashes which differ in at most this many bits are treated as the same
+/// photo. Catches resaves, small crops, and exposure tweaks.
+pub const MAX_DISTANCE : u32 = 6;
+
+/// A 64 bit difference hash, one bit per horizontally adjacent pair of
+/// pixels in a 9x8 grayscale copy of the image.
+pub fn dhash(img : &image::RgbaImage) -> u64 {
+    let small = image::imageops::resize(img, 9, 8, image::imageops::FilterType::Triangle);
+    let luma = |x, y| {
+        let p = small.get_pixel(x, y).0;
+        p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114
+    };
+
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if luma(x, y) < luma(x + 1, y) {
+                hash |= 1;
+            }
+        }
+    }
+
+    hash
+}
+
+/// Groups of indices into hashes which are within MAX_DISTANCE of each
+/// other, transitively. Only groups with more than one member are returned.
+pub fn group(hashes : &[u64]) -> Vec<Vec<usize>> {
+    // union find
+    let mut parent : Vec<usize> = (0..hashes.len()).collect();
+
+    fn root(parent : &mut Vec<usize>, mut idx : usize) -> usize {
+        while parent[idx] != idx {
+            parent[idx] = parent[parent[idx]];
+            idx = parent[idx];
+        }
+        idx
+    }
+
+    for i in 0..hashes.len() {
+        for j in (i + 1)..hashes.len() {
+            if (hashes[i] ^ hashes[j]).count_ones() <= MAX_DISTANCE {
+                let (ri, rj) = (root(&mut parent, i), root(&mut parent, j));
+                parent[ri.max(rj)] = ri.min(rj);
+            }
+        }
+    }
+
+    let mut groups : Vec<Vec<usize>> = vec![Vec::new(); hashes.len()];
+    for idx in 0..hashes.len() {
+        let r = root(&mut parent, idx);
+        groups[r].push(idx);
+    }
+
+    groups.retain(|g| g.len() > 1);
+    groups
+}
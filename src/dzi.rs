@@ -0,0 +1,82 @@
+// deep zoom image tile pyramids, the format read by OpenSeadragon and most
+// other web viewers, https://openseadragon.github.io/examples/tilesource-dzi/
+
+use std::path::Path;
+
+use photos1::Result;
+
+/// Tile edge, without the overlap. 254 + 2 * 1 keeps tiles at 256 pixels.
+pub const TILE_SIZE : u32 = 254;
+pub const OVERLAP : u32 = 1;
+const QUALITY : u8 = 90;
+
+const OPENSEADRAGON : &str = "https://cdn.jsdelivr.net/npm/openseadragon@2.4/build/openseadragon";
+
+/// Writes dir/name.dzi, the tiles under dir/name_files/, and dir/name.html
+/// with a viewer snippet that can be pasted into a page. Blocks, so run it
+/// with spawn_blocking.
+pub fn export(img : &image::RgbaImage, dir : &Path, name : &str) -> Result<()> {
+    let (width, height) = img.dimensions();
+    let tiles_dir = dir.join(format!("{}_files", name));
+
+    // level n is the image scaled to fit in 2^n pixels, down to 1x1 at 0
+    let max_level = 32 - (width.max(height).max(1) - 1).leading_zeros();
+
+    let mut level_img = img.clone();
+    for level in (0..=max_level).rev() {
+        let level_dir = tiles_dir.join(level.to_string());
+        std::fs::create_dir_all(&level_dir)?;
+
+        let (w, h) = level_img.dimensions();
+        for col in 0..(w + TILE_SIZE - 1) / TILE_SIZE {
+            for row in 0..(h + TILE_SIZE - 1) / TILE_SIZE {
+                let x = (col * TILE_SIZE).saturating_sub(OVERLAP);
+                let y = (row * TILE_SIZE).saturating_sub(OVERLAP);
+                let x_end = ((col + 1) * TILE_SIZE + OVERLAP).min(w);
+                let y_end = ((row + 1) * TILE_SIZE + OVERLAP).min(h);
+
+                let tile = image::imageops::crop_imm(&level_img, x, y, x_end - x, y_end - y)
+                    .to_image();
+                let tile = image::DynamicImage::ImageRgba8(tile).to_rgb8();
+
+                let file = std::fs::File::create(level_dir.join(format!("{}_{}.jpg", col, row)))?;
+                let mut file = std::io::BufWriter::new(file);
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut file, QUALITY)
+                    .encode_image(&tile)?;
+            }
+        }
+
+        if level > 0 {
+            level_img = image::imageops::resize(
+                &level_img,
+                (w + 1) / 2,
+                (h + 1) / 2,
+                image::imageops::FilterType::Triangle,
+            );
+        }
+    }
+
+    let dzi = format!(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" ",
+        "Format=\"jpg\" Overlap=\"{}\" TileSize=\"{}\">\n",
+        "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+        "</Image>\n",
+    ), OVERLAP, TILE_SIZE, width, height);
+    std::fs::write(dir.join(format!("{}.dzi", name)), dzi)?;
+
+    let html = format!(concat!(
+        "<div id=\"{name}-viewer\" style=\"width: 100%; height: 600px\"></div>\n",
+        "<script src=\"{osd}/openseadragon.min.js\"></script>\n",
+        "<script>\n",
+        "OpenSeadragon({{\n",
+        "    id: \"{name}-viewer\",\n",
+        "    prefixUrl: \"{osd}/images/\",\n",
+        "    tileSources: \"{name}.dzi\",\n",
+        "}});\n",
+        "</script>\n",
+    ), name = name, osd = OPENSEADRAGON);
+    std::fs::write(dir.join(format!("{}.html", name)), html)?;
+
+    Ok(())
+}
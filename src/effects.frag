@@ -7,6 +7,10 @@ varying lowp vec2 uv;
 
 uniform sampler2D texture;
 
+// see PROCESS_VERSION in shaders.rs, edits made with older versions
+// should take the old code paths so they keep rendering the same.
+uniform int process_version;
+
 uniform lowp float brightness;
 uniform lowp float contrast;
 uniform int invert;
@@ -18,6 +22,28 @@ uniform float black_pt;
 uniform float white_pt;
 
 uniform float temperature;
+// green to magenta, -150 to 150
+uniform float tint;
+
+// x, y, width, height in uv units, everything outside is cut off
+uniform vec4 crop;
+
+// half width of a box blur in uv units, 0 for none. not an edit, used to
+// hide the photo while the window is unfocused
+uniform float blur;
+
+// the width of pixel grid lines in image pixels, 0 for none, and the
+// image's size to find its pixels. not edits either
+uniform float grid;
+uniform vec2 texture_size;
+
+// 1 to paint clipped highlights red and crushed shadows blue, not an edit
+uniform int clipping;
+
+// linear sRGB to the monitor's linear RGB and the monitor's gamma, 0 for
+// the sRGB curve. not an edit either, identity and 0 for exports
+uniform mat3 display;
+uniform float display_gamma;
 
 #define PI 3.1415926535897932384626433832795
 
@@ -89,17 +115,61 @@ vec3 kelvin2linear(float temperature){
 }
 
 
+vec3 linear2display(vec3 lin) {
+    lin = clamp(display * lin, 0., 1.);
+    return display_gamma > 0. ? pow(lin, vec3(1. / display_gamma)) : linear2srgb(lin);
+}
+
+// lines between the image's pixels, over the color for the monitor
+vec3 grid_lines(vec3 color) {
+    if (grid <= 0.) {
+        return color;
+    }
+
+    vec2 f = fract(uv * texture_size);
+    vec2 d = min(f, 1. - f);
+    return min(d.x, d.y) < grid / 2. ? mix(color, vec3(.5), .6) : color;
+}
+
+// red where a channel of the edited color is blown out, blue where all of
+// them are black, by what would be written to an 8 bit file
+vec3 clipping_warning(vec3 lin) {
+    vec3 encoded = linear2srgb(clamp(lin, 0., 1.));
+    float brightest = max(encoded.r, max(encoded.g, encoded.b));
+
+    if (brightest >= 254.5 / 255.) {
+        return vec3(1., 0., 0.);
+    } else if (brightest <= .5 / 255.) {
+        return vec3(0., 0., 1.);
+    }
+    return lin;
+}
+
+vec4 blurred() {
+    vec4 sum = vec4(0.);
+    for (int x = -3; x <= 3; x++) {
+        for (int y = -3; y <= 3; y++) {
+            sum += texture2D(texture, uv + vec2(float(x), float(y)) * blur / 3.);
+        }
+    }
+    return sum / 49.;
+}
+
 void main() {
-    vec4 color = texture2D(texture, uv);
+    vec4 color = blur > 0. ? blurred() : texture2D(texture, uv);
     if (original != 0) {
-        gl_FragColor = color;
+        gl_FragColor = vec4(grid_lines(linear2display(srgb2linear(color.rgb))), color.a);
         return;
     }
 
+    if (any(lessThan(uv, crop.xy)) || any(greaterThan(uv, crop.xy + crop.zw))) {
+        discard;
+    }
+
     color.rgb = srgb2linear(color.rgb);
 
     // color correction described: https://en.wikipedia.org/wiki/Color_balance#Mathematics_of_color_balance
-    vec3 temp = kelvin2linear(temperature);
+    vec3 temp = kelvin2linear(temperature) * vec3(1., 1. + tint / 300., 1.);
     mat3 monitorScale = mat3(
         vec3(1. / temp.r, 0., 0.),
         vec3(0., 1. / temp.g, 0.),
@@ -121,5 +191,9 @@ void main() {
         color = 1. - color;
     }
 
-    gl_FragColor = vec4(linear2srgb(color.rgb), color.a);
+    if (clipping != 0) {
+        color.rgb = clipping_warning(color.rgb);
+    }
+
+    gl_FragColor = vec4(grid_lines(linear2display(color.rgb)), color.a);
 }
@@ -0,0 +1,172 @@
+// editing a photo in another program. the photo with its edits is saved to
+// a temp file for the program to open, and each time it's saved there it's
+// copied next to the original. there's no file watching library, so the file
+// is looked at every second
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::{
+    Child,
+    Command,
+};
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use std::time::{
+    Duration,
+    Instant,
+    SystemTime,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+// how often the temp file is looked at
+const POLL : Duration = Duration::from_secs(1);
+// a program that exits sooner than this handed the file to another, like
+// xdg-open does, and the file is watched until it's left alone for IDLE
+const HANDOFF : Duration = Duration::from_secs(5);
+const IDLE : Duration = Duration::from_secs(60 * 60);
+
+// temp files made so far, for the next one's name
+static NEXT_FILE : AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditFormat {
+    Tiff,
+    Png,
+}
+
+impl Default for EditFormat {
+    fn default() -> Self {
+        EditFormat::Tiff
+    }
+}
+
+impl EditFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EditFormat::Tiff => "tiff",
+            EditFormat::Png => "png",
+        }
+    }
+}
+
+/// Opens file with program, split on spaces into the program and its first
+/// arguments, or with the system's default for the file when it's empty.
+pub fn launch(program : &str, file : &Path) -> std::io::Result<Child> {
+    let mut words = program.split_whitespace();
+    let mut command = match words.next() {
+        Some(program) => {
+            let mut command = Command::new(program);
+            command.args(words);
+            command
+        },
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        // not cmd's start, which would read the name as a command line
+        None if cfg!(target_os = "windows") => Command::new("explorer"),
+        None => Command::new("xdg-open"),
+    };
+
+    command.arg(file).spawn()
+}
+
+/// A new file in dir for the program to open. It's named by a count rather
+/// than after the photo, whose name the program could take for an option.
+pub fn temp_file(dir : &Path, format : EditFormat) -> PathBuf {
+    // an earlier run with the same pid may have left its files
+    std::iter::repeat_with(|| NEXT_FILE.fetch_add(1, Ordering::Relaxed))
+        .map(|n| dir.join(format!("edit-{}-{}.{}", std::process::id(), n, format.extension())))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Where the edited copy of original goes, the first of stem_edited.ext,
+/// stem_edited-2.ext, ... that isn't taken.
+pub fn dest_for(original : &Path, format : EditFormat) -> PathBuf {
+    let dir = original.parent().unwrap_or_else(|| Path::new("."));
+    let stem = original.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "photo".to_string());
+
+    (1..)
+        .map(|n| match n {
+            1 => dir.join(format!("{}_edited.{}", stem, format.extension())),
+            n => dir.join(format!("{}_edited-{}.{}", stem, n, format.extension())),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// A photo open in another program.
+#[derive(Debug)]
+pub struct Session {
+    pub original : PathBuf,
+    /// The temp file the program has open
+    pub file : PathBuf,
+    /// Where saves are copied to
+    pub dest : PathBuf,
+    program : Child,
+    started : Instant,
+    // how long the program ran, once it's exited
+    ran_for : Option<Duration>,
+    modified : Option<SystemTime>,
+    last_save : Instant,
+}
+
+impl Session {
+    /// Watches file, already saved, in program.
+    pub fn new(original : PathBuf, file : PathBuf, dest : PathBuf, program : Child) -> Self {
+        let modified = std::fs::metadata(&file).and_then(|m| m.modified()).ok();
+        Session {
+            original,
+            file,
+            dest,
+            program,
+            started : Instant::now(),
+            ran_for : None,
+            modified,
+            last_save : Instant::now(),
+        }
+    }
+
+    /// Waits for the program to save the file, true, or to be done with
+    /// it, false.
+    pub async fn next_save(&mut self) -> bool {
+        loop {
+            tokio::time::sleep(POLL).await;
+
+            let modified = match tokio::fs::metadata(&self.file).await.and_then(|m| m.modified()) {
+                Ok(modified) => Some(modified),
+                // deleted, nothing more will come of it
+                Err(_) => return false,
+            };
+
+            if modified != self.modified {
+                // a save in progress, it's copied once it stops changing
+                tokio::time::sleep(POLL).await;
+                let now = tokio::fs::metadata(&self.file).await.and_then(|m| m.modified()).ok();
+                if now == modified {
+                    self.modified = modified;
+                    self.last_save = Instant::now();
+                    return true
+                }
+                continue
+            }
+
+            if self.ran_for.is_none() && !matches!(self.program.try_wait(), Ok(None)) {
+                self.ran_for = Some(self.started.elapsed());
+            }
+
+            let closed = self.ran_for.map_or(false, |ran_for| ran_for >= HANDOFF);
+            if closed || self.last_save.elapsed() > IDLE {
+                return false
+            }
+        }
+    }
+}
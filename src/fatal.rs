@@ -0,0 +1,73 @@
+// when App::init fails there's no app to hand the error to, the window
+// already there shows it until it's closed, and the process exits with 1.
+// scripted and headless runs exit right away, there's nobody to close it
+
+use glium::glutin;
+use glium::Surface;
+
+crate fn show<T>(
+    event_loop : glutin::event_loop::EventLoop<T>,
+    display : glium::Display,
+    mut egui_gl : egui_glium::EguiGlium,
+    name : &'static str,
+    message : String,
+    unattended : bool) -> !
+{
+    println!("{} couldn't start: {}", name, message);
+    if unattended {
+        std::process::exit(1);
+    }
+
+    event_loop.run(move |event, _, control_flow| {
+        use glutin::event::Event::*;
+        use glutin::event_loop::ControlFlow;
+
+        *control_flow = ControlFlow::Wait;
+
+        match event {
+            RedrawRequested(_) => {
+                egui_gl.begin_frame(&display);
+                let mut quit = false;
+
+                egui::Window::new(format!("{} couldn't start", name))
+                    .collapsible(false)
+                    .resizable(false)
+                    .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                    .show(egui_gl.ctx(), |ui| {
+                        ui.label(&message);
+                        ui.horizontal(|ui| {
+                            if ui.button("copy").clicked() {
+                                ui.output().copied_text = message.clone();
+                            }
+                            quit = ui.button("quit").clicked();
+                        });
+                    });
+
+                let (needs_repaint, shapes) = egui_gl.end_frame(&display);
+                if quit {
+                    *control_flow = ControlFlow::Exit;
+                } else if needs_repaint {
+                    display.gl_window().window().request_redraw();
+                }
+
+                let mut frame = display.draw();
+                frame.clear_color(0.1, 0.1, 0.1, 1.0);
+                egui_gl.paint(&display, &mut frame, shapes);
+                if let Err(err) = frame.finish() {
+                    println!("could not show the frame: {:?}", err);
+                }
+            },
+            WindowEvent{ event, .. } => {
+                if egui_gl.is_quit_event(&event) {
+                    *control_flow = ControlFlow::Exit;
+                    return
+                }
+
+                egui_gl.on_event(&event);
+                display.gl_window().window().request_redraw();
+            },
+            LoopDestroyed => std::process::exit(1),
+            _ => {},
+        }
+    })
+}
@@ -0,0 +1,195 @@
+// the formats images are decoded from. each is a Decoder in a list, the
+// image crate's are there from the start and apps add their own with
+// AppRunner::decoder, so a new format is one more registration instead of
+// another arm in the loader, and the app can show what it opens
+
+use std::path::Path;
+use std::sync::Arc;
+
+use image::ImageFormat;
+
+use crate::DecodeLimits;
+use crate::Result;
+
+/// A format images can be decoded from, see `AppRunner::decoder`.
+pub trait Decoder : Send + Sync {
+    /// Shown in lists of formats, like "PNG"
+    fn name(&self) -> &str;
+
+    /// File extensions, lowercase without the dot
+    fn extensions(&self) -> &[&str];
+
+    /// Whether byt starts the way this format does. Formats without a
+    /// signature return false, they're picked by extension instead.
+    fn sniff(&self, byt : &[u8]) -> bool;
+
+    /// The size from the header, without decoding, for the limits.
+    fn dimensions(&self, byt : &[u8]) -> Result<(u32, u32)>;
+
+    fn decode(&self, byt : &[u8]) -> Result<image::DynamicImage>;
+}
+
+// the image crate's formats which it can load. avif needs dav1d, a C
+// library, so it's behind a feature
+const IMAGE_CRATE : &[(ImageFormat, &str)] = &[
+    (ImageFormat::Png, "PNG"),
+    (ImageFormat::Jpeg, "JPEG"),
+    (ImageFormat::Gif, "GIF"),
+    (ImageFormat::WebP, "WebP"),
+    (ImageFormat::Tiff, "TIFF"),
+    (ImageFormat::Tga, "TGA"),
+    (ImageFormat::Bmp, "BMP"),
+    (ImageFormat::Ico, "ICO"),
+    (ImageFormat::Hdr, "Radiance HDR"),
+    (ImageFormat::Pnm, "PNM"),
+    (ImageFormat::Farbfeld, "farbfeld"),
+    #[cfg(feature = "avif")]
+    (ImageFormat::Avif, "AVIF"),
+];
+
+struct ImageCrate {
+    format : ImageFormat,
+    name : &'static str,
+}
+
+impl Decoder for ImageCrate {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.format.extensions_str()
+    }
+
+    fn sniff(&self, byt : &[u8]) -> bool {
+        image::guess_format(byt).ok() == Some(self.format)
+    }
+
+    fn dimensions(&self, byt : &[u8]) -> Result<(u32, u32)> {
+        let reader = image::io::Reader::with_format(std::io::Cursor::new(byt), self.format);
+        Ok(reader.into_dimensions()?)
+    }
+
+    fn decode(&self, byt : &[u8]) -> Result<image::DynamicImage> {
+        Ok(image::load_from_memory_with_format(byt, self.format)?)
+    }
+}
+
+// the image crate has a decoder for dds but its loader doesn't use it
+struct Dds;
+
+impl Decoder for Dds {
+    fn name(&self) -> &str {
+        "DirectDraw Surface"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dds"]
+    }
+
+    fn sniff(&self, byt : &[u8]) -> bool {
+        byt.starts_with(b"DDS ")
+    }
+
+    fn dimensions(&self, byt : &[u8]) -> Result<(u32, u32)> {
+        let decoder = image::codecs::dds::DdsDecoder::new(byt)?;
+        Ok(image::ImageDecoder::dimensions(&decoder))
+    }
+
+    fn decode(&self, byt : &[u8]) -> Result<image::DynamicImage> {
+        let decoder = image::codecs::dds::DdsDecoder::new(byt)?;
+        Ok(image::DynamicImage::from_decoder(decoder)?)
+    }
+}
+
+/// The decoders an app opens images with, from `InitCtx::formats`.
+#[derive(Clone)]
+pub struct Formats {
+    // the last registered first, so apps can replace the builtin ones
+    decoders : Vec<Arc<dyn Decoder>>,
+}
+
+impl std::fmt::Debug for Formats {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.decoders.iter().map(|decoder| decoder.name()))
+            .finish()
+    }
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        Formats::builtin()
+    }
+}
+
+impl Formats {
+    /// The image crate's formats, and AVIF with the `avif` feature.
+    pub fn builtin() -> Self {
+        let mut formats = Formats{
+            decoders : Vec::new(),
+        };
+
+        for &(format, name) in IMAGE_CRATE {
+            formats.register(ImageCrate{format, name});
+        }
+        formats.register(Dds);
+
+        formats
+    }
+
+    /// Adds a decoder, it's tried before the ones already there for its
+    /// extensions and signature.
+    pub fn register<D : Decoder + 'static>(&mut self, decoder : D) {
+        self.decoders.insert(0, Arc::new(decoder));
+    }
+
+    /// Each decoder, in the order they were registered, for listing them.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Decoder> {
+        self.decoders.iter().rev().map(|decoder| &**decoder)
+    }
+
+    /// The decoder for the path's extension, any case.
+    pub fn for_path(&self, path : &Path) -> Option<&dyn Decoder> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.decoders.iter()
+            .find(|decoder| decoder.extensions().contains(&ext.as_str()))
+            .map(|decoder| &**decoder)
+    }
+
+    /// Whether there's a decoder for the path's extension.
+    pub fn is_supported(&self, path : &Path) -> bool {
+        self.for_path(path).is_some()
+    }
+
+    /// The decoder whose signature byt has, or else the one for the path's
+    /// extension.
+    pub fn find(&self, byt : &[u8], path : &Path) -> Option<&dyn Decoder> {
+        self.decoders.iter()
+            .find(|decoder| decoder.sniff(byt))
+            .map(|decoder| &**decoder)
+            .or_else(|| self.for_path(path))
+    }
+
+    /// Decodes the file at path, already read into byt, unless the bytes or
+    /// the size in the header are over limits.
+    pub fn decode(&self, byt : &[u8], path : &Path, limits : &DecodeLimits) -> Result<image::DynamicImage> {
+        limits.check_file_size(byt.len() as u64)?;
+
+        let decoder = match self.find(byt, path) {
+            Some(decoder) => decoder,
+            None => {
+                let hint = path.extension().map_or(
+                    image::error::ImageFormatHint::Unknown,
+                    |ext| image::error::ImageFormatHint::PathExtension(ext.into()),
+                );
+                return Err(image::ImageError::Unsupported(hint.into()).into())
+            },
+        };
+
+        let (width, height) = decoder.dimensions(byt)?;
+        limits.check_dimensions(width, height)?;
+
+        decoder.decode(byt)
+    }
+}
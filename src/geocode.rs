@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// anything further than this from the closest place is in the middle of
+// nowhere, or the ocean
+const MAX_DISTANCE_KM : f64 = 100.0;
+
+const EARTH_RADIUS_KM : f64 = 6371.0;
+// along a meridian, and along the equator
+const KM_PER_DEGREE : f64 = EARTH_RADIUS_KM * std::f64::consts::PI / 180.0;
+
+// places are bucketed into cells this many degrees on a side, so a lookup
+// only measures the distance to the places in the cells around it
+const CELL_DEGREES : f64 = 1.0;
+const LON_CELLS : i32 = (360.0 / CELL_DEGREES) as i32;
+
+#[derive(Debug)]
+struct Place {
+    name : String,
+    // the ISO code, or the name from countryInfo.txt
+    country : String,
+    lat : f64,
+    lon : f64,
+}
+
+/// Offline reverse geocoding against a GeoNames dump, like cities15000.txt
+/// from https://download.geonames.org/export/dump/
+#[derive(Debug, Default)]
+pub struct Geocoder {
+    places : Vec<Place>,
+    // indices into places by the cell they're in, see cell
+    grid : HashMap<(i32, i32), Vec<usize>>,
+}
+
+// the cell with lat and lon, longitudes wrap around so the cells on either
+// side of the antimeridian are neighbours
+fn cell(lat : f64, lon : f64) -> (i32, i32) {
+    let row = (lat / CELL_DEGREES).floor() as i32;
+    let col = (lon / CELL_DEGREES).floor() as i32;
+    (row, col.rem_euclid(LON_CELLS))
+}
+
+impl Geocoder {
+    /// Where the dataset is expected to be,
+    /// $XDG_DATA_HOME/photos1/cities.txt or ~/.local/share/photos1/cities.txt
+    pub fn data_path() -> Option<PathBuf> {
+        crate::library::data_dir().map(|dir| dir.join("cities.txt"))
+    }
+
+    /// GeoNames' countryInfo.txt, next to the cities. Without it countries
+    /// are their ISO codes.
+    pub fn countries_path() -> Option<PathBuf> {
+        crate::library::data_dir().map(|dir| dir.join("countryInfo.txt"))
+    }
+
+    pub async fn load() -> std::io::Result<Self> {
+        let path = Self::data_path().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no data directory")
+        })?;
+
+        let s = tokio::fs::read_to_string(path).await?;
+        let mut geocoder = Self::parse(&s);
+
+        if let Some(path) = Self::countries_path() {
+            match tokio::fs::read_to_string(&path).await {
+                Ok(s) => geocoder.name_countries(&parse_countries(&s)),
+                Err(err) => tracing::debug!("no country names from {}: {}", path.display(), err),
+            }
+        }
+
+        Ok(geocoder)
+    }
+
+    // tab separated, the columns are documented in the GeoNames readme.
+    // Lines that don't parse are skipped.
+    pub fn parse(s : &str) -> Self {
+        let places : Vec<Place> = s.lines()
+            .filter_map(|line| {
+                let cols : Vec<&str> = line.split('\t').collect();
+
+                Some(Place{
+                    name : cols.get(1)?.to_string(),
+                    lat : cols.get(4)?.parse().ok().filter(|lat : &f64| lat.abs() <= 90.0)?,
+                    lon : cols.get(5)?.parse().ok().filter(|lon : &f64| lon.abs() <= 180.0)?,
+                    country : cols.get(8)?.to_string(),
+                })
+            })
+            .collect();
+
+        let mut grid : HashMap<_, Vec<usize>> = HashMap::new();
+        for (idx, place) in places.iter().enumerate() {
+            grid.entry(cell(place.lat, place.lon)).or_default().push(idx);
+        }
+
+        Geocoder{ places, grid }
+    }
+
+    // ISO codes to names, codes without one are left as they are
+    fn name_countries(&mut self, names : &HashMap<String, String>) {
+        for place in &mut self.places {
+            if let Some(name) = names.get(&place.country) {
+                place.country = name.clone();
+            }
+        }
+    }
+
+    /// "City, Country" for the closest place to the coordinates, in degrees
+    pub fn lookup(&self, lat : f64, lon : f64) -> Option<String> {
+        let (row, col) = cell(lat, lon);
+
+        // a degree of longitude shrinks away from the equator, so the cells
+        // within reach are counted at the reach's furthest latitude
+        let reach_lat = MAX_DISTANCE_KM / KM_PER_DEGREE;
+        let furthest = (lat.abs() + reach_lat).min(90.0).to_radians().cos();
+        let reach_lon = (reach_lat / furthest.max(1e-6)).min(180.0);
+        let rows = (reach_lat / CELL_DEGREES).ceil() as i32;
+        let cols = ((reach_lon / CELL_DEGREES).ceil() as i32).min(LON_CELLS / 2);
+
+        let (place, dist) = (row - rows..=row + rows)
+            .flat_map(|r| (col - cols..=col + cols).map(move |c| (r, c.rem_euclid(LON_CELLS))))
+            .filter_map(|key| self.grid.get(&key))
+            .flatten()
+            .map(|&idx| &self.places[idx])
+            .map(|place| (place, distance_km(lat, lon, place.lat, place.lon)))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))?;
+
+        if dist > MAX_DISTANCE_KM {
+            return None
+        }
+
+        Some(format!("{}, {}", place.name, place.country))
+    }
+}
+
+// countryInfo.txt, tab separated with the ISO code first and the name fifth,
+// and comments starting with #
+fn parse_countries(s : &str) -> HashMap<String, String> {
+    s.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let cols : Vec<&str> = line.split('\t').collect();
+            let code = cols.get(0)?.trim();
+            let name = cols.get(4)?.trim();
+            if code.is_empty() || name.is_empty() {
+                return None
+            }
+            Some((code.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+// haversine
+fn distance_km(lat0 : f64, lon0 : f64, lat1 : f64, lon1 : f64) -> f64 {
+    let (lat0, lon0, lat1, lon1) = (
+        lat0.to_radians(), lon0.to_radians(),
+        lat1.to_radians(), lon1.to_radians(),
+    );
+
+    let a = ((lat1 - lat0) / 2.0).sin().powi(2) +
+        lat0.cos() * lat1.cos() * ((lon1 - lon0) / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cities15000.txt's columns, with the ones not read left empty
+    fn line(name : &str, lat : f64, lon : f64, country : &str) -> String {
+        format!("1\t{}\t{}\t\t{}\t{}\tP\tPPL\t{}\t\t", name, name, lat, lon, country)
+    }
+
+    fn close(a : f64, b : f64, within : f64) -> bool {
+        (a - b).abs() <= within
+    }
+
+    #[test]
+    fn distance_same_place() {
+        assert!(close(distance_km(35.68, 139.69, 35.68, 139.69), 0.0, 1e-9));
+    }
+
+    #[test]
+    fn distance_known() {
+        // london to paris
+        assert!(close(distance_km(51.5074, -0.1278, 48.8566, 2.3522), 343.5, 1.0));
+        // a degree along the equator
+        assert!(close(distance_km(0.0, 0.0, 0.0, 1.0), KM_PER_DEGREE, 1e-6));
+    }
+
+    #[test]
+    fn distance_antipodes() {
+        let half = EARTH_RADIUS_KM * std::f64::consts::PI;
+        assert!(close(distance_km(0.0, 0.0, 0.0, 180.0), half, 1e-6));
+        assert!(close(distance_km(90.0, 0.0, -90.0, 0.0), half, 1e-6));
+    }
+
+    #[test]
+    fn distance_across_antimeridian() {
+        assert!(close(distance_km(0.0, 179.5, 0.0, -179.5), KM_PER_DEGREE, 1e-6));
+    }
+
+    #[test]
+    fn parse_skips_malformed() {
+        let s = [
+            line("Tokyo", 35.6895, 139.69171, "JP"),
+            "not\ta\tplace".to_string(),
+            line("Nowhere", 91.0, 0.0, "XX"),
+            "2\tParis\tParis\t\tnorth\t2.35\tP\tPPLC\tFR".to_string(),
+            String::new(),
+            line("Paris", 48.85341, 2.3488, "FR"),
+        ].join("\n");
+
+        let geocoder = Geocoder::parse(&s);
+        let names : Vec<&str> = geocoder.places.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Tokyo", "Paris"]);
+        assert_eq!(geocoder.places[1].country, "FR");
+    }
+
+    #[test]
+    fn lookup_closest() {
+        let s = [
+            line("Tokyo", 35.6895, 139.69171, "JP"),
+            line("Yokohama", 35.44778, 139.6425, "JP"),
+            line("Paris", 48.85341, 2.3488, "FR"),
+        ].join("\n");
+        let geocoder = Geocoder::parse(&s);
+
+        assert_eq!(geocoder.lookup(35.46, 139.62).as_deref(), Some("Yokohama, JP"));
+        assert_eq!(geocoder.lookup(35.70, 139.70).as_deref(), Some("Tokyo, JP"));
+        // the middle of the pacific
+        assert_eq!(geocoder.lookup(0.0, -150.0), None);
+    }
+
+    #[test]
+    fn lookup_neighbouring_cells() {
+        // just across a cell's edge, and across the antimeridian
+        let s = [
+            line("Edge", 10.01, 20.01, "AA"),
+            line("Suva", -18.14161, 178.44149, "FJ"),
+            line("Taveuni", -16.85, 179.99, "FJ"),
+        ].join("\n");
+        let geocoder = Geocoder::parse(&s);
+
+        assert_eq!(geocoder.lookup(9.99, 19.99).as_deref(), Some("Edge, AA"));
+        assert_eq!(geocoder.lookup(-16.85, -179.9).as_deref(), Some("Taveuni, FJ"));
+    }
+
+    #[test]
+    fn lookup_near_pole() {
+        // a degree of longitude is a few km this far north
+        let s = line("Alert", 82.5, 10.0, "CA");
+        let geocoder = Geocoder::parse(&s);
+        assert_eq!(geocoder.lookup(82.6, 4.5).as_deref(), Some("Alert, CA"));
+    }
+
+    #[test]
+    fn country_names() {
+        let info = "#ISO\tISO3\tISO-Numeric\tfips\tCountry\n\
+            JP\tJPN\t392\tJA\tJapan\tTokyo\n\
+            broken line\n\
+            FR\tFRA\t250\tFR\tFrance\tParis\n";
+        let names = parse_countries(info);
+        assert_eq!(names.len(), 2);
+        assert_eq!(names["JP"], "Japan");
+
+        let s = [
+            line("Tokyo", 35.6895, 139.69171, "JP"),
+            line("Ushuaia", -54.8, -68.3, "AR"),
+        ].join("\n");
+        let mut geocoder = Geocoder::parse(&s);
+        geocoder.name_countries(&names);
+        assert_eq!(geocoder.lookup(35.69, 139.69).as_deref(), Some("Tokyo, Japan"));
+        // codes without a name stay
+        assert_eq!(geocoder.lookup(-54.8, -68.3).as_deref(), Some("Ushuaia, AR"));
+    }
+}
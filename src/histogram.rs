@@ -0,0 +1,82 @@
+// how many of the edited photo's pixels have each value, for the photo
+// screen's panel. counted from a small render of the photo with its
+// effects, so it moves with the sliders
+
+use egui::Color32;
+
+const BINS : usize = 64;
+const HEIGHT : f32 = 80.0;
+
+/// The longest side of the render a histogram is counted from.
+pub const RENDER_SIZE : u32 = 256;
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    // red, green, blue then luma
+    channels : [[u32; BINS]; 4],
+}
+
+impl Histogram {
+    /// Drawn flat, for photos that couldn't be rendered.
+    pub fn empty() -> Self {
+        Histogram{
+            channels : [[0; BINS]; 4],
+        }
+    }
+
+    /// Of the pixels of img that aren't transparent, the ones around the
+    /// photo are.
+    pub fn of(img : &image::RgbaImage) -> Self {
+        let mut channels = [[0; BINS]; 4];
+        let bin = |v : u8| v as usize * BINS / 256;
+
+        for pixel in img.pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a == 0 {
+                continue
+            }
+
+            // rec. 709
+            let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+
+            channels[0][bin(r)] += 1;
+            channels[1][bin(g)] += 1;
+            channels[2][bin(b)] += 1;
+            channels[3][bin(luma.round().min(255.0) as u8)] += 1;
+        }
+
+        Histogram{channels}
+    }
+
+    pub fn show(&self, ui : &mut egui::Ui) {
+        let size = egui::vec2(ui.available_width(), HEIGHT);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 2.0, Color32::from_gray(16));
+
+        // a photo mostly black or white would flatten the rest, so the tall
+        // bins at the ends are cut off
+        let tallest = self.channels.iter()
+            .flat_map(|channel| channel[1..BINS - 1].iter())
+            .cloned()
+            .max()
+            .unwrap_or(0)
+            .max(1) as f32;
+
+        let colors = [
+            Color32::from_rgb(220, 60, 60),
+            Color32::from_rgb(60, 200, 60),
+            Color32::from_rgb(70, 110, 240),
+            Color32::from_gray(200),
+        ];
+
+        for (channel, color) in self.channels.iter().zip(colors.iter()) {
+            let points = channel.iter().enumerate().map(|(idx, count)| {
+                let x = rect.left() + rect.width() * idx as f32 / (BINS - 1) as f32;
+                let y = rect.bottom() - rect.height() * (*count as f32 / tallest).min(1.0);
+                egui::pos2(x, y)
+            });
+            painter.add(egui::Shape::line(points.collect(), (1.0, *color)));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+// just enough of ICC profiles to color manage a matrix display: the
+// primaries and the tone curve. LUT based profiles aren't supported
+
+use std::path::Path;
+
+use glam::f32::{
+    Mat3,
+    Vec3,
+};
+
+// the sRGB primaries in the profile connection space, adapted to D50
+const SRGB_TO_XYZ : [[f32; 3]; 3] = [
+    [0.436_074_7, 0.222_504_5, 0.013_932_2],
+    [0.385_064_9, 0.716_878_6, 0.097_104_5],
+    [0.143_080_4, 0.060_616_9, 0.714_173_3],
+];
+
+/// How to turn linear sRGB into what a monitor expects, from its ICC
+/// profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayProfile {
+    /// Linear sRGB to the display's linear RGB
+    pub matrix : Mat3,
+    /// The display's tone curve as a power, None for the sRGB curve
+    pub gamma : Option<f32>,
+}
+
+impl Default for DisplayProfile {
+    /// An sRGB display, nothing changes.
+    fn default() -> Self {
+        DisplayProfile {
+            matrix : Mat3::IDENTITY,
+            gamma : None,
+        }
+    }
+}
+
+impl DisplayProfile {
+    /// The profile in the .icc or .icm file at `dir/<monitor>.icc` or
+    /// `dir/<monitor>.icm`, if there is one it can use.
+    pub fn load(dir : &Path, monitor : &str) -> Option<Self> {
+        ["icc", "icm"].iter()
+            .filter_map(|ext| std::fs::read(dir.join(format!("{}.{}", monitor, ext))).ok())
+            .find_map(|byt| Self::parse(&byt))
+    }
+
+    pub fn parse(byt : &[u8]) -> Option<Self> {
+        let tags = tags(byt)?;
+        let tag = |sig : &[u8]| {
+            tags.iter()
+                .find(|(s, _)| *s == sig)
+                .map(|(_, data)| *data)
+        };
+
+        let red = xyz(tag(b"rXYZ")?)?;
+        let green = xyz(tag(b"gXYZ")?)?;
+        let blue = xyz(tag(b"bXYZ")?)?;
+
+        let to_xyz = Mat3::from_cols(red, green, blue);
+        if to_xyz.determinant().abs() < 1e-6 {
+            return None
+        }
+
+        let srgb = Mat3::from_cols_array_2d(&SRGB_TO_XYZ);
+
+        Some(DisplayProfile {
+            matrix : to_xyz.inverse() * srgb,
+            // the green curve stands in for all three
+            gamma : tag(b"gTRC").and_then(gamma)?,
+        })
+    }
+}
+
+fn u32_at(byt : &[u8], at : usize) -> Option<u32> {
+    let b = byt.get(at..at + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u16_at(byt : &[u8], at : usize) -> Option<u16> {
+    let b = byt.get(at..at + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn s15_fixed16_at(byt : &[u8], at : usize) -> Option<f32> {
+    Some(u32_at(byt, at)? as i32 as f32 / 65536.)
+}
+
+// signatures and data of the tags, after the 128 byte header
+fn tags(byt : &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    if byt.get(36..40)? != b"acsp" {
+        return None
+    }
+
+    let count = u32_at(byt, 128)? as usize;
+    (0..count.min(256))
+        .map(|idx| {
+            let entry = 132 + idx * 12;
+            let sig = byt.get(entry..entry + 4)?;
+            let offset = u32_at(byt, entry + 4)? as usize;
+            let size = u32_at(byt, entry + 8)? as usize;
+            Some((sig, byt.get(offset..offset.checked_add(size)?)?))
+        })
+        .collect()
+}
+
+fn xyz(data : &[u8]) -> Option<Vec3> {
+    if data.get(..4)? != b"XYZ " {
+        return None
+    }
+
+    Some(Vec3::new(
+        s15_fixed16_at(data, 8)?,
+        s15_fixed16_at(data, 12)?,
+        s15_fixed16_at(data, 16)?,
+    ))
+}
+
+// Some(None) for the sRGB curve, Some(Some(power)) for anything close
+// enough to one
+fn gamma(data : &[u8]) -> Option<Option<f32>> {
+    match data.get(..4)? {
+        b"curv" => {
+            let count = u32_at(data, 8)? as usize;
+            match count {
+                0 => Some(Some(1.)),
+                1 => Some(Some(u16_at(data, 12)? as f32 / 256.)),
+                _ => {
+                    // a table, estimated from the middle
+                    let mid = u16_at(data, 12 + (count / 2) * 2)? as f32 / 65535.;
+                    let x = (count / 2) as f32 / (count - 1) as f32;
+                    if mid <= 0. || mid >= 1. {
+                        return None
+                    }
+                    Some(Some(mid.ln() / x.ln()))
+                },
+            }
+        },
+        b"para" => {
+            let function = u16_at(data, 8)?;
+            let g = s15_fixed16_at(data, 12)?;
+            // type 3 with the sRGB parameters
+            if function == 3 && (g - 2.4).abs() < 0.01 {
+                Some(None)
+            } else {
+                Some(Some(g))
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Linear sRGB to the profile connection space, columns are the primaries.
+pub fn srgb_to_xyz() -> Mat3 {
+    Mat3::from_cols_array_2d(&SRGB_TO_XYZ)
+}
+
+// D50, the profile connection space's white
+const D50 : [f32; 3] = [0.9642, 1.0, 0.8249];
+
+// entries in the table standing in for the sRGB curve, which v2 profiles
+// can't have as a formula
+const CURVE_ENTRIES : usize = 1024;
+
+/// A v2 display profile with `to_xyz`'s columns for primaries and a tone
+/// curve, a power or None for the sRGB curve, for embedding in exports.
+/// `parse` reads it back.
+pub fn matrix_profile(description : &str, to_xyz : Mat3, gamma : Option<f32>) -> Vec<u8> {
+    let xyz_tag = |v : [f32; 3]| {
+        let mut data = b"XYZ \0\0\0\0".to_vec();
+        for c in v.iter() {
+            data.extend_from_slice(&s15_fixed16(*c));
+        }
+        data
+    };
+
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(description.len() as u32 + 1).to_be_bytes());
+    desc.extend(description.bytes().map(|b| if b.is_ascii() { b } else { b'?' }));
+    desc.push(0);
+    // no unicode or scriptcode versions
+    desc.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+
+    let mut cprt = b"text\0\0\0\0".to_vec();
+    cprt.extend_from_slice(b"no copyright, use freely\0");
+
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    match gamma {
+        Some(gamma) => {
+            curve.extend_from_slice(&1u32.to_be_bytes());
+            curve.extend_from_slice(&((gamma * 256.).round() as u16).to_be_bytes());
+        },
+        None => {
+            curve.extend_from_slice(&(CURVE_ENTRIES as u32).to_be_bytes());
+            for idx in 0..CURVE_ENTRIES {
+                let v = idx as f32 / (CURVE_ENTRIES - 1) as f32;
+                let linear = if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) };
+                curve.extend_from_slice(&((linear * 65535.).round() as u16).to_be_bytes());
+            }
+        },
+    }
+
+    // the curves share their data
+    let tags : [(&[u8], &[u8]); 9] = [
+        (b"desc", &desc),
+        (b"cprt", &cprt),
+        (b"wtpt", &xyz_tag(D50)),
+        (b"rXYZ", &xyz_tag(to_xyz.x_axis.to_array())),
+        (b"gXYZ", &xyz_tag(to_xyz.y_axis.to_array())),
+        (b"bXYZ", &xyz_tag(to_xyz.z_axis.to_array())),
+        (b"rTRC", &curve),
+        (b"gTRC", &curve),
+        (b"bTRC", &curve),
+    ];
+
+    let mut table = Vec::new();
+    let mut data : Vec<u8> = Vec::new();
+    let start = 128 + 4 + tags.len() * 12;
+    let mut curve_at = None;
+
+    for (sig, tag) in tags.iter() {
+        let shared = sig.ends_with(b"TRC");
+        let offset = match (shared, curve_at) {
+            (true, Some(at)) => at,
+            _ => {
+                let at = start + data.len();
+                data.extend_from_slice(tag);
+                // tags start on 4 byte boundaries
+                while data.len() % 4 != 0 {
+                    data.push(0);
+                }
+                at
+            },
+        };
+        if shared {
+            curve_at = Some(offset);
+        }
+
+        table.extend_from_slice(sig);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+    }
+
+    let size = start + data.len();
+    let mut header = [0u8; 128];
+    header[0..4].copy_from_slice(&(size as u32).to_be_bytes());
+    // 2.1
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+    for (idx, c) in D50.iter().enumerate() {
+        header[68 + idx * 4..72 + idx * 4].copy_from_slice(&s15_fixed16(*c));
+    }
+
+    let mut out = Vec::with_capacity(size);
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    out.extend(table);
+    out.extend(data);
+    out
+}
+
+fn s15_fixed16(v : f32) -> [u8; 4] {
+    ((v * 65536.).round() as i32).to_be_bytes()
+}
@@ -0,0 +1,127 @@
+// numbers about the edited photo for the photo screen's statistics card,
+// counted on a worker from a render of it, like the histogram but larger
+// so a few clipped pixels aren't lost
+
+/// The longest side of the render the statistics are counted from.
+pub const RENDER_SIZE : u32 = 1024;
+
+// the share of pixels at either end left out of the dynamic range, so a
+// stray hot pixel doesn't count
+const TAIL : f32 = 0.005;
+
+#[derive(Debug, Clone)]
+pub struct ImageStats {
+    // red, green, blue, 0 to 255
+    min : [u8; 3],
+    max : [u8; 3],
+    mean : [f32; 3],
+    // fractions of the pixels with a channel at 0 or 255
+    clipped_shadows : f32,
+    clipped_highlights : f32,
+    // stops between the dark and the bright ends, None for no pixels
+    dynamic_range : Option<f32>,
+}
+
+impl ImageStats {
+    /// Said to have no pixels, for photos that couldn't be rendered.
+    pub fn empty() -> Self {
+        ImageStats{
+            min : [0; 3],
+            max : [0; 3],
+            mean : [0.; 3],
+            clipped_shadows : 0.,
+            clipped_highlights : 0.,
+            dynamic_range : None,
+        }
+    }
+
+    /// Of the pixels of img that aren't transparent, the ones around the
+    /// photo are.
+    pub fn of(img : &image::RgbaImage) -> Self {
+        let mut min = [255; 3];
+        let mut max = [0; 3];
+        let mut sum = [0u64; 3];
+        let mut shadows = 0;
+        let mut highlights = 0;
+        let mut luminance = Vec::with_capacity(img.len() / 4);
+
+        for pixel in img.pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a == 0 {
+                continue
+            }
+
+            for (idx, v) in [r, g, b].iter().enumerate() {
+                min[idx] = min[idx].min(*v);
+                max[idx] = max[idx].max(*v);
+                sum[idx] += *v as u64;
+            }
+
+            if r == 0 || g == 0 || b == 0 {
+                shadows += 1;
+            }
+            if r == 255 || g == 255 || b == 255 {
+                highlights += 1;
+            }
+
+            // rec. 709, of the light rather than the encoded values
+            let y = 0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b);
+            luminance.push(y);
+        }
+
+        let count = luminance.len();
+        if count == 0 {
+            return ImageStats::empty()
+        }
+
+        luminance.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let at = |fraction : f32| luminance[((count - 1) as f32 * fraction) as usize];
+        // black is as dark as the first step above it, or it'd be infinite
+        let darkest = at(TAIL).max(linear(1));
+        let brightest = at(1. - TAIL).max(darkest);
+
+        ImageStats{
+            min,
+            max,
+            mean : [0, 1, 2].map(|idx| sum[idx] as f32 / count as f32),
+            clipped_shadows : shadows as f32 / count as f32,
+            clipped_highlights : highlights as f32 / count as f32,
+            dynamic_range : Some((brightest / darkest).log2()),
+        }
+    }
+
+    pub fn show(&self, ui : &mut egui::Ui) {
+        egui::Grid::new("image stats").striped(true).show(ui, |ui| {
+            ui.label("");
+            ui.label("min");
+            ui.label("max");
+            ui.label("mean");
+            ui.end_row();
+
+            for (idx, name) in ["red", "green", "blue"].iter().enumerate() {
+                ui.label(*name);
+                ui.monospace(self.min[idx].to_string());
+                ui.monospace(self.max[idx].to_string());
+                ui.monospace(format!("{:.1}", self.mean[idx]));
+                ui.end_row();
+            }
+        });
+
+        ui.label(format!("{:.2}% clipped to black", self.clipped_shadows * 100.));
+        ui.label(format!("{:.2}% clipped to white", self.clipped_highlights * 100.));
+        match self.dynamic_range {
+            Some(stops) => ui.label(format!("about {:.1} stops of dynamic range", stops)),
+            None => ui.label("no pixels"),
+        };
+    }
+}
+
+// sRGB to linear light
+fn linear(v : u8) -> f32 {
+    let v = v as f32 / 255.;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,341 @@
+// photos exported from google photos and apple photos, which keep what the
+// files are missing next to them. takeout has a .json per photo and a
+// metadata.json per album folder, apple photos writes .xmp sidecars and
+// exports albums as folders. each export is a Source, the app tries them
+// in turn, so another is one more in Sources::builtin
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::Arc;
+use std::time::{
+    Duration,
+    SystemTime,
+};
+
+use photos1::async_trait;
+
+use crate::metadata::DateTime;
+use crate::xmp;
+
+// takeout cuts the names of its json files to this many characters before
+// the .json
+const TAKEOUT_NAME_LEN : usize = 46;
+
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    /// When the photo was taken
+    pub date : Option<SystemTime>,
+    pub description : Option<String>,
+    /// The album the photo was exported from
+    pub album : Option<String>,
+}
+
+impl Info {
+    pub fn is_empty(&self) -> bool {
+        self.date.is_none() && self.description.is_none() && self.album.is_none()
+    }
+}
+
+/// An export photos are imported from, which keeps what the files are
+/// missing next to them.
+#[async_trait]
+pub trait Source : Send + Sync {
+    /// Shown in the preferences
+    fn name(&self) -> &str;
+
+    /// What the export knows about the photo, source is the folder being
+    /// imported. None if the photo isn't from this export.
+    async fn read(&self, path : &Path, source : &Path) -> Option<Info>;
+}
+
+struct Takeout;
+
+#[async_trait]
+impl Source for Takeout {
+    fn name(&self) -> &str {
+        "Google Takeout"
+    }
+
+    async fn read(&self, path : &Path, source : &Path) -> Option<Info> {
+        takeout(path, source).await
+    }
+}
+
+struct Apple;
+
+#[async_trait]
+impl Source for Apple {
+    fn name(&self) -> &str {
+        "Apple Photos"
+    }
+
+    async fn read(&self, path : &Path, source : &Path) -> Option<Info> {
+        apple(path, source).await
+    }
+}
+
+/// The exports imports are read from, tried in order.
+#[derive(Clone)]
+pub struct Sources {
+    sources : Vec<Arc<dyn Source>>,
+}
+
+impl Sources {
+    pub fn builtin() -> Self {
+        let mut sources = Sources{
+            sources : Vec::new(),
+        };
+
+        // takeout's json is only ever takeout's, apple's sidecars could be
+        // anyone's
+        sources.register(Takeout);
+        sources.register(Apple);
+        sources
+    }
+
+    /// Adds a source, tried after the ones already there.
+    pub fn register<S : Source + 'static>(&mut self, source : S) {
+        self.sources.push(Arc::new(source));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Source> {
+        self.sources.iter().map(|source| &**source)
+    }
+
+    /// What the first export to know the photo says about it. Empty for
+    /// photos which aren't from an export.
+    pub async fn read(&self, path : &Path, source : &Path) -> Info {
+        for export in &self.sources {
+            if let Some(info) = export.read(path, source).await {
+                return info
+            }
+        }
+
+        Info::default()
+    }
+}
+
+// the folder a photo is in, if it isn't the one being imported
+fn folder<'a>(path : &'a Path, source : &Path) -> Option<&'a Path> {
+    path.parent().filter(|dir| *dir != source)
+}
+
+async fn takeout(path : &Path, source : &Path) -> Option<Info> {
+    let json = takeout_json(path).await?;
+    let byt = tokio::fs::read(json).await.ok()?;
+    let mut info = parse_takeout(&byt)?;
+
+    info.album = match folder(path, source) {
+        Some(dir) => takeout_album(dir).await,
+        None => None,
+    };
+
+    Some(info)
+}
+
+/// The date and description in a takeout photo's json, None if it isn't
+/// json. The album comes from the folder.
+pub fn parse_takeout(byt : &[u8]) -> Option<Info> {
+    let value : serde_json::Value = serde_json::from_slice(byt).ok()?;
+
+    // seconds since the epoch, as a string
+    let date = value["photoTakenTime"]["timestamp"]
+        .as_str()
+        .and_then(|secs| secs.parse().ok())
+        .and_then(|secs| SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs)));
+
+    let description = value["description"]
+        .as_str()
+        .filter(|d| !d.is_empty())
+        .map(String::from);
+
+    Some(Info{
+        date,
+        description,
+        album : None,
+    })
+}
+
+// the json next to a takeout photo, which is named a few different ways
+async fn takeout_json(path : &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let mut names = vec![
+        format!("{}.json", name),
+        format!("{}.supplemental-metadata.json", name),
+        format!("{}.json", name.chars().take(TAKEOUT_NAME_LEN).collect::<String>()),
+        format!("{}.json", stem),
+    ];
+
+    // edited copies share the original's
+    if let Some(original) = stem.strip_suffix("-edited") {
+        names.push(format!("{}.{}.json", original, ext));
+    }
+
+    for name in names {
+        let json = path.with_file_name(name);
+        if tokio::fs::metadata(&json).await.is_ok() {
+            return Some(json)
+        }
+    }
+
+    None
+}
+
+// the year folders aren't albums
+async fn takeout_album(dir : &Path) -> Option<String> {
+    if let Ok(byt) = tokio::fs::read(dir.join("metadata.json")).await {
+        let value : serde_json::Value = serde_json::from_slice(&byt).ok()?;
+        if let Some(title) = value["title"].as_str().filter(|t| !t.is_empty()) {
+            return Some(title.to_string())
+        }
+    }
+
+    let name = dir.file_name()?.to_string_lossy().into_owned();
+    if name.starts_with("Photos from ") {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+async fn apple(path : &Path, source : &Path) -> Option<Info> {
+    let sidecar = xmp::find(path).await?;
+    let byt = tokio::fs::read(sidecar).await.ok()?;
+    let sidecar = xmp::Sidecar::parse(&String::from_utf8_lossy(&byt));
+
+    let date = sidecar.date
+        .as_deref()
+        .and_then(DateTime::parse_iso)
+        .map(|date| date.to_system_time());
+
+    // there's no telling apple's folders from others with sidecars, like
+    // lightroom's, so any of them is taken as an album
+    let album = folder(path, source)
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().into_owned());
+
+    Some(Info{
+        date,
+        description : sidecar.description,
+        album,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F : std::future::Future>(fut : F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(fut)
+    }
+
+    // an empty folder of its own under the temp dir
+    fn scratch(name : &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("photos1-importers-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_takeout_sample() {
+        let json = br#"{
+            "title": "IMG_0001.jpg",
+            "description": "Birthday",
+            "photoTakenTime": {"timestamp": "1622730130", "formatted": "Jun 3, 2021, 2:22:10 PM UTC"}
+        }"#;
+
+        let info = parse_takeout(json).unwrap();
+        assert_eq!(info.date, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1622730130)));
+        assert_eq!(info.description.as_deref(), Some("Birthday"));
+        assert_eq!(info.album, None);
+    }
+
+    #[test]
+    fn parse_takeout_missing_fields() {
+        let info = parse_takeout(br#"{"title": "IMG_0001.jpg", "description": ""}"#).unwrap();
+        assert!(info.is_empty());
+
+        // the timestamp is a string, anything else isn't a date
+        for json in [
+            &br#"{"photoTakenTime": {"timestamp": 1622730130}}"#[..],
+            br#"{"photoTakenTime": {"timestamp": "yesterday"}}"#,
+            br#"{"photoTakenTime": {"timestamp": "-5"}}"#,
+            br#"{"photoTakenTime": "1622730130"}"#,
+            br#"[]"#,
+        ] {
+            assert!(parse_takeout(json).unwrap().date.is_none());
+        }
+    }
+
+    #[test]
+    fn parse_takeout_malformed() {
+        assert!(parse_takeout(b"").is_none());
+        assert!(parse_takeout(b"not json").is_none());
+        assert!(parse_takeout(br#"{"description": "Birth"#).is_none());
+        assert!(parse_takeout(&[0xFF, 0xFE, 0x00]).is_none());
+    }
+
+    // fuzz/regressions/takeout_json, past what SystemTime holds
+    #[test]
+    fn parse_takeout_timestamp_overflow() {
+        let info = parse_takeout(include_bytes!("../fuzz/regressions/takeout_json/timestamp-overflow.json")).unwrap();
+        assert_eq!(info.date, None);
+    }
+
+    #[test]
+    fn takeout_folder() {
+        let source = scratch("takeout");
+        let album = source.join("Trip to Rome");
+        let year = source.join("Photos from 2021");
+        std::fs::create_dir_all(&album).unwrap();
+        std::fs::create_dir_all(&year).unwrap();
+        std::fs::write(album.join("metadata.json"), r#"{"title": "Rome 2021"}"#).unwrap();
+        std::fs::write(album.join("IMG_0001.jpg.json"), r#"{"description": "Colosseum"}"#).unwrap();
+        // edited copies share the original's json
+        std::fs::write(year.join("IMG_0002.jpg.json"), r#"{"description": "Trevi"}"#).unwrap();
+
+        let info = block_on(takeout(&album.join("IMG_0001.jpg"), &source)).unwrap();
+        assert_eq!(info.description.as_deref(), Some("Colosseum"));
+        assert_eq!(info.album.as_deref(), Some("Rome 2021"));
+
+        let info = block_on(takeout(&year.join("IMG_0002-edited.jpg"), &source)).unwrap();
+        assert_eq!(info.description.as_deref(), Some("Trevi"));
+        // the year folders aren't albums
+        assert_eq!(info.album, None);
+
+        assert!(block_on(takeout(&album.join("IMG_0003.jpg"), &source)).is_none());
+
+        let _ = std::fs::remove_dir_all(&source);
+    }
+
+    #[test]
+    fn apple_sidecar() {
+        let source = scratch("apple");
+        let album = source.join("Holiday");
+        std::fs::create_dir_all(&album).unwrap();
+        std::fs::write(album.join("IMG_0001.xmp"), concat!(
+            "<rdf:Description photoshop:DateCreated=\"1970-01-02T00:00:10\">",
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">Beach</rdf:li></rdf:Alt></dc:description>",
+            "</rdf:Description>")).unwrap();
+
+        let info = block_on(apple(&album.join("IMG_0001.jpg"), &source)).unwrap();
+        assert_eq!(info.date, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(86410)));
+        assert_eq!(info.description.as_deref(), Some("Beach"));
+        assert_eq!(info.album.as_deref(), Some("Holiday"));
+
+        // no sidecar, not from apple
+        assert!(block_on(apple(&album.join("IMG_0002.jpg"), &source)).is_none());
+
+        let _ = std::fs::remove_dir_all(&source);
+    }
+}
@@ -1,5 +1,11 @@
 use glium::glutin;
 
+// how far a wheel click scrolls, in points
+const LINE_HEIGHT : f32 = 20.0;
+
+/// Pointer input that egui didn't want. Positions and deltas are in
+/// physical pixels from the top left, like `RenderCtx::dimensions`, see
+/// `RenderCtx::to_points` for egui's coordinates.
 #[derive(Debug, Default)]
 pub struct Input {
     /// Some if currently in a drag action, the start x, start y, and if
@@ -9,9 +15,18 @@ pub struct Input {
     pub pointer : (f32, f32),
     pub scroll_delta : (f32, f32),
     pub modifiers : glutin::event::ModifiersState,
+    /// Physical pixels per point
+    pub scale_factor : f32,
 }
 
 impl Input {
+    crate fn new(scale_factor : f32) -> Self {
+        Input {
+            scale_factor,
+            ..Default::default()
+        }
+    }
+
     crate fn frame_reset(&mut self) {
         if matches!(self.pointer_drag, Some((_, _, true))) {
             self.pointer_drag = None;
@@ -47,15 +62,24 @@ impl Input {
                     }
                 }
             },
+            Resized(_) => {
+                // the drag start is in the old coordinates
+                self.pointer_drag = None;
+            },
+            ScaleFactorChanged{scale_factor, ..} => {
+                self.scale_factor = scale_factor as f32;
+                self.pointer_drag = None;
+            },
             ModifiersChanged(modifiers) => {
                 self.modifiers = modifiers;
             },
             MouseWheel{ delta, ..} => {
                 match delta {
                     MouseScrollDelta::LineDelta(x, y) => {
-                        // TODO: test this code path
-                        self.scroll_delta.0 += x;
-                        self.scroll_delta.1 += y;
+                        // in pixels and the same direction as touchpads
+                        let line = LINE_HEIGHT * self.scale_factor;
+                        self.scroll_delta.0 -= x * line;
+                        self.scroll_delta.1 -= y * line;
                     },
                     MouseScrollDelta::PixelDelta(PhysicalPosition{x, y}) => {
                         self.scroll_delta.0 -= x as f32;
@@ -1,33 +1,164 @@
 #![feature(crate_visibility_modifier)]
 
+//! An Elm-like application framework on top of glium and egui. Implement
+//! [`App`] and hand it to [`run_app`], the framework owns the window, the
+//! render loop, and a tokio runtime for the blocking work.
+//!
+//! The photo editing parts (the effects shader) are behind the `photos`
+//! feature, which is on by default. Embedders can turn it off with
+//! `default-features = false`, see `examples/viewer.rs` for a minimal app.
+
+// re-exported so apps build against the same versions as the framework
+pub use async_trait::async_trait;
+pub use egui;
+pub use glium;
+pub use image;
+pub use tracing;
+
 mod double_buffer;
 pub use double_buffer::*;
 
+mod slot;
+pub use slot::Slot;
+use slot::SwapSlot;
+
 mod task_channel;
-use task_channel::TaskChannel;
+use task_channel::{
+    RuntimeOptions,
+    TaskChannel,
+};
+pub use task_channel::{
+    Job,
+    JobManager,
+    JobStatus,
+    Priority,
+};
+
+mod cmd;
+pub use cmd::Cmd;
+
+mod wake;
+use wake::LoopEvent;
+pub use wake::Waker;
+
+mod sub;
+pub use sub::Sub;
+
+mod middleware;
+pub use middleware::*;
+
+mod retry;
+pub use retry::{
+    Backoff,
+    is_transient,
+};
+
+mod toasts;
+pub use toasts::{
+    Level,
+    Toast,
+    Toasts,
+};
+
+mod stats;
+pub use stats::Stats;
 
+mod logs;
+pub use logs::{
+    LogLine,
+    Logs,
+};
+
+mod shader_cache;
+pub use shader_cache::program as cached_program;
+
+#[cfg(feature = "photos")]
 mod shaders;
+#[cfg(feature = "photos")]
 pub use shaders::{
     Effects,
     EffectsShader,
+    GRID_ZOOM,
+    Overlays,
+    PEAKING_THRESHOLD,
+    PROCESS_VERSION,
+    PeakingShader,
 };
 
 mod input;
-use input::Input;
+pub use input::Input;
 
 mod color;
 pub use color::*;
 
+mod icc;
+pub use icc::{
+    DisplayProfile,
+    matrix_profile,
+    srgb_to_xyz,
+};
+
+mod recover;
+pub use recover::{
+    MutexExt,
+    Panic,
+};
+
+mod decode;
+pub use decode::{
+    DecodeLimits,
+    LimitError,
+};
+
+mod formats;
+pub use formats::{
+    Decoder,
+    Formats,
+};
+
+mod style;
+use style::Style;
+
+mod script;
+pub use script::Script;
+
+mod settings;
+pub use settings::{
+    Preferences,
+    Settings,
+    Theme,
+    WindowGeometry,
+};
+
 mod utils;
 use utils::{
     UniformsCons,
     create_display,
+    recreate_display,
+    WindowOptions,
+    keep_on_screen,
 };
 
+mod preview;
+use preview::Preview;
+
+mod watchdog;
+use watchdog::Watchdog;
+
+mod fatal;
+
+pub mod testing;
+
+use std::any::Any;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::time::{
+    Duration,
+    Instant,
+};
 
 use quick_from::QuickFrom;
-use async_trait::async_trait;
 
 use glium::{
     implement_vertex,
@@ -53,6 +184,47 @@ pub enum Error {
     Io(std::io::Error),
     #[quick_from]
     Image(image::ImageError),
+    #[quick_from]
+    Texture(glium::texture::TextureCreationError),
+    #[quick_from]
+    Framebuffer(glium::framebuffer::ValidationError),
+    #[quick_from]
+    Program(glium::program::ProgramCreationError),
+    #[quick_from]
+    VertexBuffer(glium::vertex::BufferCreationError),
+    #[quick_from]
+    IndexBuffer(glium::index::BufferCreationError),
+    /// Showing the frame failed, usually because the context was lost
+    #[quick_from]
+    Swap(glium::SwapBuffersError),
+    /// The image was deleted, or its upload failed
+    MissingImage(ImageId),
+    /// A texture read back with fewer pixels than its size
+    ReadBack{width : u32, height : u32},
+    #[quick_from]
+    Limit(LimitError),
+    #[quick_from]
+    Panic(Panic),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Draw(err) => write!(f, "draw error: {}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Image(err) => write!(f, "{}", err),
+            Error::Texture(err) => write!(f, "texture error: {}", err),
+            Error::Framebuffer(err) => write!(f, "framebuffer error: {}", err),
+            Error::Program(err) => write!(f, "shader error: {}", err),
+            Error::VertexBuffer(err) => write!(f, "vertex buffer error: {}", err),
+            Error::IndexBuffer(err) => write!(f, "index buffer error: {}", err),
+            Error::Swap(err) => write!(f, "could not show the frame: {}", err),
+            Error::MissingImage(img_id) => write!(f, "no image with id {}", img_id.id),
+            Error::ReadBack{width, height} => write!(f, "could not read back a {}x{} texture", width, height),
+            Error::Limit(err) => write!(f, "{}", err),
+            Error::Panic(panic) => write!(f, "{}", panic),
+        }
+    }
 }
 
 
@@ -75,24 +247,89 @@ pub trait App : Send + Sync + Sized {
     /// Only lives on the render thread
     type LocalModel : Debug + 'static;
     type Msg : Debug + Send + 'static;
-    type Error : Debug;
+    /// Panics in workers come back as errors, see `Panic`, and so do the
+    /// framework's GPU errors outside of `render`
+    type Error : Debug + From<Panic> + From<Error>;
 
     // name and handle_error do not run on a specified thread, thus should not
-    // block or make assumptions of the runtime.
+    // block or make assumptions of the runtime. To show errors in the window,
+    // keep a clone of InitCtx::toasts and push to it here.
     fn name() -> &'static str;
     fn handle_error(&self, err : Self::Error) {
-        println!("error: {:?}", err);
+        tracing::error!("{:?}", err);
+    }
+
+    /// Which lane msg is handled in, called on the render thread. Messages
+    /// in the same lane are handled in the order they were sent, there's no
+    /// order between lanes.
+    fn priority(&self, _msg : &Self::Msg) -> Priority {
+        Priority::Interactive
     }
 
-    /// initialize the app state, runs on the render thread
-    fn init(ctx : &mut InitCtx, msgs : &mut Vec<Self::Msg>) -> (Self, Self::LocalModel, Self::Model);
+    /// Called on the render thread when msg is sent while the last message
+    /// sent in its lane is still waiting for a worker. Fold msg into it and
+    /// return None to send one message instead of two, for example when a
+    /// newer message makes the older one pointless. The default keeps both.
+    fn coalesce(&self, _last : &mut Self::Msg, msg : Self::Msg) -> Option<Self::Msg> {
+        Some(msg)
+    }
+
+    /// initialize the app state, runs on the render thread. If it fails
+    /// there's no app to handle the error, the window shows it instead and
+    /// the process exits with 1 once it's closed.
+    fn init(ctx : &mut InitCtx) -> std::result::Result<(Self, Self::LocalModel, Self::Model, Cmd<Self::Msg>), Self::Error>;
 
     /// render the app to the screen
     fn render(&self,
               ctx : &mut RenderCtx,
               local_model : &mut Self::LocalModel,
-              model : &mut Self::Model,
-              msgs : &mut Vec<Self::Msg>);
+              model : &mut Self::Model) -> Cmd<Self::Msg>;
+
+    /// Called on the render thread with every window event, before egui
+    /// sees it, for things like focus changes and dropped files. The
+    /// message, if any, is sent to update.
+    fn on_event(&self,
+                _local_model : &mut Self::LocalModel,
+                _event : &glutin::event::WindowEvent<'_>) -> Option<Self::Msg>
+    {
+        None
+    }
+
+    /// Called on the render thread when the window is asked to close, by
+    /// the window manager or egui's quit shortcut. Return false to keep it
+    /// open, for example to ask about unsaved work in the next render, and
+    /// call `RenderCtx::quit` once it's ok to close.
+    fn close_requested(&self, _local_model : &mut Self::LocalModel) -> bool {
+        true
+    }
+
+    /// Called on the render thread as the window closes, to put ui state
+    /// worth keeping in settings.ui, see `Settings::set`. It's read back
+    /// from `InitCtx::settings` on the next run.
+    fn save_settings(&self, _local_model : &Self::LocalModel, _settings : &mut Settings) {}
+
+    /// Called on the render thread with the visuals for the user's theme
+    /// and accent color, before the first frame and whenever they change,
+    /// to adjust them further.
+    fn customize_visuals(&self, _visuals : &mut egui::Visuals) {}
+
+    /// Timers the framework runs while they're returned, called on the
+    /// render thread after every render. See Sub.
+    fn subscriptions(&self, _local_model : &Self::LocalModel) -> Vec<Sub<Self::Msg>> {
+        Vec::new()
+    }
+
+    /// Called on the render thread after the GL context was lost, after a
+    /// driver reset, a GPU hang or a suspend, and a new one made. ImageIds
+    /// stay valid, their images are uploaded again, but other glium objects
+    /// from before are gone, make programs and buffers again here.
+    fn context_lost(&self,
+                    _ctx : &mut InitCtx,
+                    _local_model : &mut Self::LocalModel,
+                    _model : &mut Self::Model) -> Cmd<Self::Msg>
+    {
+        Cmd::none()
+    }
 
     /// used for managing gpu resources
     fn swap(&self, ctx : &mut SwapCtx, old : &mut Self::Model, new : &mut Self::Model);
@@ -100,24 +337,63 @@ pub trait App : Send + Sync + Sized {
     /// the following methods run in the tokio runtime
     async fn update(&'static self,
                     model : &BufBufWrite<Self::Model>,
-                    msg : Self::Msg) -> std::result::Result<(), Self::Error>;
+                    msg : Self::Msg) -> std::result::Result<Cmd<Self::Msg>, Self::Error>;
+
+    /// Called once when the window closes, after close_requested agreed or
+    /// `RenderCtx::quit`, to save what needs saving and cancel jobs. The
+    /// process exits when it returns or after `AppRunner::shutdown_timeout`,
+    /// whichever is first, tasks still running are dropped.
+    async fn shutdown(&'static self, _model : &BufBufWrite<Self::Model>) {}
 }
 
 
-#[derive(Clone, Copy, Debug)]
+/// An uploaded image. It stays the same when the context is lost and the
+/// image is uploaded again, and isn't reused once the image is deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ImageId {
-    gl_id : std::os::raw::c_uint,
-    egui_id : egui::TextureId,
-    ctx_id : usize,
+    id : u64,
 }
 
-impl ImageId {
-    pub fn gl_id(&self) -> u64 {
-        self.gl_id as u64
+/// A part of the window to draw into, in physical pixels from the top left
+/// like `Input`, see `RenderCtx::draw_image_viewport`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x : f32,
+    pub y : f32,
+    pub width : f32,
+    pub height : f32,
+}
+
+impl Viewport {
+    pub fn dimensions(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    pub fn contains(&self, (x, y) : (f32, f32)) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    /// The whole window, of the given size.
+    pub fn window((width, height) : (f32, f32)) -> Viewport {
+        Viewport{
+            x : 0.0,
+            y : 0.0,
+            width,
+            height,
+        }
     }
 
-    pub fn egui_id(&self) -> egui::TextureId {
-        self.egui_id
+    /// The window split into count columns of the same width, left to right.
+    pub fn columns((width, height) : (f32, f32), count : usize) -> Vec<Viewport> {
+        let column = width / count.max(1) as f32;
+        (0..count)
+            .map(|idx| Viewport{
+                x : (idx as f32 * column).round(),
+                y : 0.0,
+                width : column.floor(),
+                height,
+            })
+            .collect()
     }
 }
 
@@ -133,15 +409,27 @@ struct Vertex {
 implement_vertex!(Vertex, position, texcoord);
 
 
+struct Image {
+    texture : glium::texture::SrgbTexture2d,
+    egui_id : egui::TextureId,
+    // a copy of the pixels to upload again after the context is lost. kept
+    // even when the context says it can't be, some drivers don't say and
+    // scripts lose it on purpose
+    source : image::RgbaImage,
+}
+
 pub struct GraphicsCtx {
     // TODO: rename to image_*_buffer
     vertex_buffer : glium::VertexBuffer<Vertex>,
     index_buffer : glium::IndexBuffer<u16>,
-    images : Vec<Option<glium::texture::SrgbTexture2d>>,
+    // by ImageId rather than by GL name or egui id, which both change with
+    // the context
+    images : HashMap<ImageId, Image>,
+    next_id : u64,
 }
 
 impl GraphicsCtx {
-    fn new(display : &glium::Display) -> Self {
+    fn new(display : &glium::Display) -> Result<Self> {
         let vertex_buffer = {
             glium::VertexBuffer::new(display,
                 &[
@@ -150,27 +438,28 @@ impl GraphicsCtx {
                     Vertex { position: [ 1.0, -1.0], texcoord: [1.0, 1.0] },
                     Vertex { position: [ 1.0,  1.0], texcoord: [1.0, 0.0] }
                 ]
-            ).unwrap()
+            )?
         };
 
         let index_buffer = glium::IndexBuffer::new(
             display,
             glium::index::PrimitiveType::TriangleStrip,
             &[1 as u16, 2, 0, 3]
-        ).unwrap();
+        )?;
 
-        Self{
+        Ok(Self{
             vertex_buffer,
             index_buffer,
-            images : Vec::new(),
-        }
+            images : HashMap::new(),
+            next_id : 0,
+        })
     }
 
-    fn add_image(
-        &mut self,
+    // the texture, and one not owning it for egui
+    fn upload(
         display : &glium::Display,
-        egui : &mut egui_glium::Painter,
-        img : image::RgbaImage) -> ImageId
+        img : image::RgbaImage,
+    ) -> Result<(glium::texture::SrgbTexture2d, glium::texture::SrgbTexture2d)>
     {
         let dim = img.dimensions();
 
@@ -180,7 +469,7 @@ impl GraphicsCtx {
             img,
             glium::texture::SrgbFormat::U8U8U8,
             glium::texture::MipmapsOption::NoMipmap,
-        ).unwrap();
+        )?;
 
         let gl_id = tex.get_id();
 
@@ -198,43 +487,149 @@ impl GraphicsCtx {
             )
         };
 
+        Ok((tex, non_owned))
+    }
+
+    // how many textures there are and about how big, drivers pad RGB to 4
+    // bytes a pixel
+    fn texture_usage(&self) -> (usize, u64) {
+        self.images.values().fold((0, 0), |(count, bytes), image| {
+            let width = image.texture.get_width() as u64;
+            let height = image.texture.get_height().unwrap_or(1) as u64;
+            (count + 1, bytes + width * height * 4)
+        })
+    }
+
+    fn add_image(
+        &mut self,
+        display : &glium::Display,
+        egui : &mut egui_glium::Painter,
+        img : image::RgbaImage) -> Result<ImageId>
+    {
+        let source = img.clone();
+        let (texture, non_owned) = Self::upload(display, img)?;
         let egui_id = egui.register_glium_texture(non_owned);
 
-        for (idx, tex_opt) in self.images.iter_mut().enumerate() {
-            if tex_opt.is_none() {
-                *tex_opt = Some(tex);
-                return ImageId {
-                    ctx_id : idx,
-                    egui_id,
-                    gl_id,
-                }
+        let img_id = ImageId{ id : self.next_id };
+        self.next_id += 1;
+        self.images.insert(img_id, Image {
+            texture,
+            egui_id,
+            source,
+        });
+
+        Ok(img_id)
+    }
+
+    // uploads every image again into a new context, egui is its new
+    // painter. the ImageIds held by the app stay valid, images which can't
+    // be uploaded are left out, and drawing them fails with
+    // Error::MissingImage
+    fn restore(&mut self, display : &glium::Display, egui : &mut egui_glium::Painter) -> Result<()> {
+        let fresh = GraphicsCtx::new(display)?;
+        self.vertex_buffer = fresh.vertex_buffer;
+        self.index_buffer = fresh.index_buffer;
+
+        let mut lost = Vec::new();
+        let mut failed = None;
+        for (img_id, image) in self.images.iter_mut() {
+            match Self::upload(display, image.source.clone()) {
+                Ok((texture, non_owned)) => {
+                    image.texture = texture;
+                    image.egui_id = egui.register_glium_texture(non_owned);
+                },
+                Err(err) => {
+                    lost.push(*img_id);
+                    failed = Some(err);
+                },
             }
         }
 
-        let idx = self.images.len();
-        self.images.push(Some(tex));
-        ImageId{
-            ctx_id : idx,
-            egui_id,
-            gl_id
+        for img_id in lost {
+            self.images.remove(&img_id);
+        }
+
+        match failed {
+            Some(err) => Err(err),
+            None => Ok(()),
         }
     }
 
     pub fn delete_image(&mut self, egui : &mut egui_glium::Painter, img_id : ImageId) {
-        match self.images.get_mut(img_id.ctx_id) {
-            Some(x) => {
-                x.take();
-            },
-            _ => {},
+        if let Some(image) = self.images.remove(&img_id) {
+            egui.free_user_texture(image.egui_id);
         }
+    }
 
-        egui.free_user_texture(img_id.egui_id);
+    // draws the image centered on a surface of the given size in pixels,
+    // trans is in pixels too
+    // into the whole surface, or only viewport of it, of the given size
+    fn draw_image<S, U>(
+        &self,
+        surface : &mut S,
+        (width, height) : (f32, f32),
+        viewport : Option<glium::Rect>,
+        img_id : ImageId,
+        trans : &Mat4,
+        program : &glium::Program,
+        uniforms : U
+    ) -> Result<()>
+    where
+        S : Surface,
+        U : glium::uniforms::Uniforms
+    {
+        let texture = self.get_image_texture(img_id)?;
+
+        let tex_width = texture.get_width() as f32;
+        // 2d textures always have a height
+        let tex_height = texture.get_height().unwrap_or(1) as f32;
+
+        // modify the translation matrix for gl_coords
+        let trans = Mat4::from_scale(Vec3::new(2. / width, 2. / height, 1.0))
+            .mul_mat4(&trans)
+            .mul_mat4(&Mat4::from_scale(Vec3::new(width / 2., height / 2., 1.0)));
+
+        let window_scale = Mat4::from_scale(
+            Vec3::new(tex_width / width, tex_height / height, 1.0),
+        );
+
+        let uniforms = UniformsCons {
+            name : "matrix",
+            value : trans.mul_mat4(&window_scale).to_cols_array_2d(),
+            rest : uniforms,
+        };
+
+        let uniforms = UniformsCons{
+            name : "texture",
+            value : texture,
+            rest : uniforms,
+        };
+
+        let params = glium::DrawParameters{
+            viewport,
+            ..Default::default()
+        };
+
+        Ok(surface.draw(
+            &self.vertex_buffer,
+            &self.index_buffer,
+            &program,
+            &uniforms,
+            &params,
+        )?)
     }
 
-    fn get_image_texture(&self, img_id : ImageId) -> Option<&glium::texture::SrgbTexture2d> {
-        match self.images.get(img_id.ctx_id) {
-            Some(Some(x)) => Some(x),
-            _ => None,
+    fn get_image_texture(&self, img_id : ImageId) -> Result<&glium::texture::SrgbTexture2d> {
+        match self.images.get(&img_id) {
+            Some(image) => Ok(&image.texture),
+            None => Err(Error::MissingImage(img_id)),
+        }
+    }
+
+    fn get_egui_id(&self, img_id : ImageId) -> Result<egui::TextureId> {
+        match self.images.get(&img_id) {
+            Some(image) => Ok(image.egui_id),
+            None => Err(Error::MissingImage(img_id)),
         }
     }
 
@@ -250,10 +645,67 @@ pub struct UnrenderCtx<'a> {
     pub display : &'a glium::Display,
     egui_glium : &'a mut egui_glium::Painter,
     gfx : &'a mut GraphicsCtx,
+    toasts : &'a Toasts,
+    logs : &'a Logs,
+    jobs : &'a JobManager,
+    settings : &'a Settings,
+    waker : &'a Waker,
+    formats : &'a Formats,
+    slots : &'a mut Vec<std::rc::Rc<dyn SwapSlot>>,
+    // from AppRunner::args, for init
+    args : Option<Box<dyn Any>>,
 }
 
 impl UnrenderCtx<'_> {
-    pub fn add_image(&mut self, img : image::RgbaImage) -> ImageId {
+    /// Another model next to `App::Model` with its own buffer, for state
+    /// which outlives screens or is written by workers that have nothing to
+    /// do with the screen, so they never replace or wait on each other. The
+    /// `Slot` goes in the LocalModel and the `BufBufWrite` to the workers,
+    /// usually in the app's Self. Values replaced by `set_next` are dropped
+    /// without an `App::swap`.
+    pub fn slot<T : Send + 'static>(&mut self, v : T) -> (Slot<T>, BufBufWrite<T>) {
+        let mut buf = BufBuf::new(v);
+        buf.set_waker(self.waker.clone());
+        let write = buf.new_write();
+
+        let (slot, swap) = slot::new(buf);
+        self.slots.push(swap);
+        (slot, write)
+    }
+
+    pub fn toasts(&self) -> &Toasts {
+        self.toasts
+    }
+
+    pub fn logs(&self) -> &Logs {
+        self.logs
+    }
+
+    /// What was given to `AppRunner::args`, in `App::init`. None after
+    /// it's taken, everywhere else, or if it isn't a T.
+    pub fn args<T : 'static>(&mut self) -> Option<T> {
+        let args = self.args.take()?.downcast().ok()?;
+        Some(*args)
+    }
+
+    /// What was saved when the window last closed, see
+    /// `App::save_settings`.
+    pub fn settings(&self) -> &Settings {
+        self.settings
+    }
+
+    pub fn jobs(&self) -> &JobManager {
+        self.jobs
+    }
+
+    /// The builtin decoders and the ones from `AppRunner::decoder`, clone
+    /// them for the workers.
+    pub fn formats(&self) -> &Formats {
+        self.formats
+    }
+
+    /// Uploads the image as a texture, fails if the GPU can't hold it.
+    pub fn add_image(&mut self, img : image::RgbaImage) -> Result<ImageId> {
         self.gfx.add_image(self.display, self.egui_glium, img)
     }
 
@@ -270,10 +722,50 @@ pub struct RenderCtx<'a> {
     egui_glium : &'a mut egui_glium::Painter,
     frame : &'a mut glium::Frame,
     background_input : Option<&'a Input>,
+    exit_code : &'a mut i32,
+    resized : bool,
+    focused : bool,
+    dropped_files : &'a [PathBuf],
+    toasts : &'a Toasts,
+    logs : &'a Logs,
+    stats : &'a Stats,
+    jobs : &'a JobManager,
     quit : &'a mut bool,
+    // from renew_model, BufBuf::renew after the frame
+    renew : &'a mut bool,
+    dt : Duration,
+    elapsed : Duration,
+    frame_count : u64,
+    display_profile : &'a DisplayProfile,
+    scale_factor : f32,
+    title : &'a mut String,
+    icon : &'a mut Option<glutin::window::Icon>,
+    prefs : &'a mut Preferences,
+    preview : &'a mut Option<Preview>,
+    preview_monitor : &'a mut Option<usize>,
+    // from Script::name_widgets, None without a script
+    widgets : Option<&'a mut HashMap<String, (f64, f64)>>,
 }
 
 impl RenderCtx<'_> {
+    pub fn toasts(&self) -> &Toasts {
+        self.toasts
+    }
+
+    /// For a log window, see `Logs::show`.
+    pub fn logs(&self) -> &Logs {
+        self.logs
+    }
+
+    /// For a performance overlay, see `Stats::show`.
+    pub fn stats(&self) -> &Stats {
+        self.stats
+    }
+
+    pub fn jobs(&self) -> &JobManager {
+        self.jobs
+    }
+
     pub fn clear_color(&mut self, color : Color) {
         self.frame.clear_color_srgb(color[0], color[1], color[2], color[3]);
     }
@@ -282,12 +774,81 @@ impl RenderCtx<'_> {
         self.background_input
     }
 
+    /// Files dropped on the window since the last frame.
+    pub fn dropped_files(&self) -> &[PathBuf] {
+        self.dropped_files
+    }
+
+    /// False while another window has keyboard focus.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Time since the last frame was rendered, 0 for the first one.
+    pub fn dt(&self) -> Duration {
+        self.dt
+    }
+
+    /// Time since the first frame.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Frames rendered before this one.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The color profile of the monitor the window is on, see
+    /// `AppRunner::display_profiles`. sRGB if there isn't one.
+    pub fn display_profile(&self) -> &DisplayProfile {
+        self.display_profile
+    }
+
+    /// True if the window size or scale factor changed since the last frame,
+    /// for example when the display was rotated.
+    pub fn resized(&self) -> bool {
+        self.resized
+    }
+
+    /// Physical pixels per point, 2 on most HiDPI displays. egui works in
+    /// points, while dimensions, background input and images are in
+    /// physical pixels, so textures drawn by egui should be this much
+    /// bigger than their size in the ui to stay sharp.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// A position in physical pixels, like `Input::pointer`, in egui's
+    /// points.
+    pub fn to_points(&self, (x, y) : (f32, f32)) -> egui::Pos2 {
+        let ppp = self.egui.pixels_per_point();
+        egui::pos2(x / ppp, y / ppp)
+    }
+
+    /// A position in egui's points in physical pixels.
+    pub fn to_pixels(&self, pos : egui::Pos2) -> (f32, f32) {
+        let ppp = self.egui.pixels_per_point();
+        (pos.x * ppp, pos.y * ppp)
+    }
+
+    /// Names a widget this frame, for a `Script`'s `click on name`. Does
+    /// nothing unless a script is running.
+    pub fn name_widget(&mut self, name : &str, rect : egui::Rect) {
+        let (x, y) = self.to_pixels(rect.center());
+        if let Some(widgets) = &mut self.widgets {
+            widgets.insert(name.to_string(), (x as f64, y as f64));
+        }
+    }
+
+    /// The size of the window in physical pixels.
     pub fn dimensions(&self) -> (f32, f32) {
         let (x, y) = self.frame.get_dimensions();
         (x as f32, y as f32)
     }
 
-    pub fn add_image(&mut self, img : image::RgbaImage) -> ImageId {
+    /// Uploads the image as a texture, fails if the GPU can't hold it.
+    pub fn add_image(&mut self, img : image::RgbaImage) -> Result<ImageId> {
         self.gfx.add_image(self.display, self.egui_glium, img)
     }
 
@@ -295,6 +856,138 @@ impl RenderCtx<'_> {
         self.gfx.delete_image(self.egui_glium, img_id)
     }
 
+    /// The size of the image's texture in pixels.
+    pub fn image_dimensions(&self, img_id : ImageId) -> Result<(u32, u32)> {
+        let texture = self.gfx.get_image_texture(img_id)?;
+        Ok((texture.get_width(), texture.get_height().unwrap_or(1)))
+    }
+
+    /// The image for egui's widgets. Ask every frame, it changes when the
+    /// context is lost.
+    pub fn egui_id(&self, img_id : ImageId) -> Result<egui::TextureId> {
+        self.gfx.get_egui_id(img_id)
+    }
+
+    /// Sets the window's title, it starts as `App::name`. Cheap to call
+    /// every frame, the window is only told when it changes.
+    pub fn set_title(&mut self, title : &str) {
+        if self.title.as_str() != title {
+            self.display.gl_window().window().set_title(title);
+            *self.title = title.to_string();
+        }
+    }
+
+    /// Sets the window's icon, None goes back to the platform's default.
+    /// Not every platform shows one, macOS uses the app bundle's.
+    pub fn set_window_icon(&mut self, icon : Option<&image::RgbaImage>) {
+        // an RgbaImage always has the pixels its size says, which is all
+        // from_rgba checks
+        let icon = icon.and_then(|img| {
+            let (width, height) = img.dimensions();
+            glutin::window::Icon::from_rgba(img.as_raw().clone(), width, height).ok()
+        });
+
+        self.display.gl_window().window().set_window_icon(icon.clone());
+        *self.icon = icon;
+    }
+
+    pub fn preferences(&self) -> &Preferences {
+        self.prefs
+    }
+
+    /// For the app's preferences window. The theme changes after the
+    /// frame, the rest after a restart, and all of them are saved with the
+    /// settings.
+    pub fn preferences_mut(&mut self) -> &mut Preferences {
+        self.prefs
+    }
+
+    /// Names of the connected monitors, the index into this is what
+    /// set_fullscreen takes.
+    pub fn monitors(&self) -> Vec<String> {
+        let gl_window = self.display.gl_window();
+
+        gl_window.window()
+            .available_monitors()
+            .enumerate()
+            .map(|(idx, monitor)| monitor.name().unwrap_or_else(|| format!("monitor {}", idx)))
+            .collect()
+    }
+
+    /// The size in pixels of the monitor the window is on.
+    pub fn monitor_size(&self) -> Option<(u32, u32)> {
+        let size = self.display.gl_window().window().current_monitor()?.size();
+        Some((size.width, size.height))
+    }
+
+    /// Borderless fullscreen on the given monitor, None goes back to windowed.
+    pub fn set_fullscreen(&mut self, monitor : Option<usize>) {
+        use glutin::window::Fullscreen;
+
+        let gl_window = self.display.gl_window();
+        let window = gl_window.window();
+
+        let fullscreen = monitor
+            .and_then(|idx| window.available_monitors().nth(idx))
+            .map(|monitor| Fullscreen::Borderless(Some(monitor)));
+
+        window.set_fullscreen(fullscreen);
+    }
+
+    /// The monitor the preview window is on, None if there isn't one. It's
+    /// closed when the user closes it or drawing into it fails.
+    pub fn preview(&self) -> Option<usize> {
+        *self.preview_monitor
+    }
+
+    /// Opens a second window, fullscreen on the given monitor and without
+    /// the ui, or closes it with None. It shows what `draw_image_preview`
+    /// draws each frame, black when nothing is, for dual screen setups with
+    /// the controls on one monitor and the photo on the other. It's opened
+    /// after the frame, keyboard input in it goes to the app like the
+    /// window's.
+    pub fn set_preview(&mut self, monitor : Option<usize>) {
+        *self.preview_monitor = monitor;
+    }
+
+    /// The size of the preview window in physical pixels.
+    pub fn preview_dimensions(&self) -> Option<(f32, f32)> {
+        let (width, height) = self.preview.as_ref()?.dimensions();
+        Some((width as f32, height as f32))
+    }
+
+    /// Like draw_image_screen, into the preview window instead. Does nothing
+    /// without one.
+    pub fn draw_image_preview<U>(
+        &mut self,
+        img_id : ImageId,
+        trans : &Mat4,
+        program : &glium::Program,
+        uniforms : U
+    ) -> Result<()>
+    where
+        U : glium::uniforms::Uniforms
+    {
+        let preview = match self.preview.as_mut() {
+            Some(preview) => preview,
+            None => return Ok(()),
+        };
+
+        let target = preview.target(self.display)?;
+        let (width, height) = (target.get_width(), target.get_height().unwrap_or(1));
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(self.display, target)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 1.0);
+        self.gfx.draw_image(
+            &mut framebuffer,
+            (width as f32, height as f32),
+            None,
+            img_id,
+            trans,
+            program,
+            uniforms,
+        )
+    }
+
     pub fn draw_image_screen<U>(
         &mut self,
         img_id : ImageId,
@@ -305,26 +998,117 @@ impl RenderCtx<'_> {
     where
         U : glium::uniforms::Uniforms
     {
-        let texture = self.gfx.get_image_texture(img_id).unwrap();
+        let dims = self.dimensions();
+        self.gfx.draw_image(&mut *self.frame, dims, None, img_id, trans, program, uniforms)
+    }
 
-        let tex_width = texture.get_width() as f32;
-        let tex_height = texture.get_height().unwrap() as f32;
+    /// Like draw_image_screen, into a part of the window. trans is relative
+    /// to the viewport, at identity the image is 1:1 in its middle, and
+    /// nothing is drawn outside it.
+    pub fn draw_image_viewport<U>(
+        &mut self,
+        img_id : ImageId,
+        trans : &Mat4,
+        program : &glium::Program,
+        uniforms : U,
+        viewport : Viewport,
+    ) -> Result<()>
+    where
+        U : glium::uniforms::Uniforms
+    {
+        let (_, win_height) = self.dimensions();
+        // gl's viewports are from the bottom left
+        let rect = glium::Rect{
+            left : viewport.x.max(0.0) as u32,
+            bottom : (win_height - viewport.y - viewport.height).max(0.0) as u32,
+            width : viewport.width.max(0.0) as u32,
+            height : viewport.height.max(0.0) as u32,
+        };
 
+        self.gfx.draw_image(&mut *self.frame, viewport.dimensions(), Some(rect), img_id, trans, program, uniforms)
+    }
 
+    /// Renders what draw_image_screen would draw, without the ui, at another
+    /// resolution. trans is scaled so the window's view covers `size`, and
+    /// the result is rendered `samples` times larger in each direction then
+    /// scaled down to smooth the edges.
+    pub fn render_view<U>(
+        &mut self,
+        img_id : ImageId,
+        trans : &Mat4,
+        program : &glium::Program,
+        uniforms : U,
+        size : (u32, u32),
+        samples : u32,
+        clear : Color,
+    ) -> Result<image::RgbaImage>
+    where
+        U : glium::uniforms::Uniforms
+    {
+        let samples = samples.max(1);
+        let (width, height) = (size.0 * samples, size.1 * samples);
         let (win_width, win_height) = self.dimensions();
 
-        // modify the translation matrix for gl_coords
-        let trans = Mat4::from_scale(Vec3::new(2. / win_width, 2. / win_height, 1.0))
-            .mul_mat4(&trans)
-            .mul_mat4(&Mat4::from_scale(Vec3::new(win_width / 2., win_height / 2., 1.0)));
+        let scale = (size.0 as f32 / win_width).max(size.1 as f32 / win_height) * samples as f32;
+        let trans = Mat4::from_scale(Vec3::new(scale, scale, 1.0)).mul_mat4(trans);
 
-        let window_scale = Mat4::from_scale(
-            Vec3::new(tex_width / win_width, tex_height / win_height, 1.0),
-        );
+        let target = glium::texture::SrgbTexture2d::empty_with_format(
+            self.display,
+            glium::texture::SrgbFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            width,
+            height,
+        )?;
+
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(self.display, &target)?;
+        framebuffer.clear_color_srgb(clear[0], clear[1], clear[2], clear[3]);
+        self.gfx.draw_image(
+            &mut framebuffer,
+            (width as f32, height as f32),
+            None,
+            img_id,
+            &trans,
+            program,
+            uniforms,
+        )?;
+
+        let img = read_back(&target, width, height)?;
+
+        if samples == 1 {
+            Ok(img)
+        } else {
+            Ok(image::imageops::resize(&img, size.0, size.1, image::imageops::FilterType::Triangle))
+        }
+    }
+
+    /// Draws an image with a program at the image's full resolution into an
+    /// offscreen texture and reads the result back, for exporting edits.
+    /// Uses the same uniforms as `draw_image_screen`, with an identity
+    /// `matrix`. Blocks until the GPU is done, so call it sparingly.
+    pub fn render_image<U>(
+        &mut self,
+        img_id : ImageId,
+        program : &glium::Program,
+        uniforms : U
+    ) -> Result<image::RgbaImage>
+    where
+        U : glium::uniforms::Uniforms
+    {
+        let texture = self.gfx.get_image_texture(img_id)?;
+        let width = texture.get_width();
+        let height = texture.get_height().unwrap_or(1);
+
+        let target = glium::texture::SrgbTexture2d::empty_with_format(
+            self.display,
+            glium::texture::SrgbFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            width,
+            height,
+        )?;
 
         let uniforms = UniformsCons {
             name : "matrix",
-            value : trans.mul_mat4(&window_scale).to_cols_array_2d(),
+            value : Mat4::IDENTITY.to_cols_array_2d(),
             rest : uniforms,
         };
 
@@ -334,63 +1118,510 @@ impl RenderCtx<'_> {
             rest : uniforms,
         };
 
-        Ok(self.frame.draw(
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(self.display, &target)?;
+        framebuffer.draw(
             &self.gfx.vertex_buffer,
             &self.gfx.index_buffer,
             &program,
             &uniforms,
             &Default::default(),
-        )?)
+        )?;
+
+        read_back(&target, width, height)
     }
 
     pub fn quit(&mut self) {
         *self.quit = true;
     }
+
+    /// After this frame the model gets a new generation, see
+    /// `BufBuf::renew`, and the jobs spawned for the old one are cancelled.
+    /// For changes made in render, like going back to an earlier screen,
+    /// that the workers of the one before shouldn't patch.
+    pub fn renew_model(&mut self) {
+        *self.renew = true;
+    }
+
+    /// Like quit, and the process exits with code once the app has shut
+    /// down, for headless runs driven by scripts.
+    pub fn quit_with(&mut self, code : i32) {
+        *self.quit = true;
+        *self.exit_code = code;
+    }
 }
 
 
 
+// the texture's pixels, the right way up
+fn read_back(target : &glium::texture::SrgbTexture2d, width : u32, height : u32) -> Result<image::RgbaImage> {
+    let raw : glium::texture::RawImage2d<u8> = target.read();
+    let img = image::RgbaImage::from_raw(width, height, raw.data.into_owned())
+        .ok_or(Error::ReadBack{width, height})?;
+
+    // gl's first row is the bottom one
+    Ok(image::imageops::flip_vertical(&img))
+}
+
 pub fn run_app<A : App + 'static >() {
-    let event_loop = glutin::event_loop::EventLoop::with_user_event();
-    let display = create_display(A::name(), &event_loop);
+    AppRunner::<A>::new().run()
+}
 
-    let mut egui_gl = egui_glium::EguiGlium::new(&display);
+/// Runs the app without showing a window, see `AppRunner::headless`. The
+/// same as `AppRunner::<A>::new().headless().run()`.
+pub fn run_headless<A : App + 'static >() {
+    AppRunner::<A>::new().headless().run()
+}
 
-    let mut gfx = GraphicsCtx::new(&display);
-    let mut background_input : Option<Input> = None;
+/// Options for running an App, run_app is the same as
+/// `AppRunner::<A>::new().run()`.
+pub struct AppRunner<A : App> {
+    middleware : Vec<Box<dyn Middleware<A>>>,
+    runtime : RuntimeOptions,
+    window : WindowOptions,
+    shutdown_timeout : Duration,
+    frame_period : Duration,
+    display_profiles : Option<PathBuf>,
+    stall_threshold : Duration,
+    settings_file : Option<PathBuf>,
+    fonts : Option<egui::FontDefinitions>,
+    script : Option<Script>,
+    formats : Formats,
+    args : Option<Box<dyn Any>>,
+}
+
+impl<A : App + 'static> AppRunner<A> {
+    pub fn new() -> Self {
+        AppRunner {
+            middleware : Vec::new(),
+            runtime : Default::default(),
+            shutdown_timeout : Duration::from_secs(5),
+            frame_period : Duration::from_secs_f64(1. / 60.),
+            display_profiles : None,
+            stall_threshold : Duration::from_secs(2),
+            settings_file : Settings::default_path(A::name()),
+            fonts : None,
+            script : None,
+            formats : Formats::builtin(),
+            args : None,
+            window : WindowOptions{
+                app_id : A::name().to_string(),
+                drag_and_drop : true,
+                headless : false,
+            },
+        }
+    }
+
+    /// The id desktops use to find the window's .desktop file and icon,
+    /// set as the app_id on Wayland and WM_CLASS on X11. Defaults to
+    /// `App::name`.
+    pub fn app_id(mut self, app_id : impl Into<String>) -> Self {
+        self.window.app_id = app_id.into();
+        self
+    }
+
+    /// Files dropped on the window show up in `RenderCtx::dropped_files`. On
+    /// by default, only Windows can turn it off, which it needs for OLE
+    /// clipboard access from other libraries on the render thread.
+    pub fn drag_and_drop(mut self, enabled : bool) -> Self {
+        self.window.drag_and_drop = enabled;
+        self
+    }
+
+    /// Threads in the runtime `App::update` runs on, 4 by default. Apps
+    /// doing heavy batch work may want one per core.
+    pub fn worker_threads(mut self, n : usize) -> Self {
+        self.runtime.worker_threads = n.max(1);
+        self.runtime.current_thread = false;
+        self
+    }
+
+    /// The most threads `tokio::task::spawn_blocking` and tokio::fs will
+    /// use, tokio's default is 512.
+    pub fn max_blocking_threads(mut self, n : usize) -> Self {
+        self.runtime.max_blocking_threads = Some(n.max(1));
+        self
+    }
+
+    /// Runs `App::update` on a single thread instead, for small apps which
+    /// don't need a thread pool. The blocking pool is still there.
+    pub fn current_thread(mut self) -> Self {
+        self.runtime.current_thread = true;
+        self
+    }
+
+    /// Runs `App::update` on a runtime the caller already has, instead of
+    /// one made by the runner. The other runtime options are ignored.
+    pub fn runtime(mut self, handle : tokio::runtime::Handle) -> Self {
+        self.runtime.handle = Some(handle);
+        self
+    }
 
+    /// How often the window is redrawn when nothing else asks for it, 60
+    /// frames per second by default. Input and animating egui widgets
+    /// redraw sooner.
+    pub fn target_frame_rate(mut self, fps : f64) -> Self {
+        self.frame_period = Duration::from_secs_f64(1. / fps.max(1.));
+        self
+    }
 
-    let mut msgs = Vec::new();
+    /// A folder of ICC profiles named after monitors, like `DELL U2720Q.icc`,
+    /// see `RenderCtx::monitors` for the names. The one for the monitor the
+    /// window is on is loaded whenever it moves to another one, and is
+    /// `RenderCtx::display_profile`.
+    pub fn display_profiles(mut self, dir : impl Into<PathBuf>) -> Self {
+        self.display_profiles = Some(dir.into());
+        self
+    }
+
+    /// How long a frame can take before the render thread is considered
+    /// stuck in the driver, 2 seconds by default. Stalls are logged, and if
+    /// the context was lost the window is made again, see
+    /// `App::context_lost`.
+    pub fn stall_threshold(mut self, threshold : Duration) -> Self {
+        self.stall_threshold = threshold;
+        self
+    }
+
+    /// How long to wait for `App::shutdown` before exiting anyway, 5 seconds
+    /// by default.
+    pub fn shutdown_timeout(mut self, timeout : Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Where the window's geometry and `App::save_settings` are kept,
+    /// `Settings::default_path` by default. None keeps nothing.
+    pub fn settings_file(mut self, path : Option<PathBuf>) -> Self {
+        self.settings_file = path;
+        self
+    }
+
+    /// The fonts egui uses instead of its own, their sizes are scaled by
+    /// `Preferences::text_scale`.
+    pub fn fonts(mut self, fonts : egui::FontDefinitions) -> Self {
+        self.fonts = Some(fonts);
+        self
+    }
+
+    /// Plays the script's input into the app and compares its screenshots,
+    /// then quits, with an exit code of 1 if any were different. Settings
+    /// aren't loaded or saved, so runs start the same.
+    pub fn script(mut self, script : Script) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    /// What the app was started with, like its parsed command line, for
+    /// `InitCtx::args` to take in `App::init`.
+    pub fn args<T : 'static>(mut self, args : T) -> Self {
+        self.args = Some(Box::new(args));
+        self
+    }
+
+    /// Runs the app in a window that's never shown, for tests of its logic
+    /// and batch jobs. init, update, swap and render run as usual, frames
+    /// are drawn but not presented, and offscreen renders like
+    /// `RenderCtx::render_image` work the same. The context can be a
+    /// software renderer, and settings aren't loaded or saved. The app
+    /// ends the run with `RenderCtx::quit`. It still needs a display
+    /// server, `xvfb-run` on machines without one.
+    pub fn headless(mut self) -> Self {
+        self.window.headless = true;
+        self
+    }
+
+    /// Opens another format, or replaces a builtin decoder for the
+    /// extensions and signature it has. See `InitCtx::formats`.
+    pub fn decoder<D : Decoder + 'static>(mut self, decoder : D) -> Self {
+        self.formats.register(decoder);
+        self
+    }
+
+    /// Adds a middleware after the ones already added.
+    pub fn middleware<M : Middleware<A> + 'static>(mut self, middleware : M) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub fn run(self) {
+        run(self)
+    }
+}
+
+// the window events egui doesn't want go to the background input
+fn background_event(
+    background_input : &mut Option<Input>,
+    egui_gl : &egui_glium::EguiGlium,
+    scale_factor : f64,
+    event : glutin::event::WindowEvent<'_>)
+{
+    if egui_gl.ctx().wants_pointer_input() {
+        *background_input = None;
+        return
+    }
+
+    background_input
+        .get_or_insert_with(|| Input::new(scale_factor as f32))
+        .update(event);
+}
+
+fn run<A : App + 'static>(runner : AppRunner<A>) {
+    let logs = Logs::default();
+    logs::install(&logs);
+
+    let event_loop = glutin::event_loop::EventLoop::<LoopEvent<A::Msg>>::with_user_event();
+    let waker = Waker::new(event_loop.create_proxy());
+    let mut script = runner.script;
+    let formats = runner.formats;
+    // scripted and headless runs start the same every time, and nobody
+    // is there to answer a dialog
+    let unattended = script.is_some() || runner.window.headless;
+    let settings_file = if unattended { None } else { runner.settings_file };
+    let mut settings = settings_file.as_deref().map(Settings::load).unwrap_or_default();
+    let mut display = match create_display(A::name(), &runner.window, settings.window.as_ref(), &event_loop) {
+        Ok(display) => display,
+        Err(err) => {
+            tracing::error!("{} couldn't open a window: {}", A::name(), err);
+            std::process::exit(1);
+        },
+    };
+    let window_options = runner.window;
+    let watchdog = Watchdog::new(
+        runner.stall_threshold,
+        format!("{} ({})", display.get_opengl_renderer_string(), display.get_opengl_version_string()),
+    );
+
+    let mut egui_gl = egui_glium::EguiGlium::new(&display);
+    let mut style = Style::new(runner.fonts);
+
+    let mut runtime = runner.runtime;
+    if let Some(n) = settings.prefs.worker_threads {
+        runtime.worker_threads = n.max(1);
+        runtime.current_thread = false;
+    }
+    if let Some(n) = settings.prefs.max_blocking_threads {
+        runtime.max_blocking_threads = Some(n.max(1));
+    }
+
+    let mut gfx = match GraphicsCtx::new(&display) {
+        Ok(gfx) => gfx,
+        Err(err) => fatal::show(event_loop, display, egui_gl, A::name(), err.to_string(), unattended),
+    };
+    let mut background_input : Option<Input> = None;
+    let mut resized = false;
+    let mut focused = true;
+    // the last frame couldn't be shown
+    let mut finish_failed = false;
+    // a lost context to pretend, from a script or ctrl+shift+f12 in debug
+    // builds
+    let mut lose_context = false;
+    let mut modifiers = glutin::event::ModifiersState::default();
+    // from RenderCtx::quit_with
+    let mut exit_code = 0;
+    // minimized or suspended, nothing is drawn and jobs wait
+    let mut paused = false;
+    let mut dropped_files = Vec::new();
+    let toasts = Toasts::default();
+    let jobs = JobManager::new(waker.clone());
+
+    let mut cmds = Vec::new();
+    // App::Model's is bufbuf, the rest are from UnrenderCtx::slot
+    let mut slots = Vec::new();
 
     let mut init_ctx = InitCtx{
         gfx : &mut gfx,
         display : &display,
         egui_glium: egui_gl.ctx_and_painter_mut().1,
+        toasts : &toasts,
+        logs : &logs,
+        jobs : &jobs,
+        settings : &settings,
+        waker : &waker,
+        formats : &formats,
+        slots : &mut slots,
+        args : runner.args,
     };
 
-    let (app, mut local_model, model) = A::init(&mut init_ctx, &mut msgs);
+    let (app, mut local_model, model, cmd) = match A::init(&mut init_ctx) {
+        Ok(init) => init,
+        Err(err) => {
+            let message = format!("{:?}", err);
+            fatal::show(event_loop, display, egui_gl, A::name(), message, unattended)
+        },
+    };
+    cmds.push(cmd);
     let app : &'static A = Box::leak(Box::new(app));
+    style.update(egui_gl.ctx(), &settings.prefs, app);
+    jobs.on_panic(move |panic| app.handle_error(panic.into()));
     let app_ref : &'static &'static A = Box::leak(Box::new(app));
     let bufbuf = Box::leak(Box::new(BufBuf::new(model)));
-    let task_channel = TaskChannel::<A>::new(app, bufbuf.new_write());
-
-    event_loop.run(move |event, _, control_flow| {
-
-        let next = std::time::Instant::now() +
-            std::time::Duration::from_nanos(16_666);
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next);
+    bufbuf.set_waker(waker.clone());
+    let shutdown_timeout = runner.shutdown_timeout;
+    let frame_period = runner.frame_period;
+    let start = Instant::now();
+    let mut stats = Stats::default();
+    let mut last_frame : Option<Instant> = None;
+    let mut frame_count = 0;
+    let display_profiles = runner.display_profiles;
+    // the monitor the window was on last frame and its profile
+    let mut monitor : Option<String> = None;
+    let mut display_profile = DisplayProfile::default();
+    let mut scale_factor = display.gl_window().window().scale_factor();
+    // kept to give a recreated window the same ones
+    let mut title = A::name().to_string();
+    let mut icon = None;
+    // from RenderCtx::set_preview, opened or closed after the frame
+    let mut preview : Option<Preview> = None;
+    let mut preview_monitor : Option<usize> = None;
+    let mut task_channel = TaskChannel::<A>::new(
+        app,
+        bufbuf.new_write(),
+        runner.middleware,
+        runtime,
+        event_loop.create_proxy(),
+    );
+
+    event_loop.run(move |event, target, control_flow| {
 
         use glutin::event::Event::*;
         use glutin::event::StartCause;
 
+        // a minimized window is resized to nothing on windows and x11,
+        // Suspended comes from mobile platforms
+        let pause = match &event {
+            Suspended => Some(true),
+            Resumed => Some(false),
+            WindowEvent{ event : glutin::event::WindowEvent::Resized(size), .. } => {
+                Some(size.width == 0 || size.height == 0)
+            },
+            _ => None,
+        };
+
+        if let Some(pause) = pause.filter(|pause| *pause != paused) {
+            paused = pause;
+            jobs.set_paused(pause);
+            if pause {
+                // they start again with the next frame's subscriptions
+                task_channel.subscribe(Vec::new());
+            } else {
+                // dt would be the whole time away
+                last_frame = None;
+                display.gl_window().window().request_redraw();
+            }
+        }
+
+        if paused {
+            // only events wake the loop, and frames aren't drawn
+            *control_flow = glutin::event_loop::ControlFlow::Wait;
+        } else {
+            let next = last_frame.unwrap_or(start) + frame_period;
+            *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next);
+        }
+
         match (cfg!(windows), event) {
             (true, RedrawEventsCleared) |
             (false, | RedrawRequested(_)) |
-            (_, Resumed) => {
+            (_, Resumed) if !paused => {
+                // checked every frame, it's only a query when the context
+                // can be lost. this catches resets after a suspend or a
+                // driver update as well as ones after a stall
+                lose_context |= script.as_mut().map_or(false, |script| script.take_lose_context());
+                let lost = display.is_context_loss_possible() && display.is_context_lost();
+                if lost || std::mem::take(&mut lose_context) {
+                    match recreate_display(&display, &title, &window_options, target) {
+                        Some(new_display) => {
+                            // the old textures and buffers went with the
+                            // old context, egui keeps its state but needs a
+                            // painter for the new one
+                            display = new_display;
+                            display.gl_window().window().set_window_icon(icon.clone());
+                            // it shared the lost context, it's opened again
+                            // after the frame
+                            preview = None;
+                            scale_factor = display.gl_window().window().scale_factor();
+                            let painter = egui_gl.ctx_and_painter_mut().1;
+                            *painter = egui_glium::Painter::new(&display);
+                            if let Err(err) = gfx.restore(&display, painter) {
+                                app_ref.handle_error(err.into());
+                            }
+
+                            let mut ctx = InitCtx{
+                                gfx : &mut gfx,
+                                display : &display,
+                                egui_glium: painter,
+                                toasts : &toasts,
+                                logs : &logs,
+                                jobs : &jobs,
+                                settings : &settings,
+                                waker : &waker,
+                                formats : &formats,
+                                slots : &mut slots,
+                                args : None,
+                            };
+                            cmds.push(app_ref.context_lost(&mut ctx, &mut local_model, bufbuf.get_mut()));
+                            toasts.push(Level::Warn, "the graphics driver was reset");
+                        },
+                        None => {
+                            *control_flow = glutin::event_loop::ControlFlow::Exit;
+                            return
+                        },
+                    }
+                }
+
+                if let Some(script) = &mut script {
+                    let window = display.gl_window();
+                    match script.frame(window.window(), jobs.jobs().is_empty()) {
+                        Some(events) => {
+                            for event in events {
+                                cmds.extend(app_ref.on_event(&mut local_model, &event).map(Cmd::msg));
+                                egui_gl.on_event(&event);
+                                background_event(&mut background_input, &egui_gl, scale_factor, event);
+                            }
+                        },
+                        None => {
+                            *control_flow = glutin::event_loop::ControlFlow::Exit;
+                            return
+                        },
+                    }
+                }
+
+                watchdog.enter("render");
+                let frame_span = tracing::trace_span!("frame", n = frame_count);
+                let _in_frame = frame_span.enter();
                 egui_gl.begin_frame(&display);
+                waker.frame_started();
+
+                let now = Instant::now();
+                let dt = last_frame.map_or(Duration::ZERO, |last| now - last);
+                last_frame = Some(now);
+
+                if frame_count > 0 {
+                    stats.push_frame(dt);
+                }
+                let (textures, texture_bytes) = gfx.texture_usage();
+                stats.textures = textures;
+                stats.texture_bytes = texture_bytes;
+                stats.queued = task_channel.queued();
+                stats.jobs = jobs.jobs().len();
+                stats.swaps = bufbuf.swap_count();
+                stats.patches = bufbuf.patch_count();
+
+                if let Some(dir) = &display_profiles {
+                    let current = display.gl_window().window()
+                        .current_monitor()
+                        .and_then(|m| m.name());
+
+                    if current != monitor {
+                        display_profile = current.as_deref()
+                            .and_then(|name| DisplayProfile::load(dir, name))
+                            .unwrap_or_default();
+                        monitor = current;
+                    }
+                }
 
                 let mut frame = display.draw();
                 let mut quit = false;
+                let mut renew = false;
                 let (egui_ctx, egui_painter) = egui_gl.ctx_and_painter_mut();
 
                 let mut render_ctx = RenderCtx {
@@ -400,15 +1631,46 @@ pub fn run_app<A : App + 'static >() {
                     display : &display,
                     frame : &mut frame,
                     quit : &mut quit,
+                    renew : &mut renew,
+                    exit_code : &mut exit_code,
                     background_input : background_input.as_ref(),
+                    resized,
+                    focused,
+                    dropped_files : &dropped_files,
+                    toasts : &toasts,
+                    logs : &logs,
+                    stats : &stats,
+                    jobs : &jobs,
+                    dt,
+                    elapsed : now - start,
+                    frame_count,
+                    display_profile : &display_profile,
+                    scale_factor : scale_factor as f32,
+                    title : &mut title,
+                    icon : &mut icon,
+                    prefs : &mut settings.prefs,
+                    preview : &mut preview,
+                    preview_monitor : &mut preview_monitor,
+                    widgets : script.as_mut().map(Script::name_widgets),
                 };
 
 
-                app_ref.render(&mut render_ctx, &mut local_model, &mut bufbuf.lock(), &mut msgs);
+                cmds.push(app_ref.render(&mut render_ctx, &mut local_model, bufbuf.get_mut()));
+                if renew {
+                    bufbuf.renew();
+                    jobs.cancel_replaced(bufbuf.generation());
+                }
+                task_channel.subscribe(app_ref.subscriptions(&local_model));
+                toasts.show(egui_ctx);
+
+                style.update(egui_ctx, &settings.prefs, app);
 
                 if let Some(input) = background_input.as_mut() {
                     input.frame_reset();
                 }
+                resized = false;
+                dropped_files.clear();
+                frame_count += 1;
 
                 let (needs_repaint, shapes) = egui_gl.end_frame(&display);
 
@@ -419,47 +1681,185 @@ pub fn run_app<A : App + 'static >() {
                     *control_flow = glutin::event_loop::ControlFlow::Poll;
                 }
 
+                watchdog.enter("paint");
                 egui_gl.paint(&display, &mut frame, shapes);
-                frame.finish().unwrap();
+                watchdog.enter("finish");
+                let finished = frame.finish();
+
+                let stalled = watchdog.leave();
+                if let Some(stage) = stalled {
+                    tracing::warn!("render thread got through {} after getting stuck", stage);
+                }
+                tracing::trace!(ms = now.elapsed().as_secs_f64() * 1000.0, "rendered");
+                // once for every run of failures, not every frame of one
+                let failed = finished.is_err();
+                if let Err(err) = finished {
+                    if !finish_failed {
+                        app_ref.handle_error(Error::from(err).into());
+                    }
+                }
+
+                if let Some(script) = &mut script {
+                    if let Some(name) = script.take_capture() {
+                        script.compare(&name, script::capture(&display));
+                    }
+                }
+
+                if let Some(shown) = &mut preview {
+                    if let Err(err) = shown.present(&display) {
+                        app_ref.handle_error(err.into());
+                        preview = None;
+                        preview_monitor = None;
+                    }
+                }
+
+                // opened, moved or closed by the app this frame. headless
+                // runs have nowhere to show it
+                if preview.as_ref().map(|p| p.monitor) != preview_monitor {
+                    preview = preview_monitor
+                        .filter(|_| !window_options.headless)
+                        .and_then(|monitor| {
+                            let title = format!("{} preview", title);
+                            Preview::new(&display, &title, monitor, &window_options, target)
+                        });
+                    preview_monitor = preview.as_ref().map(|p| p.monitor);
+                }
+
+                // a lost context is made again at the start of the next one
+                if stalled.is_some() || failed {
+                    display.gl_window().window().request_redraw();
+                }
+                finish_failed = failed;
             },
+            (_, WindowEvent{ window_id, event }) if preview.as_ref().map_or(false, |p| p.id() == window_id) => {
+                use glutin::event::WindowEvent::*;
+                match &event {
+                    // only the preview is closed, see RenderCtx::preview
+                    CloseRequested | Destroyed => {
+                        preview = None;
+                        preview_monitor = None;
+                    },
+                    // clicking on it focuses it, the keyboard should still
+                    // go through the photos
+                    KeyboardInput{..} | ReceivedCharacter(_) | ModifiersChanged(_) => {
+                        cmds.extend(app_ref.on_event(&mut local_model, &event).map(Cmd::msg));
+                        egui_gl.on_event(&event);
+                    },
+                    _ => {},
+                }
+
+                display.gl_window().window().request_redraw();
+            },
+            // left over from a window lost with its context
+            (_, WindowEvent{ window_id, .. }) if window_id != display.gl_window().window().id() => {},
             (_, WindowEvent{ event, .. }) => {
-                if egui_gl.is_quit_event(&event) {
+                cmds.extend(app_ref.on_event(&mut local_model, &event).map(Cmd::msg));
+
+                // there's nothing left to keep open once it's Destroyed
+                let destroyed = matches!(event, glutin::event::WindowEvent::Destroyed);
+                if egui_gl.is_quit_event(&event) &&
+                    (destroyed || app_ref.close_requested(&mut local_model))
+                {
                     *control_flow = glium::glutin::event_loop::ControlFlow::Exit;
                     return
                 }
 
-                egui_gl.on_event(&event);
-
-                if !egui_gl.ctx().wants_pointer_input() {
-                    if background_input.is_none() {
-                        background_input = Some(Default::default());
-                    }
-
-                    background_input.as_mut().unwrap().update(event);
-                } else {
-                    background_input = None;
+                use glutin::event::WindowEvent::*;
+                match &event {
+                    Resized(_) => {
+                        resized = true;
+                        keep_on_screen(display.gl_window().window());
+                    },
+                    ScaleFactorChanged{scale_factor : factor, ..} => {
+                        // moved to a monitor with another scale, the window
+                        // keeps its size in points
+                        scale_factor = *factor;
+                        resized = true;
+                        keep_on_screen(display.gl_window().window());
+                    },
+                    Focused(f) => focused = *f,
+                    DroppedFile(path) => dropped_files.push(path.clone()),
+                    KeyboardInput{
+                        input : glutin::event::KeyboardInput{
+                            state : glutin::event::ElementState::Pressed,
+                            virtual_keycode : Some(glutin::event::VirtualKeyCode::F12),
+                            ..
+                        },
+                        ..
+                    } if cfg!(debug_assertions) && modifiers.ctrl() && modifiers.shift() => {
+                        lose_context = true;
+                    },
+                    ModifiersChanged(state) => modifiers = *state,
+                    _ => {},
                 }
 
+                egui_gl.on_event(&event);
+                background_event(&mut background_input, &egui_gl, scale_factor, event);
+
                 display.gl_window().window().request_redraw();
             },
             (_, NewEvents(StartCause::ResumeTimeReached{..})) => {
                 display.gl_window().window().request_redraw();
             },
+            (_, UserEvent(LoopEvent::Msg(msg))) => cmds.push(Cmd::msg(msg)),
+            (_, UserEvent(LoopEvent::Wake)) => {
+                display.gl_window().window().request_redraw();
+            },
+            // every way of exiting ends up here, once
+            (_, LoopDestroyed) => {
+                if let Some(path) = &settings_file {
+                    settings.capture(display.gl_window().window());
+                    app_ref.save_settings(&local_model, &mut settings);
+                    if let Err(err) = settings.save(path) {
+                        tracing::error!("could not save settings to {}: {}", path.display(), err);
+                    }
+                }
+
+                if !task_channel.shutdown(shutdown_timeout) {
+                    tracing::warn!("shutdown timed out after {:?}", shutdown_timeout);
+                }
+
+                if let Some(script) = &script {
+                    if !script.report() {
+                        std::process::exit(1);
+                    }
+                }
+                if exit_code != 0 {
+                    std::process::exit(exit_code);
+                }
+                return
+            },
             _ => {},
         }
 
-        for msg in msgs.drain(..) {
-            task_channel.send(msg);
+        for cmd in cmds.drain(..) {
+            task_channel.run(cmd);
         }
 
-        bufbuf.swap(|old, new| {
+        let swaps = bufbuf.swap_count();
+        let panics = bufbuf.swap(|old, new| {
             let mut swap_ctx = SwapCtx{
                 gfx : &mut gfx,
                 display : &display,
                 egui_glium: egui_gl.ctx_and_painter_mut().1,
+                toasts : &toasts,
+                logs : &logs,
+                jobs : &jobs,
+                settings : &settings,
+                waker : &waker,
+                formats : &formats,
+                slots : &mut slots,
+                args : None,
             };
             app.swap(&mut swap_ctx, old, new)
         });
+        if bufbuf.swap_count() != swaps {
+            jobs.cancel_replaced(bufbuf.generation());
+        }
+        let slot_panics = slots.iter().flat_map(|slot| slot.swap()).collect::<Vec<_>>();
+        for panic in panics.into_iter().chain(slot_panics) {
+            app.handle_error(panic.into());
+        }
     });
 }
 
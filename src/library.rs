@@ -0,0 +1,419 @@
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+    HashMap,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::SystemTime;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use photos1::Effects;
+
+use crate::{
+    GalleryLayout,
+    GroupBy,
+};
+use crate::xmp::Sidecar;
+
+/// $XDG_DATA_HOME/photos1 or ~/.local/share/photos1
+pub fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".local").join("share"))
+        })?;
+
+    Some(base.join("photos1"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edit {
+    pub time : SystemTime,
+    pub effects : Effects,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditHistory {
+    pub entries : Vec<Edit>,
+}
+
+impl EditHistory {
+    pub fn new(effects : &Effects) -> Self {
+        EditHistory {
+            entries : vec![Edit{
+                time : SystemTime::now(),
+                effects : effects.clone(),
+            }],
+        }
+    }
+
+    // adds an entry if the effects changed since the last one, returns
+    // whether it did
+    pub fn record(&mut self, effects : &Effects) -> bool {
+        match self.entries.last() {
+            Some(last) if last.effects == *effects => false,
+            _ => {
+                self.entries.push(Edit{
+                    time : SystemTime::now(),
+                    effects : effects.clone(),
+                });
+                true
+            },
+        }
+    }
+
+    pub fn current(&self) -> Option<&Effects> {
+        self.entries.last().map(|e| &e.effects)
+    }
+}
+
+/// Everything the app knows about a photo that isn't in the file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhotoRecord {
+    #[serde(default)]
+    pub history : Option<EditHistory>,
+    /// Where the photo was exported to
+    #[serde(default)]
+    pub exports : Vec<PathBuf>,
+    /// 1 to 5, -1 for rejected, from a sidecar or culling
+    #[serde(default)]
+    pub rating : Option<i8>,
+    /// Kept while culling, see `Mark`
+    #[serde(default)]
+    pub picked : bool,
+    /// A color label, from a sidecar
+    #[serde(default)]
+    pub label : Option<String>,
+    #[serde(default)]
+    pub keywords : Vec<String>,
+    /// A caption, from a sidecar or an export
+    #[serde(default)]
+    pub description : Option<String>,
+    /// When the photo was taken, for photos without it in the file
+    #[serde(default)]
+    pub taken : Option<SystemTime>,
+    /// When the imported sidecar was last modified
+    #[serde(default)]
+    pub sidecar : Option<SystemTime>,
+}
+
+impl PhotoRecord {
+    pub fn edited(&self) -> bool {
+        self.history
+            .as_ref()
+            .and_then(|h| h.current())
+            .map_or(false, |effects| *effects != Effects::default())
+    }
+
+    /// Takes the rating, label, keywords, and caption from a sidecar, and
+    /// its adjustments if the photo hasn't been edited here.
+    pub fn import(&mut self, sidecar : Sidecar, modified : SystemTime) {
+        self.rating = sidecar.rating;
+        self.label = sidecar.label;
+        self.keywords = sidecar.keywords;
+        self.description = sidecar.description.or_else(|| self.description.take());
+        self.sidecar = Some(modified);
+
+        if let (Some(effects), None) = (sidecar.effects, &self.history) {
+            self.history = Some(EditHistory::new(&effects));
+        }
+    }
+}
+
+/// A decision made while culling, kept in a `PhotoRecord`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    Pick,
+    Reject,
+    /// Neither picked nor rejected, the stars are kept
+    Unflag,
+    /// 0 takes the stars away
+    Rate(u8),
+}
+
+impl Mark {
+    /// Onto a photo's flags, the library's or a thumbnail's copy of them.
+    /// Picking or rating a rejected photo takes it out of the rejects.
+    pub fn apply(&self, picked : &mut bool, rating : &mut Option<i8>) {
+        let rejected = *rating == Some(-1);
+        match *self {
+            Mark::Pick => {
+                *picked = true;
+                if rejected {
+                    *rating = None;
+                }
+            },
+            Mark::Reject => {
+                *picked = false;
+                *rating = Some(-1);
+            },
+            Mark::Unflag => {
+                *picked = false;
+                if rejected {
+                    *rating = None;
+                }
+            },
+            Mark::Rate(0) => *rating = None,
+            Mark::Rate(stars) => *rating = Some(stars.min(5) as i8),
+        }
+    }
+}
+
+/// How a folder was last looked at in the gallery.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FolderView {
+    pub thumb_size : f32,
+    pub layout : GalleryLayout,
+    pub group_by : GroupBy,
+}
+
+/// Photos from anywhere, in an order picked by the user.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Album {
+    pub photos : Vec<PathBuf>,
+    /// Shown in the albums screen instead of the first photo
+    #[serde(default)]
+    pub cover : Option<PathBuf>,
+    #[serde(default)]
+    pub created : Option<SystemTime>,
+}
+
+impl Album {
+    pub fn new(photos : Vec<PathBuf>) -> Self {
+        Album {
+            photos,
+            cover : None,
+            created : Some(SystemTime::now()),
+        }
+    }
+
+    pub fn cover(&self) -> Option<&Path> {
+        self.cover.as_deref().or_else(|| self.photos.first().map(PathBuf::as_path))
+    }
+
+    /// Adds the photos which aren't in it yet at the end, in order. Returns
+    /// how many were added.
+    pub fn add(&mut self, photos : impl IntoIterator<Item = PathBuf>) -> usize {
+        let before = self.photos.len();
+        for path in photos {
+            if !self.photos.contains(&path) {
+                self.photos.push(path);
+            }
+        }
+        self.photos.len() - before
+    }
+
+    /// Takes the photos out, and the cover if it's one of them. Returns
+    /// how many were in it.
+    pub fn remove(&mut self, photos : &[PathBuf]) -> usize {
+        let before = self.photos.len();
+        self.photos.retain(|path| !photos.contains(path));
+        if self.cover.as_ref().map_or(false, |cover| photos.contains(cover)) {
+            self.cover = None;
+        }
+        before - self.photos.len()
+    }
+
+    /// Moves from to where to is, shifting the photos in between. Returns
+    /// whether both were in the album.
+    pub fn reorder(&mut self, from : &Path, to : &Path) -> bool {
+        let from_idx = self.photos.iter().position(|p| p == from);
+        let to_idx = self.photos.iter().position(|p| p == to);
+
+        match (from_idx, to_idx) {
+            (Some(from_idx), Some(to_idx)) => {
+                let path = self.photos.remove(from_idx);
+                self.photos.insert(to_idx, path);
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// What happened to a file, see `Library::operations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationKind {
+    Import,
+    Export,
+    Trash,
+    Rename,
+    Move,
+    Edit,
+}
+
+impl OperationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperationKind::Import => "import",
+            OperationKind::Export => "export",
+            OperationKind::Trash => "trash",
+            OperationKind::Rename => "rename",
+            OperationKind::Move => "move",
+            OperationKind::Edit => "edit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub time : SystemTime,
+    pub kind : OperationKind,
+    pub path : PathBuf,
+    /// Where it went, for everything but trash and edits
+    #[serde(default)]
+    pub dest : Option<PathBuf>,
+    /// Why it failed, None if it worked
+    #[serde(default)]
+    pub error : Option<String>,
+}
+
+// the oldest operations are dropped after this many
+const MAX_OPERATIONS : usize = 5000;
+
+/// The app's own data about photos, kept as json in the data dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    #[serde(default)]
+    pub photos : HashMap<PathBuf, PhotoRecord>,
+    #[serde(default)]
+    pub folders : HashMap<PathBuf, FolderView>,
+    /// By name
+    #[serde(default)]
+    pub albums : BTreeMap<String, Album>,
+    /// Saved searches by name, see `query::Query`
+    #[serde(default)]
+    pub smart_albums : BTreeMap<String, String>,
+    /// Imports, exports, deletes, and edits, oldest first
+    #[serde(default)]
+    pub operations : Vec<Operation>,
+}
+
+impl Library {
+    pub fn path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join("library.json"))
+    }
+
+    /// An empty library if there isn't one yet or it can't be read.
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Default::default(),
+        };
+
+        let byt = match std::fs::read(&path) {
+            Ok(byt) => byt,
+            Err(_) => return Default::default(),
+        };
+
+        match serde_json::from_slice(&byt) {
+            Ok(library) => library,
+            Err(err) => {
+                tracing::warn!("could not parse {}: {:?}", path.display(), err);
+                Default::default()
+            },
+        }
+    }
+
+    /// Fails for paths that aren't UTF-8, JSON keys have to be strings.
+    pub fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(self)
+    }
+
+    pub fn record(&self, path : &Path) -> Option<&PhotoRecord> {
+        self.photos.get(path)
+    }
+
+    pub fn record_mut(&mut self, path : &Path) -> &mut PhotoRecord {
+        self.photos.entry(path.to_path_buf()).or_default()
+    }
+
+    /// Every photo the library knows of, with a record or in an album,
+    /// sorted.
+    pub fn catalog(&self) -> BTreeSet<PathBuf> {
+        let in_albums = self.albums.values().flat_map(|album| album.photos.iter());
+        self.photos.keys().chain(in_albums).cloned().collect()
+    }
+
+    /// Adds to the operations, the library still has to be saved.
+    pub fn log(&mut self, kind : OperationKind, path : &Path, dest : Option<&Path>, error : Option<String>) {
+        if self.operations.len() >= MAX_OPERATIONS {
+            let over = self.operations.len() + 1 - MAX_OPERATIONS;
+            self.operations.drain(..over);
+        }
+
+        self.operations.push(Operation{
+            time : SystemTime::now(),
+            kind,
+            path : path.to_path_buf(),
+            dest : dest.map(Path::to_path_buf),
+            error,
+        });
+    }
+
+    /// Moves a photo's record and album entries along with its file,
+    /// returns whether there were any.
+    pub fn rename(&mut self, from : &Path, to : &Path) -> bool {
+        let mut found = false;
+
+        for album in self.albums.values_mut() {
+            let paths = album.photos.iter_mut().chain(album.cover.as_mut());
+            for path in paths.filter(|p| *p == from) {
+                *path = to.to_path_buf();
+                found = true;
+            }
+        }
+
+        if let Some(record) = self.photos.remove(from) {
+            self.photos.insert(to.to_path_buf(), record);
+            found = true;
+        }
+
+        found
+    }
+
+    /// Forgets a photo whose file is gone, its record and album entries,
+    /// returns whether there were any.
+    pub fn remove(&mut self, path : &Path) -> bool {
+        let mut found = false;
+
+        for album in self.albums.values_mut() {
+            let before = album.photos.len();
+            album.photos.retain(|p| p != path);
+            found |= album.photos.len() != before;
+
+            if album.cover.as_deref() == Some(path) {
+                album.cover = None;
+                found = true;
+            }
+        }
+
+        found |= self.photos.remove(path).is_some();
+        found
+    }
+}
+
+/// Writes the output of Library::to_json, through a temporary file so a
+/// crash doesn't leave half a library.
+pub async fn save(json : Vec<u8>) -> std::io::Result<()> {
+    let path = match Library::path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, json).await?;
+    tokio::fs::rename(&tmp, &path).await
+}
@@ -0,0 +1,239 @@
+// the framework's diagnostics are tracing events. run installs a
+// subscriber which prints them, at the level in RUST_LOG or info, and keeps
+// the last ones for the log window. apps log to the same place with the
+// tracing macros, and an app that installs its own subscriber first keeps
+// it, the window is empty then
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use std::time::SystemTime;
+
+use tracing::field::{
+    Field,
+    Visit,
+};
+use tracing::{
+    Event,
+    Level,
+    Metadata,
+    Subscriber,
+};
+use tracing_subscriber::layer::{
+    Context,
+    Layer,
+    SubscriberExt,
+};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::MutexExt;
+
+const LOG_LEN : usize = 2000;
+
+/// An event, see `Logs`.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time : SystemTime,
+    pub level : Level,
+    /// The module it's from, like photos1::task_channel
+    pub target : String,
+    /// The spans it's in, outermost first, like "frame{n=3}:update{msg=Open}"
+    pub spans : String,
+    /// The message and the other fields
+    pub message : String,
+}
+
+/// The last tracing events, debug and up, from `InitCtx::logs` or
+/// `RenderCtx::logs`. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct Logs {
+    lines : Arc<Mutex<VecDeque<LogLine>>>,
+    // the window shows debug lines
+    verbose : Arc<AtomicBool>,
+}
+
+impl Logs {
+    /// Oldest first.
+    pub fn lines(&self) -> Vec<LogLine> {
+        self.lines.lock_or_recover().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lines.lock_or_recover().clear();
+    }
+
+    fn push(&self, line : LogLine) {
+        let mut lines = self.lines.lock_or_recover();
+        if lines.len() >= LOG_LEN {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// A window with the lines, newest at the bottom, for debugging.
+    pub fn show(&self, egui : &egui::CtxRef, open : &mut bool) {
+        let mut verbose = self.verbose.load(Ordering::Relaxed);
+
+        egui::Window::new("Log").open(open).default_height(400.0).show(egui, |ui| {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut verbose, "debug");
+                if ui.button("clear").clicked() {
+                    self.clear();
+                }
+            });
+            ui.separator();
+
+            egui::ScrollArea::auto_sized().show(ui, |ui| {
+                for line in self.lines() {
+                    if line.level == Level::DEBUG && !verbose {
+                        continue
+                    }
+
+                    let text = if line.spans.is_empty() {
+                        format!("{} {}", line.target, line.message)
+                    } else {
+                        format!("{} {}: {}", line.target, line.spans, line.message)
+                    };
+                    ui.add(egui::Label::new(text).monospace().text_color(color(&line.level)));
+                }
+            });
+        });
+
+        self.verbose.store(verbose, Ordering::Relaxed);
+    }
+}
+
+fn color(level : &Level) -> egui::Color32 {
+    if *level == Level::ERROR {
+        egui::Color32::from_rgb(220, 80, 80)
+    } else if *level == Level::WARN {
+        egui::Color32::from_rgb(220, 180, 60)
+    } else if *level == Level::INFO {
+        egui::Color32::from_gray(210)
+    } else {
+        egui::Color32::from_gray(140)
+    }
+}
+
+// 0 for errors up to 4 for trace
+fn verbosity(level : &Level) -> u8 {
+    [Level::ERROR, Level::WARN, Level::INFO, Level::DEBUG, Level::TRACE]
+        .iter()
+        .position(|l| l == level)
+        .unwrap_or(4) as u8
+}
+
+// the fields as text, the message without its name
+#[derive(Default)]
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field : &Field, value : &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, "{}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field : &Field, value : &str) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+
+        if field.name() == "message" {
+            self.0.push_str(value);
+        } else {
+            let _ = write!(self.0, "{}={}", field.name(), value);
+        }
+    }
+}
+
+struct LogLayer {
+    logs : Logs,
+    // the most verbose level printed
+    print : u8,
+}
+
+impl<S> Layer<S> for LogLayer
+where
+    S : Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, metadata : &Metadata<'_>, _ctx : Context<'_, S>) -> bool {
+        // debug is always kept for the window
+        verbosity(metadata.level()) <= self.print.max(verbosity(&Level::DEBUG))
+    }
+
+    fn new_span(&self, attrs : &tracing::span::Attributes<'_>, id : &tracing::span::Id, ctx : Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_event(&self, event : &Event<'_>, ctx : Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        // innermost first, then turned around
+        let mut spans = Vec::new();
+        let mut current = ctx.lookup_current();
+        while let Some(span) = current {
+            let text = match span.extensions().get::<Fields>() {
+                Some(Fields(f)) if !f.is_empty() => format!("{}{{{}}}", span.name(), f),
+                _ => span.name().to_string(),
+            };
+            spans.push(text);
+            current = span.parent();
+        }
+        spans.reverse();
+
+        let metadata = event.metadata();
+        let line = LogLine{
+            time : SystemTime::now(),
+            level : *metadata.level(),
+            target : metadata.target().to_string(),
+            spans : spans.join(":"),
+            message : fields.0,
+        };
+
+        if verbosity(&line.level) <= self.print {
+            if line.spans.is_empty() {
+                println!("{:>5} {}: {}", line.level, line.target, line.message);
+            } else {
+                println!("{:>5} {}: {}: {}", line.level, line.target, line.spans, line.message);
+            }
+        }
+
+        if line.level != Level::TRACE {
+            self.logs.push(line);
+        }
+    }
+}
+
+// makes the subscriber the global default, unless there already is one
+crate fn install(logs : &Logs) {
+    let print = std::env::var("RUST_LOG").ok()
+        .and_then(|level| level.trim().parse::<Level>().ok())
+        .map_or(verbosity(&Level::INFO), |level| verbosity(&level));
+
+    let subscriber = tracing_subscriber::registry().with(LogLayer{
+        logs : logs.clone(),
+        print,
+    });
+
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
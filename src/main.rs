@@ -1,9 +1,155 @@
 #![allow(unused_macros)]
 
+mod metadata;
+use metadata::{
+    DateTime,
+    Metadata,
+};
+
+mod geocode;
+use geocode::Geocoder;
+
+mod map;
+
+mod dupes;
+
+mod dzi;
+
+mod crop;
+
+mod sequence;
+
+mod stacks;
+
+mod manifest;
+
+mod xmp;
+
+mod importers;
+
+mod slideshow;
+use slideshow::Slideshow;
+
+mod white_balance;
+
+mod presets;
+use presets::{
+    Fit,
+    PRESETS,
+};
+
+mod clipboard;
+use clipboard::{
+    Clipboard,
+    Pasted,
+};
+
+mod pdf;
+
+mod contact_sheet;
+use contact_sheet::{
+    Cell,
+    SheetLayout,
+};
+
+mod print;
+use print::PrintLayout;
+
+mod wallpaper;
+
+mod remote;
+use remote::{
+    Bandwidth,
+    Location,
+};
+
+mod web;
+
+mod external;
+use external::{
+    EditFormat,
+    Session,
+};
+
+mod output;
+use output::{
+    ColorSpace,
+    Output,
+    Resample,
+    Sharpen,
+};
+
+#[cfg(feature = "audio")]
+mod audio;
+
+mod histogram;
+use histogram::Histogram;
+
+mod image_stats;
+use image_stats::ImageStats;
+
+mod panel;
+use panel::{
+    Card,
+    PanelLayout,
+};
+
+mod samples;
+use samples::{
+    Sample,
+    Samples,
+};
+
+mod query;
+use query::Query;
+
+mod library;
+use library::{
+    Album,
+    EditHistory,
+    FolderView,
+    Library,
+    Mark,
+    Operation,
+    OperationKind,
+};
+
+mod captions;
+use captions::{
+    CaptionFields,
+    Captions,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
 use std::path::{
+    Path,
     PathBuf,
 };
 
+use std::time::{
+    Duration,
+    SystemTime,
+};
+
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+
+use std::collections::{
+    HashMap,
+    HashSet,
+    VecDeque,
+};
+
 use glam::f32::{
     Quat,
     Mat4,
@@ -16,13 +162,31 @@ use photos1::{
     Error,
     Result,
     App,
-    run_app,
+    AppRunner,
+    Backoff,
+    LogMsgs,
+    MutexExt,
+    Slot,
     BufBufWrite,
+    Generation,
+    Cmd,
+    DecodeLimits,
     Effects,
     EffectsShader,
+    Formats,
+    Overlays,
+    PEAKING_THRESHOLD,
+    PeakingShader,
     RenderCtx,
     UnrenderCtx,
+    Viewport,
+    Script,
+    Settings,
     ImageId,
+    JobManager,
+    Level,
+    Priority,
+    Toasts,
     GRAY,
 };
 
@@ -50,9 +214,21 @@ macro_rules! opt_unwrap_or {
     };
 }
 
-macro_rules! spawn_err {
-    ($handler:ident, $b:tt) => {
-        tokio::spawn(async move {
+// runs $b as a job named $name, with the Job handle bound to $job. errors
+// go to handle_error. jobs for a generation are cancelled when its screen
+// is replaced
+macro_rules! spawn_job {
+    ($handler:ident, for $generation:expr, $name:expr, $job:ident, $b:tt) => {
+        $handler.jobs.spawn_for($generation, $name, move |$job| async move {
+            let res = (async move $b).await;
+            match res {
+                Err(err) => $handler.handle_error(err),
+                Ok(_) => {},
+            }
+        })
+    };
+    ($handler:ident, $name:expr, $job:ident, $b:tt) => {
+        $handler.jobs.spawn($name, move |$job| async move {
             let res = (async move $b).await;
             match res {
                 Err(err) => $handler.handle_error(err),
@@ -62,512 +238,7493 @@ macro_rules! spawn_err {
     }
 }
 
-fn main() {
-    run_app::<Photos>();
+// command line flags
+#[derive(Debug, Clone, Default)]
+struct Options {
+    // for old laptops and single board computers: fewer worker threads and
+    // photos opened at half resolution
+    low_memory : bool,
+    // opened instead of the default folder
+    open : Option<PathBuf>,
+    // a ui test, see photos1::Script
+    script : Option<PathBuf>,
+    // the script's screenshots replace its references, missing ones are
+    // saved either way
+    record : bool,
+    // without showing the window, see AppRunner::headless
+    headless : bool,
+    // photos1 export ..., exports instead of opening the window
+    export : Option<BatchExport>,
 }
 
-struct Photos{}
-
-enum PhotoData {
-    GPU(ImageId),
-    CPU(image::RgbaImage),
+// photos1 export [--preset edits.json] [--for 4x5] [--pad] --out dir photos...
+#[derive(Debug, Clone, Default)]
+struct BatchExport {
+    // edits saved as json, applied instead of each photo's own
+    preset : Option<PathBuf>,
+    // the suffix of one of PRESETS, full size without
+    size : Option<String>,
+    // padded to the size instead of cropped
+    pad : bool,
+    out : Option<PathBuf>,
+    paths : Vec<PathBuf>,
 }
 
-impl PhotoData {
-    fn get_image_id(&mut self, ctx : &mut RenderCtx) -> ImageId {
-        match self {
-            PhotoData::GPU(img_id) => *img_id,
-            PhotoData::CPU(img) => {
-                let img_id = ctx.add_image(img.clone());
-                *self = PhotoData::GPU(img_id);
-                img_id
-            },
+impl BatchExport {
+    // takes the export's own arguments, false for any other
+    fn parse(&mut self, arg : &str, args : &mut impl Iterator<Item = String>) -> bool {
+        match arg {
+            "--preset" => self.preset = args.next().map(PathBuf::from),
+            "--for" => self.size = args.next(),
+            "--pad" => self.pad = true,
+            "--out" => self.out = args.next().map(PathBuf::from),
+            path if !path.starts_with("--") => self.paths.push(path.into()),
+            _ => return false,
         }
+        true
     }
-}
 
-struct Photo {
-    id : PathBuf,
-    data : PhotoData,
-    effects : Effects,
-}
+    // the PRESETS index of size, None if it's missing or not one
+    fn preset(&self) -> Option<usize> {
+        let size = self.size.as_deref()?;
+        PRESETS.iter().position(|preset| preset.suffix == size)
+    }
 
-impl Photo {
-    async fn new(path : PathBuf) -> Result<Self> {
-        let byt = tokio::fs::read(&path).await?;
-        let image = image::load_from_memory(&byt)?.to_rgba8();
+    fn fit(&self) -> Fit {
+        if self.pad { Fit::Pad } else { Fit::Crop }
+    }
 
-        Ok(Photo{
-            id : path,
-            data : PhotoData::CPU(image),
-            effects : Default::default(),
-        })
+    // what's wrong with the arguments, if anything
+    fn check(&self) -> Option<String> {
+        if self.out.is_none() {
+            return Some("--out is missing".to_string())
+        }
+        if self.paths.is_empty() {
+            return Some("no photos to export".to_string())
+        }
+        if self.size.is_some() && self.preset().is_none() {
+            let sizes : Vec<&str> = PRESETS.iter().map(|preset| preset.suffix).collect();
+            return Some(format!("--for is one of {}", sizes.join(", ")))
+        }
+        None
     }
 }
 
-impl std::fmt::Debug for Photo {
-    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Image")
-            .field("id", &self.id)
-            .field("effects", &self.effects)
-            .finish_non_exhaustive()
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--low-memory" => options.low_memory = true,
+                "--open" => options.open = args.next().map(PathBuf::from),
+                "--script" => options.script = args.next().map(PathBuf::from),
+                "--record" => options.record = true,
+                "--headless" => options.headless = true,
+                "export" if options.export.is_none() => options.export = Some(BatchExport::default()),
+                _ => {
+                    let taken = options.export.as_mut().map_or(false, |export| export.parse(&arg, &mut args));
+                    if !taken {
+                        println!("unknown argument: {}", arg);
+                    }
+                },
+            }
+        }
+
+        options
     }
 }
 
-// like a Photo but, probably, lower resolution and the data
-// might not be filled in yet.
-struct Thumb {
-    id : PathBuf,
-    data : PhotoData,
-}
+fn main() {
+    let options = Options::from_args();
 
-impl Thumb {
-    async fn new<P>(path : P, size : f32) -> Result<Self>
-    where P : Into<PathBuf>
-    {
-        let path : PathBuf = path.into();
-        let byt = tokio::fs::read(&path).await?;
-        let image = image::load_from_memory(&byt)?
-            .thumbnail(size as u32, size as u32)
-            .into_rgba8();
+    let mut runner = AppRunner::<Photos>::new()
+        .app_id("photos1")
+        .middleware(LogMsgs);
 
-        Ok(Thumb{
-            id : path,
-            data : PhotoData::CPU(image),
-        })
+    if let Some(dir) = library::data_dir() {
+        runner = runner.display_profiles(dir.join("icc"));
+    }
 
+    if options.low_memory {
+        // exports hold a full size copy of the photo in each blocking thread
+        runner = runner
+            .worker_threads(1)
+            .max_blocking_threads(2);
     }
-}
 
-impl std::fmt::Debug for Thumb {
-    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Thumb")
-            .field("id", &self.id)
-            .finish_non_exhaustive()
+    if let Some(export) = &options.export {
+        if let Some(problem) = export.check() {
+            println!("{}", problem);
+            println!("usage: photos1 export [--preset edits.json] [--for 4x5] [--pad] --out dir photos...");
+            std::process::exit(2);
+        }
+    }
+
+    if options.headless || options.export.is_some() {
+        runner = runner.headless();
+    }
+
+    if let Some(path) = &options.script {
+        match Script::load(path) {
+            Ok(script) => runner = runner.script(script.record(options.record)),
+            Err(err) => {
+                println!("could not load {}: {}", path.display(), err);
+                std::process::exit(2);
+            },
+        }
     }
+
+    runner.args(options).run();
 }
 
-#[derive(Debug)]
-struct Gallery {
-    thumbs : Vec<Thumb>,
+struct Photos{
+    library : Mutex<Library>,
+    // held while the library is written out
+    library_save : tokio::sync::Mutex<()>,
+    // loaded on first use, None if the dataset isn't there
+    geocoder : tokio::sync::OnceCell<Option<Geocoder>>,
+    // for reading photos off of flaky disks and network mounts
+    backoff : Backoff,
+    // what photos are opened from, and imported from
+    formats : Formats,
+    sources : importers::Sources,
+    toasts : Toasts,
+    jobs : JobManager,
+    options : Options,
+    // edits sent to be saved which haven't been written yet
+    unsaved_edits : AtomicUsize,
+    // decoded for a batch export, waiting for the render thread to apply
+    // their edits
+    pending_exports : Mutex<VecDeque<PendingExport>>,
+    // from the preferences, and the files the user opened over them anyway
+    limits : Mutex<DecodeLimits>,
+    allow_large : Mutex<HashSet<PathBuf>>,
+    // from the preferences too, and the downloads running under them
+    bandwidth : Mutex<Bandwidth>,
+    downloads : Mutex<Arc<tokio::sync::Semaphore>>,
+    too_large : BufBufWrite<TooLarge>,
+    // set when a command line export is done, what to exit with
+    exit_code : Mutex<Option<i32>>,
+    clipboard : Clipboard,
+    // copies opened because their remote or link couldn't be reached, and
+    // how to try again, see Msg::Refetch
+    offline : Mutex<HashMap<PathBuf, Refetch>>,
 }
 
+#[derive(Clone)]
+enum Refetch {
+    Url(String),
+    // a path from the remote's root
+    Remote(Arc<Location>, String),
+}
 
-#[derive(Debug)]
-enum PhotoSet {
-    Folder(String),
-    List(Vec<String>),
+// a photo in a batch export, waiting for its edits to be rendered
+struct PendingExport {
+    path : PathBuf,
+    image : image::RgbaImage,
+    effects : Effects,
+    to : ExportTo,
 }
 
-#[derive(Debug)]
-enum Msg {
-    // Rename to OpenPhoto/OpenSingle/OpenEditor
-    Open{
-        path : PathBuf,
+// where the rendering of a PendingExport goes, cropped
+enum ExportTo {
+    // Msg::ExportPreset, for a batch from the app
+    Preset{
+        preset : Option<usize>,
+        fit : Fit,
+        output : Output,
+        dest : PathBuf,
+        caption : bool,
+        // photos left in the batch, including this one
+        left : Arc<AtomicUsize>,
     },
-    // TODO: when the database is implemented
-    // this should be an enum:
-    //  enum PhotoSet {
-    //      Folder(String), // folder not in database
-    //      Album(u32), // an album in the database
-    //      Selection(Vec<u32>), // a selection of images in the database
-    //  }
-    OpenSet(PhotoSet),
-        //paths : Vec<String>,
-    //}
+    // back to the command line export waiting for it
+    Reply(tokio::sync::oneshot::Sender<Result<image::RgbaImage>>),
 }
 
-#[derive(Debug)]
-struct PhotoScreen {
-    photo : Photo,
-    view_mat : Mat4,
-}
+// photos of a command line export decoded at once, each in a job
+const BATCH_JOBS : usize = 4;
 
-impl PhotoScreen {
-    fn new(photo : Photo) -> Self {
-        PhotoScreen {
-            photo,
-            view_mat : Mat4::IDENTITY,
-        }
-    }
+// decoded photos waiting on the render thread, so a batch doesn't fill up
+// memory faster than it can be rendered
+const PENDING_EXPORTS : usize = 2;
 
-    fn update_view(&mut self, ctx : &mut RenderCtx<'_>) -> Mat4 {
-        let scale = self.view_mat.transform_vector3(Vec3::new(1.0, 0.0, 0.0)).length();
-        let mut new_scale = scale;
+impl Photos {
+    // loads thumbnails into the gallery of generation's screen, in the
+    // background
+    fn scan(&'static self, photo_set : PhotoSet, thumb_res : f32, model : BufBufWrite<Model>, generation : Generation) {
+        let name = match &photo_set {
+            PhotoSet::Folder{path, ..} => format!("scanning {}", path),
+            PhotoSet::List(paths) => format!("loading {} photos", paths.len()),
+            PhotoSet::Album(name) |
+            PhotoSet::Smart(name) => format!("loading {}", name),
+        };
 
-        match ctx.background_input().map(|i| (i.modifiers, i.scroll_delta)) {
-            Some((modifiers, (dx, dy))) => {
+        // the years a smart album wants, they're only known once a photo
+        // is read
+        let mut years = None;
 
-                if modifiers.shift() {
-                    // zoom
-                    new_scale *= 1.0 - dy.clamp(-10.0, 10.0) / 30.0;
-                } else {
-                    // pan
-                    let pan = Mat4::from_scale_rotation_translation(
-                        Vec3::ONE,
-                        Quat::from_rotation_z(0.0),
-                        Vec3::new(dx, dy, 0.0)
-                    );
-                    self.view_mat = pan.mul_mat4(&self.view_mat);
-                }
+        // an album is a list kept in the library, and a smart album one
+        // found in it again every time
+        let photo_set = match photo_set {
+            PhotoSet::Album(name) => {
+                let paths = self.library.lock_or_recover()
+                    .albums
+                    .get(&name)
+                    .map(|album| {
+                        album.photos.iter().map(|p| p.to_string_lossy().into_owned()).collect()
+                    })
+                    .unwrap_or_default();
 
+                PhotoSet::List(paths)
             },
-            _ => {},
-        }
+            PhotoSet::Smart(name) => {
+                let library = self.library.lock_or_recover();
+                let query = Query::parse(library.smart_albums.get(&name).map_or("", String::as_str));
+                let unrecorded = Default::default();
+                let paths = library.catalog()
+                    .into_iter()
+                    .filter(|path| query.matches_record(path, library.record(path).unwrap_or(&unrecorded)))
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect();
 
-        new_scale = new_scale.clamp(0.125, 8.0);
-        if scale != new_scale {
-            let (origin_x, origin_y) = ctx.background_input()
-                .map_or((0.0, 0.0), |i| {
-                    let (dim_x, dim_y) = ctx.dimensions();
-                    let (px, py) = i.pointer;
-                    (dim_x/2.0 - px, py - dim_y/2.0)
-                });
+                years = Some(query);
+                PhotoSet::List(paths)
+            },
+            photo_set => photo_set,
+        };
 
-            let to = Mat4::from_scale_rotation_translation(
-                Vec3::ONE,
-                Quat::from_rotation_z(0.0),
-                Vec3::new(origin_x, origin_y, 0.0)
-            );
+        spawn_job!(self, for generation, name, job, {
+            let walk = model.clone();
+            let res : Result<()> = async {
+                match photo_set {
+                    // walked like the disk, with each photo downloaded as it's
+                    // got to
+                    PhotoSet::Folder{path, max_depth} if remote::is_remote(&path) => {
+                        let location = Arc::new(Location::parse(&path)?);
+                        let mut dirs = std::collections::VecDeque::new();
+                        dirs.push_back((location.root.clone(), 0));
 
-            let fro = Mat4::from_scale_rotation_translation(
-                Vec3::ONE,
-                Quat::from_rotation_z(0.0),
-                Vec3::new(-origin_x, -origin_y, 0.0)
-            );
+                        while let Some((dir, depth)) = dirs.pop_front() {
+                            let lister = location.clone();
+                            let listed = self.download(move |_| lister.list(&dir)).await?;
+                            let (entries, listed_offline) = (listed.value, listed.offline);
+                            let mut files = Vec::new();
 
-            self.view_mat = fro
-                .mul_mat4(&Mat4::from_scale(Vec3::ONE * (new_scale / scale)))
-                .mul_mat4(&to)
-                .mul_mat4(&self.view_mat);
-        }
+                            for entry in entries {
+                                if entry.is_dir {
+                                    if depth < max_depth {
+                                        dirs.push_back((entry.path, depth + 1));
+                                    }
+                                } else if self.formats.is_supported(Path::new(entry.name())) {
+                                    files.push(entry);
+                                }
+                            }
 
-        let drag_delta = ctx
-            .background_input()
-            .map(|i| i.drag_delta())
-            .flatten();
+                            files.sort_by(|a, b| a.path.cmp(&b.path));
+                            job.add_total(files.len());
 
-        match drag_delta {
-            Some((dx, dy, released)) => {
-                let pan = Mat4::from_scale_rotation_translation(
-                    Vec3::ONE,
-                    Quat::from_rotation_z(0.0),
-                    Vec3::new(dx, dy, 0.0)
-                );
+                            let (found, more_dirs) = (files.len(), !dirs.is_empty());
+                            let alive = patch_gallery(&model, generation, move |gallery| {
+                                gallery.progress.found += found;
+                                gallery.progress.walking = more_dirs;
+                                gallery.offline |= listed_offline;
+                            });
 
-                if released {
-                    self.view_mat = pan.mul_mat4(&self.view_mat);
-                    self.view_mat
-                } else {
-                    pan.mul_mat4(&self.view_mat)
-                }
-            },
-            _ => self.view_mat,
-        }
-    }
-}
+                            if !alive || job.cancelled() {
+                                // the screen was dropped
+                                break;
+                            }
 
+                            for entry in files {
+                                job.resumed().await;
+                                if !model.is_current(generation) || job.cancelled() {
+                                    break;
+                                }
 
-#[derive(Debug)]
-enum Screen {
-    Empty,
-    Gallery(Gallery),
-    Photo(PhotoScreen),
-}
+                                let fetcher = location.clone();
+                                let path = entry.path.clone();
+                                let local = self.download(move |rate| fetcher.fetch(&entry, rate)).await;
 
-impl Screen {
-    fn gallery_mut(&mut self) -> Option<&mut Gallery> {
-        match self {
-            Screen::Gallery(v) => Some(v),
-            _ => None,
-        }
-    }
-}
+                                // one bad file shouldn't stop the scan
+                                let thumb = match local {
+                                    Ok(local) => {
+                                        let offline = listed_offline || local.offline;
+                                        self.set_offline(&local.value, offline, || Refetch::Remote(location.clone(), path));
+                                        self.thumb(local.value, thumb_res).await.map(|mut thumb| {
+                                            thumb.badges.offline = offline;
+                                            thumb
+                                        })
+                                    },
+                                    Err(err) => Err(err.into()),
+                                };
+                                let thumb = match thumb {
+                                    Ok(thumb) => Some(thumb),
+                                    Err(err) => {
+                                        self.handle_error(err);
+                                        None
+                                    },
+                                };
 
-#[derive(Debug)]
-struct Model {
-    screen : Screen,
-}
+                                patch_gallery(&model, generation, move |gallery| {
+                                    gallery.progress.scanned += 1;
+                                    gallery.offline |= thumb.as_ref().map_or(false, |thumb| thumb.badges.offline);
+                                    gallery.thumbs.extend(thumb);
+                                });
+                                job.inc();
+                            }
+                        }
 
+                        Ok(())
+                    },
+                    PhotoSet::Folder{path, max_depth} => {
+                        let mut dirs = std::collections::VecDeque::new();
+                        dirs.push_back((PathBuf::from(path), 0));
+                        let mut imported = 0;
 
-#[derive(Debug)]
-struct LocalModel {
-    effects_render : EffectsShader,
-    open_dialog : bool,
-    open_dialog_input : String,
-}
+                        while let Some((dir, depth)) = dirs.pop_front() {
+                            let mut entries = tokio::fs::read_dir(dir).await?;
+                            let mut files = Vec::new();
 
-impl LocalModel {
-    fn new(effects_render : EffectsShader) -> Self {
-        LocalModel {
-            effects_render,
-            open_dialog : false,
-            open_dialog_input : "/Users/julio/Pictures/wallpapers/".to_string(),
-        }
-    }
+                            while let Some(entry) = entries.next_entry().await? {
+                                let path = entry.path();
+                                if entry.file_type().await?.is_dir() {
+                                    if depth < max_depth {
+                                        dirs.push_back((path, depth + 1));
+                                    }
+                                } else if self.formats.is_supported(&path) {
+                                    files.push(path);
+                                }
+                            }
 
-}
+                            files.sort();
+                            job.add_total(files.len());
 
+                            let (found, more_dirs) = (files.len(), !dirs.is_empty());
+                            let alive = patch_gallery(&model, generation, move |gallery| {
+                                gallery.progress.found += found;
+                                gallery.progress.walking = more_dirs;
+                            });
 
-#[async_trait]
-impl App for Photos {
-    type LocalModel = LocalModel;
-    type Model = Model;
-    type Msg = Msg;
-    type Error = Error;
+                            if !alive || job.cancelled() {
+                                // the screen was dropped
+                                break;
+                            }
 
-    fn name() -> &'static str {
-        "photos"
-    }
+                            for path in files {
+                                job.resumed().await;
+                                if !model.is_current(generation) || job.cancelled() {
+                                    break;
+                                }
 
-    fn init(ctx : &mut UnrenderCtx, msgs : &mut Vec<Msg>) -> (Self, Self::LocalModel, Self::Model) {
-        let effects_shader = EffectsShader::new(ctx.display);
+                                if self.import_sidecar(&path).await {
+                                    imported += 1;
+                                }
 
-        msgs.push(Msg::OpenSet(PhotoSet::Folder("/Users/julio/Pictures/wallpapers".into())));
+                                // one bad file shouldn't stop the scan
+                                let thumb = match self.thumb(path, thumb_res).await {
+                                    Ok(thumb) => Some(thumb),
+                                    Err(err) => {
+                                        self.handle_error(err);
+                                        None
+                                    },
+                                };
 
-        let self_ = Photos {};
+                                patch_gallery(&model, generation, move |gallery| {
+                                    gallery.progress.scanned += 1;
+                                    gallery.thumbs.extend(thumb);
+                                });
+                                job.inc();
+                            }
+                        }
 
-        let model = Model {
-            screen : Screen::Empty,
-            // errors : Vec::new(),
-        };
+                        if imported > 0 {
+                            self.toasts.info(format!("imported {} sidecars", imported));
+                            self.save_library().await?;
+                        }
 
+                        Ok(())
+                    },
+                    PhotoSet::List(paths) => {
+                        let found = paths.len();
+                        patch_gallery(&model, generation, move |gallery| {
+                            gallery.progress.found = found;
+                            gallery.progress.walking = false;
+                        });
+                        job.set_total(paths.len());
 
-        (self_, LocalModel::new(effects_shader), model)
-    }
+                        for path in paths {
+                            job.resumed().await;
+                            if !model.is_current(generation) || job.cancelled() {
+                                break;
+                            }
 
-    fn swap(&self, ctx : &mut UnrenderCtx, old : &mut Model, _new : &mut Model) {
-        // TODO: reuse textures from old? allocate textures for new?
-        match old.screen {
-            Screen::Photo(PhotoScreen{photo: Photo{data : PhotoData::GPU(img_id), ..}, ..}) => {
-                ctx.delete_image(img_id);
-            }
-            _ => {},
-        }
-    }
+                            // one bad file shouldn't stop the scan
+                            let thumb = match self.thumb(path, thumb_res).await {
+                                Ok(thumb) => Some(thumb),
+                                Err(err) => {
+                                    self.handle_error(err);
+                                    None
+                                },
+                            };
+                            job.inc();
 
-    fn render(&self,
-              ctx : &mut RenderCtx,
+                            let thumb = thumb.filter(|thumb| {
+                                let year = thumb.meta.date.map(|date| date.year);
+                                years.as_ref().map_or(true, |q| q.matches_year(year))
+                            });
+
+                            let alive = patch_gallery(&model, generation, move |gallery| {
+                                gallery.progress.scanned += 1;
+                                gallery.thumbs.extend(thumb);
+                            });
+
+                            if !alive {
+                                // the screen was dropped
+                                break;
+                            }
+                        }
+
+                        Ok(())
+                    },
+                    PhotoSet::Album(_) |
+                    PhotoSet::Smart(_) => unreachable!(),
+                }
+            }.await;
+
+            // a walk that failed is over too, with what it found
+            patch_gallery(&walk, generation, |gallery| {
+                gallery.progress.walking = false;
+            });
+            res
+        });
+    }
+
+    fn limits(&self, path : &Path) -> DecodeLimits {
+        if self.allow_large.lock_or_recover().contains(path) {
+            DecodeLimits::unlimited()
+        } else {
+            *self.limits.lock_or_recover()
+        }
+    }
+
+    // the file's bytes, for the metadata, and the image in it. the limits
+    // are checked before either is read in full
+    async fn read_image(&self, path : &Path) -> Result<(Vec<u8>, image::DynamicImage)> {
+        let limits = self.limits(path);
+        limits.check_file_size(tokio::fs::metadata(path).await?.len())?;
+        let byt = self.backoff.run(|| tokio::fs::read(path)).await?;
+        let image = self.formats.decode(&byt, path, &limits)?;
+        Ok((byt, image))
+    }
+
+    // the photo with its edits and caption from the library, None when it's
+    // over the decode limits, which it's then asked about
+    async fn load_photo(&self, path : PathBuf) -> Result<Option<Photo>> {
+        let (byt, image) = match self.read_image(&path).await {
+            Err(Error::Limit(err)) => {
+                self.too_large.patch_current(move |too_large| *too_large = Some((path, err.to_string())));
+                return Ok(None)
+            },
+            res => res?,
+        };
+        let mut photo = Photo::new(path, &byt, image, self.options.low_memory);
+        self.locate(&mut photo.meta).await;
+
+        let (history, caption) = self.library.lock_or_recover()
+            .record(&photo.id)
+            .map_or((None, None), |r| (r.history.clone(), r.description.clone()));
+        photo.caption = caption.unwrap_or_default();
+        if let Some(history) = history {
+            photo.effects = history.current().cloned().unwrap_or_default();
+            photo.history = history;
+        }
+
+        Ok(Some(photo))
+    }
+
+    async fn thumb(&self, path : PathBuf, thumb_res : f32) -> Result<Thumb> {
+        let (byt, image) = self.read_image(&path).await?;
+        let mut thumb = Thumb::new(path, thumb_res, &byt, image).await?;
+        thumb.badges = self.badges(&thumb.id).await;
+        // for the map and the places to search
+        self.locate(&mut thumb.meta).await;
+
+        let library = self.library.lock_or_recover();
+        let record = library.record(&thumb.id);
+        thumb.caption = record.and_then(|r| r.description.clone());
+
+        // from an import, for photos which don't say in the file
+        if thumb.meta.date.is_none() {
+            thumb.meta.date = record
+                .and_then(|r| r.taken)
+                .and_then(DateTime::from_system_time);
+        }
+        Ok(thumb)
+    }
+
+    async fn badges(&self, path : &Path) -> Badges {
+        let (edited, exported, picked, rating) = self.library.lock_or_recover()
+            .record(path)
+            .map_or((false, false, false, None), |r| (r.edited(), !r.exports.is_empty(), r.picked, r.rating));
+
+        let sidecar = xmp::find(path).await.is_some();
+
+        Badges{
+            edited,
+            sidecar,
+            exported,
+            picked,
+            rating,
+            offline : false,
+        }
+    }
+
+    // runs fetch, which blocks on the network, once fewer than the
+    // preferences' downloads are running, with its share of the bandwidth
+    async fn download<T, F>(&self, fetch : F) -> std::io::Result<T>
+    where
+        F : FnOnce(Option<u32>) -> std::io::Result<T> + Send + 'static,
+        T : Send + 'static,
+    {
+        let rate = self.bandwidth.lock_or_recover().per_download();
+        let downloads = self.downloads.lock_or_recover().clone();
+
+        // the semaphore is never closed
+        let _permit = downloads.acquire().await
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        tokio::task::spawn_blocking(move || fetch(rate))
+            .await
+            .map_err(std::io::Error::from)?
+    }
+
+    // the preferences' bandwidth, a new limit on downloads applies to the
+    // ones after the ones running
+    fn set_bandwidth(&self, bandwidth : Bandwidth) {
+        let mut current = self.bandwidth.lock_or_recover();
+        if current.max_requests != bandwidth.max_requests {
+            *self.downloads.lock_or_recover() = Arc::new(tokio::sync::Semaphore::new(bandwidth.max_requests.max(1)));
+        }
+        *current = bandwidth;
+    }
+
+    // remembers how to download path again while it's an offline copy, and
+    // forgets it once it isn't
+    fn set_offline(&self, path : &Path, offline : bool, refetch : impl FnOnce() -> Refetch) {
+        let mut copies = self.offline.lock_or_recover();
+        if offline {
+            copies.insert(path.to_path_buf(), refetch());
+        } else {
+            copies.remove(path);
+        }
+    }
+
+    // reads the photo's lightroom or darktable sidecar into the library if
+    // it changed since the last time, returns whether it did
+    async fn import_sidecar(&self, path : &Path) -> bool {
+        let sidecar = match xmp::find(path).await {
+            Some(sidecar) => sidecar,
+            None => return false,
+        };
+
+        let modified = match tokio::fs::metadata(&sidecar).await.and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return false,
+        };
+
+        let seen = self.library.lock_or_recover()
+            .record(path)
+            .and_then(|r| r.sidecar);
+        if seen == Some(modified) {
+            return false
+        }
+
+        let byt = match tokio::fs::read(&sidecar).await {
+            Ok(byt) => byt,
+            Err(_) => return false,
+        };
+
+        let parsed = xmp::Sidecar::parse(&String::from_utf8_lossy(&byt));
+        if parsed.is_empty() && seen.is_none() {
+            return false
+        }
+
+        self.library.lock_or_recover().record_mut(path).import(parsed, modified);
+        true
+    }
+
+    // what the library has from sidecars, for the manage file window
+    fn tags(&self, path : &Path) -> Vec<String> {
+        let library = self.library.lock_or_recover();
+        let record = match library.record(path) {
+            Some(record) => record,
+            None => return Vec::new(),
+        };
+
+        let mut tags = Vec::new();
+        if record.picked {
+            tags.push("picked".to_string());
+        }
+        match record.rating {
+            Some(-1) => tags.push("rejected".to_string()),
+            Some(n) => tags.push("\u{2605}".repeat(n.max(0) as usize)),
+            None => {},
+        }
+        tags.extend(record.label.clone());
+        tags.extend(record.keywords.iter().cloned());
+        tags.extend(record.description.clone());
+        tags
+    }
+
+    fn record_export(&self, path : &Path, export : PathBuf) {
+        let mut library = self.library.lock_or_recover();
+        library.log(OperationKind::Export, path, Some(&export), None);
+
+        let exports = &mut library.record_mut(path).exports;
+        if !exports.contains(&export) {
+            exports.push(export);
+        }
+    }
+
+    // a copy edited in another program is the same photo, so it's rated,
+    // tagged, and in albums like the original, right after it
+    fn record_edited_copy(&self, original : &Path, copy : &Path) {
+        let mut library = self.library.lock_or_recover();
+
+        let from = library.record(original).cloned().unwrap_or_default();
+        let record = library.record_mut(copy);
+        record.rating = from.rating;
+        record.label = from.label;
+        record.keywords = from.keywords;
+        record.description = from.description;
+
+        for album in library.albums.values_mut() {
+            let idx = album.photos.iter().position(|p| p == original);
+            if let (Some(idx), false) = (idx, album.photos.iter().any(|p| p == copy)) {
+                album.photos.insert(idx + 1, copy.to_path_buf());
+            }
+        }
+    }
+
+    fn albums_with(&self, path : &Path) -> Vec<String> {
+        self.library.lock_or_recover()
+            .albums
+            .iter()
+            .filter(|(_, album)| album.photos.iter().any(|p| p == path))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    // adds an operation's outcome to the library, it's saved with the next
+    // change or at shutdown
+    fn log_op<T>(&self, kind : OperationKind, path : &Path, dest : Option<&Path>, res : &Result<T>) {
+        let error = res.as_ref().err().map(|err| err.to_string());
+        self.library.lock_or_recover().log(kind, path, dest, error);
+    }
+
+    // the photo's caption into its export, if it has one
+    async fn write_caption(&self, path : &Path, export : &Path) -> Result<()> {
+        let caption = self.library.lock_or_recover()
+            .record(path)
+            .and_then(|r| r.description.clone());
+
+        if let Some(caption) = caption {
+            xmp::write_description(export, &caption).await?;
+        }
+        Ok(())
+    }
+
+    // the edits saved in the library for path, as they're exported
+    fn saved_effects(&self, path : &Path) -> Effects {
+        let effects = self.library.lock_or_recover()
+            .record(path)
+            .and_then(|r| r.history.as_ref())
+            .and_then(|h| h.current().cloned())
+            .unwrap_or_default();
+
+        Effects{
+            original : 0,
+            ..effects
+        }
+    }
+
+    // hands export to the render thread, once there's room
+    async fn queue_export(&self, export : PendingExport) {
+        while self.pending_exports.lock_or_recover().len() >= PENDING_EXPORTS {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        self.pending_exports.lock_or_recover().push_back(export);
+    }
+
+    // one photo of a command line export, where it was saved
+    async fn export_one(&self, batch : &BatchExport, edits : Option<&Effects>, path : &Path) -> Result<PathBuf> {
+        let (_, image) = self.read_image(path).await?;
+        let effects = match edits {
+            Some(edits) => Effects{
+                original : 0,
+                ..edits.clone()
+            },
+            None => self.saved_effects(path),
+        };
+
+        let (reply, rendered) = tokio::sync::oneshot::channel();
+        self.queue_export(PendingExport{
+            path : path.to_path_buf(),
+            image : image.to_rgba8(),
+            effects,
+            to : ExportTo::Reply(reply),
+        }).await;
+        let image = rendered.await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "the render thread stopped"))??;
+
+        let stem = path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "photo".to_string());
+        let out = batch.out.clone().unwrap_or_default();
+        let (dest, fit) = match batch.preset() {
+            Some(preset) => (out.join(format!("{}_{}.jpg", stem, PRESETS[preset].suffix)), Some((preset, batch.fit()))),
+            None => (out.join(format!("{}.jpg", stem)), None),
+        };
+
+        let save_to = dest.clone();
+        tokio::task::spawn_blocking(move || match fit {
+            Some((preset, fit)) => PRESETS[preset].apply(&image, fit).save(save_to),
+            None => image.save(save_to),
+        })
+            .await
+            .map_err(std::io::Error::from)??;
+
+        Ok(dest)
+    }
+
+    // ends a command line export, the render thread quits on the next frame
+    fn quit_with(&self, code : i32, model : &BufBufWrite<Model>) {
+        *self.exit_code.lock_or_recover() = Some(code);
+        model.wake();
+    }
+
+    // a photo of a batch export was saved or failed, the last one says so
+    fn export_done(&self, left : &AtomicUsize, dest : &Path) {
+        if left.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.toasts.info(format!("exported to {}", dest.display()));
+        }
+    }
+
+    // what's open, with a * while edits are being made or saved
+    fn title(&self, model : &Model) -> String {
+        let open = model.screen.name();
+        // a slider being dragged
+        let edited = match &model.screen {
+            Screen::Photo(photo_screen) => {
+                let photo = &photo_screen.photo;
+                photo.history.current() != Some(&photo.effects)
+            },
+            _ => false,
+        };
+
+        let edited = edited || self.unsaved_edits.load(Ordering::Relaxed) > 0;
+        let marker = if edited { "*" } else { "" };
+
+        match open {
+            Some(open) => format!("{}{} - {}", marker, open, Self::name()),
+            None => format!("{}{}", marker, Self::name()),
+        }
+    }
+
+    async fn save_library(&self) -> Result<()> {
+        // serialize under the save lock so the last write has the newest data
+        let _guard = self.library_save.lock().await;
+        let json = self.library.lock_or_recover().to_json().map_err(std::io::Error::from)?;
+        library::save(json).await?;
+        Ok(())
+    }
+
+    async fn locate(&self, meta : &mut Metadata) {
+        let (lat, lon) = match meta.gps {
+            Some(gps) => gps,
+            None => return,
+        };
+
+        let geocoder = self.geocoder.get_or_init(|| async {
+            match Geocoder::load().await {
+                Ok(geocoder) => Some(geocoder),
+                Err(err) => {
+                    tracing::warn!("geocoder not loaded: {:?}", err);
+                    None
+                },
+            }
+        }).await;
+
+        meta.location = geocoder.as_ref().and_then(|g| g.lookup(lat, lon));
+    }
+}
+
+enum PhotoData {
+    GPU(ImageId),
+    CPU(image::RgbaImage),
+    // the upload failed, it's not tried again every frame
+    Failed,
+}
+
+impl PhotoData {
+    // None if it couldn't be uploaded, which is said once
+    fn get_image_id(&mut self, ctx : &mut RenderCtx) -> Option<ImageId> {
+        match self {
+            PhotoData::GPU(img_id) => Some(*img_id),
+            PhotoData::CPU(img) => match ctx.add_image(img.clone()) {
+                Ok(img_id) => {
+                    *self = PhotoData::GPU(img_id);
+                    Some(img_id)
+                },
+                Err(err) => {
+                    ctx.toasts().error(format!("could not show an image: {}", err));
+                    *self = PhotoData::Failed;
+                    None
+                },
+            },
+            PhotoData::Failed => None,
+        }
+    }
+
+    // what to draw it with in egui, a gray square in place of one that
+    // couldn't be uploaded, from the white texel of egui's own texture
+    fn egui_texture(&mut self, ctx : &mut RenderCtx) -> (egui::TextureId, egui::Rect, egui::Color32) {
+        match self.get_image_id(ctx).and_then(|img_id| ctx.egui_id(img_id).ok()) {
+            Some(egui_id) => (
+                egui_id,
+                egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                egui::Color32::WHITE,
+            ),
+            None => (
+                egui::TextureId::Egui,
+                egui::Rect::from_min_max(egui::epaint::WHITE_UV, egui::epaint::WHITE_UV),
+                egui::Color32::DARK_GRAY,
+            ),
+        }
+    }
+
+    fn button(&mut self, ctx : &mut RenderCtx, size : egui::Vec2) -> egui::ImageButton {
+        let (texture_id, uv, tint) = self.egui_texture(ctx);
+        egui::ImageButton::new(texture_id, size).uv(uv).tint(tint)
+    }
+
+    fn image(&mut self, ctx : &mut RenderCtx, size : egui::Vec2) -> egui::Image {
+        let (texture_id, uv, tint) = self.egui_texture(ctx);
+        egui::Image::new(texture_id, size).uv(uv).tint(tint)
+    }
+}
+
+// the open photo rendered with its edits for the print dialog, kept while
+// it's open
+struct PrintRender {
+    path : PathBuf,
+    image : image::RgbaImage,
+    preview : PhotoData,
+}
+
+// the longest side of the print dialog's preview
+const PRINT_PREVIEW_SIZE : u32 = 512;
+
+impl std::fmt::Debug for PrintRender {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrintRender")
+            .field("path", &self.path)
+            .field("size", &self.image.dimensions())
+            .finish()
+    }
+}
+
+struct Photo {
+    id : PathBuf,
+    data : PhotoData,
+    meta : Metadata,
+    effects : Effects,
+    history : EditHistory,
+    crop_suggestions : Vec<crop::Suggestion>,
+    // free text notes, kept in the library as the description
+    caption : String,
+    // typed in since the last save, saved when the field loses focus
+    caption_edited : bool,
+    // of the image in data, which is only a texture once it's shown
+    dims : (u32, u32),
+    // what's in data, kept for sampling colors after it's uploaded
+    pixels : Arc<image::RgbaImage>,
+    // of the last recorded edit, made again when there's a new one
+    histogram : Option<Histogram>,
+    // the same, counted by Msg::ComputeStats while pending
+    stats : Option<ImageStats>,
+    stats_pending : bool,
+    // bumped by each recorded edit, stats counted for an older one are
+    // dropped
+    stats_edit : u64,
+}
+
+impl Photo {
+    // from Photos::read_image, a proxy is kept at half resolution
+    fn new(path : PathBuf, byt : &[u8], image : image::DynamicImage, proxy : bool) -> Self {
+        let image = if proxy {
+            let (width, height) = image::GenericImageView::dimensions(&image);
+            image.thumbnail((width / 2).max(1), (height / 2).max(1)).to_rgba8()
+        } else {
+            image.to_rgba8()
+        };
+
+        let effects = Effects::default();
+
+        Photo{
+            id : path,
+            dims : image.dimensions(),
+            histogram : None,
+            stats : None,
+            stats_pending : false,
+            stats_edit : 0,
+            crop_suggestions : crop::suggest(&image),
+            data : PhotoData::CPU(image.clone()),
+            pixels : Arc::new(image),
+            meta : Metadata::read(byt),
+            history : EditHistory::new(&effects),
+            effects,
+            caption : String::new(),
+            caption_edited : false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Photo {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("id", &self.id)
+            .field("effects", &self.effects)
+            .field("history", &self.history.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+fn fmt_ago(time : SystemTime) -> String {
+    let secs = time.elapsed().map_or(0, |d| d.as_secs());
+
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+// like a Photo but, probably, lower resolution and the data
+// might not be filled in yet.
+struct Thumb {
+    id : PathBuf,
+    data : PhotoData,
+    dims : (u32, u32),
+    meta : Metadata,
+    // perceptual hash, see dupes
+    dhash : u64,
+    badges : Badges,
+    selected : bool,
+    // the library's description, for searching
+    caption : Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Badges {
+    edited : bool,
+    // has an .xmp next to it
+    sidecar : bool,
+    exported : bool,
+    // from culling, or a sidecar's rating
+    picked : bool,
+    rating : Option<i8>,
+    // a copy from a remote that couldn't be reached
+    offline : bool,
+}
+
+impl Badges {
+    fn rejected(&self) -> bool {
+        self.rating == Some(-1)
+    }
+
+    // like "picked, 3 stars", for under a photo
+    fn describe(&self) -> String {
+        let flag = if self.picked {
+            Some("picked".to_string())
+        } else if self.rejected() {
+            Some("rejected".to_string())
+        } else {
+            None
+        };
+        let stars = match self.rating {
+            Some(1) => Some("1 star".to_string()),
+            Some(n) if n > 1 => Some(format!("{} stars", n)),
+            _ => None,
+        };
+
+        let marks : Vec<String> = flag.into_iter().chain(stars).collect();
+        if marks.is_empty() {
+            "unmarked".to_string()
+        } else {
+            marks.join(", ")
+        }
+    }
+
+    // E for edited, X for xmp, O for output, P for picked and R for
+    // rejected in the top right corner of the thumbnail, the stars in the
+    // bottom left and offline in the bottom right. rejected ones are
+    // darkened
+    fn paint(&self, painter : &egui::Painter, rect : egui::Rect) {
+        if self.rejected() {
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(150));
+        }
+
+        let marks = [
+            (self.edited, "E", egui::Color32::from_rgb(230, 160, 40)),
+            (self.sidecar, "X", egui::Color32::from_rgb(80, 160, 230)),
+            (self.exported, "O", egui::Color32::from_rgb(90, 200, 90)),
+            (self.picked, "P", egui::Color32::from_gray(230)),
+            (self.rejected(), "R", egui::Color32::from_rgb(220, 80, 80)),
+        ];
+
+        let size = egui::Vec2::splat(14.0);
+        let mut right_top = rect.right_top() + egui::Vec2{ x : -3.0, y : 3.0 };
+        for (_, text, color) in marks.iter().filter(|(on, _, _)| *on) {
+            let badge = egui::Rect::from_min_size(right_top - egui::Vec2{ x : size.x, y : 0.0 }, size);
+
+            painter.rect_filled(badge, 3.0, *color);
+            painter.text(
+                badge.center(),
+                egui::Align2::CENTER_CENTER,
+                text,
+                egui::TextStyle::Small,
+                egui::Color32::BLACK,
+            );
+
+            right_top.x -= size.x + 2.0;
+        }
+
+        if let Some(stars) = self.rating.filter(|n| *n > 0) {
+            painter.text(
+                rect.left_bottom() + egui::Vec2{ x : 4.0, y : -3.0 },
+                egui::Align2::LEFT_BOTTOM,
+                "\u{2605}".repeat(stars as usize),
+                egui::TextStyle::Small,
+                egui::Color32::from_rgb(240, 200, 60),
+            );
+        }
+
+        if self.offline {
+            let size = egui::Vec2{ x : 44.0, y : 14.0 };
+            let badge = egui::Rect::from_min_size(rect.right_bottom() - size - egui::Vec2::splat(3.0), size);
+            painter.rect_filled(badge, 3.0, OFFLINE_COLOR);
+            painter.text(
+                badge.center(),
+                egui::Align2::CENTER_CENTER,
+                "offline",
+                egui::TextStyle::Small,
+                egui::Color32::BLACK,
+            );
+        }
+    }
+}
+
+// the badge on copies from remotes that couldn't be reached
+const OFFLINE_COLOR : egui::Color32 = egui::Color32::from_rgb(150, 150, 150);
+
+impl Thumb {
+    // from Photos::read_image
+    async fn new(path : PathBuf, size : f32, byt : &[u8], image : image::DynamicImage) -> Result<Self> {
+        let image = image
+            .thumbnail(size as u32, size as u32)
+            .into_rgba8();
+
+        let mut meta = Metadata::read(byt);
+        if meta.date.is_none() {
+            // fall back to the file time so everything can be grouped
+            meta.date = tokio::fs::metadata(&path).await?
+                .modified()
+                .ok()
+                .and_then(DateTime::from_system_time);
+        }
+
+        Ok(Thumb{
+            id : path,
+            dims : image.dimensions(),
+            dhash : dupes::dhash(&image),
+            data : PhotoData::CPU(image),
+            meta,
+            badges : Default::default(),
+            selected : false,
+            caption : None,
+        })
+
+    }
+
+    fn aspect(&self) -> f32 {
+        let (w, h) = self.dims;
+        w.max(1) as f32 / h.max(1) as f32
+    }
+}
+
+impl std::fmt::Debug for Thumb {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Thumb")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Gallery {
+    set : PhotoSet,
+    // the size the thumbnails were decoded at
+    thumb_res : f32,
+    thumbs : Vec<Thumb>,
+    progress : ScanProgress,
+    // the folder's saved view, taken on the first frame
+    restore_view : Option<FolderView>,
+    // the view as of the last frame, to notice changes worth saving
+    shown_view : Option<FolderView>,
+    // numbered sequences among the thumbs, found once the scan is done
+    sequences : Option<Vec<sequence::Sequence>>,
+    // brackets and panoramas among the thumbs, likewise
+    stacks : Option<Vec<stacks::Stack>>,
+    // the thumb shown bigger over the others, see ThumbAction::QuickLook
+    quick_look : Option<usize>,
+    // the thumb being culled, see show_cull
+    cull : Option<usize>,
+    previews : Previews,
+    // some of the thumbs are copies from a remote that couldn't be reached
+    offline : bool,
+}
+
+// the longest side of a quick look preview
+const PREVIEW_SIZE : u32 = 1600;
+// halved with --low-memory
+const PREVIEWS_KEPT : usize = 8;
+
+// photos decoded bigger than their thumbnails for quick look. the last few
+// are kept, so going back and forth doesn't decode them again
+#[derive(Default)]
+struct Previews {
+    // the last shown last
+    loaded : VecDeque<(PathBuf, PhotoData)>,
+    // asked for and not loaded yet
+    pending : HashSet<PathBuf>,
+}
+
+impl Previews {
+    // the preview of path, asked for the first time it isn't there
+    fn get(&mut self, path : &Path, msgs : &mut Vec<Msg>) -> Option<&mut PhotoData> {
+        let idx = match self.loaded.iter().position(|(p, _)| p == path) {
+            Some(idx) => idx,
+            None => {
+                if self.pending.insert(path.to_path_buf()) {
+                    msgs.push(Msg::LoadPreview{path : path.to_path_buf()});
+                }
+                return None
+            },
+        };
+
+        let entry = self.loaded.remove(idx)?;
+        self.loaded.push_back(entry);
+        self.loaded.back_mut().map(|(_, data)| data)
+    }
+
+    // from Msg::LoadPreview, keeps the last kept. the textures of the ones
+    // pushed out go to dropped
+    fn insert(&mut self, path : PathBuf, image : image::RgbaImage, kept : usize, dropped : &mut Vec<ImageId>) {
+        // asked for by a gallery since replaced
+        if !self.pending.remove(&path) {
+            return
+        }
+
+        self.loaded.push_back((path, PhotoData::CPU(image)));
+        while self.loaded.len() > kept {
+            if let Some((_, PhotoData::GPU(img_id))) = self.loaded.pop_front() {
+                dropped.push(img_id);
+            }
+        }
+    }
+
+    fn image_ids(&self) -> impl Iterator<Item = ImageId> + '_ {
+        self.loaded.iter().filter_map(|(_, data)| match data {
+            PhotoData::GPU(img_id) => Some(*img_id),
+            _ => None,
+        })
+    }
+}
+
+impl std::fmt::Debug for Previews {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Previews")
+            .field("loaded", &self.loaded.len())
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct ScanProgress {
+    scanned : usize,
+    // grows while walking the folders
+    found : usize,
+    walking : bool,
+}
+
+// pending, an empty gallery isn't done before its scan has started
+impl Default for ScanProgress {
+    fn default() -> Self {
+        ScanProgress {
+            scanned : 0,
+            found : 0,
+            walking : true,
+        }
+    }
+}
+
+impl ScanProgress {
+    fn done(&self) -> bool {
+        !self.walking && self.scanned >= self.found
+    }
+}
+
+impl std::fmt::Display for ScanProgress {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {}{} scanned",
+            fmt_count(self.scanned),
+            if self.walking { "~" } else { "" },
+            fmt_count(self.found))
+    }
+}
+
+// 1234567 -> 1,234,567
+fn fmt_count(n : usize) -> String {
+    let digits = n.to_string();
+    let mut s = String::new();
+
+    for (idx, c) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx) % 3 == 0 {
+            s.push(',');
+        }
+        s.push(c);
+    }
+
+    s
+}
+
+// brackets and panoramas among the thumbs. photos without exposure settings
+// didn't come from a camera or lost their exif, and their date might be the
+// file's, so they're left out
+fn find_stacks(thumbs : &[Thumb]) -> Vec<stacks::Stack> {
+    let shots : Vec<stacks::Shot> = thumbs.iter()
+        .map(|thumb| {
+            let meta = &thumb.meta;
+            let time = meta.date
+                .filter(|_| meta.exposure.time.is_some())
+                .and_then(|date| date.to_system_time().duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|secs| secs.as_secs_f64() + meta.date_millis.unwrap_or(0) as f64 / 1000.0);
+
+            stacks::Shot{
+                path : &thumb.id,
+                time,
+                exposure : &meta.exposure,
+                // the thumbnails', which match when the photos' do
+                dims : thumb.dims,
+                dhash : thumb.dhash,
+            }
+        })
+        .collect();
+
+    stacks::detect(&shots)
+}
+
+// the gallery's quick look thumbnail, bigger and over the others. space or
+// escape closes it, unless space was what opened it this frame, and the
+// arrows go to the thumbnails next to it
+fn show_quick_look(
+    ctx : &mut RenderCtx,
+    gallery : &mut Gallery,
+    search : &str,
+    toggled : bool,
+    msgs : &mut Vec<Msg>)
+{
+    let (space, escape, left, right, screen) = {
+        let input = ctx.egui.input();
+        (
+            input.key_pressed(egui::Key::Space),
+            input.key_pressed(egui::Key::Escape),
+            input.key_pressed(egui::Key::ArrowLeft),
+            input.key_pressed(egui::Key::ArrowRight),
+            input.screen_rect(),
+        )
+    };
+
+    let shown = gallery.matching(search);
+    // searched away, or removed
+    let at = gallery.quick_look.and_then(|idx| shown.iter().position(|&i| i == idx));
+    let at = match at {
+        Some(at) if !escape && !(space && !toggled) => at,
+        _ => {
+            gallery.quick_look = None;
+            return
+        },
+    };
+
+    let at = if left {
+        at.saturating_sub(1)
+    } else if right {
+        (at + 1).min(shown.len() - 1)
+    } else {
+        at
+    };
+    let idx = shown[at];
+    gallery.quick_look = Some(idx);
+
+    let (width, height) = gallery.thumbs[idx].dims;
+    let path = gallery.thumbs[idx].id.clone();
+    let scale = (screen.width() * 0.85 / width.max(1) as f32)
+        .min(screen.height() * 0.8 / height.max(1) as f32);
+    let size = egui::Vec2{
+        x : width as f32 * scale,
+        y : height as f32 * scale,
+    };
+
+    // the thumbnail scaled up until the preview is in
+    let data = match gallery.previews.get(&path, msgs) {
+        Some(data) => data,
+        None => &mut gallery.thumbs[idx].data,
+    };
+
+    let mut close = false;
+    egui::Area::new("quick look")
+        .order(egui::Order::Foreground)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx.egui, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                close = ui.add(data.image(ctx, size).sense(egui::Sense::click())).clicked();
+                ui.label(format!("{}  ({} of {})",
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    at + 1,
+                    shown.len()));
+            });
+        });
+
+    if close {
+        gallery.quick_look = None;
+    }
+}
+
+// the photo on screen, or the gallery's culled, quick looked, or first
+// selected one, into the preview window. it's for showing photos to others,
+// so the privacy blur, which is about the window, doesn't cover it
+fn draw_preview(ctx : &mut RenderCtx, effects_render : &EffectsShader, screen : &mut Screen, msgs : &mut Vec<Msg>) {
+    let defaults = Effects::default();
+    let (data, effects) = match screen {
+        Screen::Photo(photo_screen) => (&mut photo_screen.photo.data, &photo_screen.photo.effects),
+        Screen::Gallery(gallery) => {
+            let idx = gallery.cull
+                .or(gallery.quick_look)
+                .or_else(|| gallery.thumbs.iter().position(|t| t.selected));
+            let idx = opt_unwrap_or!(idx, {
+                return
+            });
+
+            // the thumbnail until the preview is in
+            let path = gallery.thumbs[idx].id.clone();
+            let data = match gallery.previews.get(&path, msgs) {
+                Some(data) => data,
+                None => &mut gallery.thumbs[idx].data,
+            };
+            (data, &defaults)
+        },
+        _ => return,
+    };
+
+    if let Some(img_id) = data.get_image_id(ctx) {
+        if let Err(err) = effects_render.draw_image_preview(ctx, img_id, effects) {
+            tracing::warn!("could not draw the preview: {:?}", err);
+        }
+    }
+}
+
+// photos after the one being culled which are decoded before they're
+// reached, so going through them doesn't wait on each one. halved with
+// --low-memory, and fewer than the previews kept
+const CULL_PRELOAD : usize = 3;
+const FILMSTRIP_HEIGHT : f32 = 80.0;
+
+// the gallery's culling view, a photo at a time over the whole window with
+// the ones around it along the bottom. p picks, x rejects, and 0 to 5 rate,
+// each going on to the next photo, u unflags, the arrows go back and forth,
+// and escape leaves
+fn show_cull(
+    ctx : &mut RenderCtx,
+    gallery : &mut Gallery,
+    search : &str,
+    preload : usize,
+    msgs : &mut Vec<Msg>)
+{
+    let typing = ctx.egui.wants_keyboard_input();
+    let (escape, left, right, mark, screen) = {
+        let input = ctx.egui.input();
+        let keys = [
+            (egui::Key::P, Mark::Pick),
+            (egui::Key::X, Mark::Reject),
+            (egui::Key::U, Mark::Unflag),
+            (egui::Key::Num0, Mark::Rate(0)),
+            (egui::Key::Num1, Mark::Rate(1)),
+            (egui::Key::Num2, Mark::Rate(2)),
+            (egui::Key::Num3, Mark::Rate(3)),
+            (egui::Key::Num4, Mark::Rate(4)),
+            (egui::Key::Num5, Mark::Rate(5)),
+        ];
+        let mark = keys.iter()
+            .find(|(key, _)| input.key_pressed(*key))
+            .map(|(_, mark)| *mark)
+            .filter(|_| !typing);
+
+        (
+            input.key_pressed(egui::Key::Escape),
+            input.key_pressed(egui::Key::ArrowLeft),
+            input.key_pressed(egui::Key::ArrowRight),
+            mark,
+            input.screen_rect(),
+        )
+    };
+
+    let shown = gallery.matching(search);
+    // searched away, or removed
+    let at = gallery.cull.and_then(|idx| shown.iter().position(|&i| i == idx));
+    let at = match at {
+        Some(at) if !escape => at,
+        _ => {
+            gallery.cull = None;
+            return
+        },
+    };
+
+    if let Some(mark) = mark {
+        let thumb = &mut gallery.thumbs[shown[at]];
+        mark.apply(&mut thumb.badges.picked, &mut thumb.badges.rating);
+        msgs.push(Msg::Mark{
+            path : thumb.id.clone(),
+            mark,
+        });
+    }
+
+    let advance = matches!(mark, Some(mark) if mark != Mark::Unflag);
+    let at = if left {
+        at.saturating_sub(1)
+    } else if right || advance {
+        (at + 1).min(shown.len() - 1)
+    } else {
+        at
+    };
+    let idx = shown[at];
+    gallery.cull = Some(idx);
+
+    // the ones ahead first, so the one shown is the last used and the
+    // last pushed out
+    for &ahead in shown.iter().skip(at + 1).take(preload).rev() {
+        gallery.previews.get(&gallery.thumbs[ahead].id, msgs);
+    }
+
+    let strip = egui::Rect::from_min_max(
+        egui::pos2(screen.left(), screen.bottom() - FILMSTRIP_HEIGHT),
+        screen.max,
+    );
+    let info = egui::Rect::from_min_max(
+        egui::pos2(screen.left(), strip.top() - 24.0),
+        egui::pos2(screen.right(), strip.top()),
+    );
+    let space = egui::Rect::from_min_max(screen.min, info.right_top()).shrink(8.0);
+
+    let (width, height) = gallery.thumbs[idx].dims;
+    let scale = (space.width() / width.max(1) as f32)
+        .min(space.height() / height.max(1) as f32);
+    let size = egui::Vec2{
+        x : width as f32 * scale,
+        y : height as f32 * scale,
+    };
+
+    let path = gallery.thumbs[idx].id.clone();
+    let badges = gallery.thumbs[idx].badges;
+    let mut jump = None;
+
+    egui::Area::new("cull")
+        .order(egui::Order::Foreground)
+        .fixed_pos(screen.min)
+        .show(ctx.egui, |ui| {
+            // covers the gallery, so its thumbnails don't take the clicks
+            ui.set_min_size(screen.size());
+            ui.painter().rect_filled(screen, 0.0, egui::Color32::from_gray(20));
+
+            // the thumbnail scaled up until the preview is in
+            let data = match gallery.previews.get(&path, msgs) {
+                Some(data) => data,
+                None => &mut gallery.thumbs[idx].data,
+            };
+            ui.put(egui::Rect::from_center_size(space.center(), size), data.image(ctx, size));
+
+            ui.painter().text(
+                info.center(),
+                egui::Align2::CENTER_CENTER,
+                format!("{}  ({} of {})  {}",
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    at + 1,
+                    shown.len(),
+                    badges.describe()),
+                egui::TextStyle::Body,
+                egui::Color32::from_gray(220),
+            );
+
+            // as many as fit, with the one being culled in the middle
+            let cell = FILMSTRIP_HEIGHT - 12.0;
+            let count = ((strip.width() / (cell + 4.0)) as usize).clamp(1, shown.len());
+            let first = at.saturating_sub(count / 2).min(shown.len() - count);
+            for (n, &other) in shown[first..first + count].iter().enumerate() {
+                let thumb = &mut gallery.thumbs[other];
+                let rect = egui::Rect::from_min_size(
+                    egui::pos2(strip.left() + 4.0 + n as f32 * (cell + 4.0), strip.top() + 6.0),
+                    egui::Vec2::splat(cell),
+                );
+                let fit = cell / thumb.dims.0.max(thumb.dims.1).max(1) as f32;
+                let size = egui::Vec2{
+                    x : thumb.dims.0 as f32 * fit,
+                    y : thumb.dims.1 as f32 * fit,
+                };
+
+                let button = ui.put(rect, thumb.data.button(ctx, size).selected(other == idx));
+                thumb.badges.paint(ui.painter(), button.rect);
+                if button.clicked() {
+                    jump = Some(other);
+                }
+            }
+        });
+
+    if jump.is_some() {
+        gallery.cull = jump;
+    }
+}
+
+// runs f on the gallery before the next frame, false if the screen was
+// replaced or isn't a gallery anymore
+fn patch_gallery<F>(model : &BufBufWrite<Model>, generation : Generation, f : F) -> bool
+where F : FnOnce(&mut Gallery) + Send + 'static
+{
+    model.patch_in(generation, |model| model.screen.gallery_mut(), f)
+}
+
+impl Gallery {
+    // albums are in the user's order, which can be changed by dragging once
+    // every thumbnail is there
+    fn reorderable(&self, search : &str) -> bool {
+        matches!(self.set, PhotoSet::Album(_)) && self.progress.done() && search.is_empty()
+    }
+
+    // the thumbs whose file name, caption, or place has search in it, any
+    // case
+    fn matching(&self, search : &str) -> Vec<usize> {
+        let search = search.trim().to_lowercase();
+        let matches = |thumb : &Thumb| {
+            let name = thumb.id.file_name().map(|n| n.to_string_lossy().to_lowercase());
+            let caption = thumb.caption.as_ref().map(|c| c.to_lowercase());
+            let location = thumb.meta.location.as_ref().map(|l| l.to_lowercase());
+            name.into_iter().chain(caption).chain(location).any(|text| text.contains(&search))
+        };
+
+        (0..self.thumbs.len())
+            .filter(|&idx| search.is_empty() || matches(&self.thumbs[idx]))
+            .collect()
+    }
+
+    // the countries the thumbs were taken in, and the places in each, with
+    // how many thumbs are in them. the most photographed first
+    fn places(&self) -> Vec<(String, usize, Vec<(String, usize)>)> {
+        let mut countries : HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+        for location in self.thumbs.iter().filter_map(|t| t.meta.location.as_deref()) {
+            // "City, Country", see Geocoder::lookup
+            let country = location.rsplit(", ").next().unwrap_or(location);
+            *countries.entry(country).or_default().entry(location).or_default() += 1;
+        }
+
+        let by_count = |a : &(String, usize), b : &(String, usize)| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0));
+        let mut places : Vec<_> = countries.into_iter()
+            .map(|(country, places)| {
+                let mut places : Vec<(String, usize)> = places.into_iter()
+                    .map(|(place, count)| (place.to_string(), count))
+                    .collect();
+                places.sort_by(by_count);
+                let count = places.iter().map(|(_, count)| count).sum();
+                (country.to_string(), count, places)
+            })
+            .collect();
+        places.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        places
+    }
+
+    // the show_* methods return what was done to which thumbnail, and only
+    // show the ones matching search
+
+    fn show_grid(
+        &mut self,
+        ctx : &mut RenderCtx,
+        ui : &mut egui::Ui,
+        thumb_size : f32,
+        search : &str) -> Option<ThumbAction>
+    {
+        let spacing = ui.spacing().item_spacing;
+        let padding = ui.spacing().button_padding;
+        let cell = egui::Vec2{
+            x : thumb_size + 2.0 * padding.x,
+            y : thumb_size + 2.0 * padding.y,
+        };
+
+        let ncols = ((ui.available_width() + spacing.x) / (cell.x + spacing.x))
+            .floor()
+            .max(1.0) as usize;
+        let shown = self.matching(search);
+        let nrows = (shown.len() + ncols - 1) / ncols;
+
+        let mut action = None;
+        let mut drag = DragReorder::default();
+        let reorderable = self.reorderable(search);
+
+        egui::ScrollArea::auto_sized().show_rows(ui, cell.y, nrows, |ui, rng| {
+            let start = rng.start * ncols;
+            let end = (rng.end * ncols).min(shown.len());
+
+            for row in shown[start..end].chunks(ncols) {
+                ui.horizontal(|ui| {
+                    for &idx in row {
+                        let thumb = &mut self.thumbs[idx];
+                        let button = ui.add(thumb.data.button(
+                            ctx,
+                            egui::Vec2{
+                                x : thumb_size,
+                                y : thumb_size,
+                            }
+                        ).selected(thumb.selected));
+
+                        if reorderable {
+                            drag.track(&button, idx);
+                        }
+                        action = action.or(ThumbAction::new(ctx, button, idx, thumb));
+                    }
+                });
+            }
+        });
+
+        action.or(drag.finish())
+    }
+
+    fn show_justified(
+        &mut self,
+        ctx : &mut RenderCtx,
+        ui : &mut egui::Ui,
+        row_height : f32,
+        search : &str) -> Option<ThumbAction>
+    {
+        let spacing = ui.spacing().item_spacing;
+        let padding = ui.spacing().button_padding;
+        let width = ui.available_width();
+
+        let shown = self.matching(search);
+        let rows = justify_rows(
+            shown.iter().map(|&idx| self.thumbs[idx].aspect()),
+            width,
+            row_height,
+            spacing.x + 2.0 * padding.x,
+        );
+
+        let total_height = rows.last().map_or(0.0, |r| r.y + r.height + 2.0 * padding.y);
+
+        let mut action = None;
+        let mut drag = DragReorder::default();
+        let reorderable = self.reorderable(search);
+
+        egui::ScrollArea::auto_sized().show_viewport(ui, |ui, viewport| {
+            ui.set_height(total_height);
+            ui.set_width(width);
+
+            let origin = ui.max_rect().min;
+
+            for row in rows.iter() {
+                let row_bottom = row.y + row.height + 2.0 * padding.y;
+                if row_bottom < viewport.min.y || row.y > viewport.max.y {
+                    continue
+                }
+
+                let mut x = 0.0;
+                for idx in row.items.clone().map(|item| shown[item]) {
+                    let thumb = &mut self.thumbs[idx];
+                    let size = egui::Vec2{
+                        x : row.height * thumb.aspect(),
+                        y : row.height,
+                    };
+
+                    let rect = egui::Rect::from_min_size(
+                        origin + egui::Vec2{ x, y : row.y },
+                        size + 2.0 * padding,
+                    );
+
+                    let button = ui.put(
+                        rect,
+                        thumb.data.button(ctx, size).selected(thumb.selected),
+                    );
+
+                    if reorderable {
+                        drag.track(&button, idx);
+                    }
+                    action = action.or(ThumbAction::new(ctx, button, idx, thumb));
+
+                    x += rect.width() + spacing.x;
+                }
+            }
+        });
+
+        action.or(drag.finish())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ThumbAction {
+    // left click
+    Open(usize),
+    // ctrl or cmd and click, adds it to the selection or takes it out
+    Select(usize),
+    // right click
+    Manage(usize),
+    // dragged from onto to
+    Reorder{
+        from : usize,
+        to : usize,
+    },
+    // space, on the thumbnail with keyboard focus or, when nothing has it,
+    // the one under the pointer
+    QuickLook(usize),
+}
+
+// a thumbnail dropped on another one, over one frame of thumbnails
+#[derive(Debug, Default)]
+struct DragReorder {
+    from : Option<usize>,
+    to : Option<usize>,
+}
+
+impl DragReorder {
+    fn track(&mut self, response : &egui::Response, idx : usize) {
+        let response = response.interact(egui::Sense::drag());
+        if response.drag_released() {
+            self.from = Some(idx);
+        }
+
+        let pointer = response.ctx.input().pointer.interact_pos();
+        if !pointer.map_or(false, |pos| response.rect.contains(pos)) {
+            return
+        }
+
+        self.to = Some(idx);
+
+        // where the dragged thumbnail would go
+        if response.ctx.memory().is_anything_being_dragged() && !response.dragged() {
+            let rect = response.rect;
+            let painter = response.ctx.layer_painter(response.layer_id);
+            let stroke = (3.0, egui::Color32::LIGHT_BLUE);
+            painter.line_segment([rect.left_top(), rect.left_bottom()], stroke);
+        }
+    }
+
+    fn finish(self) -> Option<ThumbAction> {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from != to => Some(ThumbAction::Reorder{from, to}),
+            _ => None,
+        }
+    }
+}
+
+impl ThumbAction {
+    fn new(ctx : &mut RenderCtx, response : egui::Response, idx : usize, thumb : &Thumb) -> Option<Self> {
+        // by file name, for ui tests
+        if let Some(name) = thumb.id.file_name() {
+            ctx.name_widget(&name.to_string_lossy(), response.rect);
+        }
+
+        let painter = response.ctx.layer_painter(response.layer_id);
+        thumb.badges.paint(&painter, response.rect);
+
+        // before clicked, which space is for a focused button
+        let space = response.ctx.input().key_pressed(egui::Key::Space);
+        let pointed = response.hovered() && !response.ctx.wants_keyboard_input();
+        let quick_look = space && (response.has_focus() || pointed);
+
+        let response = response.on_hover_text(thumb.id.display());
+
+        if quick_look {
+            Some(ThumbAction::QuickLook(idx))
+        } else if response.clicked() && response.ctx.input().modifiers.command {
+            Some(ThumbAction::Select(idx))
+        } else if response.clicked() {
+            Some(ThumbAction::Open(idx))
+        } else if response.secondary_clicked() {
+            Some(ThumbAction::Manage(idx))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum GroupBy {
+    None,
+    Day,
+    Month,
+    Year,
+}
+
+impl GroupBy {
+    // thumbs with the same key are in the same section, None is for
+    // photos without a date
+    fn key(&self, date : Option<DateTime>) -> Option<(i32, u32, u32)> {
+        date.map(|d| match self {
+            GroupBy::None => (0, 0, 0),
+            GroupBy::Day => (d.year, d.month, d.day),
+            GroupBy::Month => (d.year, d.month, 0),
+            GroupBy::Year => (d.year, 0, 0),
+        })
+    }
+
+    fn label(&self, date : Option<DateTime>) -> String {
+        match (self, date) {
+            (_, None) => "Unknown date".to_string(),
+            (GroupBy::None, _) => String::new(),
+            (GroupBy::Day, Some(d)) => format!("{} {}, {}", d.month_name(), d.day, d.year),
+            (GroupBy::Month, Some(d)) => format!("{} {}", d.month_name(), d.year),
+            (GroupBy::Year, Some(d)) => format!("{}", d.year),
+        }
+    }
+}
+
+struct Section {
+    label : String,
+    year : Option<i32>,
+    // range into the sorted order of thumbs
+    items : std::ops::Range<usize>,
+    y : f32,
+}
+
+impl Gallery {
+    fn years(&self) -> Vec<i32> {
+        let mut years : Vec<i32> = self.thumbs.iter()
+            .filter_map(|t| t.meta.date.map(|d| d.year))
+            .collect();
+
+        years.sort_unstable_by(|a, b| b.cmp(a));
+        years.dedup();
+        years
+    }
+
+    // a grid with a header for each date, newest first. Scrolls to the
+    // first section of scroll_to_year if it is set.
+    fn show_grouped(
+        &mut self,
+        ctx : &mut RenderCtx,
+        ui : &mut egui::Ui,
+        thumb_size : f32,
+        group_by : GroupBy,
+        scroll_to_year : &mut Option<i32>,
+        search : &str) -> Option<ThumbAction>
+    {
+        let spacing = ui.spacing().item_spacing;
+        let padding = ui.spacing().button_padding;
+        let header_height = ui.fonts().row_height(egui::TextStyle::Heading) + spacing.y;
+        let cell = egui::Vec2{
+            x : thumb_size + 2.0 * padding.x,
+            y : thumb_size + 2.0 * padding.y,
+        };
+
+        let ncols = ((ui.available_width() + spacing.x) / (cell.x + spacing.x))
+            .floor()
+            .max(1.0) as usize;
+
+        // newest first, None sorts after every date
+        let mut order = self.matching(search);
+        order.sort_by_key(|&idx| std::cmp::Reverse(self.thumbs[idx].meta.date));
+
+        let mut sections : Vec<Section> = Vec::new();
+        let mut y = 0.0;
+        let mut start = 0;
+        while start < order.len() {
+            let date = self.thumbs[order[start]].meta.date;
+            let key = group_by.key(date);
+
+            let mut end = start + 1;
+            while end < order.len() && group_by.key(self.thumbs[order[end]].meta.date) == key {
+                end += 1;
+            }
+
+            sections.push(Section{
+                label : group_by.label(date),
+                year : date.map(|d| d.year),
+                items : start..end,
+                y,
+            });
+
+            let nrows = (end - start + ncols - 1) / ncols;
+            y += header_height + nrows as f32 * (cell.y + spacing.y);
+            start = end;
+        }
+
+        let mut action = None;
+
+        egui::ScrollArea::auto_sized().show_viewport(ui, |ui, viewport| {
+            ui.set_height(y);
+
+            let origin = ui.max_rect().min;
+            let width = ui.available_width();
+
+            for section in sections.iter() {
+                let header_rect = egui::Rect::from_min_size(
+                    origin + egui::Vec2{ x : 0.0, y : section.y },
+                    egui::Vec2{ x : width, y : header_height },
+                );
+
+                let scroll_here = scroll_to_year.is_some() && *scroll_to_year == section.year;
+                let visible = section.y + header_height >= viewport.min.y &&
+                    section.y <= viewport.max.y;
+
+                if visible || scroll_here {
+                    let header = ui.put(header_rect, egui::Label::new(&section.label).heading());
+                    if scroll_here {
+                        header.scroll_to_me(egui::Align::TOP);
+                        *scroll_to_year = None;
+                    }
+                }
+
+                let rows_y = section.y + header_height;
+                let items = &order[section.items.clone()];
+                for (row_idx, row) in items.chunks(ncols).enumerate() {
+                    let row_y = rows_y + row_idx as f32 * (cell.y + spacing.y);
+                    if row_y + cell.y < viewport.min.y || row_y > viewport.max.y {
+                        continue
+                    }
+
+                    for (col_idx, &idx) in row.iter().enumerate() {
+                        let rect = egui::Rect::from_min_size(
+                            origin + egui::Vec2{
+                                x : col_idx as f32 * (cell.x + spacing.x),
+                                y : row_y,
+                            },
+                            cell,
+                        );
+
+                        let thumb = &mut self.thumbs[idx];
+                        let button = ui.put(rect, thumb.data.button(
+                            ctx,
+                            egui::Vec2{
+                                x : thumb_size,
+                                y : thumb_size,
+                            }
+                        ).selected(thumb.selected));
+
+                        action = action.or(ThumbAction::new(ctx, button, idx, thumb));
+                    }
+                }
+            }
+
+            // keep the header of the section at the top of the view visible
+            let current = sections.iter()
+                .take_while(|s| s.y <= viewport.min.y)
+                .last();
+
+            if let Some(section) = current {
+                if section.y < viewport.min.y {
+                    let rect = egui::Rect::from_min_size(
+                        origin + egui::Vec2{ x : 0.0, y : viewport.min.y },
+                        egui::Vec2{ x : width, y : header_height },
+                    );
+
+                    let painter = ui.painter();
+                    painter.rect_filled(rect, 0.0, ui.visuals().window_fill());
+                    painter.text(
+                        rect.left_top(),
+                        egui::Align2::LEFT_TOP,
+                        &section.label,
+                        egui::TextStyle::Heading,
+                        ui.visuals().text_color(),
+                    );
+                }
+            }
+        });
+
+        action
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum GalleryLayout {
+    // uniform square cells
+    Grid,
+    // rows of a fixed height which keep the aspect ratio of each photo
+    Justified,
+}
+
+struct JustifiedRow {
+    items : std::ops::Range<usize>,
+    y : f32,
+    height : f32,
+}
+
+// packs items of the given aspect ratios into rows that fill width, each row
+// is scaled from target_height so that it fits exactly. The last row is left
+// at target_height.
+fn justify_rows<I>(aspects : I, width : f32, target_height : f32, gap : f32) -> Vec<JustifiedRow>
+where I : Iterator<Item = f32>
+{
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut row_width = 0.0;
+    let mut y = 0.0;
+    let mut idx = 0;
+
+    for aspect in aspects {
+        row_width += target_height * aspect;
+        idx += 1;
+
+        let gaps = gap * (idx - start - 1) as f32;
+        if row_width + gaps >= width {
+            let height = target_height * (width - gaps) / row_width;
+            rows.push(JustifiedRow{
+                items : start..idx,
+                y,
+                height,
+            });
+
+            y += height + gap;
+            start = idx;
+            row_width = 0.0;
+        }
+    }
+
+    if start < idx {
+        rows.push(JustifiedRow{
+            items : start..idx,
+            y,
+            height : target_height,
+        });
+    }
+
+    rows
+}
+
+// thumbnails are decoded in steps of this size so that the slider doesn't
+// decode them again on every change
+const THUMB_RES_STEP : f32 = 128.0;
+
+// size is in egui's points, thumbnails are decoded in physical pixels so
+// they're sharp on HiDPI displays
+fn thumb_res(size : f32, scale_factor : f32) -> f32 {
+    (size * scale_factor / THUMB_RES_STEP).ceil().max(1.0) * THUMB_RES_STEP
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum PhotoSet {
+    // max_depth is how many levels of subfolders to scan
+    Folder{
+        path : String,
+        max_depth : usize,
+    },
+    List(Vec<String>),
+    // by name, in the album's order
+    Album(String),
+    // a saved search by name, see query
+    Smart(String),
+}
+
+// rendered pixels headed for a worker, Debug skips the pixels
+struct Pixels(image::RgbaImage);
+
+impl std::fmt::Debug for Pixels {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (width, height) = self.0.dimensions();
+        write!(f, "Pixels({}x{})", width, height)
+    }
+}
+
+#[derive(Debug)]
+enum Msg {
+    // Rename to OpenPhoto/OpenSingle/OpenEditor
+    Open{
+        path : PathBuf,
+    },
+    // Open without the decode limits
+    OpenAnyway{
+        path : PathBuf,
+    },
+    // Open once it's downloaded
+    OpenUrl{
+        url : String,
+    },
+    // downloads an offline copy again and opens it, see Photos::offline
+    Refetch{
+        path : PathBuf,
+    },
+    // OpenUrl of the link in a .url or .desktop file
+    OpenShortcut{
+        path : PathBuf,
+    },
+    // TODO: when the database is implemented
+    // this should be an enum:
+    //  enum PhotoSet {
+    //      Folder(String), // folder not in database
+    //      Album(u32), // an album in the database
+    //      Selection(Vec<u32>), // a selection of images in the database
+    //  }
+    OpenSet{
+        set : PhotoSet,
+        thumb_res : f32,
+    },
+    OpenImport{
+        source : PathBuf,
+        thumb_res : f32,
+    },
+    // two photos side by side with the same pan and zoom
+    OpenCompare{
+        paths : [PathBuf; 2],
+    },
+    // a numbered sequence as a flipbook in the photo screen
+    OpenSequence{
+        frames : Vec<PathBuf>,
+    },
+    // a bigger image than the thumbnail for the gallery's quick look
+    LoadPreview{
+        path : PathBuf,
+    },
+    // the current gallery's thumbnails decoded again at thumb_res, in place
+    RescaleThumbs{
+        thumb_res : f32,
+    },
+    // an exposure bracket fused into one photo next to its first frame
+    MergeBracket{
+        frames : Vec<PathBuf>,
+    },
+    // looks for duplicates among every photo of the library, see
+    // Library::catalog, and the open gallery
+    FindDuplicates{
+        thumb_res : f32,
+    },
+    Import{
+        paths : Vec<PathBuf>,
+        // the folder they're imported from
+        source : PathBuf,
+        dest : PathBuf,
+        template : String,
+    },
+    Trash{
+        path : PathBuf,
+    },
+    Rename{
+        from : PathBuf,
+        to : PathBuf,
+    },
+    Move{
+        from : PathBuf,
+        to_dir : PathBuf,
+    },
+    SaveEdits{
+        path : PathBuf,
+        history : EditHistory,
+    },
+    SaveFolderView{
+        path : PathBuf,
+        view : FolderView,
+    },
+    // a culling decision, see show_cull
+    Mark{
+        path : PathBuf,
+        mark : Mark,
+    },
+    // an empty caption removes it
+    SaveCaption{
+        path : PathBuf,
+        caption : String,
+    },
+    // makes the album if there isn't one by that name, photos already in
+    // it stay where they are
+    AddToAlbum{
+        name : String,
+        photos : Vec<PathBuf>,
+    },
+    RemoveFromAlbum{
+        name : String,
+        photos : Vec<PathBuf>,
+    },
+    // replaces the smart album by that name, if there is one
+    SaveSmartAlbum{
+        name : String,
+        query : String,
+    },
+    // the album, not its photos, or a smart album
+    DeleteAlbum{
+        name : String,
+    },
+    SetAlbumCover{
+        name : String,
+        path : PathBuf,
+    },
+    OpenAlbums{
+        thumb_res : f32,
+    },
+    // what was done to which files, from the library
+    OpenOperations,
+    // the photos listed in a csv, json, or m3u file, as a gallery
+    OpenManifest{
+        path : PathBuf,
+        thumb_res : f32,
+    },
+    ExportManifest{
+        entries : Vec<manifest::Entry>,
+        dest : PathBuf,
+    },
+    // moves from to where to is
+    ReorderAlbum{
+        name : String,
+        from : PathBuf,
+        to : PathBuf,
+    },
+    // the statistics card of the photo at path, from a render of it as of
+    // its edit-th recorded edit
+    ComputeStats{
+        path : PathBuf,
+        image : Pixels,
+        edit : u64,
+    },
+    // a deep zoom pyramid of the edited photo at path, in dest
+    ExportDzi{
+        path : PathBuf,
+        image : Pixels,
+        dest : PathBuf,
+    },
+    // a rendering of the photo at path, saved to dest in the format of its
+    // extension. caption writes the photo's caption as xmp with it
+    ExportImage{
+        path : PathBuf,
+        image : Pixels,
+        dest : PathBuf,
+        caption : bool,
+    },
+    // pages of thumbnails saved as a PDF
+    ExportContactSheet{
+        title : String,
+        cells : Vec<Cell>,
+        layout : SheetLayout,
+        dest : PathBuf,
+    },
+    // the photo with its edits, on the OS clipboard
+    CopyImage{
+        image : Pixels,
+    },
+    // opens an image on the clipboard, saved in the data dir first, or a
+    // photo or folder named there
+    Paste{
+        thumb_res : f32,
+    },
+    // the photo with its edits, opened in another program and watched
+    EditExternally{
+        path : PathBuf,
+        image : Pixels,
+        program : String,
+        format : EditFormat,
+    },
+    // the other program saved the photo, or was done with it
+    ExternalEdit{
+        session : Session,
+        saved : bool,
+    },
+    // the view at the monitor's size, saved in dir and made the desktop's
+    // wallpaper
+    SetWallpaper{
+        path : PathBuf,
+        image : Pixels,
+        dir : PathBuf,
+    },
+    // saved as a PDF at dest, or printed without one
+    Print{
+        path : PathBuf,
+        image : Pixels,
+        layout : PrintLayout,
+        dest : Option<PathBuf>,
+    },
+    // photos1 export, quits once every photo is done
+    BatchExport(BatchExport),
+    // a rendering of the photo at path fit to one of PRESETS, saved in the
+    // dest folder. left is set for batches
+    ExportPreset{
+        path : PathBuf,
+        image : Pixels,
+        // the PRESETS index, None for the photo's own size
+        preset : Option<usize>,
+        fit : Fit,
+        output : Output,
+        dest : PathBuf,
+        caption : bool,
+        left : Option<Arc<AtomicUsize>>,
+    },
+    // decodes the photos for the render thread to export, see
+    // PendingExport
+    ExportPresets{
+        paths : Vec<PathBuf>,
+        preset : Option<usize>,
+        fit : Fit,
+        output : Output,
+        dest : PathBuf,
+        caption : bool,
+    },
+}
+
+// the longer side flipbook frames are decoded at, every frame is kept in
+// memory
+const FRAME_SIZE : u32 = 1280;
+
+struct Frame {
+    data : PhotoData,
+    width : u32,
+}
+
+// plays a sequence in place of the photo, with the photo's effects
+struct Flipbook {
+    frames : Vec<PathBuf>,
+    // filled in by a job as frames are decoded
+    loaded : Vec<Option<Frame>>,
+    // the width of the photo, frames are scaled to match it
+    photo_width : u32,
+    current : usize,
+    fps : f32,
+    playing : bool,
+    looping : bool,
+    // how long the current frame has been playing
+    shown_for : Duration,
+}
+
+impl Flipbook {
+    fn new(frames : Vec<PathBuf>, photo_width : u32) -> Self {
+        Flipbook {
+            loaded : frames.iter().map(|_| None).collect(),
+            frames,
+            photo_width,
+            current : 0,
+            fps : 24.0,
+            playing : true,
+            looping : true,
+            shown_for : Duration::ZERO,
+        }
+    }
+
+    fn loaded_count(&self) -> usize {
+        self.loaded.iter().filter(|f| f.is_some()).count()
+    }
+
+    // moves to the next frame when it's time, waiting on frames which
+    // haven't loaded yet
+    fn advance(&mut self, dt : Duration) {
+        if !self.playing {
+            return
+        }
+
+        self.shown_for += dt;
+        let period = Duration::from_secs_f32(1.0 / self.fps);
+        if self.shown_for < period {
+            return
+        }
+
+        let next = match self.current + 1 {
+            next if next < self.frames.len() => next,
+            _ if self.looping => 0,
+            _ => {
+                self.playing = false;
+                return
+            },
+        };
+
+        if self.loaded[next].is_some() {
+            self.current = next;
+            // keep the remainder so the rate doesn't drift with the frames,
+            // but don't catch up after a stall
+            self.shown_for = (self.shown_for - period).min(period);
+        }
+    }
+
+    // the image for the current frame and how much to scale it by to be the
+    // size of the photo, None until it's loaded
+    fn image_id(&mut self, ctx : &mut RenderCtx) -> Option<(ImageId, f32)> {
+        let photo_width = self.photo_width;
+        let frame = self.loaded[self.current].as_mut()?;
+        let scale = photo_width as f32 / frame.width.max(1) as f32;
+        Some((frame.data.get_image_id(ctx)?, scale))
+    }
+
+    fn image_ids(&self) -> impl Iterator<Item = ImageId> + '_ {
+        self.loaded.iter().filter_map(|f| match f {
+            Some(Frame{data : PhotoData::GPU(img_id), ..}) => Some(*img_id),
+            _ => None,
+        })
+    }
+}
+
+impl std::fmt::Debug for Flipbook {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Flipbook")
+            .field("frames", &self.frames.len())
+            .field("loaded", &self.loaded_count())
+            .field("current", &self.current)
+            .finish_non_exhaustive()
+    }
+}
+
+// where the photo screen's strip of the gallery it was opened from is
+// scrolled to. the thumbnails are the gallery's, which is kept on the back
+// stack with their textures, see App::swap. it's handed from photo to photo
+// while going through them
+#[derive(Debug, Default)]
+struct Filmstrip {
+    // in points
+    offset : f32,
+    // scrolled to the open photo, which is done once so going through the
+    // strip doesn't move it
+    centered : bool,
+}
+
+impl Filmstrip {
+    // the thumbnails in a row with the open one selected, scrolled by the
+    // wheel over them. returns the one clicked
+    fn show(
+        &mut self,
+        ctx : &mut RenderCtx,
+        ui : &mut egui::Ui,
+        thumbs : &mut [Thumb],
+        current : &Path) -> Option<PathBuf>
+    {
+        let padding = ui.spacing().button_padding;
+        let spacing = ui.spacing().item_spacing.x;
+        let height = FILMSTRIP_HEIGHT - 12.0;
+        let image_height = height - 2.0 * padding.y;
+        let width = |thumb : &Thumb| image_height * thumb.aspect() + 2.0 * padding.x;
+
+        let (rect, response) = ui.allocate_exact_size(
+            egui::Vec2{ x : ui.available_width(), y : height },
+            egui::Sense::hover(),
+        );
+
+        // from the start of the strip
+        let mut lefts = Vec::with_capacity(thumbs.len());
+        let mut x = 0.0;
+        for thumb in thumbs.iter() {
+            lefts.push(x);
+            x += width(thumb) + spacing;
+        }
+        let max_offset = (x - spacing - rect.width()).max(0.0);
+
+        if !self.centered {
+            if let Some(idx) = thumbs.iter().position(|thumb| thumb.id == current) {
+                self.offset = lefts[idx] + width(&thumbs[idx]) / 2.0 - rect.width() / 2.0;
+            }
+            self.centered = true;
+        }
+        if response.hovered() {
+            let delta = ui.input().scroll_delta;
+            self.offset -= delta.x + delta.y;
+        }
+        self.offset = self.offset.clamp(0.0, max_offset);
+
+        let mut strip = ui.child_ui(rect, *ui.layout());
+        strip.set_clip_rect(rect.intersect(ui.clip_rect()));
+
+        let mut clicked = None;
+        for (thumb, left) in thumbs.iter_mut().zip(lefts) {
+            let thumb_rect = egui::Rect::from_min_size(
+                egui::pos2(rect.left() + left - self.offset, rect.top()),
+                egui::Vec2{ x : width(thumb), y : height },
+            );
+            // not uploaded until they're scrolled to
+            if thumb_rect.right() < rect.left() || thumb_rect.left() > rect.right() {
+                continue
+            }
+
+            let size = thumb_rect.size() - 2.0 * padding;
+            let button = strip.put(thumb_rect, thumb.data.button(ctx, size).selected(thumb.id == current));
+            thumb.badges.paint(strip.painter(), button.rect);
+            if button.on_hover_text(thumb.id.display()).clicked() && thumb.id != current {
+                clicked = Some(thumb.id.clone());
+            }
+        }
+
+        clicked
+    }
+}
+
+#[derive(Debug)]
+struct PhotoScreen {
+    photo : Photo,
+    view_mat : Mat4,
+    flipbook : Option<Flipbook>,
+    // shown when the screen before is a gallery with the photo in it
+    filmstrip : Filmstrip,
+    // shows the color under the pointer, and a click samples it
+    eyedropper : bool,
+    // the photo is a copy from a remote or link that couldn't be reached
+    offline : bool,
+}
+
+impl PhotoScreen {
+    fn new(photo : Photo) -> Self {
+        PhotoScreen {
+            photo,
+            view_mat : Mat4::IDENTITY,
+            flipbook : None,
+            filmstrip : Default::default(),
+            eyedropper : false,
+            offline : false,
+        }
+    }
+
+    // the pixel of the photo under pos, in window pixels, when it's drawn
+    // with view_mat
+    fn pixel_at(&self, ctx : &RenderCtx<'_>, view_mat : &Mat4, pos : (f32, f32)) -> Option<(u32, u32)> {
+        let (dim_x, dim_y) = ctx.dimensions();
+        let (width, height) = self.photo.pixels.dimensions();
+
+        // from the middle of the window, y up, the photo is drawn 1:1 there
+        // at identity
+        let from_center = Vec3::new(pos.0 - dim_x / 2.0, dim_y / 2.0 - pos.1, 0.0);
+        let p = view_mat.inverse().transform_point3(from_center);
+
+        let x = p.x + width as f32 / 2.0;
+        let y = height as f32 / 2.0 - p.y;
+        if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+            return None
+        }
+
+        Some((x as u32, y as u32))
+    }
+
+    // the color under pos from pixels, not the edited photo on screen
+    fn sample_at(&self, ctx : &RenderCtx<'_>, view_mat : &Mat4, pos : (f32, f32)) -> Option<Sample> {
+        let (x, y) = self.pixel_at(ctx, view_mat, pos)?;
+        Some(Sample{
+            path : self.photo.id.clone(),
+            x,
+            y,
+            rgba : self.photo.pixels.get_pixel(x, y).0,
+        })
+    }
+
+    fn update_view(&mut self, ctx : &mut RenderCtx<'_>) -> Mat4 {
+        update_view(&mut self.view_mat, ctx, Viewport::window(ctx.dimensions()))
+    }
+}
+
+// two photos from the gallery's selection with the same pan and zoom, side
+// by side or one at a time in the same place
+#[derive(Debug)]
+struct CompareScreen {
+    photos : [Photo; 2],
+    view_mat : Mat4,
+    side_by_side : bool,
+    // which is shown when they aren't side by side
+    shown : usize,
+}
+
+impl CompareScreen {
+    fn new(a : Photo, b : Photo) -> Self {
+        CompareScreen{
+            photos : [a, b],
+            view_mat : Mat4::IDENTITY,
+            side_by_side : true,
+            shown : 0,
+        }
+    }
+
+    // where each photo is drawn, None for the hidden one
+    fn viewports(&self, dims : (f32, f32)) -> [Option<Viewport>; 2] {
+        if self.side_by_side {
+            let columns = Viewport::columns(dims, 2);
+            [Some(columns[0]), Some(columns[1])]
+        } else {
+            let mut viewports = [None, None];
+            viewports[self.shown] = Some(Viewport::window(dims));
+            viewports
+        }
+    }
+}
+
+// pans and zooms view_mat with the pointer input, for a photo drawn into
+// viewport. returns what to draw with, which includes a drag that's still
+// going
+fn update_view(view_mat : &mut Mat4, ctx : &RenderCtx<'_>, viewport : Viewport) -> Mat4 {
+    let (dim_x, dim_y) = viewport.dimensions();
+
+    if ctx.resized() {
+        // keep the photo on screen when the window shrinks, like when
+        // the display is rotated
+        let translation = &mut view_mat.w_axis;
+        translation.x = translation.x.clamp(-dim_x / 2.0, dim_x / 2.0);
+        translation.y = translation.y.clamp(-dim_y / 2.0, dim_y / 2.0);
+    }
+
+    let scale = view_mat.transform_vector3(Vec3::new(1.0, 0.0, 0.0)).length();
+    let mut new_scale = scale;
+
+    match ctx.background_input().map(|i| (i.modifiers, i.scroll_delta)) {
+        Some((modifiers, (dx, dy))) => {
+
+            if modifiers.shift() {
+                // zoom
+                new_scale *= 1.0 - dy.clamp(-10.0, 10.0) / 30.0;
+            } else {
+                // pan
+                let pan = Mat4::from_scale_rotation_translation(
+                    Vec3::ONE,
+                    Quat::from_rotation_z(0.0),
+                    Vec3::new(dx, dy, 0.0)
+                );
+                *view_mat = pan.mul_mat4(view_mat);
+            }
+
+        },
+        _ => {},
+    }
+
+    new_scale = new_scale.clamp(0.125, 8.0);
+    if scale != new_scale {
+        // around the pointer, from the viewport's middle
+        let (origin_x, origin_y) = ctx.background_input()
+            .map_or((0.0, 0.0), |i| {
+                let (px, py) = (i.pointer.0 - viewport.x, i.pointer.1 - viewport.y);
+                (dim_x/2.0 - px, py - dim_y/2.0)
+            });
+
+        let to = Mat4::from_scale_rotation_translation(
+            Vec3::ONE,
+            Quat::from_rotation_z(0.0),
+            Vec3::new(origin_x, origin_y, 0.0)
+        );
+
+        let fro = Mat4::from_scale_rotation_translation(
+            Vec3::ONE,
+            Quat::from_rotation_z(0.0),
+            Vec3::new(-origin_x, -origin_y, 0.0)
+        );
+
+        *view_mat = fro
+            .mul_mat4(&Mat4::from_scale(Vec3::ONE * (new_scale / scale)))
+            .mul_mat4(&to)
+            .mul_mat4(view_mat);
+    }
+
+    let drag_delta = ctx
+        .background_input()
+        .map(|i| i.drag_delta())
+        .flatten();
+
+    match drag_delta {
+        Some((dx, dy, released)) => {
+            let pan = Mat4::from_scale_rotation_translation(
+                Vec3::ONE,
+                Quat::from_rotation_z(0.0),
+                Vec3::new(dx, dy, 0.0)
+            );
+
+            if released {
+                *view_mat = pan.mul_mat4(view_mat);
+                *view_mat
+            } else {
+                pan.mul_mat4(view_mat)
+            }
+        },
+        _ => *view_mat,
+    }
+}
+
+
+#[derive(Debug)]
+enum Screen {
+    Empty,
+    Gallery(Gallery),
+    Photo(PhotoScreen),
+    Compare(CompareScreen),
+    Import(ImportScreen),
+    Duplicates(DuplicatesScreen),
+    Albums(AlbumsScreen),
+    Operations(OperationsScreen),
+}
+
+#[derive(Debug)]
+struct AlbumTile {
+    name : String,
+    count : usize,
+    created : Option<DateTime>,
+    // None until it's loaded, or if the album is empty
+    cover : Option<Thumb>,
+    // the search, for smart albums
+    query : Option<String>,
+}
+
+// every album in the library, by name
+#[derive(Debug)]
+struct AlbumsScreen {
+    tiles : Vec<AlbumTile>,
+    // the album delete was clicked for, to be sure
+    confirm_delete : Option<String>,
+}
+
+// the library's operations, newest first
+#[derive(Debug)]
+struct OperationsScreen {
+    operations : Vec<Operation>,
+    // only ones of this kind, all of them if None
+    kind : Option<OperationKind>,
+    failed_only : bool,
+}
+
+// groups of similar photos, the selected ones get trashed
+#[derive(Debug)]
+struct DuplicatesScreen {
+    groups : Vec<Vec<Thumb>>,
+    // still loading thumbnails
+    loading : bool,
+    // asking before the marked ones are trashed
+    confirm_trash : bool,
+}
+
+// copies photos from a camera or card into a folder per date
+#[derive(Debug)]
+struct ImportScreen {
+    gallery : Gallery,
+    source : PathBuf,
+    dest : String,
+    // YYYY, MM, and DD are replaced with the capture date
+    template : String,
+    progress : Option<ImportProgress>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ImportProgress {
+    total : usize,
+    copied : usize,
+    duplicates : usize,
+    failed : usize,
+}
+
+impl ImportProgress {
+    fn done(&self) -> bool {
+        self.copied + self.duplicates + self.failed >= self.total
+    }
+}
+
+impl std::fmt::Display for ImportProgress {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "imported {} of {}", fmt_count(self.copied), fmt_count(self.total))?;
+
+        if self.duplicates > 0 {
+            write!(f, ", {} duplicates skipped", fmt_count(self.duplicates))?;
+        }
+
+        if self.failed > 0 {
+            write!(f, ", {} failed", fmt_count(self.failed))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn expand_template(template : &str, date : Option<DateTime>) -> PathBuf {
+    match date {
+        Some(date) => template
+            .replace("YYYY", &format!("{:04}", date.year))
+            .replace("MM", &format!("{:02}", date.month))
+            .replace("DD", &format!("{:02}", date.day))
+            .into(),
+        None => "undated".into(),
+    }
+}
+
+fn hash_bytes(byt : &[u8]) -> u64 {
+    use std::hash::Hasher;
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hasher.write(byt);
+    hasher.finish()
+}
+
+// hashes of the files already in dir, empty if it doesn't exist yet
+async fn hash_dir(dir : &Path) -> Result<HashSet<u64>> {
+    let mut hashes = HashSet::new();
+
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(hashes),
+        Err(err) => return Err(err.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            hashes.insert(hash_bytes(&tokio::fs::read(entry.path()).await?));
+        }
+    }
+
+    Ok(hashes)
+}
+
+// a path in dir named like file_name which doesn't exist yet
+async fn free_path(dir : &Path, file_name : &std::ffi::OsStr) -> PathBuf {
+    let path = dir.join(file_name);
+    if tokio::fs::metadata(&path).await.is_err() {
+        return path
+    }
+
+    let name = Path::new(file_name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = name.extension().map(|e| e.to_string_lossy());
+
+    for n in 1.. {
+        let candidate = match &ext {
+            Some(ext) => dir.join(format!("{}-{}.{}", stem, n, ext)),
+            None => dir.join(format!("{}-{}", stem, n)),
+        };
+
+        if tokio::fs::metadata(&candidate).await.is_err() {
+            return candidate
+        }
+    }
+
+    unreachable!()
+}
+
+fn patch_import<F>(model : &BufBufWrite<Model>, generation : Generation, f : F) -> bool
+where F : FnOnce(&mut ImportScreen) + Send + 'static
+{
+    model.patch_in(generation, |model| model.screen.import_mut(), f)
+}
+
+// for BufBufWrite::patch_in and with, which stop the screen's workers when
+// it has changed to another kind
+impl Screen {
+    fn gallery_mut(&mut self) -> Option<&mut Gallery> {
+        match self {
+            Screen::Gallery(v) => Some(v),
+            Screen::Import(v) => Some(&mut v.gallery),
+            _ => None,
+        }
+    }
+
+    fn import_mut(&mut self) -> Option<&mut ImportScreen> {
+        match self {
+            Screen::Import(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn photo_mut(&mut self) -> Option<&mut Photo> {
+        match self {
+            Screen::Photo(v) => Some(&mut v.photo),
+            _ => None,
+        }
+    }
+
+    fn flipbook_mut(&mut self) -> Option<&mut Flipbook> {
+        match self {
+            Screen::Photo(PhotoScreen{flipbook : Some(flipbook), ..}) => Some(flipbook),
+            _ => None,
+        }
+    }
+
+    fn duplicates_mut(&mut self) -> Option<&mut DuplicatesScreen> {
+        match self {
+            Screen::Duplicates(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn albums_mut(&mut self) -> Option<&mut AlbumsScreen> {
+        match self {
+            Screen::Albums(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+// screens kept to go back to, older ones are forgotten
+const BACK_KEPT : usize = 8;
+
+#[derive(Debug)]
+struct Model {
+    screen : Screen,
+    // the screens before this one, the last is the one just left, see
+    // App::swap
+    back : Vec<Screen>,
+    // textures of things removed from the screen without a swap, the
+    // render thread frees them
+    dropped_images : Vec<ImageId>,
+}
+
+// a photo over the decode limits and why, for the user to open anyway. in
+// its own slot so opening something else doesn't lose it
+type TooLarge = Option<(PathBuf, String)>;
+
+impl Model {
+    fn new(screen : Screen) -> Self {
+        Model {
+            screen,
+            back : Vec::new(),
+            dropped_images : Vec::new(),
+        }
+    }
+
+    // to the screen at depth in back, as it was left. the ones after it
+    // are forgotten
+    fn go_back(&mut self, depth : usize) {
+        if depth >= self.back.len() {
+            return
+        }
+
+        let screen = self.back.remove(depth);
+        for forgotten in self.back.drain(depth..) {
+            self.dropped_images.extend(forgotten.image_ids());
+        }
+        let left = std::mem::replace(&mut self.screen, screen);
+        self.dropped_images.extend(left.image_ids());
+    }
+
+    fn file_renamed(&mut self, from : &Path, to : &Path) {
+        for screen in std::iter::once(&mut self.screen).chain(self.back.iter_mut()) {
+            screen.file_renamed(from, to);
+        }
+    }
+
+    fn file_moved(&mut self, from : &Path, to : &Path) {
+        let dropped = &mut self.dropped_images;
+        for screen in std::iter::once(&mut self.screen).chain(self.back.iter_mut()) {
+            screen.file_moved(from, to, dropped);
+        }
+        self.back.retain(|screen| !matches!(screen, Screen::Empty));
+    }
+
+    fn file_removed(&mut self, path : &Path) {
+        let dropped = &mut self.dropped_images;
+        for screen in std::iter::once(&mut self.screen).chain(self.back.iter_mut()) {
+            screen.file_removed(path, dropped);
+        }
+        self.back.retain(|screen| !matches!(screen, Screen::Empty));
+    }
+}
+
+impl Screen {
+    // for the title and the breadcrumbs, None for nothing open or a list
+    // of photos
+    fn name(&self) -> Option<String> {
+        let name = |path : &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string())
+        };
+
+        match self {
+            Screen::Photo(photo_screen) => Some(name(&photo_screen.photo.id)),
+            Screen::Gallery(gallery) => match &gallery.set {
+                PhotoSet::Folder{path, ..} => Some(name(Path::new(path))),
+                PhotoSet::Album(album) |
+                PhotoSet::Smart(album) => Some(album.clone()),
+                PhotoSet::List(_) => None,
+            },
+            Screen::Compare(compare) => {
+                let [a, b] = &compare.photos;
+                Some(format!("{} and {}", name(&a.id), name(&b.id)))
+            },
+            Screen::Import(import) => Some(format!("importing {}", name(&import.source))),
+            Screen::Albums(_) => Some("albums".to_string()),
+            Screen::Duplicates(_) => Some("duplicates".to_string()),
+            Screen::Operations(_) => Some("operations".to_string()),
+            Screen::Empty => None,
+        }
+    }
+
+    // the textures it has, freed when it's replaced or forgotten
+    fn image_ids(&self) -> Vec<ImageId> {
+        let uploaded = |data : &PhotoData| match data {
+            PhotoData::GPU(img_id) => Some(*img_id),
+            _ => None,
+        };
+
+        match self {
+            Screen::Photo(photo_screen) => uploaded(&photo_screen.photo.data).into_iter()
+                .chain(photo_screen.flipbook.iter().flat_map(|f| f.image_ids()))
+                .collect(),
+            Screen::Compare(compare) => compare.photos.iter()
+                .filter_map(|photo| uploaded(&photo.data))
+                .collect(),
+            Screen::Gallery(gallery) |
+            Screen::Import(ImportScreen{gallery, ..}) => gallery.thumbs.iter()
+                .filter_map(|thumb| uploaded(&thumb.data))
+                .chain(gallery.previews.image_ids())
+                .collect(),
+            Screen::Duplicates(dupes) => dupes.groups.iter().flatten()
+                .filter_map(|thumb| uploaded(&thumb.data))
+                .collect(),
+            Screen::Albums(albums) => albums.tiles.iter()
+                .filter_map(|tile| tile.cover.as_ref())
+                .filter_map(|thumb| uploaded(&thumb.data))
+                .collect(),
+            Screen::Empty |
+            Screen::Operations(_) => Vec::new(),
+        }
+    }
+
+    fn file_renamed(&mut self, from : &Path, to : &Path) {
+        match self {
+            Screen::Gallery(gallery) => {
+                for thumb in gallery.thumbs.iter_mut().filter(|t| t.id == from) {
+                    thumb.id = to.to_path_buf();
+                }
+            },
+            Screen::Photo(photo_screen) if photo_screen.photo.id == from => {
+                photo_screen.photo.id = to.to_path_buf();
+            },
+            Screen::Compare(compare) => {
+                for photo in compare.photos.iter_mut().filter(|p| p.id == from) {
+                    photo.id = to.to_path_buf();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // moved out of the gallery's folder
+    fn file_moved(&mut self, from : &Path, to : &Path, dropped : &mut Vec<ImageId>) {
+        match self {
+            // albums follow their photos
+            Screen::Gallery(gallery) if !matches!(gallery.set, PhotoSet::Album(_) | PhotoSet::Smart(_)) => {
+                self.file_removed(from, dropped)
+            },
+            _ => self.file_renamed(from, to),
+        }
+    }
+
+    // the textures of what's taken out go to dropped
+    fn file_removed(&mut self, path : &Path, dropped : &mut Vec<ImageId>) {
+        match self {
+            Screen::Gallery(gallery) => {
+                let removed = gallery.thumbs.iter().position(|thumb| thumb.id == path);
+                gallery.thumbs.retain(|thumb| {
+                    if thumb.id != path {
+                        return true
+                    }
+
+                    if let PhotoData::GPU(img_id) = thumb.data {
+                        dropped.push(img_id);
+                    }
+
+                    false
+                });
+                // the indices moved, culling goes on from the next one
+                gallery.quick_look = None;
+                gallery.cull = gallery.cull.map(|idx| {
+                    let idx = match removed {
+                        Some(removed) if removed < idx => idx - 1,
+                        _ => idx,
+                    };
+                    idx.min(gallery.thumbs.len().saturating_sub(1))
+                });
+            },
+            Screen::Photo(photo_screen) if photo_screen.photo.id == path => {
+                dropped.extend(self.image_ids());
+                *self = Screen::Empty;
+            },
+            Screen::Compare(compare) if compare.photos.iter().any(|p| p.id == path) => {
+                dropped.extend(self.image_ids());
+                *self = Screen::Empty;
+            },
+            Screen::Duplicates(dupes) => {
+                for group in dupes.groups.iter_mut() {
+                    group.retain(|thumb| {
+                        if thumb.id != path {
+                            return true
+                        }
+
+                        if let PhotoData::GPU(img_id) = thumb.data {
+                            dropped.push(img_id);
+                        }
+
+                        false
+                    });
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+// the side button browsers go back with, which winit numbers after the
+// platform's own: x11's button 8, evdev's BTN_SIDE on wayland, XBUTTON1 on
+// windows, and the fourth button on macos
+fn is_back_button(button : glium::glutin::event::MouseButton) -> bool {
+    use glium::glutin::event::MouseButton::Other;
+
+    if cfg!(target_os = "windows") {
+        button == Other(1)
+    } else if cfg!(target_os = "macos") {
+        button == Other(3)
+    } else {
+        button == Other(8) || button == Other(0x113)
+    }
+}
+
+// rename, falling back to copy and delete when going across file systems
+async fn move_file(from : &Path, to : &Path) -> std::io::Result<()> {
+    if tokio::fs::metadata(to).await.is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ))
+    }
+
+    match tokio::fs::rename(from, to).await {
+        Err(err) if crosses_devices(&err) => {
+            tokio::fs::copy(from, to).await?;
+            tokio::fs::remove_file(from).await
+        },
+        res => res,
+    }
+}
+
+// what rename fails with when to is on another file system, EXDEV or
+// ERROR_NOT_SAME_DEVICE on windows. ErrorKind::CrossesDevices isn't stable
+// yet. anything else, like permissions, would fail the copy too or leave
+// two copies
+fn crosses_devices(err : &std::io::Error) -> bool {
+    let code = if cfg!(windows) { 17 } else { 18 };
+    err.raw_os_error() == Some(code)
+}
+
+
+// what the file dialog asks about before doing it
+#[derive(Debug, Clone)]
+enum FileAction {
+    Rename(PathBuf),
+    Move(PathBuf),
+    Trash,
+}
+
+// a name for the file in its folder, not a path which could put it
+// anywhere
+fn is_file_name(name : &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(std::path::Component::Normal(_))) &&
+        components.next().is_none() &&
+        !name.contains(std::path::is_separator)
+}
+
+// rename, move, or trash a single file
+#[derive(Debug)]
+struct FileDialog {
+    path : PathBuf,
+    rename_input : String,
+    move_input : String,
+    // asked about, done once it's confirmed
+    confirm : Option<FileAction>,
+    // names of the albums the photo is in
+    albums : Vec<String>,
+    // rating, label, and keywords
+    tags : Vec<String>,
+}
+
+impl FileDialog {
+    fn new(path : PathBuf, albums : Vec<String>, tags : Vec<String>) -> Self {
+        let rename_input = path.file_name()
+            .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        let move_input = path.parent()
+            .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+
+        FileDialog {
+            path,
+            rename_input,
+            move_input,
+            confirm : None,
+            albums,
+            tags,
+        }
+    }
+
+    // returns false when the dialog should close
+    fn show(&mut self, egui : &egui::CtxRef, msgs : &mut Vec<Msg>) -> bool {
+        let mut open = true;
+        let mut done = false;
+
+        egui::Window::new("Manage File")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(egui, |ui| {
+                ui.label(self.path.display().to_string());
+
+                if !self.tags.is_empty() {
+                    ui.label(self.tags.join(", "));
+                }
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.rename_input);
+                    let valid = is_file_name(&self.rename_input);
+                    if ui.add(egui::Button::new("Rename").enabled(valid)).clicked() {
+                        self.confirm = Some(FileAction::Rename(self.path.with_file_name(&self.rename_input)));
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.move_input);
+                    if ui.add(egui::Button::new("Move").enabled(!self.move_input.is_empty())).clicked() {
+                        self.confirm = Some(FileAction::Move(PathBuf::from(&self.move_input)));
+                    }
+                });
+
+                for name in self.albums.iter() {
+                    if ui.button(format!("Use as cover of {}", name)).clicked() {
+                        msgs.push(Msg::SetAlbumCover{
+                            name : name.clone(),
+                            path : self.path.clone(),
+                        });
+                        done = true;
+                    }
+                }
+
+                ui.separator();
+
+                let action = match &self.confirm {
+                    None => {
+                        if ui.button("Move to Trash").clicked() {
+                            self.confirm = Some(FileAction::Trash);
+                        }
+                        return
+                    },
+                    Some(action) => action,
+                };
+
+                let question = match action {
+                    FileAction::Rename(to) => format!("Rename this file to {}?", to.display()),
+                    FileAction::Move(to_dir) => format!("Move this file to {}?", to_dir.display()),
+                    FileAction::Trash => "Move this file to the trash?".to_string(),
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label(question);
+                    if ui.button("Yes").clicked() {
+                        let from = self.path.clone();
+                        msgs.push(match self.confirm.take() {
+                            Some(FileAction::Rename(to)) => Msg::Rename{from, to},
+                            Some(FileAction::Move(to_dir)) => Msg::Move{from, to_dir},
+                            _ => Msg::Trash{path : from},
+                        });
+                        done = true;
+                    }
+                    if ui.button("No").clicked() {
+                        self.confirm = None;
+                    }
+                });
+            });
+
+        open && !done
+    }
+}
+
+// radius of the blur over the photo while the window is unfocused, as a
+// fraction of the photo's size
+const PRIVACY_BLUR : f32 = 0.03;
+
+// how far the pointer can move, in pixels, for a press and release on the
+// photo to be a click rather than a pan
+const CLICK_SLOP : f32 = 4.0;
+
+// the app's half of the preferences window, the framework's are in
+// Settings::prefs. kept in Settings::ui under "prefs"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct Prefs {
+    // opened at startup and where Open starts
+    default_folder : String,
+    limits : DecodeLimits,
+    // the program for Edit Externally, empty for the system's default
+    external_editor : String,
+    external_format : EditFormat,
+    // for remote folders and links
+    bandwidth : Bandwidth,
+}
+
+impl Default for Prefs {
+    fn default() -> Self {
+        let pictures = std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join("Pictures"))
+            .unwrap_or_default();
+
+        Prefs {
+            default_folder : pictures.to_string_lossy().into_owned(),
+            limits : DecodeLimits::default(),
+            external_editor : String::new(),
+            external_format : EditFormat::default(),
+            bandwidth : Bandwidth::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LocalModel {
+    effects_render : EffectsShader,
+    peaking_render : PeakingShader,
+    captions : Captions,
+    captions_dialog : bool,
+    prefs : Prefs,
+    prefs_window : bool,
+    open_dialog : bool,
+    open_dialog_input : String,
+    open_dialog_depth : usize,
+    thumb_size : f32,
+    gallery_layout : GalleryLayout,
+    group_by : GroupBy,
+    scroll_to_year : Option<i32>,
+    // filters the gallery by file name, caption, and place
+    gallery_search : String,
+    // the gallery's photos by where they were taken, see map::show
+    map_view : bool,
+    fullscreen : Option<usize>,
+    // the monitor the selected photo is shown on without the ui, see
+    // draw_preview
+    preview : Option<usize>,
+    file_dialog : Option<FileDialog>,
+    import_dialog : bool,
+    import_dialog_input : String,
+    dzi_dialog : bool,
+    dzi_dialog_input : String,
+    album_dialog : bool,
+    album_dialog_input : String,
+    smart_album_dialog : bool,
+    smart_album_name : String,
+    // see query
+    smart_album_query : String,
+    manifest_dialog : bool,
+    manifest_dialog_input : String,
+    sheet_dialog : bool,
+    // where the PDF's saved
+    sheet_dialog_input : String,
+    sheet_layout : SheetLayout,
+    slideshow_dialog : bool,
+    // seconds per photo
+    slideshow_dialog_interval : f32,
+    // a song or a playlist
+    slideshow_dialog_music : String,
+    slideshow : Option<Slideshow>,
+    preset_dialog : bool,
+    preset_dialog_input : String,
+    // an index into PRESETS
+    preset_dialog_preset : Option<usize>,
+    preset_dialog_fit : Fit,
+    preset_dialog_output : Output,
+    view_dialog : bool,
+    view_dialog_input : String,
+    view_dialog_size : (u32, u32),
+    view_dialog_samples : u32,
+    print_dialog : bool,
+    // where the PDF's saved
+    print_dialog_input : String,
+    print_layout : PrintLayout,
+    print_render : Option<PrintRender>,
+    // export the caption as xmp with the photo
+    export_caption : bool,
+    // hide photos while the window is unfocused
+    privacy_blur : bool,
+    // lines between pixels when zoomed in far, see GRID_ZOOM
+    pixel_grid : bool,
+    // blown highlights in red and crushed shadows in blue on the photo
+    clipping : bool,
+    // the sharp edges of the photo in green
+    focus_peaking : bool,
+    // the gallery's thumbnails under a photo opened from it
+    filmstrip : bool,
+    // the albums next to galleries
+    album_sidebar : bool,
+    activity_log : bool,
+    log_window : bool,
+    perf_overlay : bool,
+    jobs_window : bool,
+    // the mouse's back button was pressed since the last frame, see
+    // App::on_event
+    mouse_back : bool,
+    // picked with alt and click, for this run only
+    samples : Samples,
+    samples_window : bool,
+    // the window was asked to close with jobs still running
+    confirm_quit : bool,
+    // the photo screen's side panel
+    panel : PanelLayout,
+    too_large : Slot<TooLarge>,
+}
+
+impl LocalModel {
+    fn new(effects_render : EffectsShader, peaking_render : PeakingShader, too_large : Slot<TooLarge>) -> Self {
+        LocalModel {
+            effects_render,
+            peaking_render,
+            too_large,
+            captions : Captions::default(),
+            captions_dialog : false,
+            prefs : Prefs::default(),
+            prefs_window : false,
+            open_dialog : false,
+            open_dialog_input : String::new(),
+            open_dialog_depth : 0,
+            thumb_size : 100.0,
+            gallery_layout : GalleryLayout::Grid,
+            group_by : GroupBy::None,
+            scroll_to_year : None,
+            gallery_search : String::new(),
+            map_view : false,
+            fullscreen : None,
+            preview : None,
+            file_dialog : None,
+            import_dialog : false,
+            import_dialog_input : String::new(),
+            dzi_dialog : false,
+            dzi_dialog_input : String::new(),
+            album_dialog : false,
+            album_dialog_input : String::new(),
+            smart_album_dialog : false,
+            smart_album_name : String::new(),
+            smart_album_query : String::new(),
+            manifest_dialog : false,
+            manifest_dialog_input : String::new(),
+            sheet_dialog : false,
+            sheet_dialog_input : String::new(),
+            sheet_layout : SheetLayout::default(),
+            slideshow_dialog : false,
+            slideshow_dialog_interval : 5.0,
+            slideshow_dialog_music : String::new(),
+            slideshow : None,
+            preset_dialog : false,
+            preset_dialog_input : String::new(),
+            preset_dialog_preset : Some(0),
+            preset_dialog_fit : Fit::Crop,
+            preset_dialog_output : Output::default(),
+            view_dialog : false,
+            view_dialog_input : String::new(),
+            view_dialog_size : (3840, 2160),
+            view_dialog_samples : 2,
+            print_dialog : false,
+            print_dialog_input : String::new(),
+            print_layout : PrintLayout::default(),
+            print_render : None,
+            export_caption : true,
+            privacy_blur : false,
+            pixel_grid : true,
+            clipping : false,
+            focus_peaking : false,
+            filmstrip : true,
+            album_sidebar : false,
+            activity_log : false,
+            log_window : false,
+            perf_overlay : false,
+            jobs_window : false,
+            mouse_back : false,
+            samples : Samples::default(),
+            samples_window : false,
+            confirm_quit : false,
+            panel : PanelLayout::default(),
+        }
+    }
+
+    // the ui state kept between runs, see App::save_settings
+    fn load_settings(&mut self, settings : &Settings) {
+        self.prefs = settings.get("prefs").unwrap_or_default();
+        self.open_dialog_input = self.prefs.default_folder.clone();
+        if let Some(size) = settings.get::<f64>("thumb_size") {
+            self.thumb_size = size as f32;
+        }
+        if let Some(interval) = settings.get::<f64>("slideshow_interval") {
+            self.slideshow_dialog_interval = interval as f32;
+        }
+        self.captions = settings.get("captions").unwrap_or_default();
+        self.privacy_blur = settings.get("privacy_blur").unwrap_or(self.privacy_blur);
+        self.pixel_grid = settings.get("pixel_grid").unwrap_or(self.pixel_grid);
+        self.clipping = settings.get("clipping").unwrap_or(self.clipping);
+        self.focus_peaking = settings.get("focus_peaking").unwrap_or(self.focus_peaking);
+        self.filmstrip = settings.get("filmstrip").unwrap_or(self.filmstrip);
+        self.album_sidebar = settings.get("album_sidebar").unwrap_or(self.album_sidebar);
+        self.activity_log = settings.get("activity_log").unwrap_or(self.activity_log);
+        self.log_window = settings.get("log_window").unwrap_or(self.log_window);
+        self.perf_overlay = settings.get("perf_overlay").unwrap_or(self.perf_overlay);
+        self.jobs_window = settings.get("jobs_window").unwrap_or(self.jobs_window);
+        self.samples_window = settings.get("samples_window").unwrap_or(self.samples_window);
+        self.export_caption = settings.get("export_caption").unwrap_or(self.export_caption);
+        self.panel = settings.get::<PanelLayout>("panel").map_or_else(PanelLayout::default, PanelLayout::fixed);
+    }
+
+    fn save_settings(&self, settings : &mut Settings) {
+        settings.set("prefs", &self.prefs);
+        // toml only has 64 bit floats
+        settings.set("thumb_size", &(self.thumb_size as f64));
+        settings.set("slideshow_interval", &(self.slideshow_dialog_interval as f64));
+        settings.set("captions", &self.captions);
+        settings.set("privacy_blur", &self.privacy_blur);
+        settings.set("pixel_grid", &self.pixel_grid);
+        settings.set("clipping", &self.clipping);
+        settings.set("focus_peaking", &self.focus_peaking);
+        settings.set("filmstrip", &self.filmstrip);
+        settings.set("album_sidebar", &self.album_sidebar);
+        settings.set("activity_log", &self.activity_log);
+        settings.set("log_window", &self.log_window);
+        settings.set("perf_overlay", &self.perf_overlay);
+        settings.set("jobs_window", &self.jobs_window);
+        settings.set("samples_window", &self.samples_window);
+        settings.set("export_caption", &self.export_caption);
+        settings.set("panel", &self.panel);
+    }
+}
+
+
+#[async_trait]
+impl App for Photos {
+    type LocalModel = LocalModel;
+    type Model = Model;
+    type Msg = Msg;
+    type Error = Error;
+
+    fn name() -> &'static str {
+        "photos"
+    }
+
+    fn init(ctx : &mut UnrenderCtx) -> Result<(Self, Self::LocalModel, Self::Model, Cmd<Msg>)> {
+        let effects_shader = EffectsShader::new(ctx.display)?;
+        let peaking_shader = PeakingShader::new(ctx.display)?;
+
+        let (too_large, too_large_write) = ctx.slot(None);
+        let mut local_model = LocalModel::new(effects_shader, peaking_shader, too_large);
+        local_model.load_settings(ctx.settings());
+
+        let options : Options = ctx.args().unwrap_or_default();
+        let folder = options.open.as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_else(|| local_model.prefs.default_folder.clone());
+
+        let cmd = match &options.export {
+            Some(export) => Cmd::msg(Msg::BatchExport(export.clone())),
+            None => Cmd::msg(Msg::OpenSet{
+                set : PhotoSet::Folder{
+                    path : folder,
+                    max_depth : 0,
+                },
+                thumb_res : THUMB_RES_STEP,
+            }),
+        };
+
+        let self_ = Photos {
+            library : Mutex::new(Library::load()),
+            library_save : tokio::sync::Mutex::new(()),
+            geocoder : tokio::sync::OnceCell::new(),
+            backoff : Backoff::default(),
+            formats : ctx.formats().clone(),
+            sources : importers::Sources::builtin(),
+            toasts : ctx.toasts().clone(),
+            jobs : ctx.jobs().clone(),
+            options,
+            unsaved_edits : AtomicUsize::new(0),
+            pending_exports : Mutex::new(VecDeque::new()),
+            limits : Mutex::new(local_model.prefs.limits),
+            allow_large : Mutex::new(HashSet::new()),
+            bandwidth : Mutex::new(local_model.prefs.bandwidth),
+            downloads : Mutex::new(Arc::new(tokio::sync::Semaphore::new(local_model.prefs.bandwidth.max_requests.max(1)))),
+            too_large : too_large_write,
+            exit_code : Mutex::new(None),
+            clipboard : Clipboard::default(),
+            offline : Mutex::new(HashMap::new()),
+        };
+
+        let model = Model::new(Screen::Empty);
+
+        Ok((self_, local_model, model, cmd))
+    }
+
+    fn save_settings(&self, local_model : &LocalModel, settings : &mut Settings) {
+        local_model.save_settings(settings);
+    }
+
+    fn customize_visuals(&self, visuals : &mut egui::Visuals) {
+        // panels a neutral gray darker than the one behind photos, so they
+        // don't change how it looks
+        if visuals.dark_mode {
+            visuals.widgets.noninteractive.bg_fill = egui::Color32::from_gray(24);
+        }
+    }
+
+    fn context_lost(&self, ctx : &mut UnrenderCtx, local_model : &mut LocalModel, _model : &mut Model) -> Cmd<Msg> {
+        // the images are uploaded again by the framework, only the programs
+        // are ours to make. if one can't be the old one stays, and drawing
+        // with it fails with an error instead
+        match EffectsShader::new(ctx.display) {
+            Ok(effects) => local_model.effects_render = effects,
+            Err(err) => self.handle_error(err),
+        }
+        match PeakingShader::new(ctx.display) {
+            Ok(peaking) => local_model.peaking_render = peaking,
+            Err(err) => self.handle_error(err),
+        }
+        Cmd::none()
+    }
+
+    fn swap(&self, ctx : &mut UnrenderCtx, old : &mut Model, new : &mut Model) {
+        // TODO: allocate textures for new?
+        for img_id in old.dropped_images.drain(..) {
+            ctx.delete_image(img_id);
+        }
+
+        // the screen left goes on the new one's back stack, textures and
+        // all. not a photo replaced by another, which is going through the
+        // filmstrip, or a gallery opened again
+        new.back = std::mem::take(&mut old.back);
+        match (&mut old.screen, &mut new.screen) {
+            (Screen::Empty, _) => {},
+            (Screen::Photo(left), Screen::Photo(opened)) => {
+                opened.filmstrip = std::mem::take(&mut left.filmstrip);
+            },
+            (Screen::Gallery(left), Screen::Gallery(opened)) if left.set == opened.set => {},
+            (left, _) => {
+                new.back.push(std::mem::replace(left, Screen::Empty));
+            },
+        }
+
+        if new.back.len() > BACK_KEPT {
+            let forgotten = new.back.len() - BACK_KEPT;
+            for screen in new.back.drain(..forgotten) {
+                for img_id in screen.image_ids() {
+                    ctx.delete_image(img_id);
+                }
+            }
+        }
+
+        for img_id in old.screen.image_ids() {
+            ctx.delete_image(img_id);
+        }
+    }
+
+    fn render(&self,
+              ctx : &mut RenderCtx,
               local_model : &mut LocalModel,
-              model : &mut Model,
-              msgs : &mut Vec<Msg>)
+              model : &mut Model) -> Cmd<Msg>
+    {
+        let mut msgs = Vec::new();
+        let scale_factor = ctx.scale_factor();
+
+        ctx.clear_color(GRAY);
+
+        for img_id in model.dropped_images.drain(..) {
+            ctx.delete_image(img_id);
+        }
+
+        // one photo of a batch export a frame, the texture only lives for
+        // the render
+        let pending = self.pending_exports.lock_or_recover().pop_front();
+        if let Some(export) = pending {
+            let effects_render = &local_model.effects_render;
+            let res = ctx.add_image(export.image).and_then(|img_id| {
+                let res = effects_render.render_image(ctx, img_id, &export.effects);
+                ctx.delete_image(img_id);
+                res
+            });
+
+            let effects = &export.effects;
+            let res = res.map(|image| {
+                let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                image::imageops::crop_imm(&image, x, y, w, h).to_image()
+            });
+
+            match (export.to, res) {
+                (ExportTo::Preset{preset, fit, output, dest, caption, left}, Ok(image)) => {
+                    msgs.push(Msg::ExportPreset{
+                        path : export.path,
+                        image : Pixels(image),
+                        preset,
+                        fit,
+                        output,
+                        dest,
+                        caption,
+                        left : Some(left),
+                    });
+                },
+                (ExportTo::Preset{dest, left, ..}, Err(err)) => {
+                    self.handle_error(err);
+                    self.export_done(&left, &dest);
+                },
+                (ExportTo::Reply(reply), res) => {
+                    // the export stopped waiting if it's gone
+                    let _ = reply.send(res);
+                },
+            }
+        }
+
+        if let Some(code) = self.exit_code.lock_or_recover().take() {
+            ctx.quit_with(code);
+        }
+
+        // a dropped folder opens as a gallery, one photo in the editor,
+        // several photos as a gallery of just them, and a manifest as a
+        // gallery of what's in it
+        let (dirs, files) : (Vec<&PathBuf>, Vec<&PathBuf>) = ctx.dropped_files()
+            .iter()
+            .partition(|path| path.is_dir());
+        let manifests : Vec<&PathBuf> = files.iter().copied().filter(|path| manifest::is_manifest_path(path)).collect();
+        // links dragged from a browser, on the desktops that make files of them
+        let shortcuts : Vec<&PathBuf> = files.iter().copied().filter(|path| web::is_shortcut(path)).collect();
+        let photos : Vec<&PathBuf> = files.into_iter().filter(|path| self.formats.is_supported(path)).collect();
+
+        if let Some(path) = manifests.first() {
+            msgs.push(Msg::OpenManifest{
+                path : path.to_path_buf(),
+                thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+            });
+        } else if let Some(path) = shortcuts.first() {
+            msgs.push(Msg::OpenShortcut{path : path.to_path_buf()});
+        } else if let Some(dir) = dirs.first() {
+            msgs.push(Msg::OpenSet{
+                set : PhotoSet::Folder{
+                    path : dir.to_string_lossy().into_owned(),
+                    max_depth : 0,
+                },
+                thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+            });
+        } else if let [path] = photos.as_slice() {
+            msgs.push(Msg::Open{path : path.to_path_buf()});
+        } else if !photos.is_empty() {
+            msgs.push(Msg::OpenSet{
+                set : PhotoSet::List(photos.iter().map(|p| p.to_string_lossy().into_owned()).collect()),
+                thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+            });
+        }
+
+        let monitors = ctx.monitors();
+        let fullscreen = local_model.fullscreen;
+        // closed from its window too
+        local_model.preview = ctx.preview();
+        let preview = local_model.preview;
+        // a depth in model.back, from the breadcrumbs
+        let mut back_to = None;
+        // from the menu or the keyboard
+        let (mut copy_image, mut paste, mut set_wallpaper, mut edit_externally) = (false, false, false, false);
+
+        egui::TopBottomPanel::top("menu bar").show(ctx.egui, |ui| {
+            egui::menu::bar(ui, |ui| {
+                egui::menu::menu(ui, "File", |ui| {
+                    local_model.open_dialog |= ui.button("Open").clicked();
+                    paste |= ui.button("Paste (ctrl+v)").clicked();
+
+                    if let Screen::Photo(photo_screen) = &model.screen {
+                        copy_image |= ui.button("Copy Image (ctrl+c)").clicked();
+                        edit_externally |= ui.button("Edit Externally").clicked();
+                        if ui.button("Manage File").clicked() {
+                            let path = photo_screen.photo.id.clone();
+                            let albums = self.albums_with(&path);
+                            let tags = self.tags(&path);
+                            local_model.file_dialog = Some(FileDialog::new(path, albums, tags));
+                        }
+
+                        local_model.dzi_dialog |= ui.button("Export Deep Zoom").clicked();
+                        local_model.view_dialog |= ui.button("Export View").clicked();
+                        local_model.preset_dialog |= ui.button("Export for...").clicked();
+                        local_model.print_dialog |= ui.button("Print").clicked();
+                        set_wallpaper |= ui.button("Set as Wallpaper").clicked();
+                    }
+
+                    local_model.import_dialog |= ui.button("Import").clicked();
+
+                    if ui.button("Find Duplicates").clicked() {
+                        msgs.push(Msg::FindDuplicates{
+                            thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+                        });
+                    }
+
+                    if let Screen::Gallery(_) = &model.screen {
+                        local_model.album_dialog |= ui.button("Add to Album").clicked();
+                        local_model.manifest_dialog |= ui.button("Export Manifest").clicked();
+                        local_model.sheet_dialog |= ui.button("Contact Sheet").clicked();
+                        local_model.slideshow_dialog |= ui.button("Slideshow").clicked();
+                        local_model.preset_dialog |= ui.button("Export for...").clicked();
+                    }
+
+                    local_model.prefs_window |= ui.button("Preferences").clicked();
+
+                    if ui.button("Gallery").clicked() {
+                        msgs.push(Msg::OpenSet{
+                            set : PhotoSet::List(vec![
+                                "test0.png".to_string(),
+                                "test1.jpg".to_string(),
+                            ]),
+                            thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+                        });
+                    }
+                });
+
+                egui::menu::menu(ui, "View", |ui| {
+                    let layout = &mut local_model.gallery_layout;
+                    ui.radio_value(layout, GalleryLayout::Grid, "Grid");
+                    ui.radio_value(layout, GalleryLayout::Justified, "Justified");
+
+                    ui.separator();
+
+                    let group_by = &mut local_model.group_by;
+                    ui.radio_value(group_by, GroupBy::None, "Ungrouped");
+                    ui.radio_value(group_by, GroupBy::Day, "Group by day");
+                    ui.radio_value(group_by, GroupBy::Month, "Group by month");
+                    ui.radio_value(group_by, GroupBy::Year, "Group by year");
+
+                    ui.separator();
+
+                    let fullscreen = &mut local_model.fullscreen;
+                    ui.radio_value(fullscreen, None, "Windowed");
+                    for (idx, name) in monitors.iter().enumerate() {
+                        ui.radio_value(fullscreen, Some(idx), format!("Fullscreen on {}", name));
+                    }
+
+                    ui.separator();
+
+                    let preview = &mut local_model.preview;
+                    ui.radio_value(preview, None, "No preview window");
+                    for (idx, name) in monitors.iter().enumerate() {
+                        ui.radio_value(preview, Some(idx), format!("Preview on {}", name));
+                    }
+
+                    local_model.captions_dialog |= ui.button("Captions").clicked();
+                    ui.separator();
+
+                    ui.checkbox(&mut local_model.privacy_blur, "Blur when unfocused");
+                    ui.checkbox(&mut local_model.pixel_grid, "Pixel grid when zoomed in");
+                    ui.checkbox(&mut local_model.focus_peaking, "Focus peaking (p)");
+                    ui.checkbox(&mut local_model.filmstrip, "Filmstrip");
+                    ui.checkbox(&mut local_model.album_sidebar, "Album Sidebar");
+                    ui.checkbox(&mut local_model.activity_log, "Activity Log");
+                    ui.checkbox(&mut local_model.log_window, "Debug Log");
+                    ui.checkbox(&mut local_model.perf_overlay, "Performance");
+                    ui.checkbox(&mut local_model.jobs_window, "Background Activity");
+                    ui.checkbox(&mut local_model.samples_window, "Color Samples");
+
+                    if ui.button("File Operations").clicked() {
+                        msgs.push(Msg::OpenOperations);
+                    }
+                });
+
+                egui::menu::menu(ui, "Albums", |ui| {
+                    if ui.button("All Albums").clicked() {
+                        msgs.push(Msg::OpenAlbums{
+                            thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+                        });
+                    }
+                    if ui.button("New Smart Album").clicked() {
+                        local_model.smart_album_dialog = true;
+                        local_model.smart_album_query = local_model.gallery_search.clone();
+                    }
+
+                    ui.separator();
+
+                    let library = self.library.lock_or_recover();
+                    if library.albums.is_empty() && library.smart_albums.is_empty() {
+                        ui.label("File > Add to Album makes one from a gallery");
+                    }
+
+                    for name in library.albums.keys() {
+                        if ui.button(name).clicked() {
+                            msgs.push(Msg::OpenSet{
+                                set : PhotoSet::Album(name.clone()),
+                                thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+                            });
+                        }
+                    }
+
+                    if !library.smart_albums.is_empty() {
+                        ui.separator();
+                    }
+                    for (name, query) in library.smart_albums.iter() {
+                        if ui.button(name).on_hover_text(query).clicked() {
+                            msgs.push(Msg::OpenSet{
+                                set : PhotoSet::Smart(name.clone()),
+                                thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+                            });
+                        }
+                    }
+                });
+
+                // the screens this one was opened from, each goes back to it
+                if !model.back.is_empty() {
+                    ui.separator();
+                    if ui.button("\u{2190}").on_hover_text("back (esc)").clicked() {
+                        back_to = Some(model.back.len() - 1);
+                    }
+
+                    for (depth, screen) in model.back.iter().enumerate() {
+                        let name = screen.name().unwrap_or_else(|| "photos".to_string());
+                        if ui.selectable_label(false, name).clicked() {
+                            back_to = Some(depth);
+                        }
+                        ui.label("\u{203a}");
+                    }
+                    ui.label(model.screen.name().unwrap_or_else(|| "photos".to_string()));
+                }
+            });
+        });
+
+        // text fields do their own copying and pasting
+        let typing = ctx.egui.wants_keyboard_input();
+        let command = ctx.egui.input().modifiers.command;
+        copy_image |= !typing && command && ctx.egui.input().key_pressed(egui::Key::C);
+        paste |= !typing && command && ctx.egui.input().key_pressed(egui::Key::V);
+
+        if paste {
+            msgs.push(Msg::Paste{
+                thumb_res : thumb_res(local_model.thumb_size, scale_factor),
+            });
+        }
+
+        if let (Screen::Photo(photo_screen), true) = (&mut model.screen, copy_image) {
+            let photo = &mut photo_screen.photo;
+            let effects = Effects{
+                original : 0,
+                ..photo.effects.clone()
+            };
+
+            let effects_render = &local_model.effects_render;
+            let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_image(ctx, img_id, &effects));
+            match res {
+                Some(Ok(image)) => {
+                    let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                    msgs.push(Msg::CopyImage{
+                        image : Pixels(image::imageops::crop_imm(&image, x, y, w, h).to_image()),
+                    });
+                },
+                Some(Err(err)) => self.handle_error(err),
+                None => {},
+            }
+        }
+
+        // the whole photo, as it's exported
+        if let (Screen::Photo(photo_screen), true) = (&mut model.screen, edit_externally) {
+            let photo = &mut photo_screen.photo;
+            let effects = Effects{
+                original : 0,
+                ..photo.effects.clone()
+            };
+
+            let effects_render = &local_model.effects_render;
+            let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_image(ctx, img_id, &effects));
+            match res {
+                Some(Ok(image)) => {
+                    let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                    msgs.push(Msg::EditExternally{
+                        path : photo.id.clone(),
+                        image : Pixels(image::imageops::crop_imm(&image, x, y, w, h).to_image()),
+                        program : local_model.prefs.external_editor.clone(),
+                        format : local_model.prefs.external_format,
+                    });
+                },
+                Some(Err(err)) => self.handle_error(err),
+                None => {},
+            }
+        }
+
+        // the view as it's shown, at the monitor's size
+        if let (Screen::Photo(photo_screen), true) = (&mut model.screen, set_wallpaper) {
+            let photo = &mut photo_screen.photo;
+            let effects = Effects{
+                original : 0,
+                ..photo.effects.clone()
+            };
+
+            let size = ctx.monitor_size().unwrap_or((1920, 1080));
+            let effects_render = &local_model.effects_render;
+            let view_mat = &photo_screen.view_mat;
+            let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_view(
+                ctx,
+                img_id,
+                view_mat,
+                &effects,
+                size,
+                2,
+                GRAY,
+            ));
+
+            match res {
+                Some(Ok(image)) => msgs.push(Msg::SetWallpaper{
+                    path : photo.id.clone(),
+                    image : Pixels(image),
+                    dir : PathBuf::from(&local_model.prefs.default_folder),
+                }),
+                Some(Err(err)) => self.handle_error(err),
+                None => {},
+            }
+        }
+
+        // escape goes back too, but not from a gallery, where it closes
+        // quick look and culling
+        let escape = !typing
+            && ctx.egui.input().key_pressed(egui::Key::Escape)
+            && !matches!(model.screen, Screen::Gallery(_) | Screen::Import(_));
+        if (escape || std::mem::take(&mut local_model.mouse_back)) && !model.back.is_empty() {
+            back_to = Some(model.back.len() - 1);
+        }
+
+        if let Some(depth) = back_to {
+            model.go_back(depth);
+            // the scan of the screen left would fill in the one back
+            ctx.renew_model();
+
+            // its scan was stopped when it was left, so it's done again
+            if let Screen::Gallery(gallery) = &model.screen {
+                if !gallery.progress.done() {
+                    msgs.push(Msg::OpenSet{
+                        set : gallery.set.clone(),
+                        thumb_res : gallery.thumb_res,
+                    });
+                }
+            }
+        }
+
+        if fullscreen != local_model.fullscreen {
+            ctx.set_fullscreen(local_model.fullscreen);
+        }
+        if preview != local_model.preview {
+            ctx.set_preview(local_model.preview);
+        }
+
+        if let Some(show) = &mut local_model.slideshow {
+            let shown = matches!(&model.screen, Screen::Photo(p) if p.photo.id.as_path() == show.current());
+            if let Some(path) = show.advance(ctx.dt(), shown) {
+                msgs.push(Msg::Open{path : path.to_path_buf()});
+            }
+
+            let mut stop = false;
+            egui::TopBottomPanel::bottom("slideshow").show(ctx.egui, |ui| {
+                ui.horizontal(|ui| {
+                    let (n, count) = show.position();
+                    ui.label(format!("{} of {}", n, count));
+                    stop = ui.button("stop").clicked();
+
+                    #[cfg(feature = "audio")]
+                    {
+                        if let Some(music) = &mut show.music {
+                            ui.checkbox(&mut music.muted, "mute");
+                            ui.add(egui::Slider::new(&mut music.volume, 0.0..=1.0).text("volume"));
+                        }
+                    }
+                });
+            });
+
+            // the music gets to finish its fade
+            #[cfg(feature = "audio")]
+            let music_done = show.music.as_ref().map_or(true, |music| music.done());
+            #[cfg(not(feature = "audio"))]
+            let music_done = true;
+
+            if stop || (show.finished() && music_done) {
+                local_model.slideshow = None;
+            }
+        }
+
+        {
+            let LocalModel{
+                captions,
+                captions_dialog,
+                ..
+            } = local_model;
+
+            egui::Window::new("Captions")
+                .collapsible(false)
+                .resizable(false)
+                .open(captions_dialog)
+                .show(ctx.egui, |ui| captions.ui(ui));
+        }
+
+        {
+            let LocalModel{
+                prefs,
+                prefs_window,
+                thumb_size,
+                slideshow_dialog_interval,
+                ..
+            } = local_model;
+
+            egui::Window::new("Preferences")
+                .collapsible(false)
+                .resizable(false)
+                .open(prefs_window)
+                .show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Default folder: ");
+                        ui.text_edit_singleline(&mut prefs.default_folder);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("External editor: ");
+                        ui.text_edit_singleline(&mut prefs.external_editor)
+                            .on_hover_text("a program, like gimp, or empty for the system's default");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Sent as: ");
+                        ui.radio_value(&mut prefs.external_format, EditFormat::Tiff, "tiff");
+                        ui.radio_value(&mut prefs.external_format, EditFormat::Png, "png");
+                    });
+
+                    ui.add(egui::Slider::new(thumb_size, 50.0..=400.0).text("thumbnail size"));
+                    ui.add(egui::Slider::new(slideshow_dialog_interval, 1.0..=60.0).text("seconds per slide"));
+
+                    // bigger photos can still be opened one at a time
+                    let limits = &mut prefs.limits;
+                    let mut megapixels = limits.max_pixels / 1_000_000;
+                    let mut megabytes = limits.max_file_size / (1024 * 1024);
+                    ui.horizontal(|ui| {
+                        ui.label("Largest photo: ");
+                        ui.add(egui::DragValue::new(&mut megapixels).clamp_range(1..=10_000).suffix(" megapixels"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Largest file: ");
+                        ui.add(egui::DragValue::new(&mut megabytes).clamp_range(1..=100_000).suffix(" MB"));
+                    });
+                    limits.max_pixels = megapixels * 1_000_000;
+                    limits.max_file_size = megabytes * 1024 * 1024;
+                    *self.limits.lock_or_recover() = *limits;
+
+                    let bandwidth = &mut prefs.bandwidth;
+                    ui.horizontal(|ui| {
+                        let mut capped = bandwidth.max_rate.is_some();
+                        ui.checkbox(&mut capped, "Download at most");
+                        match (capped, bandwidth.max_rate.as_mut()) {
+                            (true, Some(rate)) => {
+                                ui.add(egui::DragValue::new(rate).clamp_range(16..=1_000_000).suffix(" KB/s"));
+                            },
+                            (true, None) => bandwidth.max_rate = Some(1024),
+                            (false, _) => bandwidth.max_rate = None,
+                        }
+                    }).response.on_hover_text("from remote folders and links, all together");
+                    ui.horizontal(|ui| {
+                        ui.label("Downloads at once: ");
+                        ui.add(egui::DragValue::new(&mut bandwidth.max_requests).clamp_range(1..=32));
+                    });
+                    if *self.bandwidth.lock_or_recover() != *bandwidth {
+                        self.set_bandwidth(*bandwidth);
+                    }
+
+                    ui.collapsing("Formats", |ui| {
+                        for decoder in self.formats.iter() {
+                            ui.label(format!("{} (.{})", decoder.name(), decoder.extensions().join(", .")));
+                        }
+                    });
+                    ui.collapsing("Imports from", |ui| {
+                        for source in self.sources.iter() {
+                            ui.label(source.name());
+                        }
+                    });
+
+                    ui.separator();
+
+                    ctx.preferences_mut().ui(ui);
+                });
+        }
+
+        {
+            // TODO: native file open dialog?
+            let LocalModel{
+                open_dialog,
+                open_dialog_input,
+                open_dialog_depth,
+                thumb_size,
+                ..
+            } = local_model;
+
+            let mut submitted = false;
+
+            egui::Window::new("Open File")
+                .collapsible(false)
+                .resizable(false)
+                .open(open_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Folder or manifest: ");
+                        ui.text_edit_singleline(open_dialog_input)
+                            .on_hover_text("or a folder on another machine, sftp://host/path or webdav://host/path, or a photo's http(s) link");
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Subfolder depth: ");
+                        ui.add(egui::DragValue::new(open_dialog_depth).clamp_range(0..=32));
+                    });
+
+                    if ui.button("open").clicked() {
+                        tracing::debug!("opening {}", open_dialog_input);
+                        let dir = std::mem::replace(open_dialog_input, String::new());
+
+                        if web::is_url(dir.trim()) {
+                            msgs.push(Msg::OpenUrl{url : dir.trim().to_string()});
+                        } else if manifest::is_manifest_path(Path::new(&dir)) {
+                            msgs.push(Msg::OpenManifest{
+                                path : dir.into(),
+                                thumb_res : thumb_res(*thumb_size, scale_factor),
+                            });
+                        } else {
+                            msgs.push(Msg::OpenSet{
+                                set : PhotoSet::Folder{
+                                    path : dir,
+                                    max_depth : *open_dialog_depth,
+                                },
+                                thumb_res : thumb_res(*thumb_size, scale_factor),
+                            });
+                        }
+                        submitted = true;
+                    }
+                });
+
+            if submitted {
+                *open_dialog = false;
+            }
+        }
+
+        {
+            let LocalModel{
+                import_dialog,
+                import_dialog_input,
+                ..
+            } = local_model;
+
+            let mut submitted = false;
+
+            egui::Window::new("Import")
+                .collapsible(false)
+                .resizable(false)
+                .open(import_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Card or camera folder: ");
+                        ui.text_edit_singleline(import_dialog_input);
+                    });
+
+                    if ui.button("open").clicked() {
+                        msgs.push(Msg::OpenImport{
+                            source : std::mem::take(import_dialog_input).into(),
+                            thumb_res : THUMB_RES_STEP,
+                        });
+                        submitted = true;
+                    }
+                });
+
+            if submitted {
+                *import_dialog = false;
+            }
+        }
+
+        if let Screen::Gallery(gallery) = &model.screen {
+            let LocalModel{
+                album_dialog,
+                album_dialog_input,
+                ..
+            } = local_model;
+
+            let mut submitted = false;
+            // the selection, or everything if nothing's selected
+            let selected = gallery.thumbs.iter().filter(|t| t.selected).count();
+            let names : Vec<String> = self.library.lock_or_recover().albums.keys().cloned().collect();
+
+            egui::Window::new("Add to Album")
+                .collapsible(false)
+                .resizable(false)
+                .open(album_dialog)
+                .show(ctx.egui, |ui| {
+                    if selected > 0 {
+                        ui.label(format!("the {} selected photos", selected));
+                    } else {
+                        ui.label(format!("all {} photos", gallery.thumbs.len()));
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Name: ");
+                        ui.text_edit_singleline(album_dialog_input);
+                    });
+
+                    if !names.is_empty() {
+                        ui.label("or to one of");
+                        egui::ScrollArea::from_max_height(160.0).show(ui, |ui| {
+                            for name in names.iter() {
+                                if ui.selectable_label(album_dialog_input == name, name).clicked() {
+                                    *album_dialog_input = name.clone();
+                                }
+                            }
+                        });
+                    }
+
+                    // the gallery's photos as of now, wait for all of them
+                    let ready = (selected > 0 || gallery.progress.done()) && !album_dialog_input.is_empty();
+                    let label = if names.contains(album_dialog_input) { "add" } else { "make album" };
+                    submitted = ui.add(egui::Button::new(label).enabled(ready)).clicked();
+                });
+
+            if submitted {
+                *album_dialog = false;
+
+                msgs.push(Msg::AddToAlbum{
+                    name : std::mem::take(album_dialog_input),
+                    photos : gallery.thumbs.iter()
+                        .filter(|t| t.selected || selected == 0)
+                        .map(|t| t.id.clone())
+                        .collect(),
+                });
+            }
+        }
+
+        {
+            let LocalModel{
+                smart_album_dialog,
+                smart_album_name,
+                smart_album_query,
+                ..
+            } = local_model;
+
+            let mut submitted = false;
+            let taken = self.library.lock_or_recover().albums.contains_key(smart_album_name.as_str());
+
+            egui::Window::new("New Smart Album")
+                .collapsible(false)
+                .resizable(false)
+                .open(smart_album_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Name: ");
+                        ui.text_edit_singleline(smart_album_name);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Search: ");
+                        ui.text_edit_singleline(smart_album_query);
+                    });
+
+                    ui.small("every word has to match, like 5 stars and 2023 and tag:trip");
+                    ui.small("also picked, rejected, label:red, year:2021, rating:0 for unrated");
+                    ui.small("not or - before a word for photos without it, other words are searched for in names and captions");
+
+                    if taken {
+                        ui.label("there's already an album by that name");
+                    }
+
+                    let ready = !smart_album_name.is_empty() && !Query::parse(smart_album_query).is_empty() && !taken;
+                    submitted = ui.add(egui::Button::new("save").enabled(ready)).clicked();
+                });
+
+            if submitted {
+                *smart_album_dialog = false;
+
+                msgs.push(Msg::SaveSmartAlbum{
+                    name : std::mem::take(smart_album_name),
+                    query : std::mem::take(smart_album_query).trim().to_string(),
+                });
+            }
+        }
+
+        if let (Screen::Gallery(gallery), true) = (&mut model.screen, local_model.sheet_dialog) {
+            let LocalModel{
+                effects_render,
+                sheet_dialog,
+                sheet_dialog_input,
+                sheet_layout,
+                ..
+            } = local_model;
+
+            // the selection, or everything if nothing's selected
+            let selected = gallery.thumbs.iter().filter(|t| t.selected).count();
+            let count = if selected > 0 { selected } else { gallery.thumbs.len() };
+            let mut submitted = false;
+
+            egui::Window::new("Contact Sheet")
+                .collapsible(false)
+                .resizable(false)
+                .open(sheet_dialog)
+                .show(ctx.egui, |ui| {
+                    let pages = (count + sheet_layout.per_page() - 1) / sheet_layout.per_page();
+                    ui.label(format!("{} photos on {} pages", count, pages));
+
+                    sheet_layout.show(ui);
+
+                    ui.horizontal(|ui| {
+                        ui.label("PDF: ");
+                        ui.text_edit_singleline(sheet_dialog_input);
+                    });
+
+                    // the gallery's photos as of now, wait for all of them
+                    let ready = (selected > 0 || gallery.progress.done()) && count > 0 && !sheet_dialog_input.trim().is_empty();
+                    submitted = ui.add(egui::Button::new("save").enabled(ready)).clicked();
+                });
+
+            if submitted {
+                *sheet_dialog = false;
+
+                // the thumbnails as they're shown, with their edits
+                let mut cells = Vec::with_capacity(count);
+                for thumb in gallery.thumbs.iter_mut().filter(|t| t.selected || selected == 0) {
+                    let effects = self.saved_effects(&thumb.id);
+                    let res = thumb.data.get_image_id(ctx).map(|img_id| effects_render.render_image(ctx, img_id, &effects));
+                    let image = match res {
+                        Some(Ok(image)) => image,
+                        Some(Err(err)) => {
+                            self.handle_error(err);
+                            continue
+                        },
+                        None => continue,
+                    };
+
+                    let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                    cells.push(Cell{
+                        name : thumb.id.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                        caption : thumb.caption.clone(),
+                        image : image::imageops::crop_imm(&image, x, y, w, h).to_image(),
+                    });
+                }
+
+                msgs.push(Msg::ExportContactSheet{
+                    title : model.screen.name().unwrap_or_else(|| "photos".to_string()),
+                    cells,
+                    layout : *sheet_layout,
+                    dest : PathBuf::from(sheet_dialog_input.trim()),
+                });
+            }
+        }
+
+        if let Screen::Gallery(gallery) = &model.screen {
+            let LocalModel{
+                manifest_dialog,
+                manifest_dialog_input,
+                ..
+            } = local_model;
+
+            // the selection, or everything if nothing's selected
+            let selected = gallery.thumbs.iter().filter(|t| t.selected).count();
+            let thumbs : Vec<&Thumb> = gallery.thumbs.iter()
+                .filter(|t| t.selected || selected == 0)
+                .collect();
+
+            let mut submitted = false;
+
+            egui::Window::new("Export Manifest")
+                .collapsible(false)
+                .resizable(false)
+                .open(manifest_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.label(format!("{} photos, as csv, json, or m3u by the extension", thumbs.len()));
+
+                    ui.horizontal(|ui| {
+                        ui.label("File: ");
+                        ui.text_edit_singleline(manifest_dialog_input);
+                    });
+
+                    let dest = Path::new(manifest_dialog_input.as_str());
+                    let ready = manifest::is_manifest_path(dest);
+                    submitted = ui.add(egui::Button::new("export").enabled(ready)).clicked();
+                });
+
+            if submitted {
+                *manifest_dialog = false;
+
+                msgs.push(Msg::ExportManifest{
+                    entries : thumbs.iter()
+                        .map(|t| manifest::Entry::new(t.id.clone(), &t.meta, t.badges.edited))
+                        .collect(),
+                    dest : std::mem::take(manifest_dialog_input).into(),
+                });
+            }
+        }
+
+        if let Screen::Gallery(gallery) = &model.screen {
+            let LocalModel{
+                slideshow_dialog,
+                slideshow_dialog_interval,
+                slideshow_dialog_music,
+                slideshow,
+                ..
+            } = local_model;
+
+            // the selection, or everything if nothing's selected
+            let selected = gallery.thumbs.iter().filter(|t| t.selected).count();
+            let paths : Vec<PathBuf> = gallery.thumbs.iter()
+                .filter(|t| t.selected || selected == 0)
+                .map(|t| t.id.clone())
+                .collect();
+
+            let mut submitted = false;
+
+            egui::Window::new("Slideshow")
+                .collapsible(false)
+                .resizable(false)
+                .open(slideshow_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.label(format!("{} photos", paths.len()));
+
+                    ui.horizontal(|ui| {
+                        ui.label("Seconds per photo: ");
+                        ui.add(egui::DragValue::new(slideshow_dialog_interval).clamp_range(1.0..=60.0));
+                    });
+
+                    #[cfg(feature = "audio")]
+                    ui.horizontal(|ui| {
+                        ui.label("Music, a song or m3u playlist: ");
+                        ui.text_edit_singleline(slideshow_dialog_music);
+                    });
+
+                    submitted = ui.add(egui::Button::new("start").enabled(!paths.is_empty())).clicked();
+                });
+
+            if submitted {
+                *slideshow_dialog = false;
+                *slideshow = Slideshow::new(paths, Duration::from_secs_f32(*slideshow_dialog_interval));
+
+                #[cfg(feature = "audio")]
+                {
+                    let music = slideshow_dialog_music.trim();
+                    if let (Some(show), false) = (slideshow.as_mut(), music.is_empty()) {
+                        match audio::Music::open(Path::new(music)) {
+                            Ok(music) => show.music = Some(music),
+                            Err(err) => ctx.toasts().error(format!("could not play {}: {}", music, err)),
+                        }
+                    }
+                }
+                #[cfg(not(feature = "audio"))]
+                let _ = slideshow_dialog_music;
+
+                if let Some(show) = slideshow {
+                    msgs.push(Msg::Open{path : show.current().to_path_buf()});
+                }
+            }
+        }
+
+        if let Screen::Photo(photo_screen) = &mut model.screen {
+            let LocalModel{
+                effects_render,
+                dzi_dialog,
+                dzi_dialog_input,
+                ..
+            } = local_model;
+
+            let mut submitted = false;
+
+            egui::Window::new("Export Deep Zoom")
+                .collapsible(false)
+                .resizable(false)
+                .open(dzi_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Destination folder: ");
+                        ui.text_edit_singleline(dzi_dialog_input);
+                    });
+
+                    submitted = ui.button("export").clicked();
+                });
+
+            if submitted {
+                *dzi_dialog = false;
+
+                let photo = &mut photo_screen.photo;
+                let effects = Effects{
+                    original : 0,
+                    ..photo.effects.clone()
+                };
+
+                // None was already said by get_image_id
+                let res = photo.data.get_image_id(ctx)
+                    .map(|img_id| effects_render.render_image(ctx, img_id, &effects));
+                match res {
+                    None => {},
+                    Some(Ok(image)) => {
+                        let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                        let image = image::imageops::crop_imm(&image, x, y, w, h).to_image();
+
+                        msgs.push(Msg::ExportDzi{
+                            path : photo.id.clone(),
+                            image : Pixels(image),
+                            dest : std::mem::take(dzi_dialog_input).into(),
+                        });
+                    },
+                    Some(Err(err)) => self.handle_error(err),
+                }
+            }
+        }
+
+        let too_large = local_model.too_large.get().clone();
+        if let Some((path, reason)) = too_large {
+            let mut open = true;
+            let mut anyway = false;
+
+            egui::Window::new("Photo too large")
+                .collapsible(false)
+                .resizable(false)
+                .open(&mut open)
+                .show(ctx.egui, |ui| {
+                    ui.label(format!("{}: {}.", path.display(), reason));
+                    ui.label("Opening it could use up the memory and crash the app.");
+
+                    ui.horizontal(|ui| {
+                        anyway = ui.button("Open anyway").clicked();
+                        open &= !ui.button("Cancel").clicked();
+                    });
+                });
+
+            if anyway {
+                msgs.push(Msg::OpenAnyway{path});
+            }
+            if anyway || !open {
+                *local_model.too_large.get() = None;
+            }
+        }
+
+        if local_model.confirm_quit {
+            let LocalModel{
+                confirm_quit,
+                jobs_window,
+                ..
+            } = local_model;
+
+            let running = ctx.jobs().jobs().len();
+            let mut quit = false;
+
+            egui::Window::new("Quit?")
+                .collapsible(false)
+                .resizable(false)
+                .open(confirm_quit)
+                .show(ctx.egui, |ui| {
+                    ui.label(format!("{} still running in the background, they'll be stopped", running));
+
+                    ui.horizontal(|ui| {
+                        quit = ui.button("Quit").clicked();
+                        if ui.button("Show").clicked() {
+                            *jobs_window = true;
+                        }
+                    });
+                });
+
+            if quit {
+                ctx.quit();
+            }
+        }
+
+        ctx.toasts().show_log(ctx.egui, &mut local_model.activity_log);
+        ctx.logs().show(ctx.egui, &mut local_model.log_window);
+        ctx.stats().show(ctx.egui, &mut local_model.perf_overlay);
+        ctx.jobs().show(ctx.egui, &mut local_model.jobs_window);
+        local_model.samples.show(ctx.egui, &mut local_model.samples_window);
+
+        let running = ctx.jobs().jobs().len();
+        if running > 0 {
+            egui::TopBottomPanel::bottom("background activity").show(ctx.egui, |ui| {
+                let label = format!("{} running in the background", running);
+                if ui.small_button(label).clicked() {
+                    local_model.jobs_window = !local_model.jobs_window;
+                }
+            });
+        }
+
+        if let Screen::Photo(photo_screen) = &mut model.screen {
+            let LocalModel{
+                effects_render,
+                view_dialog,
+                view_dialog_input,
+                view_dialog_size,
+                view_dialog_samples,
+                export_caption,
+                ..
+            } = local_model;
+
+            let mut submitted = false;
+
+            egui::Window::new("Export View")
+                .collapsible(false)
+                .resizable(false)
+                .open(view_dialog)
+                .show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Size: ");
+                        ui.add(egui::DragValue::new(&mut view_dialog_size.0).clamp_range(1..=16384));
+                        ui.label("x");
+                        ui.add(egui::DragValue::new(&mut view_dialog_size.1).clamp_range(1..=16384));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Supersampling: ");
+                        for samples in &[1, 2, 4] {
+                            ui.radio_value(view_dialog_samples, *samples, format!("{}x", samples));
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("File: ");
+                        ui.text_edit_singleline(view_dialog_input);
+                    });
+
+                    ui.checkbox(export_caption, "Write the caption");
+
+                    submitted = ui.button("export").clicked();
+                });
+
+            if submitted {
+                *view_dialog = false;
+
+                let photo = &mut photo_screen.photo;
+                let effects = Effects{
+                    original : 0,
+                    ..photo.effects.clone()
+                };
+
+                let view_mat = &photo_screen.view_mat;
+                let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_view(
+                    ctx,
+                    img_id,
+                    view_mat,
+                    &effects,
+                    *view_dialog_size,
+                    *view_dialog_samples,
+                    GRAY,
+                ));
+
+                match res {
+                    None => {},
+                    Some(Ok(image)) => msgs.push(Msg::ExportImage{
+                        path : photo.id.clone(),
+                        image : Pixels(image),
+                        dest : std::mem::take(view_dialog_input).into(),
+                        caption : *export_caption,
+                    }),
+                    Some(Err(err)) => self.handle_error(err),
+                }
+            }
+        }
+
+        if let (Screen::Photo(photo_screen), true) = (&mut model.screen, local_model.print_dialog) {
+            let LocalModel{
+                effects_render,
+                print_dialog,
+                print_dialog_input,
+                print_layout,
+                print_render,
+                ..
+            } = local_model;
+            let photo = &mut photo_screen.photo;
+
+            // rendered once when the dialog opens, edits made while it's
+            // open aren't in it
+            if print_render.as_ref().map_or(true, |render| render.path != photo.id) {
+                let effects = Effects{
+                    original : 0,
+                    ..photo.effects.clone()
+                };
+
+                let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_image(ctx, img_id, &effects));
+                match res {
+                    Some(Ok(image)) => {
+                        let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                        let image = image::imageops::crop_imm(&image, x, y, w, h).to_image();
+                        let scale = (PRINT_PREVIEW_SIZE as f32 / w.max(h) as f32).min(1.0);
+                        let preview = image::imageops::thumbnail(
+                            &image,
+                            ((w as f32 * scale) as u32).max(1),
+                            ((h as f32 * scale) as u32).max(1),
+                        );
+
+                        if let Some(PhotoData::GPU(img_id)) = print_render.take().map(|render| render.preview) {
+                            ctx.delete_image(img_id);
+                        }
+                        if print_dialog_input.is_empty() {
+                            *print_dialog_input = photo.id.with_extension("pdf").display().to_string();
+                        }
+                        *print_render = Some(PrintRender{
+                            path : photo.id.clone(),
+                            image,
+                            preview : PhotoData::CPU(preview),
+                        });
+                    },
+                    Some(Err(err)) => {
+                        self.handle_error(err);
+                        *print_dialog = false;
+                    },
+                    None => *print_dialog = false,
+                }
+            }
+
+            let mut submitted = None;
+
+            if let Some(render) = print_render.as_mut() {
+                let size = render.image.dimensions();
+                let texture = render.preview.egui_texture(ctx);
+
+                egui::Window::new("Print")
+                    .collapsible(false)
+                    .resizable(false)
+                    .open(print_dialog)
+                    .show(ctx.egui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.vertical(|ui| print_layout.show(ui));
+                            ui.vertical(|ui| print_layout.preview(ui, size, texture));
+                        });
+
+                        ui.separator();
+
+                        ui.horizontal(|ui| {
+                            ui.label("PDF: ");
+                            ui.text_edit_singleline(print_dialog_input);
+                        });
+
+                        ui.horizontal(|ui| {
+                            let ready = !print_dialog_input.trim().is_empty();
+                            if ui.add(egui::Button::new("save PDF").enabled(ready)).clicked() {
+                                submitted = Some(Some(PathBuf::from(print_dialog_input.trim())));
+                            }
+                            if ui.button("print").on_hover_text("on the default printer").clicked() {
+                                submitted = Some(None);
+                            }
+                        });
+                    });
+            }
+
+            if let (Some(dest), Some(render)) = (submitted, print_render.as_ref()) {
+                *print_dialog = false;
+
+                msgs.push(Msg::Print{
+                    path : render.path.clone(),
+                    image : Pixels(render.image.clone()),
+                    layout : *print_layout,
+                    dest,
+                });
+            }
+        }
+
+        // the render's dropped with the dialog, or when the photo's closed
+        if !matches!(model.screen, Screen::Photo(_)) || !local_model.print_dialog {
+            if let Some(PhotoData::GPU(img_id)) = local_model.print_render.take().map(|render| render.preview) {
+                ctx.delete_image(img_id);
+            }
+        }
+
+        if matches!(model.screen, Screen::Photo(_) | Screen::Gallery(_)) {
+            let LocalModel{
+                effects_render,
+                preset_dialog,
+                preset_dialog_input,
+                preset_dialog_preset,
+                preset_dialog_fit,
+                preset_dialog_output,
+                export_caption,
+                ..
+            } = local_model;
+
+            // the selection, or everything if nothing's selected
+            let paths : Vec<PathBuf> = match &model.screen {
+                Screen::Gallery(gallery) => {
+                    let selected = gallery.thumbs.iter().filter(|t| t.selected).count();
+                    gallery.thumbs.iter()
+                        .filter(|t| t.selected || selected == 0)
+                        .map(|t| t.id.clone())
+                        .collect()
+                },
+                _ => Vec::new(),
+            };
+
+            let mut submitted = false;
+
+            egui::Window::new("Export for...")
+                .collapsible(false)
+                .resizable(false)
+                .open(preset_dialog)
+                .show(ctx.egui, |ui| {
+                    if !paths.is_empty() {
+                        ui.label(format!("{} photos", paths.len()));
+                    }
+
+                    for (idx, preset) in PRESETS.iter().enumerate() {
+                        let label = format!("{} ({}x{})", preset.name, preset.width, preset.height);
+                        ui.radio_value(preset_dialog_preset, Some(idx), label);
+                    }
+                    ui.radio_value(preset_dialog_preset, None, "The photo's own shape");
+
+                    ui.separator();
+
+                    if preset_dialog_preset.is_some() {
+                        ui.radio_value(preset_dialog_fit, Fit::Crop, "Crop to the subject");
+                        ui.radio_value(preset_dialog_fit, Fit::Pad, "Fit on a blurred background");
+                        ui.separator();
+                    }
+
+                    let output = &mut *preset_dialog_output;
+                    ui.horizontal(|ui| {
+                        let mut resize = output.long_edge.is_some();
+                        ui.checkbox(&mut resize, "Long edge at most");
+                        match (resize, output.long_edge.as_mut()) {
+                            (true, Some(edge)) => {
+                                ui.add(egui::DragValue::new(edge).clamp_range(16..=16384).suffix(" px"));
+                            },
+                            (true, None) => output.long_edge = Some(2048),
+                            (false, _) => output.long_edge = None,
+                        }
+                    });
+                    if output.long_edge.is_some() {
+                        egui::ComboBox::from_label("Resampling")
+                            .selected_text(output.resample.name())
+                            .show_ui(ui, |ui| {
+                                for resample in Resample::ALL.iter() {
+                                    ui.selectable_value(&mut output.resample, *resample, resample.name());
+                                }
+                            });
+                    }
+                    egui::ComboBox::from_label("Output sharpening")
+                        .selected_text(output.sharpen.name())
+                        .show_ui(ui, |ui| {
+                            for sharpen in Sharpen::ALL.iter() {
+                                ui.selectable_value(&mut output.sharpen, *sharpen, sharpen.name());
+                            }
+                        });
+                    egui::ComboBox::from_label("Color space")
+                        .selected_text(output.color_space.name())
+                        .show_ui(ui, |ui| {
+                            for space in ColorSpace::ALL.iter() {
+                                ui.selectable_value(&mut output.color_space, *space, space.name());
+                            }
+                        });
+
+                    let mut strip = !output.keep_metadata;
+                    ui.checkbox(&mut strip, "Strip metadata").on_hover_text("leave out the camera's exif and the caption");
+                    output.keep_metadata = !strip;
+
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        ui.label("Destination folder: ");
+                        ui.text_edit_singleline(preset_dialog_input);
+                    });
+
+                    // it's metadata too
+                    let keep_metadata = preset_dialog_output.keep_metadata;
+                    ui.scope(|ui| {
+                        ui.set_enabled(keep_metadata);
+                        ui.checkbox(export_caption, "Write the caption");
+                    });
+
+                    let ready = !preset_dialog_input.trim().is_empty();
+                    submitted = ui.add(egui::Button::new("export").enabled(ready)).clicked();
+                });
+
+            if submitted {
+                *preset_dialog = false;
+                let dest = PathBuf::from(preset_dialog_input.trim());
+
+                match &mut model.screen {
+                    Screen::Photo(photo_screen) => {
+                        let photo = &mut photo_screen.photo;
+                        let effects = Effects{
+                            original : 0,
+                            ..photo.effects.clone()
+                        };
+
+                        let res = photo.data.get_image_id(ctx)
+                            .map(|img_id| effects_render.render_image(ctx, img_id, &effects));
+                        match res {
+                            None => {},
+                            Some(Ok(image)) => {
+                                let (x, y, w, h) = effects.crop_rect(image.width(), image.height());
+                                msgs.push(Msg::ExportPreset{
+                                    path : photo.id.clone(),
+                                    image : Pixels(image::imageops::crop_imm(&image, x, y, w, h).to_image()),
+                                    preset : *preset_dialog_preset,
+                                    fit : *preset_dialog_fit,
+                                    output : *preset_dialog_output,
+                                    dest,
+                                    caption : *export_caption,
+                                    left : None,
+                                });
+                            },
+                            Some(Err(err)) => self.handle_error(err),
+                        }
+                    },
+                    _ => msgs.push(Msg::ExportPresets{
+                        paths,
+                        preset : *preset_dialog_preset,
+                        fit : *preset_dialog_fit,
+                        output : *preset_dialog_output,
+                        dest,
+                        caption : *export_caption,
+                    }),
+                }
+            }
+        }
+
+        if let Some(file_dialog) = local_model.file_dialog.as_mut() {
+            if !file_dialog.show(ctx.egui, &mut msgs) {
+                local_model.file_dialog = None;
+            }
+        }
+
+        let hidden = local_model.privacy_blur && !ctx.focused();
+
+        match &mut model.screen {
+            Screen::Empty => {},
+            Screen::Albums(albums) => {
+                let thumb_size = local_model.thumb_size;
+                let AlbumsScreen{tiles, confirm_delete} = albums;
+                let mut deleted = None;
+
+                egui::CentralPanel::default().show(ctx.egui, |ui| {
+                    if tiles.is_empty() {
+                        ui.label("No albums yet, File > Add to Album makes one from a gallery");
+                    }
+
+                    egui::ScrollArea::auto_sized().show(ui, |ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            for (idx, tile) in tiles.iter_mut().enumerate() {
+                                ui.vertical(|ui| {
+                                    let size = egui::Vec2::splat(thumb_size);
+                                    let button = match tile.cover.as_mut() {
+                                        Some(thumb) => {
+                                            let size = egui::Vec2{
+                                                x : thumb_size * thumb.aspect().min(1.0),
+                                                y : thumb_size / thumb.aspect().max(1.0),
+                                            };
+                                            ui.add(thumb.data.button(ctx, size))
+                                        },
+                                        None => ui.add_sized(size, egui::Button::new(&tile.name)),
+                                    };
+
+                                    if button.clicked() {
+                                        let set = match tile.query {
+                                            Some(_) => PhotoSet::Smart(tile.name.clone()),
+                                            None => PhotoSet::Album(tile.name.clone()),
+                                        };
+                                        msgs.push(Msg::OpenSet{
+                                            set,
+                                            thumb_res : thumb_res(thumb_size, scale_factor),
+                                        });
+                                    }
+
+                                    ui.label(egui::Label::new(&tile.name).strong());
+                                    match &tile.query {
+                                        Some(query) => ui.small(format!("smart: {}", query)),
+                                        None => ui.label(format!("{} photos", tile.count)),
+                                    };
+                                    if let Some(created) = tile.created {
+                                        ui.small(format!("made {}", created));
+                                    }
+
+                                    if confirm_delete.as_ref() == Some(&tile.name) {
+                                        ui.horizontal(|ui| {
+                                            ui.label("the photos stay");
+                                            if ui.small_button("delete").clicked() {
+                                                msgs.push(Msg::DeleteAlbum{name : tile.name.clone()});
+                                                deleted = Some(idx);
+                                            }
+                                            if ui.small_button("keep").clicked() {
+                                                *confirm_delete = None;
+                                            }
+                                        });
+                                    } else if ui.small_button("delete").clicked() {
+                                        *confirm_delete = Some(tile.name.clone());
+                                    }
+                                });
+                            }
+                        });
+                    });
+                });
+
+                if let Some(idx) = deleted {
+                    let tile = tiles.remove(idx);
+                    if let Some(PhotoData::GPU(img_id)) = tile.cover.map(|thumb| thumb.data) {
+                        model.dropped_images.push(img_id);
+                    }
+                    *confirm_delete = None;
+                }
+            },
+            Screen::Compare(compare) => {
+                let typing = ctx.egui.wants_keyboard_input();
+                if !typing && ctx.egui.input().key_pressed(egui::Key::Tab) {
+                    compare.shown = 1 - compare.shown;
+                }
+
+                egui::TopBottomPanel::bottom("compare").show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut compare.side_by_side, "side by side");
+
+                        for (idx, photo) in compare.photos.iter().enumerate() {
+                            let name = photo.id.file_name().unwrap_or_default().to_string_lossy();
+                            let label = format!("{} {}", ["A", "B"][idx], name);
+                            let shown = compare.side_by_side || compare.shown == idx;
+                            if ui.selectable_label(shown, label).on_hover_text("tab switches").clicked() {
+                                compare.shown = idx;
+                                compare.side_by_side = false;
+                            }
+                            if ui.small_button("edit").clicked() {
+                                msgs.push(Msg::Open{path : photo.id.clone()});
+                            }
+                        }
+                    });
+                });
+
+                // zoomed around the pointer in whichever half it's in
+                let viewports = compare.viewports(ctx.dimensions());
+                let pointer = ctx.background_input().map(|i| i.pointer);
+                let active = viewports.iter()
+                    .flatten()
+                    .find(|v| pointer.map_or(false, |p| v.contains(p)))
+                    .copied()
+                    .unwrap_or_else(|| Viewport::window(ctx.dimensions()));
+                let view_mat = update_view(&mut compare.view_mat, ctx, active);
+
+                let overlays = Overlays{
+                    blur : if hidden { PRIVACY_BLUR } else { 0. },
+                    pixel_grid : local_model.pixel_grid && !hidden,
+                    clipping : local_model.clipping && !hidden,
+                };
+                for (photo, viewport) in compare.photos.iter_mut().zip(viewports.iter()) {
+                    let viewport = opt_unwrap_or!(*viewport, { continue });
+                    let img_id = opt_unwrap_or!(photo.data.get_image_id(ctx), { continue });
+                    let res = local_model.effects_render.draw_image_viewport_with(
+                        ctx,
+                        img_id,
+                        &view_mat,
+                        &photo.effects,
+                        &overlays,
+                        viewport,
+                    );
+                    if let Err(err) = res {
+                        self.handle_error(err);
+                    }
+                }
+
+                if let [_, Some(right)] = viewports {
+                    let x = ctx.to_points((right.x, 0.0)).x;
+                    let height = ctx.egui.input().screen_rect().height();
+                    ctx.egui.layer_painter(egui::LayerId::background()).line_segment(
+                        [egui::pos2(x, 0.0), egui::pos2(x, height)],
+                        (1.0, egui::Color32::from_gray(90)),
+                    );
+                }
+            },
+            Screen::Operations(ops) => {
+                const KINDS : &[OperationKind] = &[
+                    OperationKind::Import,
+                    OperationKind::Export,
+                    OperationKind::Trash,
+                    OperationKind::Rename,
+                    OperationKind::Move,
+                    OperationKind::Edit,
+                ];
+
+                egui::TopBottomPanel::top("operations header").show(ctx.egui, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.radio_value(&mut ops.kind, None, "all");
+                        for kind in KINDS {
+                            ui.radio_value(&mut ops.kind, Some(*kind), kind.name());
+                        }
+
+                        ui.separator();
+                        ui.checkbox(&mut ops.failed_only, "failed only");
+                    });
+                });
+
+                let shown : Vec<&Operation> = ops.operations.iter()
+                    .filter(|op| ops.kind.map_or(true, |kind| op.kind == kind))
+                    .filter(|op| !ops.failed_only || op.error.is_some())
+                    .collect();
+
+                egui::CentralPanel::default().show(ctx.egui, |ui| {
+                    if shown.is_empty() {
+                        ui.label("Nothing yet, imports, exports, deletes, and edits show up here");
+                    }
+
+                    let row_height = ui.fonts().row_height(egui::TextStyle::Body) + ui.spacing().item_spacing.y;
+                    egui::ScrollArea::auto_sized().show_rows(ui, row_height, shown.len(), |ui, rng| {
+                        for op in &shown[rng] {
+                            ui.horizontal(|ui| {
+                                let time = DateTime::from_system_time(op.time)
+                                    .map_or_else(String::new, |time| time.to_string());
+                                ui.label(time);
+                                ui.label(egui::Label::new(op.kind.name()).strong());
+                                ui.label(op.path.display().to_string());
+                                if let Some(dest) = &op.dest {
+                                    ui.label(format!("\u{2192} {}", dest.display()));
+                                }
+
+                                match &op.error {
+                                    Some(err) => { ui.colored_label(egui::Color32::RED, err); },
+                                    None => { ui.label("ok"); },
+                                }
+
+                                // where the photo is now, if it's still anywhere
+                                let open = match op.kind {
+                                    OperationKind::Trash => None,
+                                    OperationKind::Edit => Some(&op.path),
+                                    _ => op.dest.as_ref(),
+                                };
+                                if let (Some(path), None) = (open, &op.error) {
+                                    if self.formats.is_supported(path) && ui.small_button("open").clicked() {
+                                        msgs.push(Msg::Open{path : path.clone()});
+                                    }
+                                }
+                            });
+                        }
+                    });
+                });
+            },
+            Screen::Duplicates(dupes) => {
+                egui::TopBottomPanel::top("duplicates header").show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        let marked : Vec<PathBuf> = dupes.groups.iter()
+                            .flatten()
+                            .filter(|t| t.selected)
+                            .map(|t| t.id.clone())
+                            .collect();
+
+                        if !dupes.confirm_trash {
+                            let label = format!("Move {} marked to Trash", marked.len());
+                            dupes.confirm_trash = ui.add(egui::Button::new(label).enabled(!marked.is_empty())).clicked();
+                        } else {
+                            ui.label(format!("Move {} files to the trash?", marked.len()));
+                            if ui.button("Yes").clicked() {
+                                for path in marked {
+                                    msgs.push(Msg::Trash{path});
+                                }
+                                dupes.confirm_trash = false;
+                            }
+                            if ui.button("No").clicked() {
+                                dupes.confirm_trash = false;
+                            }
+                        }
+
+                        if dupes.loading {
+                            ui.label("loading...");
+                        } else {
+                            ui.label(format!("{} groups", dupes.groups.len()));
+                        }
+                    });
+                });
+
+                let thumb_size = local_model.thumb_size;
+                egui::CentralPanel::default().show(ctx.egui, |ui| {
+                    egui::ScrollArea::auto_sized().show(ui, |ui| {
+                        for group in dupes.groups.iter_mut().filter(|g| g.len() > 1) {
+                            ui.horizontal(|ui| {
+                                for thumb in group.iter_mut() {
+                                    ui.vertical(|ui| {
+                                        let size = egui::Vec2{
+                                            x : thumb_size * thumb.aspect(),
+                                            y : thumb_size,
+                                        };
+
+                                        ui.add(thumb.data.image(ctx, size))
+                                            .on_hover_text(thumb.id.display());
+
+                                        let mut keep = !thumb.selected;
+                                        ui.checkbox(&mut keep, "keep");
+                                        thumb.selected = !keep;
+                                    });
+                                }
+                            });
+
+                            ui.separator();
+                        }
+                    });
+                });
+            },
+            Screen::Import(import) => {
+                egui::TopBottomPanel::top("import header").show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Destination: ");
+                        ui.text_edit_singleline(&mut import.dest);
+                        ui.label("Folders: ");
+                        ui.text_edit_singleline(&mut import.template);
+                    });
+
+                    ui.horizontal(|ui| {
+                        let thumbs = &mut import.gallery.thumbs;
+
+                        if ui.button("Select all").clicked() {
+                            thumbs.iter_mut().for_each(|t| t.selected = true);
+                        }
+
+                        if ui.button("Select none").clicked() {
+                            thumbs.iter_mut().for_each(|t| t.selected = false);
+                        }
+
+                        let selected : Vec<PathBuf> = thumbs.iter()
+                            .filter(|t| t.selected)
+                            .map(|t| t.id.clone())
+                            .collect();
+
+                        let busy = import.progress.as_ref().map_or(false, |p| !p.done());
+                        let label = format!("Import {} photos", selected.len());
+                        let can_import = !busy && !selected.is_empty() && !import.dest.is_empty();
+
+                        if ui.add(egui::Button::new(label).enabled(can_import)).clicked() {
+                            import.progress = Some(ImportProgress{
+                                total : selected.len(),
+                                ..Default::default()
+                            });
+
+                            msgs.push(Msg::Import{
+                                paths : selected,
+                                source : import.source.clone(),
+                                dest : PathBuf::from(&import.dest),
+                                template : import.template.clone(),
+                            });
+                        }
+
+                        if !import.gallery.progress.done() {
+                            ui.label(import.gallery.progress.to_string());
+                        }
+
+                        if let Some(progress) = &import.progress {
+                            ui.label(progress.to_string());
+                        }
+                    });
+                });
+
+                let thumb_size = local_model.thumb_size;
+                egui::CentralPanel::default().show(ctx.egui, |ui| {
+                    let gallery = &mut import.gallery;
+                    if let Some(ThumbAction::Open(idx) | ThumbAction::Select(idx)) = gallery.show_grid(ctx, ui, thumb_size, "") {
+                        let thumb = &mut gallery.thumbs[idx];
+                        thumb.selected = !thumb.selected;
+                    }
+                });
+            },
+            Screen::Photo(photo_screen) => {
+                if photo_screen.offline {
+                    egui::TopBottomPanel::top("offline notice").show(ctx.egui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(OFFLINE_COLOR, "offline");
+                            ui.label("this is the copy from before, where it's from couldn't be reached");
+                            if ui.button("retry").clicked() {
+                                msgs.push(Msg::Refetch{path : photo_screen.photo.id.clone()});
+                            }
+                        });
+                    });
+                }
+
+                let view_mat = photo_screen.update_view(ctx);
+
+                if let Some(flipbook) = &mut photo_screen.flipbook {
+                    flipbook.advance(ctx.dt());
+
+                    egui::TopBottomPanel::bottom("flipbook").show(ctx.egui, |ui| {
+                        ui.horizontal(|ui| {
+                            let label = if flipbook.playing { "pause" } else { "play" };
+                            if ui.button(label).clicked() {
+                                flipbook.playing = !flipbook.playing;
+                                flipbook.shown_for = Duration::ZERO;
+                            }
+
+                            ui.checkbox(&mut flipbook.looping, "loop");
+
+                            ui.label("fps: ");
+                            ui.add(egui::DragValue::new(&mut flipbook.fps).clamp_range(1.0..=60.0));
+
+                            let last = flipbook.frames.len() - 1;
+                            let scrub = ui.add(egui::Slider::new(&mut flipbook.current, 0..=last));
+                            if scrub.dragged() {
+                                flipbook.playing = false;
+                            }
+
+                            let loaded = flipbook.loaded_count();
+                            if loaded < flipbook.frames.len() {
+                                ui.label(format!("{} of {} frames loaded", loaded, flipbook.frames.len()));
+                            }
+                        });
+                    });
+                }
+
+                // thumbnails are drawn by egui, which can't blur them
+                let current = &photo_screen.photo.id;
+                let opened_from = model.back.last_mut()
+                    .and_then(|screen| screen.gallery_mut())
+                    .filter(|gallery| gallery.thumbs.iter().any(|t| t.id == *current))
+                    .filter(|_| local_model.filmstrip && !hidden);
+                if let Some(gallery) = opened_from {
+                    let filmstrip = &mut photo_screen.filmstrip;
+                    egui::TopBottomPanel::bottom("filmstrip").show(ctx.egui, |ui| {
+                        if let Some(path) = filmstrip.show(ctx, ui, &mut gallery.thumbs, current) {
+                            msgs.push(Msg::Open{path});
+                        }
+                    });
+                }
+
+                let photo = &mut photo_screen.photo;
+                let shown = match photo_screen.flipbook.as_mut().and_then(|f| f.image_id(ctx)) {
+                    Some((img_id, scale)) => {
+                        Some((img_id, view_mat.mul_mat4(&Mat4::from_scale(Vec3::new(scale, scale, 1.0)))))
+                    },
+                    None => photo.data.get_image_id(ctx).map(|img_id| (img_id, view_mat)),
+                };
+                // without it there's just the panels, get_image_id said why
+                if let Some((img_id, view_mat)) = shown {
+                    let overlays = Overlays{
+                        blur : if hidden { PRIVACY_BLUR } else { 0. },
+                        pixel_grid : local_model.pixel_grid && !hidden,
+                        clipping : local_model.clipping && !hidden,
+                    };
+                    let res = local_model.effects_render.draw_image_screen_with(
+                        ctx,
+                        img_id,
+                        &view_mat,
+                        &photo.effects,
+                        &overlays,
+                    );
+                    if let Err(err) = res {
+                        self.handle_error(err);
+                    }
+
+                    if local_model.focus_peaking && !hidden {
+                        let res = local_model.peaking_render.draw_image_screen(
+                            ctx,
+                            img_id,
+                            &view_mat,
+                            &photo.effects,
+                            PEAKING_THRESHOLD,
+                        );
+                        if let Err(err) = res {
+                            self.handle_error(err);
+                        }
+                    }
+                }
+
+                let typing = ctx.egui.wants_keyboard_input();
+                if !typing && ctx.egui.input().key_pressed(egui::Key::I) {
+                    photo_screen.eyedropper = !photo_screen.eyedropper;
+                }
+                // lightroom's key for it
+                if !typing && ctx.egui.input().key_pressed(egui::Key::J) {
+                    local_model.clipping = !local_model.clipping;
+                }
+                if !typing && ctx.egui.input().key_pressed(egui::Key::P) {
+                    local_model.focus_peaking = !local_model.focus_peaking;
+                }
+
+                // alt and click samples the photo, not a flipbook's frames,
+                // and so does any click with the eyedropper
+                let eyedropper = photo_screen.eyedropper;
+                let clicked = ctx.background_input()
+                    .filter(|i| eyedropper || i.modifiers.alt())
+                    .and_then(|i| match i.drag_delta() {
+                        Some((dx, dy, true)) if dx.abs() < CLICK_SLOP && dy.abs() < CLICK_SLOP => Some(i.pointer),
+                        _ => None,
+                    });
+                if let (Some(pos), None) = (clicked, &photo_screen.flipbook) {
+                    if let Some(sample) = photo_screen.sample_at(ctx, &view_mat, pos) {
+                        local_model.samples.push(sample);
+                        local_model.samples_window = true;
+                    }
+                }
+
+                let hovered = ctx.background_input()
+                    .filter(|_| eyedropper && photo_screen.flipbook.is_none())
+                    .map(|i| i.pointer)
+                    .and_then(|pos| photo_screen.sample_at(ctx, &view_mat, pos));
+                if let Some(sample) = hovered {
+                    ctx.egui.output().cursor_icon = egui::CursorIcon::Crosshair;
+                    egui::show_tooltip(ctx.egui, egui::Id::new("eyedropper"), |ui| {
+                        let [r, g, b, a] = sample.rgba;
+                        ui.horizontal(|ui| {
+                            let (rect, _) = ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+                            ui.painter().rect_filled(rect, 2.0, egui::Color32::from_rgba_unmultiplied(r, g, b, a));
+                            ui.monospace(sample.hex());
+                        });
+                        ui.monospace(format!("R {:3}  G {:3}  B {:3}  A {:3}", r, g, b, a));
+                        ui.monospace(format!("x {}  y {}", sample.x, sample.y));
+                    });
+                }
+                let photo = &mut photo_screen.photo;
+
+                let captions = &local_model.captions;
+                let presenting = local_model.fullscreen.is_some() || local_model.slideshow.is_some();
+                if captions.show && presenting && !hidden {
+                    let name = photo.id.file_name().unwrap_or_default().to_string_lossy();
+                    let stem = photo.id.file_stem().unwrap_or_default().to_string_lossy();
+                    let title : &str = if photo.caption.trim().is_empty() { &stem } else { &photo.caption };
+                    let text = captions.text(&CaptionFields{
+                        title,
+                        date : photo.meta.date,
+                        location : photo.meta.location.as_deref(),
+                        name : &name,
+                    });
+                    captions.paint(ctx.egui, &text);
+                }
+
+                if local_model.panel.is_open(Card::Histogram) && photo.histogram.is_none() {
+                    // the whole photo, edited, in a few pixels. render_view
+                    // scales the view to the window, which is undone so the
+                    // photo fills size
+                    let (width, height) = photo.dims;
+                    let fit = histogram::RENDER_SIZE as f32 / width.max(height).max(1) as f32;
+                    let size = (((width as f32 * fit) as u32).max(1), ((height as f32 * fit) as u32).max(1));
+                    let (win_width, win_height) = ctx.dimensions();
+                    let scale = fit / (size.0 as f32 / win_width).max(size.1 as f32 / win_height);
+
+                    let effects_render = &local_model.effects_render;
+                    let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_view(
+                        ctx,
+                        img_id,
+                        &Mat4::from_scale(Vec3::new(scale, scale, 1.0)),
+                        &photo.effects,
+                        size,
+                        1,
+                        [0.; 4],
+                    ));
+
+                    match res {
+                        None => {},
+                        Some(Ok(image)) => photo.histogram = Some(Histogram::of(&image)),
+                        Some(Err(err)) => {
+                            // not tried again until the next edit
+                            photo.histogram = Some(Histogram::empty());
+                            self.handle_error(err);
+                        },
+                    }
+                }
+
+                if local_model.panel.is_open(Card::Statistics) && photo.stats.is_none() && !photo.stats_pending {
+                    // like the histogram, larger
+                    let (width, height) = photo.dims;
+                    let fit = image_stats::RENDER_SIZE as f32 / width.max(height).max(1) as f32;
+                    let fit = fit.min(1.0);
+                    let size = (((width as f32 * fit) as u32).max(1), ((height as f32 * fit) as u32).max(1));
+                    let (win_width, win_height) = ctx.dimensions();
+                    let scale = fit / (size.0 as f32 / win_width).max(size.1 as f32 / win_height);
+
+                    let effects_render = &local_model.effects_render;
+                    let res = photo.data.get_image_id(ctx).map(|img_id| effects_render.render_view(
+                        ctx,
+                        img_id,
+                        &Mat4::from_scale(Vec3::new(scale, scale, 1.0)),
+                        &photo.effects,
+                        size,
+                        1,
+                        [0.; 4],
+                    ));
+
+                    match res {
+                        None => {},
+                        Some(Ok(image)) => {
+                            photo.stats_pending = true;
+                            msgs.push(Msg::ComputeStats{
+                                path : photo.id.clone(),
+                                image : Pixels(image),
+                                edit : photo.stats_edit,
+                            });
+                        },
+                        Some(Err(err)) => {
+                            // not tried again until the next edit
+                            photo.stats = Some(ImageStats::empty());
+                            self.handle_error(err);
+                        },
+                    }
+                }
+
+                let panel = &mut local_model.panel;
+                let clipping = &mut local_model.clipping;
+                let eyedropper = &mut photo_screen.eyedropper;
+                egui::SidePanel::right("effects").resizable(false).show(ctx.egui, |ui| {
+                    let mut revert = None;
+
+                    panel.show(ui, |card, ui| match card {
+                        Card::Info => {
+                            ui.checkbox(eyedropper, "eyedropper (i)")
+                                .on_hover_text("the color under the pointer, before edits");
+
+                            let meta = &photo.meta;
+
+                            if let Some(date) = meta.date {
+                                ui.label(date.to_string());
+                            }
+
+                            match (&meta.location, meta.gps) {
+                                (Some(location), _) => { ui.label(location); },
+                                (None, Some((lat, lon))) => { ui.label(format!("{:.4}, {:.4}", lat, lon)); },
+                                (None, None) => {},
+                            }
+
+                            ui.label("caption");
+                            let caption = ui.text_edit_multiline(&mut photo.caption);
+                            photo.caption_edited |= caption.changed();
+                            if caption.lost_focus() && photo.caption_edited {
+                                photo.caption_edited = false;
+                                msgs.push(Msg::SaveCaption{
+                                    path : photo.id.clone(),
+                                    caption : photo.caption.clone(),
+                                });
+                            }
+                        },
+                        Card::Histogram => {
+                            if let Some(histogram) = &photo.histogram {
+                                histogram.show(ui);
+                            }
+                            ui.checkbox(clipping, "clipping warnings (j)")
+                                .on_hover_text("blown highlights in red, crushed shadows in blue");
+                        },
+                        Card::Statistics => {
+                            match &photo.stats {
+                                Some(stats) => stats.show(ui),
+                                None if photo.stats_pending => { ui.label("counting..."); },
+                                None => {},
+                            }
+                        },
+                        Card::Adjustments => {
+                            let as_shot = photo.meta.light_source;
+                            let effects = &mut photo.effects;
+
+                            ui.label("brightness");
+                            ui.add(egui::Slider::new(&mut effects.brightness, -0.5..=0.5));
+
+                            ui.label("contrast");
+                            ui.add(egui::Slider::new(&mut effects.contrast, 0.0..=1.0));
+
+                            let mut invert = effects.invert > 0;
+                            ui.checkbox(&mut invert, "invert");
+                            effects.invert = if invert { 1 } else { 0 };
+
+                            let mut original = effects.original > 0;
+                            ui.checkbox(&mut original, "original");
+                            effects.original = if original { 1 } else { 0 };
+
+                            // ui.separator();
+
+                            ui.label("highlight");
+                            ui.add(egui::Slider::new(&mut effects.highlight, 0.0..=1.0));
+
+                            ui.label("shadow");
+                            ui.add(egui::Slider::new(&mut effects.shadow, 0.0..=1.0));
+
+                            ui.label("white point");
+                            ui.add(egui::Slider::new(&mut effects.white_pt, 0.0..=1.0));
+
+                            ui.label("black point");
+                            ui.add(egui::Slider::new(&mut effects.black_pt, 0.0..=1.0));
+
+                            // ui.separator();
+
+                            ui.label("temperature");
+                            ui.add(egui::Slider::new(&mut effects.temperature, white_balance::TEMPERATURE).suffix(" K"));
+
+                            ui.label("tint");
+                            ui.add(egui::Slider::new(&mut effects.tint, white_balance::TINT));
+
+                            ui.horizontal_wrapped(|ui| {
+                                if let Some(shot) = as_shot {
+                                    if ui.button(format!("as shot ({})", shot.name)).clicked() {
+                                        effects.temperature = shot.temperature;
+                                        effects.tint = shot.tint;
+                                    }
+                                }
+
+                                for illuminant in white_balance::ILLUMINANTS {
+                                    if ui.button(illuminant.name).clicked() {
+                                        effects.temperature = illuminant.temperature;
+                                        effects.tint = illuminant.tint;
+                                    }
+                                }
+
+                                if ui.button("neutral").clicked() {
+                                    effects.temperature = Effects::default().temperature;
+                                    effects.tint = Effects::default().tint;
+                                }
+                            });
+
+                            ui.label(format!("process version {}", effects.process_version));
+                            if effects.needs_upgrade() && ui.button("upgrade processing").clicked() {
+                                effects.upgrade_processing();
+                            }
+                        },
+                        Card::Crop => {
+                            let crop_suggestions = &photo.crop_suggestions;
+                            let effects = &mut photo.effects;
+                            let [x, y, w, h] = &mut effects.crop;
+                            ui.add(egui::Slider::new(w, 0.05..=1.0).text("width"));
+                            ui.add(egui::Slider::new(h, 0.05..=1.0).text("height"));
+                            ui.add(egui::Slider::new(x, 0.0..=1.0 - *w).text("left"));
+                            ui.add(egui::Slider::new(y, 0.0..=1.0 - *h).text("top"));
+
+                            ui.horizontal_wrapped(|ui| {
+                                for suggestion in crop_suggestions {
+                                    if ui.button(suggestion.name).clicked() {
+                                        effects.crop = suggestion.crop;
+                                    }
+                                }
+
+                                if ui.button("uncrop").clicked() {
+                                    effects.crop = Effects::default().crop;
+                                }
+                            });
+                        },
+                        Card::History => {
+                            let entries = photo.history.entries.iter().enumerate().rev();
+                            for (idx, edit) in entries {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{}. {}", idx, fmt_ago(edit.time)));
+                                    if ui.small_button("revert").clicked() {
+                                        revert = Some(idx);
+                                    }
+                                });
+                            }
+                        },
+                    });
+
+                    // whichever cards are open. wait for the slider to be
+                    // released so a drag is one entry
+                    if !ui.ctx().is_using_pointer() && photo.history.record(&photo.effects) {
+                        photo.histogram = None;
+                        photo.stats = None;
+                        photo.stats_pending = false;
+                        photo.stats_edit += 1;
+                        self.unsaved_edits.fetch_add(1, Ordering::Relaxed);
+                        msgs.push(Msg::SaveEdits{
+                            path : photo.id.clone(),
+                            history : photo.history.clone(),
+                        });
+                    }
+
+                    // reverting is recorded as a new entry on the next frame
+                    if let Some(idx) = revert {
+                        photo.effects = photo.history.entries[idx].effects.clone();
+                    }
+                });
+            },
+            Screen::Gallery(gallery) => {
+                if let Some(view) = gallery.restore_view.take() {
+                    local_model.thumb_size = view.thumb_size;
+                    local_model.gallery_layout = view.layout;
+                    local_model.group_by = view.group_by;
+                }
+
+                let view = FolderView{
+                    thumb_size : local_model.thumb_size,
+                    layout : local_model.gallery_layout,
+                    group_by : local_model.group_by,
+                };
+
+                if let PhotoSet::Folder{path, ..} = &gallery.set {
+                    // the first frame is how the folder was opened, not a change
+                    if gallery.shown_view.map_or(false, |shown| shown != view) {
+                        msgs.push(Msg::SaveFolderView{
+                            path : path.into(),
+                            view,
+                        });
+                    }
+                }
+                gallery.shown_view = Some(view);
+
+                let thumb_size = &mut local_model.thumb_size;
+                let search = &mut local_model.gallery_search;
+                let map_view = &mut local_model.map_view;
+
+                // taken out of the album this frame
+                let mut remove_selected = false;
+
+                egui::TopBottomPanel::top("gallery header").show(ctx.egui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(thumb_size, 50.0..=400.0).text("thumbnail size"));
+
+                        ui.label("search: ");
+                        ui.text_edit_singleline(search);
+
+                        // picking a country or a place in it searches for it
+                        let places = gallery.places();
+                        if !places.is_empty() {
+                            egui::ComboBox::from_id_source("places").selected_text("places").show_ui(ui, |ui| {
+                                for (country, count, in_country) in &places {
+                                    if ui.selectable_label(search == country, format!("{} ({})", country, count)).clicked() {
+                                        *search = country.clone();
+                                    }
+                                    for (place, count) in in_country {
+                                        let name = place.rsplitn(2, ", ").last().unwrap_or(place);
+                                        if ui.selectable_label(search == place, format!("    {} ({})", name, count)).clicked() {
+                                            *search = place.clone();
+                                        }
+                                    }
+                                }
+                            });
+                        }
+                        ui.checkbox(map_view, "map");
+
+                        if !gallery.progress.done() {
+                            ui.label(gallery.progress.to_string());
+                        }
+
+                        if gallery.offline {
+                            ui.colored_label(OFFLINE_COLOR, "offline")
+                                .on_hover_text("the remote couldn't be reached, these are the copies from before");
+                            if ui.button("retry").clicked() {
+                                msgs.push(Msg::OpenSet{
+                                    set : gallery.set.clone(),
+                                    thumb_res : gallery.thumb_res,
+                                });
+                            }
+                        }
+
+                        let selected : Vec<&Thumb> = gallery.thumbs.iter().filter(|t| t.selected).collect();
+                        if let [a, b] = selected[..] {
+                            if ui.button("compare").on_hover_text("the two selected photos side by side").clicked() {
+                                msgs.push(Msg::OpenCompare{
+                                    paths : [a.id.clone(), b.id.clone()],
+                                });
+                            }
+                        }
+
+                        let cull = ui.button("cull")
+                            .on_hover_text("a photo at a time, p picks, x rejects, 0 to 5 rate");
+                        if cull.clicked() {
+                            // from the first selected one, or the start
+                            let shown = gallery.matching(search);
+                            gallery.cull = shown.iter()
+                                .find(|&&idx| gallery.thumbs[idx].selected)
+                                .or_else(|| shown.first())
+                                .copied();
+                            gallery.quick_look = None;
+                        }
+
+                        let selected : Vec<PathBuf> = gallery.thumbs.iter()
+                            .filter(|t| t.selected)
+                            .map(|t| t.id.clone())
+                            .collect();
+                        if let (PhotoSet::Album(name), false) = (&gallery.set, selected.is_empty()) {
+                            let remove = ui.button("remove from album")
+                                .on_hover_text("takes the selected photos out, the files stay");
+                            if remove.clicked() {
+                                msgs.push(Msg::RemoveFromAlbum{
+                                    name : name.clone(),
+                                    photos : selected,
+                                });
+                                remove_selected = true;
+                            }
+                        }
+                    });
+
+                    if gallery.sequences.is_none() && gallery.progress.done() {
+                        let paths = gallery.thumbs.iter().map(|t| t.id.as_path());
+                        gallery.sequences = Some(sequence::detect(paths));
+                    }
+
+                    let sequences = gallery.sequences.as_deref().unwrap_or_default();
+                    if !sequences.is_empty() {
+                        ui.horizontal_wrapped(|ui| {
+                            for sequence in sequences {
+                                let label = format!("play {}", sequence.name);
+                                if ui.button(label).clicked() {
+                                    msgs.push(Msg::OpenSequence{
+                                        frames : sequence.frames.clone(),
+                                    });
+                                }
+                            }
+                        });
+                    }
+
+                    if gallery.stacks.is_none() && gallery.progress.done() {
+                        gallery.stacks = Some(find_stacks(&gallery.thumbs));
+                    }
+
+                    // brackets can be merged here, panoramas are selected
+                    // to be exported to a stitcher
+                    let mut select = None;
+                    let found = gallery.stacks.as_deref().unwrap_or_default();
+                    if !found.is_empty() {
+                        ui.horizontal_wrapped(|ui| {
+                            for stack in found {
+                                let first = stack.frames[0].file_name().unwrap_or_default().to_string_lossy();
+                                ui.label(format!("{} of {} from {}:", stack.kind.name(), stack.frames.len(), first));
+
+                                if stack.kind == stacks::StackKind::Bracket && ui.button("merge").clicked() {
+                                    msgs.push(Msg::MergeBracket{
+                                        frames : stack.frames.clone(),
+                                    });
+                                }
+                                if ui.button("select").clicked() {
+                                    select = Some(stack.frames.clone());
+                                }
+                            }
+                        });
+                    }
+
+                    if let Some(frames) = select {
+                        for thumb in &mut gallery.thumbs {
+                            thumb.selected = frames.contains(&thumb.id);
+                        }
+                    }
+                });
+
+                if *map_view {
+                    // the ones searched for, like the thumbnails
+                    let located : Vec<(usize, (f64, f64), String)> = gallery.matching(search).into_iter()
+                        .filter_map(|idx| {
+                            let thumb = &gallery.thumbs[idx];
+                            let name = thumb.id.file_name().unwrap_or_default().to_string_lossy();
+                            let label = match &thumb.meta.location {
+                                Some(location) => format!("{}, {}", name, location),
+                                None => name.into_owned(),
+                            };
+                            Some((idx, thumb.meta.gps?, label))
+                        })
+                        .collect();
+                    let pins : Vec<map::Pin> = located.iter()
+                        .map(|(_, (lat, lon), label)| map::Pin{ lat : *lat, lon : *lon, label })
+                        .collect();
+
+                    egui::Window::new("Map")
+                        .open(map_view)
+                        .default_size([480.0, 320.0])
+                        .show(ctx.egui, |ui| {
+                            if let Some(pin) = map::show(ui, &pins) {
+                                msgs.push(Msg::Open{
+                                    path : gallery.thumbs[located[pin].0].id.clone(),
+                                });
+                            }
+                        });
+                }
+
+                if remove_selected {
+                    let dropped = &mut model.dropped_images;
+                    gallery.thumbs.retain(|thumb| {
+                        if !thumb.selected {
+                            return true
+                        }
+
+                        if let PhotoData::GPU(img_id) = thumb.data {
+                            dropped.push(img_id);
+                        }
+
+                        false
+                    });
+                    // the indices moved
+                    gallery.quick_look = None;
+                    gallery.cull = None;
+                }
+
+                if local_model.album_sidebar {
+                    let current = match &gallery.set {
+                        PhotoSet::Album(name) |
+                        PhotoSet::Smart(name) => Some(name.as_str()),
+                        _ => None,
+                    };
+
+                    egui::SidePanel::left("albums").resizable(false).show(ctx.egui, |ui| {
+                        if ui.selectable_label(false, "All Albums").clicked() {
+                            msgs.push(Msg::OpenAlbums{
+                                thumb_res : thumb_res(*thumb_size, scale_factor),
+                            });
+                        }
+                        ui.separator();
+
+                        egui::ScrollArea::auto_sized().show(ui, |ui| {
+                            let library = self.library.lock_or_recover();
+                            for name in library.albums.keys() {
+                                if ui.selectable_label(current == Some(name.as_str()), name).clicked() {
+                                    msgs.push(Msg::OpenSet{
+                                        set : PhotoSet::Album(name.clone()),
+                                        thumb_res : thumb_res(*thumb_size, scale_factor),
+                                    });
+                                }
+                            }
+
+                            if !library.smart_albums.is_empty() {
+                                ui.separator();
+                            }
+                            for (name, query) in library.smart_albums.iter() {
+                                let label = ui.selectable_label(current == Some(name.as_str()), name).on_hover_text(query);
+                                if label.clicked() {
+                                    msgs.push(Msg::OpenSet{
+                                        set : PhotoSet::Smart(name.clone()),
+                                        thumb_res : thumb_res(*thumb_size, scale_factor),
+                                    });
+                                }
+                            }
+                        });
+                    });
+                }
+
+                // the thumbnails would be upscaled, ask for bigger ones. the
+                // ones there are drawn scaled until they're replaced
+                let res = thumb_res(*thumb_size, scale_factor);
+                if res > gallery.thumb_res {
+                    gallery.thumb_res = res;
+                    msgs.push(Msg::RescaleThumbs{
+                        thumb_res : res,
+                    });
+                }
+
+                let thumb_size = *thumb_size;
+
+                let layout = local_model.gallery_layout;
+                let group_by = local_model.group_by;
+                let scroll_to_year = &mut local_model.scroll_to_year;
+
+                if group_by != GroupBy::None {
+                    // date scrubber
+                    egui::SidePanel::right("years").resizable(false).show(ctx.egui, |ui| {
+                        egui::ScrollArea::auto_sized().show(ui, |ui| {
+                            for year in gallery.years() {
+                                if ui.selectable_label(false, year.to_string()).clicked() {
+                                    *scroll_to_year = Some(year);
+                                }
+                            }
+                        });
+                    });
+                }
+
+                let file_dialog = &mut local_model.file_dialog;
+                let search = local_model.gallery_search.as_str();
+                // opened or closed by a thumbnail this frame
+                let mut toggled = false;
+
+                egui::CentralPanel::default().show(ctx.egui, |ui| {
+                    let action = match (group_by, layout) {
+                        (GroupBy::None, GalleryLayout::Grid) =>
+                            gallery.show_grid(ctx, ui, thumb_size, search),
+                        (GroupBy::None, GalleryLayout::Justified) =>
+                            gallery.show_justified(ctx, ui, thumb_size, search),
+                        (_, _) =>
+                            gallery.show_grouped(ctx, ui, thumb_size, group_by, scroll_to_year, search),
+                    };
+
+                    match action {
+                        Some(ThumbAction::Open(idx)) => {
+                            let path = &gallery.thumbs[idx].id;
+                            tracing::debug!("loading {}", path.display());
+                            msgs.push(Msg::Open{path : path.clone()});
+                        },
+                        Some(ThumbAction::Select(idx)) => {
+                            let thumb = &mut gallery.thumbs[idx];
+                            thumb.selected = !thumb.selected;
+                        },
+                        Some(ThumbAction::Manage(idx)) => {
+                            let path = gallery.thumbs[idx].id.clone();
+                            let albums = self.albums_with(&path);
+                            let tags = self.tags(&path);
+                            *file_dialog = Some(FileDialog::new(path, albums, tags));
+                        },
+                        Some(ThumbAction::Reorder{from, to}) => {
+                            if let PhotoSet::Album(name) = &gallery.set {
+                                msgs.push(Msg::ReorderAlbum{
+                                    name : name.clone(),
+                                    from : gallery.thumbs[from].id.clone(),
+                                    to : gallery.thumbs[to].id.clone(),
+                                });
+                            }
+
+                            // same as the library, so it doesn't jump back
+                            let thumb = gallery.thumbs.remove(from);
+                            gallery.thumbs.insert(to, thumb);
+                            gallery.quick_look = None;
+                        },
+                        Some(ThumbAction::QuickLook(idx)) => {
+                            gallery.quick_look = match gallery.quick_look {
+                                Some(_) => None,
+                                None => Some(idx),
+                            };
+                            toggled = true;
+                        },
+                        None => {},
+                    }
+                });
+
+                if gallery.quick_look.is_some() {
+                    show_quick_look(ctx, gallery, search, toggled, &mut msgs);
+                }
+                if gallery.cull.is_some() {
+                    let preload = if self.options.low_memory { CULL_PRELOAD / 2 } else { CULL_PRELOAD };
+                    show_cull(ctx, gallery, search, preload, &mut msgs);
+                }
+            },
+        }
+
+        if local_model.preview.is_some() {
+            draw_preview(ctx, &local_model.effects_render, &mut model.screen, &mut msgs);
+        }
+
+        // thumbnails are drawn by egui, which can't blur them, so cover them
+        let has_thumbs = !matches!(model.screen, Screen::Empty | Screen::Photo(_) | Screen::Compare(_) | Screen::Operations(_));
+        if hidden && has_thumbs {
+            let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("privacy"));
+            let rect = ctx.egui.input().screen_rect();
+            ctx.egui.layer_painter(layer).rect_filled(rect, 0.0, egui::Color32::from_gray(40));
+        }
+
+        ctx.set_title(&self.title(model));
+
+        msgs.into()
+    }
+
+    fn priority(&self, msg : &Msg) -> Priority {
+        use Msg::*;
+
+        match msg {
+            // slow and nobody is waiting to see the result
+            FindDuplicates{..} |
+            RescaleThumbs{..} |
+            MergeBracket{..} |
+            ComputeStats{..} |
+            Import{..} |
+            ExportDzi{..} |
+            ExportImage{..} |
+            Print{..} |
+            SetWallpaper{..} |
+            EditExternally{..} |
+            ExportContactSheet{..} |
+            ExportPreset{..} |
+            ExportPresets{..} |
+            BatchExport(_) |
+            ExportManifest{..} => Priority::Background,
+            // the Save* and *Album messages have to be done before the next
+            // Open or OpenSet reads them
+            Open{..} |
+            OpenAnyway{..} |
+            OpenUrl{..} |
+            Refetch{..} |
+            OpenShortcut{..} |
+            OpenSet{..} |
+            OpenImport{..} |
+            OpenCompare{..} |
+            OpenSequence{..} |
+            OpenManifest{..} |
+            LoadPreview{..} |
+            Trash{..} |
+            Rename{..} |
+            Move{..} |
+            SaveFolderView{..} |
+            SaveEdits{..} |
+            SaveCaption{..} |
+            Mark{..} |
+            AddToAlbum{..} |
+            RemoveFromAlbum{..} |
+            SaveSmartAlbum{..} |
+            DeleteAlbum{..} |
+            SetAlbumCover{..} |
+            ReorderAlbum{..} |
+            CopyImage{..} |
+            Paste{..} |
+            ExternalEdit{..} |
+            OpenAlbums{..} |
+            OpenOperations => Priority::Interactive,
+        }
+    }
+
+    fn coalesce(&self, last : &mut Msg, msg : Msg) -> Option<Msg> {
+        use Msg::*;
+
+        match (&*last, &msg) {
+            // only the last screen opened would be seen
+            (Open{..}, Open{..}) |
+            (Open{..}, OpenSet{..}) |
+            (Open{..}, OpenImport{..}) |
+            (OpenSet{..}, Open{..}) |
+            (OpenSet{..}, OpenSet{..}) |
+            (OpenSet{..}, OpenImport{..}) |
+            (OpenImport{..}, Open{..}) |
+            (OpenImport{..}, OpenSet{..}) |
+            (OpenImport{..}, OpenImport{..}) |
+            (FindDuplicates{..}, FindDuplicates{..}) |
+            // the slider is still moving, only the last size matters
+            (RescaleThumbs{..}, RescaleThumbs{..}) => {
+                *last = msg;
+                None
+            },
+            // the newer history has the older one's entries
+            (SaveEdits{path : a, ..}, SaveEdits{path : b, ..}) |
+            (SaveFolderView{path : a, ..}, SaveFolderView{path : b, ..}) |
+            (SaveCaption{path : a, ..}, SaveCaption{path : b, ..}) if a == b => {
+                *last = msg;
+                None
+            },
+            _ => Some(msg),
+        }
+    }
+
+    fn on_event(&self,
+                local_model : &mut LocalModel,
+                event : &glium::glutin::event::WindowEvent<'_>) -> Option<Msg>
     {
-        ctx.clear_color(GRAY);
+        use glium::glutin::event::{
+            ElementState,
+            WindowEvent,
+        };
+
+        // egui doesn't know about the button
+        if let WindowEvent::MouseInput{state : ElementState::Pressed, button, ..} = event {
+            local_model.mouse_back |= is_back_button(*button);
+        }
+        None
+    }
+
+    fn close_requested(&self, local_model : &mut LocalModel) -> bool {
+        // an import or export stopped halfway leaves a mess
+        if self.jobs.jobs().is_empty() {
+            return true
+        }
+
+        local_model.confirm_quit = true;
+        false
+    }
+
+    async fn shutdown(&'static self, _model : &BufBufWrite<Model>) {
+        // scans and exports check this between photos, the library is
+        // saved as it changes but a failed save may have been missed
+        self.jobs.cancel_all();
+        if let Err(err) = self.save_library().await {
+            self.handle_error(err);
+        }
+    }
+
+    fn handle_error(&self, err : Error) {
+        tracing::error!("{:?}", err);
+        self.toasts.error(err.to_string());
+    }
+
+    async fn update(&'static self,
+                    model_buf : &BufBufWrite<Self::Model>,
+                    msg : Self::Msg) -> Result<Cmd<Msg>> {
+
+        match msg {
+            Msg::Open{path} => {
+                let photo = opt_unwrap_or!(self.load_photo(path).await?, {
+                    return Ok(Cmd::none())
+                });
+
+                let mut screen = PhotoScreen::new(photo);
+                screen.offline = self.offline.lock_or_recover().contains_key(&screen.photo.id);
+                model_buf.set_next(Model::new(Screen::Photo(screen)));
+
+                Ok(Cmd::none())
+            },
+            Msg::OpenCompare{paths : [a, b]} => {
+                let a = self.load_photo(a).await?;
+                let b = self.load_photo(b).await?;
+                let (a, b) = opt_unwrap_or!(a.zip(b), {
+                    return Ok(Cmd::none())
+                });
+
+                model_buf.set_next(Model::new(Screen::Compare(CompareScreen::new(a, b))));
+
+                Ok(Cmd::none())
+            },
+            Msg::OpenAnyway{path} => {
+                self.allow_large.lock_or_recover().insert(path.clone());
+                Ok(Cmd::msg(Msg::Open{path}))
+            },
+            Msg::OpenUrl{url} => {
+                let max_size = self.limits.lock_or_recover().max_file_size;
+                let link = url.clone();
+                let fetched = self.download(move |rate| web::fetch(&link, max_size, rate)).await?;
+
+                self.set_offline(&fetched.value, fetched.offline, || Refetch::Url(url));
+                Ok(Cmd::msg(Msg::Open{path : fetched.value}))
+            },
+            Msg::Refetch{path} => {
+                let refetch = self.offline.lock_or_recover().get(&path).cloned();
+                let refetch = opt_unwrap_or!(refetch, {
+                    return Ok(Cmd::msg(Msg::Open{path}))
+                });
+
+                let max_size = self.limits.lock_or_recover().max_file_size;
+                let fetched = self.download(move |rate| match refetch {
+                    Refetch::Url(url) => web::fetch(&url, max_size, rate),
+                    Refetch::Remote(location, path) => location.refetch(&path, rate),
+                }).await?;
+
+                if fetched.offline {
+                    self.toasts.info(format!("{} still can't be reached", path.display()));
+                    return Ok(Cmd::none())
+                }
+
+                self.offline.lock_or_recover().remove(&path);
+                Ok(Cmd::msg(Msg::Open{path : fetched.value}))
+            },
+            Msg::OpenShortcut{path} => {
+                let contents = tokio::fs::read_to_string(&path).await?;
+                match web::shortcut_url(&contents) {
+                    Some(url) => Ok(Cmd::msg(Msg::OpenUrl{url})),
+                    None => {
+                        self.toasts.info(format!("no link in {}", path.display()));
+                        Ok(Cmd::none())
+                    },
+                }
+            },
+            Msg::OpenSet{set : photo_set, thumb_res} => {
+                let restore_view = match &photo_set {
+                    PhotoSet::Folder{path, ..} => {
+                        self.library.lock_or_recover().folders.get(Path::new(path)).copied()
+                    },
+                    PhotoSet::List(_) |
+                    PhotoSet::Album(_) |
+                    PhotoSet::Smart(_) => None,
+                };
+
+                let generation = model_buf.set_next(Model::new(Screen::Gallery(Gallery{
+                    set : photo_set.clone(),
+                    thumb_res,
+                    thumbs : Vec::new(),
+                    progress : Default::default(),
+                    restore_view,
+                    shown_view : None,
+                    sequences : None,
+                    stacks : None,
+                    quick_look : None,
+                    cull : None,
+                    previews : Default::default(),
+                    offline : false,
+                })));
+
+                self.scan(photo_set, thumb_res, model_buf.clone(), generation);
+
+                Ok(Cmd::none())
+            },
+            Msg::OpenImport{source, thumb_res} => {
+                let photo_set = PhotoSet::Folder{
+                    path : source.to_string_lossy().into_owned(),
+                    // cameras nest things in DCIM/100CANON etc.
+                    max_depth : 4,
+                };
+
+                let generation = model_buf.set_next(Model::new(Screen::Import(ImportScreen{
+                    source,
+                    gallery : Gallery{
+                        set : photo_set.clone(),
+                        thumb_res,
+                        thumbs : Vec::new(),
+                        progress : Default::default(),
+                        restore_view : None,
+                        shown_view : None,
+                        sequences : None,
+                        stacks : None,
+                        quick_look : None,
+                        cull : None,
+                        previews : Default::default(),
+                        offline : false,
+                    },
+                    dest : String::new(),
+                    template : "YYYY/MM/DD".to_string(),
+                    progress : None,
+                })));
+
+                self.scan(photo_set, thumb_res, model_buf.clone(), generation);
+
+                Ok(Cmd::none())
+            },
+            Msg::OpenSequence{frames} => {
+                let first = opt_unwrap_or!(frames.first(), {
+                    return Ok(Cmd::none())
+                });
+
+                let (byt, image) = self.read_image(first).await?;
+                let mut photo = Photo::new(first.clone(), &byt, image, self.options.low_memory);
+                self.locate(&mut photo.meta).await;
+
+                let photo_width = match &photo.data {
+                    PhotoData::CPU(image) => image.width(),
+                    // Photo::new decodes to the cpu
+                    PhotoData::GPU(_) | PhotoData::Failed => unreachable!(),
+                };
+
+                let mut screen = PhotoScreen::new(photo);
+                screen.flipbook = Some(Flipbook::new(frames.clone(), photo_width));
+
+                let generation = model_buf.set_next(Model::new(Screen::Photo(screen)));
+                let model = model_buf.clone();
+
+                let frame_size = if self.options.low_memory { FRAME_SIZE / 2 } else { FRAME_SIZE };
+                let name = format!("loading {} frames", frames.len());
+
+                spawn_job!(self, for generation, name, job, {
+                    job.set_total(frames.len());
+
+                    for (idx, path) in frames.into_iter().enumerate() {
+                        job.resumed().await;
+                        if !model.is_current(generation) || job.cancelled() {
+                            break;
+                        }
+
+                        let (_, mut image) = self.read_image(&path).await?;
+                        let (width, height) = image::GenericImageView::dimensions(&image);
+                        if width.max(height) > frame_size {
+                            image = image.thumbnail(frame_size, frame_size);
+                        }
+                        let image = image.to_rgba8();
+                        job.inc();
+
+                        let frame = Frame{
+                            width : image.width(),
+                            data : PhotoData::CPU(image),
+                        };
+                        let alive = model.patch_in(generation, |model| model.screen.flipbook_mut(), move |flipbook| {
+                            flipbook.loaded[idx] = Some(frame);
+                        });
+
+                        if !alive {
+                            break;
+                        }
+                    }
+
+                    Ok(())
+                });
+
+                Ok(Cmd::none())
+            },
+            Msg::LoadPreview{path} => {
+                let res = self.read_image(&path).await;
+                let (_, image) = res_unwrap_or!(res, err, {
+                    // so it's asked for again next time
+                    model_buf.patch_current(move |model| {
+                        if let Some(gallery) = model.screen.gallery_mut() {
+                            gallery.previews.pending.remove(&path);
+                        }
+                    });
+                    return Err(err)
+                });
+
+                let size = if self.options.low_memory { PREVIEW_SIZE / 2 } else { PREVIEW_SIZE };
+                let kept = if self.options.low_memory { PREVIEWS_KEPT / 2 } else { PREVIEWS_KEPT };
+                let (width, height) = image::GenericImageView::dimensions(&image);
+                let image = if width.max(height) > size {
+                    image.thumbnail(size, size).into_rgba8()
+                } else {
+                    image.into_rgba8()
+                };
+
+                // not patch_in, another screen by now shouldn't be retired
+                model_buf.patch_current(move |model| {
+                    let Model{screen, dropped_images, ..} = model;
+                    if let Some(gallery) = screen.gallery_mut() {
+                        gallery.previews.insert(path, image, kept, dropped_images);
+                    }
+                });
+
+                Ok(Cmd::none())
+            },
+            Msg::RescaleThumbs{thumb_res} => {
+                let generation = model_buf.current();
+                let paths = model_buf.with(generation, |model| model.screen.gallery_mut(), |gallery| {
+                    gallery.thumbs.iter().map(|t| t.id.clone()).collect::<Vec<_>>()
+                }).await;
+                let paths = res_unwrap_or!(paths, {
+                    return Ok(Cmd::none())
+                });
+                let model = model_buf.clone();
+
+                spawn_job!(self, for generation, "sharpening thumbnails", job, {
+                    job.set_total(paths.len());
+
+                    for path in paths {
+                        job.resumed().await;
+                        if !model.is_current(generation) || job.cancelled() {
+                            break;
+                        }
+
+                        // a file gone since the scan keeps its old thumbnail
+                        let image = match self.read_image(&path).await {
+                            Ok((_, image)) => image.thumbnail(thumb_res as u32, thumb_res as u32).into_rgba8(),
+                            Err(_) => {
+                                job.inc();
+                                continue
+                            },
+                        };
+
+                        // the whole model rather than the gallery, for the
+                        // old texture to be freed
+                        model.patch_in(generation, |model| model.screen.gallery_mut().is_some().then(|| model), move |model| {
+                            let Model{screen, dropped_images, ..} = model;
+                            let gallery = opt_unwrap_or!(screen.gallery_mut(), {
+                                return
+                            });
+                            // a bigger size was asked for since
+                            if gallery.thumb_res != thumb_res {
+                                return
+                            }
+                            if let Some(thumb) = gallery.thumbs.iter_mut().find(|t| t.id == path) {
+                                thumb.dims = image.dimensions();
+                                let old = std::mem::replace(&mut thumb.data, PhotoData::CPU(image));
+                                if let PhotoData::GPU(img_id) = old {
+                                    dropped_images.push(img_id);
+                                }
+                            }
+                        });
+                        job.inc();
+                    }
+
+                    Ok(())
+                });
+
+                Ok(Cmd::none())
+            },
+            Msg::MergeBracket{frames} => {
+                let first = opt_unwrap_or!(frames.first().cloned(), {
+                    return Ok(Cmd::none())
+                });
+
+                spawn_job!(self, "merging a bracket", job, {
+                    job.set_total(frames.len() + 1);
+
+                    let mut images = Vec::new();
+                    for path in &frames {
+                        let (_, image) = self.read_image(path).await?;
+                        images.push(image.into_rgb8());
+                        job.inc();
+                    }
+
+                    let merged = tokio::task::spawn_blocking(move || stacks::fuse(&images))
+                        .await
+                        .map_err(|err| Error::from(std::io::Error::from(err)))?;
+                    let merged = opt_unwrap_or!(merged, {
+                        self.toasts.error("the bracket's photos aren't all the same size");
+                        return Ok(())
+                    });
+
+                    let dir = first.parent().unwrap_or_else(|| Path::new("."));
+                    let name = format!("{}-merged.png", first.file_stem().unwrap_or_default().to_string_lossy());
+                    let dest = free_path(dir, name.as_ref()).await;
+
+                    let save_path = dest.clone();
+                    tokio::task::spawn_blocking(move || merged.save(save_path))
+                        .await
+                        .map_err(|err| Error::from(std::io::Error::from(err)))??;
+                    job.inc();
+
+                    self.toasts.info(format!("merged into {}", dest.display()));
+                    self.record_export(&first, dest);
+                    self.save_library().await?;
+
+                    Ok(())
+                });
 
-        egui::TopBottomPanel::top("menu bar").show(ctx.egui, |ui| {
-            egui::menu::bar(ui, |ui| {
-                egui::menu::menu(ui, "File", |ui| {
-                    local_model.open_dialog |= ui.button("Open").clicked();
+                Ok(Cmd::none())
+            },
+            Msg::FindDuplicates{thumb_res} => {
+                // the open gallery's are hashed already, and might not be in
+                // the library yet
+                let mut paths_hashes = model_buf.with(model_buf.current(), |model| model.screen.gallery_mut(), |gallery| {
+                    gallery.thumbs.iter().map(|t| (t.id.clone(), t.dhash)).collect::<Vec<_>>()
+                }).await.unwrap_or_default();
+                let mut paths = self.library.lock_or_recover().catalog();
+                for (path, _) in paths_hashes.iter() {
+                    paths.remove(path);
+                }
 
-                    if ui.button("Gallery").clicked() {
-                        println!("gallery!");
-                        msgs.push(Msg::OpenSet(PhotoSet::List(
-                            vec![
-                                "test0.png".to_string(),
-                                "test1.jpg".to_string(),
-                            ],
-                        )));
+                let generation = model_buf.set_next(Model::new(Screen::Duplicates(DuplicatesScreen{
+                    groups : Vec::new(),
+                    loading : true,
+                    confirm_trash : false,
+                })));
+                let model = model_buf.clone();
+
+                spawn_job!(self, for generation, "finding duplicates", job, {
+                    job.set_total(paths.len());
+
+                    // only the hashes are kept, the thumbnails of a whole
+                    // catalog wouldn't fit. the groups' are loaded again
+                    for path in paths {
+                        job.resumed().await;
+                        if !model.is_current(generation) || job.cancelled() {
+                            break;
+                        }
+
+                        match self.thumb(path, thumb_res).await {
+                            Ok(thumb) => paths_hashes.push((thumb.id, thumb.dhash)),
+                            // moved or deleted since it was added
+                            Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {},
+                            Err(err) => self.handle_error(err),
+                        }
+                        job.inc();
                     }
-                });
-            });
-        });
 
-        {
-            // TODO: native file open dialog?
-            let LocalModel{
-                open_dialog,
-                open_dialog_input,
-                ..
-            } = local_model;
+                    let hashes : Vec<u64> = paths_hashes.iter().map(|(_, h)| *h).collect();
+                    let groups = match tokio::task::spawn_blocking(move || dupes::group(&hashes)).await {
+                        Ok(groups) => groups,
+                        Err(err) => {
+                            self.handle_error(std::io::Error::from(err).into());
+                            Vec::new()
+                        },
+                    };
+                    job.add_total(groups.len());
 
-            let mut submitted = false;
+                    for group in groups {
+                        job.resumed().await;
+                        if job.cancelled() {
+                            break;
+                        }
 
-            egui::Window::new("Open File")
-                .collapsible(false)
-                .resizable(false)
-                .open(open_dialog)
-                .show(ctx.egui, |ui| {
-                    ui.horizontal(|ui| {
-                        ui.label("Folder name: ");
-                        ui.text_edit_singleline(open_dialog_input);
-                    });
+                        let mut thumbs = Vec::new();
+                        for idx in group {
+                            // one bad file shouldn't stop the search
+                            match self.thumb(paths_hashes[idx].0.clone(), thumb_res).await {
+                                Ok(thumb) => thumbs.push(thumb),
+                                Err(err) => self.handle_error(err),
+                            }
+                        }
 
-                    if ui.button("open").clicked() {
-                        println!("opening: {}", open_dialog_input);
-                        let dir = std::mem::replace(open_dialog_input, String::new());
-                        msgs.push(Msg::OpenSet(PhotoSet::Folder(dir)));
-                        submitted = true;
+                        // what's left of the group isn't a duplicate
+                        if thumbs.len() < 2 {
+                            job.inc();
+                            continue;
+                        }
+
+                        let alive = model.patch_in(generation, |model| model.screen.duplicates_mut(), move |dupes| {
+                            dupes.groups.push(thumbs);
+                        });
+
+                        if !alive {
+                            break;
+                        }
+                        job.inc();
                     }
+
+                    model.patch_in(generation, |model| model.screen.duplicates_mut(), |dupes| {
+                        dupes.loading = false;
+                    });
+
+                    Ok(())
                 });
 
-            if submitted {
-                *open_dialog = false;
-            }
-        }
+                Ok(Cmd::none())
+            },
+            Msg::Import{paths, source, dest, template} => {
+                let generation = model_buf.current();
+                let model = model_buf.clone();
 
-        match &mut model.screen {
-            Screen::Empty => {},
-            Screen::Photo(photo_screen) => {
-                let view_mat = photo_screen.update_view(ctx);
+                spawn_job!(self, format!("importing to {}", dest.display()), job, {
+                    job.set_total(paths.len());
 
-                let photo = &mut photo_screen.photo;
-                let img_id = photo.data.get_image_id(ctx);
-                local_model.effects_render.draw_image_screen(
-                    ctx,
-                    img_id,
-                    &view_mat,
-                    &photo.effects
-                ).unwrap();
+                    // hashes of the files in each destination folder
+                    let mut known : HashMap<PathBuf, HashSet<u64>> = HashMap::new();
+                    let mut summary = ImportProgress{
+                        total : paths.len(),
+                        ..Default::default()
+                    };
+                    for path in paths {
+                        if job.cancelled() {
+                            break;
+                        }
 
-                egui::SidePanel::right("effects").resizable(false).show(ctx.egui, |ui| {
-                    let effects = &mut photo.effects;
+                        let info = self.sources.read(&path, &source).await;
+
+                        let res : Result<Option<PathBuf>> = async {
+                            let byt = tokio::fs::read(&path).await?;
+                            let mut date = Metadata::read(&byt).date
+                                .or_else(|| info.date.and_then(DateTime::from_system_time));
+                            if date.is_none() {
+                                date = tokio::fs::metadata(&path).await?
+                                    .modified()
+                                    .ok()
+                                    .and_then(DateTime::from_system_time);
+                            }
+
+                            let dir = dest.join(expand_template(&template, date));
+                            if !known.contains_key(&dir) {
+                                let hashes = hash_dir(&dir).await?;
+                                known.insert(dir.clone(), hashes);
+                            }
 
-                    ui.label("brightness");
-                    ui.add(egui::Slider::new(&mut effects.brightness, -0.5..=0.5));
+                            let hashes = known.get_mut(&dir).unwrap();
+                            if !hashes.insert(hash_bytes(&byt)) {
+                                // already imported
+                                return Ok(None)
+                            }
+
+                            tokio::fs::create_dir_all(&dir).await?;
+                            let to = free_path(&dir, path.file_name().unwrap_or_default()).await;
+                            tokio::fs::write(&to, byt).await?;
 
-                    ui.label("contrast");
-                    ui.add(egui::Slider::new(&mut effects.contrast, 0.0..=1.0));
+                            Ok(Some(to))
+                        }.await;
+
+                        match &res {
+                            Ok(Some(to)) => self.log_op(OperationKind::Import, &path, Some(to), &res),
+                            Err(_) => self.log_op(OperationKind::Import, &path, None, &res),
+                            Ok(None) => {},
+                        }
 
-                    let mut invert = effects.invert > 0;
-                    ui.checkbox(&mut invert, "invert");
-                    effects.invert = if invert { 1 } else { 0 };
+                        match &res {
+                            Ok(Some(to)) => {
+                                summary.copied += 1;
 
-                    let mut original = effects.original > 0;
-                    ui.checkbox(&mut original, "original");
-                    effects.original = if original { 1 } else { 0 };
+                                if !info.is_empty() {
+                                    let mut library = self.library.lock_or_recover();
+                                    let record = library.record_mut(to);
+                                    record.taken = info.date;
+                                    record.description = info.description;
 
-                    // ui.separator();
+                                    if let Some(name) = info.album {
+                                        library.albums
+                                            .entry(name)
+                                            .or_insert_with(|| Album::new(Vec::new()))
+                                            .photos
+                                            .push(to.clone());
+                                    }
+                                }
+                            },
+                            Ok(None) => summary.duplicates += 1,
+                            Err(_) => summary.failed += 1,
+                        }
+                        job.inc();
 
-                    ui.label("highlight");
-                    ui.add(egui::Slider::new(&mut effects.highlight, 0.0..=1.0));
+                        let progress = summary.clone();
+                        let alive = patch_import(&model, generation, move |import| {
+                            import.progress = Some(progress);
+                        });
 
-                    ui.label("shadow");
-                    ui.add(egui::Slider::new(&mut effects.shadow, 0.0..=1.0));
+                        if let Err(err) = res {
+                            self.handle_error(err);
+                        }
 
-                    ui.label("white point");
-                    ui.add(egui::Slider::new(&mut effects.white_pt, 0.0..=1.0));
+                        if !alive {
+                            break;
+                        }
+                    }
 
-                    ui.label("black point");
-                    ui.add(egui::Slider::new(&mut effects.black_pt, 0.0..=1.0));
+                    let level = if summary.failed > 0 { Level::Warn } else { Level::Info };
+                    self.toasts.push(level, summary.to_string());
 
-                    // ui.separator();
+                    // for the operations if nothing else
+                    self.save_library().await?;
 
-                    ui.label("temperature");
-                    ui.add(egui::Slider::new(&mut effects.temperature, 4000.0..=9000.0));
+                    Ok(())
                 });
+
+                Ok(Cmd::none())
             },
-            Screen::Gallery(gallery) => {
-                egui::CentralPanel::default().show(ctx.egui, |ui| {
-                    let ncols = 4; //(ui.available_width() / 100.0) as usize + 1;
-                    // println!("ncols: {}", ncols);
-                    let nrows = gallery.thumbs.len() / ncols;
+            Msg::Trash{path} => {
+                let trash_path = path.clone();
+                let res : Result<()> = async {
+                    tokio::task::spawn_blocking(move || trash::delete(trash_path))
+                        .await
+                        .map_err(std::io::Error::from)?
+                        .map_err(|err| std::io::Error::new(
+                            std::io::ErrorKind::Other,
+                            format!("could not trash {}: {:?}", path.display(), err),
+                        ))?;
+                    Ok(())
+                }.await;
+                self.log_op(OperationKind::Trash, &path, None, &res);
+                res?;
 
-                    // TODO: just make the rows manually
-                    egui::ScrollArea::auto_sized().show_rows(ui, 100.0, nrows, |ui, rng| {
+                self.library.lock_or_recover().remove(&path);
+                model_buf.patch_current(move |model| model.file_removed(&path));
 
-                        let start = rng.start * ncols;
-                        let end = rng.end * ncols;
-                        for row in gallery.thumbs[start..end].chunks_mut(ncols) {
-                            ui.horizontal(|ui| {
-                                for photo in row {
-                                    let egui_id = photo.data.get_image_id(ctx).egui_id();
-                                    let button = ui.add(egui::ImageButton::new(
-                                        egui_id,
-                                        egui::Vec2{
-                                            x : 100.0,
-                                            y : 100.0,
-                                        }
-                                    ));
+                self.save_library().await?;
 
-                                    if button.on_hover_text(photo.id.display()).clicked() {
-                                        println!("loading {}", photo.id.display());
-                                        msgs.push(Msg::Open{path : photo.id.clone()});
-                                    }
-                                }
-                            });
-                        }
-                    })
+                Ok(Cmd::none())
+            },
+            Msg::Rename{from, to} => {
+                let res = move_file(&from, &to).await.map_err(Error::from);
+                self.log_op(OperationKind::Rename, &from, Some(&to), &res);
+                res?;
+
+                {
+                    let (from, to) = (from.clone(), to.clone());
+                    model_buf.patch_current(move |model| model.file_renamed(&from, &to));
+                }
+
+                self.library.lock_or_recover().rename(&from, &to);
+                self.save_library().await?;
+
+                Ok(Cmd::none())
+            },
+            Msg::Move{from, to_dir} => {
+                let to = to_dir.join(from.file_name().unwrap_or_default());
+                let res = move_file(&from, &to).await.map_err(Error::from);
+                self.log_op(OperationKind::Move, &from, Some(&to), &res);
+                res?;
+
+                {
+                    let (from, to) = (from.clone(), to.clone());
+                    model_buf.patch_current(move |model| model.file_moved(&from, &to));
+                }
+
+                self.library.lock_or_recover().rename(&from, &to);
+                self.save_library().await?;
+
+                Ok(Cmd::none())
+            },
+            Msg::SaveEdits{path, history} => {
+                {
+                    let mut library = self.library.lock_or_recover();
+                    library.record_mut(&path).history = Some(history);
+                    library.log(OperationKind::Edit, &path, None, None);
+                }
+
+                let res = self.save_library().await;
+                self.unsaved_edits.fetch_sub(1, Ordering::Relaxed);
+                res?;
+
+                Ok(Cmd::none())
+            },
+            Msg::SaveFolderView{path, view} => {
+                self.library.lock_or_recover().folders.insert(path, view);
+                self.save_library().await?;
+
+                Ok(Cmd::none())
+            },
+            Msg::ComputeStats{path, image, edit} => {
+                let stats = ImageStats::of(&image.0);
+                model_buf.patch_current(move |model| {
+                    let photo = model.screen.photo_mut().filter(|photo| photo.id == path);
+                    if let Some(photo) = photo.filter(|photo| photo.stats_edit == edit) {
+                        photo.stats = Some(stats);
+                        photo.stats_pending = false;
+                    }
                 });
+
+                Ok(Cmd::none())
             },
-        }
-    }
+            Msg::SaveCaption{path, caption} => {
+                let caption = caption.trim();
+                self.library.lock_or_recover().record_mut(&path).description =
+                    if caption.is_empty() { None } else { Some(caption.to_string()) };
+                self.save_library().await?;
 
-    fn handle_error(&self, err : Error) {
-        let s = format!("{:?}", err);
-        println!("{:}", s);
-        // model.errors.push(s);
-    }
+                Ok(Cmd::none())
+            },
+            Msg::Mark{path, mark} => {
+                {
+                    let mut library = self.library.lock_or_recover();
+                    let record = library.record_mut(&path);
+                    mark.apply(&mut record.picked, &mut record.rating);
+                }
+                self.save_library().await?;
 
-    async fn update(&'static self,
-                    model_buf : &BufBufWrite<Self::Model>,
-                    msg : Self::Msg) -> Result<()> {
+                Ok(Cmd::none())
+            },
+            Msg::AddToAlbum{name, photos} => {
+                let added = {
+                    let mut library = self.library.lock_or_recover();
+                    if library.smart_albums.contains_key(&name) {
+                        self.toasts.error(format!("{} is a smart album, its photos are the search's", name));
+                        return Ok(Cmd::none())
+                    }
 
-        dbg!(&msg);
+                    library.albums
+                        .entry(name.clone())
+                        .or_insert_with(|| Album::new(Vec::new()))
+                        .add(photos)
+                };
+                self.save_library().await?;
 
-        match msg {
-            Msg::Open{path} => {
-                let photo = Photo::new(path).await?;
-                model_buf.set_next(Model{
-                    screen : Screen::Photo(PhotoScreen::new(photo)),
+                self.toasts.info(format!("added {} photos to {}", added, name));
+                Ok(Cmd::none())
+            },
+            Msg::RemoveFromAlbum{name, photos} => {
+                let removed = self.library.lock_or_recover()
+                    .albums
+                    .get_mut(&name)
+                    .map_or(0, |album| album.remove(&photos));
+                if removed > 0 {
+                    self.save_library().await?;
+                }
+
+                Ok(Cmd::none())
+            },
+            Msg::SaveSmartAlbum{name, query} => {
+                {
+                    let mut library = self.library.lock_or_recover();
+                    if library.albums.contains_key(&name) {
+                        self.toasts.error(format!("there's already an album named {}", name));
+                        return Ok(Cmd::none())
+                    }
+
+                    library.smart_albums.insert(name.clone(), query);
+                }
+                self.save_library().await?;
+
+                self.toasts.info(format!("saved smart album {}", name));
+                Ok(Cmd::none())
+            },
+            Msg::DeleteAlbum{name} => {
+                let deleted = {
+                    let mut library = self.library.lock_or_recover();
+                    library.albums.remove(&name).is_some() | library.smart_albums.remove(&name).is_some()
+                };
+                if deleted {
+                    self.save_library().await?;
+                    self.toasts.info(format!("deleted album {}", name));
+                }
+
+                Ok(Cmd::none())
+            },
+            Msg::SetAlbumCover{name, path} => {
+                if let Some(album) = self.library.lock_or_recover().albums.get_mut(&name) {
+                    album.cover = Some(path);
+                }
+                self.save_library().await?;
+
+                Ok(Cmd::none())
+            },
+            Msg::OpenAlbums{thumb_res} => {
+                let albums : Vec<(String, Album)> = self.library.lock_or_recover()
+                    .albums
+                    .iter()
+                    .map(|(name, album)| (name.clone(), album.clone()))
+                    .collect();
+
+                let smart_albums = self.library.lock_or_recover().smart_albums.clone();
+
+                let mut tiles : Vec<AlbumTile> = albums.iter()
+                    .map(|(name, album)| AlbumTile{
+                        name : name.clone(),
+                        count : album.photos.len(),
+                        created : album.created.and_then(DateTime::from_system_time),
+                        cover : None,
+                        query : None,
+                    })
+                    .collect();
+                // smart albums after the others, they aren't counted or
+                // given covers until they're opened
+                tiles.extend(smart_albums.into_iter().map(|(name, query)| AlbumTile{
+                    name,
+                    count : 0,
+                    created : None,
+                    cover : None,
+                    query : Some(query),
+                }));
+
+                let generation = model_buf.set_next(Model::new(Screen::Albums(AlbumsScreen{
+                    tiles,
+                    confirm_delete : None,
+                })));
+                let model = model_buf.clone();
+
+                spawn_job!(self, for generation, "loading albums", job, {
+                    job.set_total(albums.len());
+
+                    for (name, album) in albums.iter() {
+                        job.resumed().await;
+                        if !model.is_current(generation) || job.cancelled() {
+                            break;
+                        }
+
+                        job.inc();
+                        let path = opt_unwrap_or!(album.cover(), {
+                            continue;
+                        });
+
+                        // a missing cover shouldn't stop the rest
+                        let thumb = match self.thumb(path.to_path_buf(), thumb_res).await {
+                            Ok(thumb) => thumb,
+                            Err(err) => {
+                                self.handle_error(err);
+                                continue;
+                            },
+                        };
+
+                        // by name, one could have been deleted since
+                        let name = name.clone();
+                        let alive = model.patch_in(generation, |model| model.screen.albums_mut(), move |albums| {
+                            if let Some(tile) = albums.tiles.iter_mut().find(|t| t.name == name) {
+                                tile.cover = Some(thumb);
+                            }
+                        });
+
+                        if !alive {
+                            break;
+                        }
+                    }
+
+                    Ok(())
                 });
 
-                Ok(())
+                Ok(Cmd::none())
+            },
+            Msg::OpenOperations => {
+                let mut operations = self.library.lock_or_recover().operations.clone();
+                operations.reverse();
+
+                model_buf.set_next(Model::new(Screen::Operations(OperationsScreen{
+                    operations,
+                    kind : None,
+                    failed_only : false,
+                })));
+
+                Ok(Cmd::none())
+            },
+            Msg::OpenManifest{path, thumb_res} => {
+                let byt = self.backoff.run(|| tokio::fs::read(&path)).await?;
+                let paths = manifest::paths(&byt, &path)?;
+
+                Ok(Cmd::msg(Msg::OpenSet{
+                    set : PhotoSet::List(paths.iter().map(|p| p.to_string_lossy().into_owned()).collect()),
+                    thumb_res,
+                }))
+            },
+            Msg::ExportManifest{entries, dest} => {
+                let byt = manifest::to_bytes(&entries, &dest)?;
+                tokio::fs::write(&dest, byt).await?;
+
+                self.toasts.info(format!("wrote {} photos to {}", entries.len(), dest.display()));
+
+                Ok(Cmd::none())
+            },
+            Msg::ReorderAlbum{name, from, to} => {
+                let reordered = self.library.lock_or_recover()
+                    .albums
+                    .get_mut(&name)
+                    .map_or(false, |album| album.reorder(&from, &to));
+
+                if reordered {
+                    self.save_library().await?;
+                }
+
+                Ok(Cmd::none())
+            },
+            Msg::ExportDzi{path, image : Pixels(image), dest} => {
+                let name = path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "photo".to_string());
+                let dzi_path = dest.join(format!("{}.dzi", name));
+
+                let res = tokio::task::spawn_blocking(move || dzi::export(&image, &dest, &name))
+                    .await
+                    .map_err(|err| Error::from(std::io::Error::from(err)))
+                    .and_then(|res| res);
+                if res.is_err() {
+                    self.log_op(OperationKind::Export, &path, Some(&dzi_path), &res);
+                }
+                res?;
+
+                self.toasts.info(format!("exported {}", dzi_path.display()));
+
+                self.record_export(&path, dzi_path);
+                self.save_library().await?;
+
+                Ok(Cmd::none())
             },
-            Msg::OpenSet(photo_set) => {
-                let weak = model_buf.set_next(Model{
-                    screen : Screen::Gallery(Gallery{
-                        thumbs : Vec::new()
+            Msg::ExportPreset{path, image : Pixels(image), preset, fit, output, dest, caption, left} => {
+                let stem = path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "photo".to_string());
+                // never the original's name, the destination could be its
+                // folder
+                let suffix = match (preset, output.long_edge) {
+                    (Some(preset), _) => PRESETS[preset].suffix.to_string(),
+                    (None, Some(edge)) => format!("{}px", edge),
+                    (None, None) => "export".to_string(),
+                };
+                let save_path = dest.join(format!("{}_{}.jpg", stem, suffix));
+
+                let res : Result<()> = async {
+                    tokio::fs::create_dir_all(&dest).await?;
+
+                    // for its exif
+                    let original = match output.keep_metadata {
+                        true => tokio::fs::read(&path).await.ok(),
+                        false => None,
+                    };
+
+                    let byt = tokio::task::spawn_blocking(move || {
+                        let image = match preset {
+                            Some(preset) => PRESETS[preset].apply(&image, fit),
+                            None => image,
+                        };
+                        output.encode(&output.apply(image), original.as_deref())
                     })
+                        .await
+                        .map_err(std::io::Error::from)??;
+                    tokio::fs::write(&save_path, byt).await?;
+
+                    if caption && output.keep_metadata {
+                        self.write_caption(&path, &save_path).await?;
+                    }
+
+                    self.record_export(&path, save_path.clone());
+                    self.save_library().await
+                }.await;
+
+                if res.is_err() {
+                    self.log_op(OperationKind::Export, &path, Some(&save_path), &res);
+                }
+
+                match left {
+                    Some(left) => {
+                        if let Err(err) = res {
+                            self.handle_error(err);
+                        }
+                        self.export_done(&left, &dest);
+                    },
+                    None => {
+                        res?;
+                        self.toasts.info(format!("exported {}", save_path.display()));
+                    },
+                }
+
+                Ok(Cmd::none())
+            },
+            Msg::ExportContactSheet{title, cells, layout, dest} => {
+                let name = format!("making a contact sheet of {} photos", cells.len());
+
+                spawn_job!(self, name, _job, {
+                    let pdf = tokio::task::spawn_blocking(move || layout.pdf(&title, &cells))
+                        .await
+                        .map_err(std::io::Error::from)??;
+                    tokio::fs::write(&dest, pdf).await?;
+
+                    self.toasts.info(format!("saved {}", dest.display()));
+                    Ok(())
                 });
 
-                spawn_err!(self, {
-                    match photo_set {
-                        PhotoSet::Folder(path) => {
-                            let mut entries = tokio::fs::read_dir(path).await?;
+                Ok(Cmd::none())
+            },
+            Msg::CopyImage{image : Pixels(image)} => {
+                tokio::task::spawn_blocking(move || self.clipboard.copy_image(&image))
+                    .await
+                    .map_err(std::io::Error::from)??;
 
-                            while let Some(entry) = entries.next_entry().await? {
-                                println!("{:?}", entry);
-                                let thumb = Thumb::new(entry.path(), 100.0).await?;
+                self.toasts.info("copied the photo");
+                Ok(Cmd::none())
+            },
+            Msg::Paste{thumb_res} => {
+                let pasted = tokio::task::spawn_blocking(move || self.clipboard.paste())
+                    .await
+                    .map_err(std::io::Error::from)??;
 
-                                let model = opt_unwrap_or!(weak.upgrade(), {
-                                    // the screen was dropped
-                                    break;
-                                });
+                match pasted {
+                    Some(Pasted::Image(image)) => {
+                        // photos are files, so it becomes one
+                        let dir = opt_unwrap_or!(library::data_dir(), {
+                            self.toasts.error("nowhere to save the pasted image");
+                            return Ok(Cmd::none())
+                        }).join("pasted");
+                        tokio::fs::create_dir_all(&dir).await?;
 
-                                model
-                                    .lock().unwrap()
-                                    .screen
-                                    .gallery_mut().unwrap()
-                                    .thumbs
-                                    .push(thumb);
-                            }
+                        let secs = SystemTime::now()
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .map_or(0, |d| d.as_secs());
+                        let path = dir.join(format!("pasted-{}.png", secs));
+                        let save_to = path.clone();
+                        tokio::task::spawn_blocking(move || image.save(save_to))
+                            .await
+                            .map_err(std::io::Error::from)??;
 
-                            Ok(())
+                        Ok(Cmd::msg(Msg::Open{path}))
+                    },
+                    Some(Pasted::Path(path)) if path.is_dir() => Ok(Cmd::msg(Msg::OpenSet{
+                        set : PhotoSet::Folder{
+                            path : path.to_string_lossy().into_owned(),
+                            max_depth : 0,
                         },
-                        PhotoSet::List(paths) => {
-                            for path in paths {
-                                let thumb = Thumb::new(path, 100.0).await?;
+                        thumb_res,
+                    })),
+                    Some(Pasted::Path(path)) if self.formats.is_supported(&path) => Ok(Cmd::msg(Msg::Open{path})),
+                    Some(Pasted::Url(url)) => Ok(Cmd::msg(Msg::OpenUrl{url})),
+                    _ => {
+                        self.toasts.info("nothing to open on the clipboard");
+                        Ok(Cmd::none())
+                    },
+                }
+            },
+            Msg::Print{path, image : Pixels(image), layout, dest} => {
+                let pdf = tokio::task::spawn_blocking(move || layout.pdf(&image))
+                    .await
+                    .map_err(std::io::Error::from)??;
 
-                                let model = opt_unwrap_or!(weak.upgrade(), {
-                                    // the screen was dropped
-                                    break;
-                                });
+                match dest {
+                    Some(dest) => {
+                        let res = tokio::fs::write(&dest, pdf).await.map_err(Error::from);
+                        if res.is_err() {
+                            self.log_op(OperationKind::Export, &path, Some(&dest), &res);
+                        }
+                        res?;
+
+                        self.toasts.info(format!("saved {}", dest.display()));
+                        self.record_export(&path, dest);
+                        self.save_library().await?;
+                    },
+                    None => {
+                        let stem = path.file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| "photo".to_string());
+                        let tmp = std::env::temp_dir().join(format!("photos1-print-{}.pdf", stem));
+                        tokio::fs::write(&tmp, pdf).await?;
+
+                        tokio::task::spawn_blocking(move || print::send(&tmp))
+                            .await
+                            .map_err(std::io::Error::from)??;
+                        self.toasts.info(format!("sent {} to the printer", stem));
+                    },
+                }
 
-                                model
-                                    .lock().unwrap()
-                                    .screen
-                                    .gallery_mut().unwrap()
-                                    .thumbs
-                                    .push(thumb);
+                Ok(Cmd::none())
+            },
+            Msg::EditExternally{path, image : Pixels(image), program, format} => {
+                let dir = std::env::temp_dir().join("photos1-edit");
+                tokio::fs::create_dir_all(&dir).await?;
+
+                let file = external::temp_file(&dir, format);
+                let save_to = file.clone();
+                tokio::task::spawn_blocking(move || image.save(save_to))
+                    .await
+                    .map_err(std::io::Error::from)??;
+
+                let child = external::launch(&program, &file)?;
+                let dest = external::dest_for(&path, format);
+                let mut session = Session::new(path, file, dest, child);
+
+                Ok(Cmd::perform(async move {
+                    let saved = session.next_save().await;
+                    Msg::ExternalEdit{session, saved}
+                }))
+            },
+            Msg::ExternalEdit{mut session, saved} => {
+                if !saved {
+                    // the copies next to the original are what's kept
+                    let _ = tokio::fs::remove_file(&session.file).await;
+                    return Ok(Cmd::none())
+                }
+
+                let res = tokio::fs::copy(&session.file, &session.dest).await.map(|_| ()).map_err(Error::from);
+                self.log_op(OperationKind::Import, &session.original, Some(&session.dest), &res);
+                if let Err(err) = res {
+                    self.handle_error(err);
+                } else {
+                    self.record_edited_copy(&session.original, &session.dest);
+                    self.save_library().await?;
+                    self.toasts.info(format!("saved {}", session.dest.display()));
+                }
+
+                Ok(Cmd::perform(async move {
+                    let saved = session.next_save().await;
+                    Msg::ExternalEdit{session, saved}
+                }))
+            },
+            Msg::SetWallpaper{path, image : Pixels(image), dir} => {
+                let stem = path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "photo".to_string());
+                // the desktop keeps reading it, so it's kept with the others
+                let dest = dir.join(format!("{}_wallpaper.jpg", stem));
+
+                tokio::fs::create_dir_all(&dir).await?;
+                let save_to = dest.clone();
+                let rgb = image::DynamicImage::ImageRgba8(image).to_rgb8();
+                let res = tokio::task::spawn_blocking(move || rgb.save(save_to))
+                    .await
+                    .map_err(|err| Error::from(std::io::Error::from(err)))
+                    .and_then(|res| res.map_err(Error::from));
+                if res.is_err() {
+                    self.log_op(OperationKind::Export, &path, Some(&dest), &res);
+                }
+                res?;
+                self.record_export(&path, dest.clone());
+                self.save_library().await?;
+
+                let backend = wallpaper::backend();
+                let name = backend.name();
+                tokio::task::spawn_blocking(move || backend.set(&dest))
+                    .await
+                    .map_err(std::io::Error::from)??;
+
+                self.toasts.info(format!("set the {} wallpaper to {}", name, stem));
+                Ok(Cmd::none())
+            },
+            Msg::BatchExport(batch) => {
+                let res : Result<Option<Effects>> = async {
+                    tokio::fs::create_dir_all(batch.out.as_deref().unwrap_or_else(|| Path::new("."))).await?;
+
+                    let byt = match &batch.preset {
+                        Some(path) => tokio::fs::read(path).await?,
+                        None => return Ok(None),
+                    };
+                    // missing fields are the defaults, so {"temperature": 5000} is fine
+                    Ok(Some(serde_json::from_slice(&byt).map_err(std::io::Error::from)?))
+                }.await;
+                let edits = res_unwrap_or!(res, err, {
+                    println!("{}", err);
+                    self.quit_with(2, model_buf);
+                    return Ok(Cmd::none())
+                });
+
+                let total = batch.paths.len();
+                let queue = Arc::new(Mutex::new(batch.paths.iter().cloned().collect::<VecDeque<_>>()));
+                let batch = Arc::new(batch);
+                let edits = Arc::new(edits);
+                let failed = Arc::new(AtomicUsize::new(0));
+                // the last job to finish quits
+                let jobs = if self.options.low_memory { 1 } else { BATCH_JOBS };
+                let running = Arc::new(AtomicUsize::new(jobs));
+
+                for _ in 0..jobs {
+                    let (queue, batch, edits) = (queue.clone(), batch.clone(), edits.clone());
+                    let (failed, running) = (failed.clone(), running.clone());
+                    let model = model_buf.clone();
+
+                    spawn_job!(self, "exporting", job, {
+                        loop {
+                            let path = opt_unwrap_or!(queue.lock_or_recover().pop_front(), {
+                                break
+                            });
+
+                            match self.export_one(&batch, (*edits).as_ref(), &path).await {
+                                Ok(dest) => println!("{} -> {}", path.display(), dest.display()),
+                                Err(err) => {
+                                    println!("could not export {}: {}", path.display(), err);
+                                    failed.fetch_add(1, Ordering::Relaxed);
+                                },
                             }
+                            job.inc();
+                        }
+
+                        if running.fetch_sub(1, Ordering::AcqRel) == 1 {
+                            let failed = failed.load(Ordering::Relaxed);
+                            println!("exported {} of {} photos", total - failed, total);
+                            self.quit_with(if failed == 0 { 0 } else { 1 }, &model);
+                        }
+
+                        Ok(())
+                    });
+                }
+
+                Ok(Cmd::none())
+            },
+            Msg::ExportPresets{paths, preset, fit, output, dest, caption} => {
+                let left = Arc::new(AtomicUsize::new(paths.len()));
+                let name = format!("exporting {} photos", paths.len());
+
+                spawn_job!(self, name, job, {
+                    job.set_total(paths.len());
 
-                            Ok(())
+                    for path in paths {
+                        // a cancelled batch doesn't say it's done
+                        if job.cancelled() {
+                            break;
                         }
+
+                        let res : Result<image::RgbaImage> = async {
+                            let (_, image) = self.read_image(&path).await?;
+                            Ok(image.to_rgba8())
+                        }.await;
+
+                        let image = match res {
+                            Ok(image) => image,
+                            Err(err) => {
+                                self.handle_error(err);
+                                self.export_done(&left, &dest);
+                                job.inc();
+                                continue
+                            },
+                        };
+
+                        let effects = self.saved_effects(&path);
+                        self.queue_export(PendingExport{
+                            path,
+                            image,
+                            effects,
+                            to : ExportTo::Preset{
+                                preset,
+                                fit,
+                                output,
+                                dest : dest.clone(),
+                                caption,
+                                left : left.clone(),
+                            },
+                        }).await;
+
+                        // and wakes the render thread to take it
+                        job.inc();
                     }
+
+                    Ok(())
                 });
 
-                Ok(())
-            }
+                Ok(Cmd::none())
+            },
+            Msg::ExportImage{path, image : Pixels(image), dest, caption} => {
+                let save_path = dest.clone();
+                let res = tokio::task::spawn_blocking(move || image.save(save_path))
+                    .await
+                    .map_err(|err| Error::from(std::io::Error::from(err)))
+                    .and_then(|res| res.map_err(Error::from));
+                if res.is_err() {
+                    self.log_op(OperationKind::Export, &path, Some(&dest), &res);
+                }
+                res?;
+
+                if caption {
+                    self.write_caption(&path, &dest).await?;
+                }
+
+                self.toasts.info(format!("exported {}", dest.display()));
+
+                self.record_export(&path, dest);
+                self.save_library().await?;
+
+                Ok(Cmd::none())
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a : f32, b : f32) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    #[test]
+    fn fmt_count_groups() {
+        assert_eq!(fmt_count(0), "0");
+        assert_eq!(fmt_count(999), "999");
+        assert_eq!(fmt_count(1000), "1,000");
+        assert_eq!(fmt_count(123456), "123,456");
+        assert_eq!(fmt_count(1234567), "1,234,567");
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn fmt_count_max() {
+        assert_eq!(fmt_count(usize::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn file_names() {
+        assert!(is_file_name("a.jpg"));
+        assert!(is_file_name("..a.jpg"));
+        assert!(!is_file_name(""));
+        assert!(!is_file_name("."));
+        assert!(!is_file_name(".."));
+        assert!(!is_file_name("a/b.jpg"));
+        assert!(!is_file_name("../b.jpg"));
+        assert!(!is_file_name("/b.jpg"));
+        assert!(!is_file_name("a.jpg/"));
+    }
+
+    #[test]
+    fn justify_rows_empty() {
+        let rows = justify_rows(std::iter::empty(), 1000.0, 200.0, 4.0);
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn justify_rows_one_wide() {
+        // a panorama wider than the window gets a row of its own, shrunk
+        let rows = justify_rows([20.0].iter().copied(), 1000.0, 200.0, 4.0);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].items, 0..1);
+        assert!(close(rows[0].y, 0.0));
+        assert!(close(rows[0].height * 20.0, 1000.0));
+    }
+
+    #[test]
+    fn justify_rows_partial_last() {
+        let aspects = [1.0, 1.5, 1.0, 0.5, 1.0, 1.0, 1.0];
+        let rows = justify_rows(aspects.iter().copied(), 1000.0, 200.0, 10.0);
+        assert_eq!(rows.len(), 2);
+
+        // full rows fill the width, gaps and all
+        let full = &rows[0];
+        assert_eq!(full.items, 0..5);
+        let width : f32 = aspects[full.items.clone()].iter().map(|a| a * full.height).sum();
+        assert!(close(width + 4.0 * 10.0, 1000.0));
+
+        // the last one is left at the target height
+        let last = &rows[1];
+        assert_eq!(last.items, 5..7);
+        assert!(close(last.y, full.height + 10.0));
+        assert!(close(last.height, 200.0));
+    }
+}
@@ -0,0 +1,164 @@
+// lists of photos for other tools, as csv, json, or an m3u playlist. the
+// format is picked by the file extension
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use photos1::Result;
+
+use crate::metadata::Metadata;
+
+/// Extensions of the files read and written here.
+pub const EXTENSIONS : &[&str] = &["csv", "json", "m3u", "m3u8"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub path : PathBuf,
+    /// Like 2021-06-30 17:04:11, when the photo was taken
+    #[serde(default)]
+    pub date : Option<String>,
+    #[serde(default)]
+    pub latitude : Option<f64>,
+    #[serde(default)]
+    pub longitude : Option<f64>,
+    #[serde(default)]
+    pub location : Option<String>,
+    /// Has edits in the library
+    #[serde(default)]
+    pub edited : bool,
+}
+
+impl Entry {
+    pub fn new(path : PathBuf, meta : &Metadata, edited : bool) -> Self {
+        Entry {
+            path,
+            date : meta.date.map(|d| d.to_string()),
+            latitude : meta.gps.map(|(lat, _)| lat),
+            longitude : meta.gps.map(|(_, lon)| lon),
+            location : meta.location.clone(),
+            edited,
+        }
+    }
+}
+
+pub fn is_manifest_path(path : &Path) -> bool {
+    extension(path).map_or(false, |ext| EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn extension(path : &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+fn invalid(msg : String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+pub fn to_bytes(entries : &[Entry], dest : &Path) -> Result<Vec<u8>> {
+    let byt = match extension(dest).as_deref() {
+        Some("csv") => {
+            let mut out = String::from("path,date,latitude,longitude,location,edited\n");
+            for e in entries {
+                let fields = [
+                    e.path.to_string_lossy().into_owned(),
+                    e.date.clone().unwrap_or_default(),
+                    e.latitude.map_or(String::new(), |v| v.to_string()),
+                    e.longitude.map_or(String::new(), |v| v.to_string()),
+                    e.location.clone().unwrap_or_default(),
+                    e.edited.to_string(),
+                ];
+
+                let fields : Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+            out.into_bytes()
+        },
+        Some("json") => serde_json::to_vec_pretty(entries).map_err(std::io::Error::from)?,
+        Some("m3u") | Some("m3u8") => {
+            let mut out = String::from("#EXTM3U\n");
+            for e in entries {
+                out.push_str(&e.path.to_string_lossy());
+                out.push('\n');
+            }
+            out.into_bytes()
+        },
+        _ => return Err(invalid(format!("{} isn't a csv, json, or m3u file", dest.display())).into()),
+    };
+
+    Ok(byt)
+}
+
+/// The paths in a manifest, relative ones are taken to be relative to the
+/// manifest's folder.
+pub fn paths(byt : &[u8], manifest : &Path) -> Result<Vec<PathBuf>> {
+    let text = String::from_utf8_lossy(byt);
+
+    let paths : Vec<PathBuf> = match extension(manifest).as_deref() {
+        Some("csv") => {
+            let mut lines = text.lines();
+            let header = lines.next().map(csv_record).unwrap_or_default();
+            let col = header.iter().position(|h| h.trim() == "path").unwrap_or(0);
+
+            lines
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| csv_record(line).into_iter().nth(col))
+                .map(PathBuf::from)
+                .collect()
+        },
+        Some("json") => {
+            let entries : Vec<Entry> = serde_json::from_slice(byt).map_err(std::io::Error::from)?;
+            entries.into_iter().map(|e| e.path).collect()
+        },
+        Some("m3u") | Some("m3u8") => {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(PathBuf::from)
+                .collect()
+        },
+        _ => return Err(invalid(format!("{} isn't a csv, json, or m3u file", manifest.display())).into()),
+    };
+
+    let dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+    Ok(paths.into_iter().map(|p| dir.join(p)).collect())
+}
+
+fn csv_field(s : &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// one line of csv, fields with newlines in them aren't supported
+fn csv_record(line : &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+
+    fields.push(field);
+    fields
+}
@@ -0,0 +1,163 @@
+// photos placed by their gps on a plain longitude by latitude plot. there's
+// no offline base map, so a grid of degrees and the place names the
+// geocoder gave the photos stand in for one
+
+/// A photo on the map.
+#[derive(Debug, Clone, Copy)]
+pub struct Pin<'a> {
+    pub lat : f64,
+    pub lon : f64,
+    /// Shown on hover, like "IMG_0001.jpg, Tokyo, Japan"
+    pub label : &'a str,
+}
+
+const PIN_RADIUS : f32 = 4.0;
+// around the pins, in degrees, and the least shown so a single photo
+// isn't zoomed in to nothing
+const MARGIN_DEGREES : f64 = 0.5;
+const MIN_SPAN_DEGREES : f64 = 2.0;
+// the grid's steps in degrees, the first with at most this many lines
+// across is used
+const GRID_STEPS : [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0];
+const GRID_LINES : f64 = 8.0;
+
+// the pins' bounds as (south, west, north, east), padded, with east past
+// 180 when that's shorter, so pins on both sides of the antimeridian are
+// shown together
+fn bounds(pins : &[Pin]) -> Option<(f64, f64, f64, f64)> {
+    if pins.is_empty() {
+        return None
+    }
+    let south = pins.iter().map(|p| p.lat).fold(f64::INFINITY, f64::min);
+    let north = pins.iter().map(|p| p.lat).fold(f64::NEG_INFINITY, f64::max);
+
+    let mut lons : Vec<f64> = pins.iter().map(|p| p.lon).collect();
+    lons.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    // the widest gap between neighbouring longitudes, around the world too,
+    // is left out of the view
+    let (gap, after) = lons.windows(2)
+        .map(|w| (w[1] - w[0], w[1]))
+        .chain(std::iter::once((lons[0] + 360.0 - lons[lons.len() - 1], lons[0])))
+        .fold((f64::NEG_INFINITY, lons[0]), |best, next| if next.0 > best.0 { next } else { best });
+    let west = after;
+    let east = west + 360.0 - gap;
+
+    let pad = |lo : f64, hi : f64, min : f64| {
+        let grow = ((min - (hi - lo)) / 2.0).max(0.0) + MARGIN_DEGREES;
+        (lo - grow, hi + grow)
+    };
+    let (south, north) = pad(south, north, MIN_SPAN_DEGREES);
+    let (west, east) = pad(west, east, MIN_SPAN_DEGREES);
+    Some((south.max(-90.0), west, north.min(90.0), east))
+}
+
+/// Draws the pins fit into the space left in ui, returns the one clicked.
+pub fn show(ui : &mut egui::Ui, pins : &[Pin]) -> Option<usize> {
+    let (south, west, north, east) = match bounds(pins) {
+        Some(bounds) => bounds,
+        None => {
+            ui.label("None of these photos say where they were taken.");
+            return None
+        },
+    };
+
+    let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::from_rgb(24, 36, 48));
+
+    // a degree of longitude is cos(latitude) of one of latitude, taken in
+    // the middle so the map isn't stretched
+    let squash = ((south + north) / 2.0).to_radians().cos().max(0.1);
+    let scale = (rect.width() as f64 / ((east - west) * squash))
+        .min(rect.height() as f64 / (north - south));
+    let center = ((west + east) / 2.0, (south + north) / 2.0);
+    let to_screen = |lat : f64, lon : f64| {
+        // the same side of the antimeridian as the view
+        let lon = if lon < west { lon + 360.0 } else { lon };
+        rect.center() + egui::vec2(
+            ((lon - center.0) * squash * scale) as f32,
+            ((center.1 - lat) * scale) as f32,
+        )
+    };
+
+    let span = (east - west).max(north - south);
+    let step = GRID_STEPS.iter().copied()
+        .find(|step| span / step <= GRID_LINES)
+        .unwrap_or(90.0);
+    let line = egui::Stroke::new(1.0, egui::Color32::from_gray(70));
+    let text = egui::Color32::from_gray(140);
+
+    let mut lat = (south / step).ceil() * step;
+    while lat <= north {
+        let y = to_screen(lat, west).y;
+        painter.line_segment([egui::pos2(rect.left(), y), egui::pos2(rect.right(), y)], line);
+        painter.text(egui::pos2(rect.left() + 4.0, y), egui::Align2::LEFT_BOTTOM, format!("{}°", lat), egui::TextStyle::Small, text);
+        lat += step;
+    }
+
+    let mut lon = (west / step).ceil() * step;
+    while lon <= east {
+        let x = to_screen(south, lon).x;
+        painter.line_segment([egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())], line);
+        // past the antimeridian it's west again
+        let shown = if lon > 180.0 { lon - 360.0 } else { lon };
+        painter.text(egui::pos2(x + 2.0, rect.bottom()), egui::Align2::LEFT_BOTTOM, format!("{}°", shown), egui::TextStyle::Small, text);
+        lon += step;
+    }
+
+    let pointer = ui.input().pointer.hover_pos().filter(|pos| rect.contains(*pos));
+    let hovered = pointer.and_then(|pos| {
+        pins.iter()
+            .enumerate()
+            .map(|(idx, pin)| (idx, to_screen(pin.lat, pin.lon).distance(pos)))
+            .filter(|(_, dist)| *dist <= PIN_RADIUS * 2.0)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(idx, _)| idx)
+    });
+
+    for (idx, pin) in pins.iter().enumerate() {
+        let (radius, color) = if Some(idx) == hovered {
+            (PIN_RADIUS * 1.5, egui::Color32::WHITE)
+        } else {
+            (PIN_RADIUS, egui::Color32::from_rgb(255, 170, 0))
+        };
+        painter.circle(to_screen(pin.lat, pin.lon), radius, color, egui::Stroke::new(1.0, egui::Color32::BLACK));
+    }
+
+    let idx = hovered?;
+    egui::show_tooltip_text(ui.ctx(), egui::Id::new("map pin"), pins[idx].label);
+    if ui.input().pointer.any_click() {
+        Some(idx)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pin(lat : f64, lon : f64) -> Pin<'static> {
+        Pin{ lat, lon, label : "" }
+    }
+
+    #[test]
+    fn bounds_empty() {
+        assert!(bounds(&[]).is_none());
+    }
+
+    #[test]
+    fn bounds_one_pin() {
+        let (south, west, north, east) = bounds(&[pin(35.0, 139.0)]).unwrap();
+        assert!(south < 35.0 && north > 35.0 && west < 139.0 && east > 139.0);
+        assert!(north - south >= MIN_SPAN_DEGREES && east - west >= MIN_SPAN_DEGREES);
+    }
+
+    #[test]
+    fn bounds_across_antimeridian() {
+        // fiji's islands are on both sides
+        let (_, west, _, east) = bounds(&[pin(-17.0, 178.0), pin(-16.8, -179.9)]).unwrap();
+        assert!(west < 178.0 && east > 180.1);
+        assert!(east - west < 10.0);
+    }
+}
@@ -0,0 +1,311 @@
+use std::time::SystemTime;
+
+use crate::white_balance::{
+    self,
+    Illuminant,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year : i32,
+    pub month : u32,
+    pub day : u32,
+    pub hour : u32,
+    pub minute : u32,
+    pub second : u32,
+}
+
+impl DateTime {
+    // UTC, which is good enough for grouping
+    pub fn from_system_time(time : SystemTime) -> Option<Self> {
+        let secs = time.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs() as i64;
+        let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+        let rem = secs.rem_euclid(86400) as u32;
+
+        Some(DateTime{
+            year,
+            month,
+            day,
+            hour : rem / 3600,
+            minute : rem / 60 % 60,
+            second : rem % 60,
+        })
+    }
+
+    /// The start of an ISO 8601 date, like 2021-06-03T14:22:10 or just
+    /// 2021-06-03. Fractions of seconds and the time zone are ignored.
+    pub fn parse_iso(s : &str) -> Option<Self> {
+        let s = s.trim();
+        let (date, time) = match s.find(|c| c == 'T' || c == ' ') {
+            Some(idx) => (&s[..idx], &s[idx + 1..]),
+            None => (s, ""),
+        };
+
+        let mut date = date.split('-');
+        let year = date.next()?.parse().ok()?;
+        let month = date.next()?.parse().ok().filter(|m| (1..=12).contains(m))?;
+        let day = date.next()?.parse().ok().filter(|d| (1..=31).contains(d))?;
+
+        // two digits each, the seconds may be followed by .123+02:00
+        let mut time = time.split(':').map(|part| part.get(..2).and_then(|p| p.parse().ok()));
+        let mut next = || time.next().flatten().unwrap_or(0);
+
+        Some(DateTime{
+            year,
+            month,
+            day,
+            hour : next(),
+            minute : next(),
+            second : next(),
+        })
+    }
+
+    // UTC, like from_system_time. the epoch for years the platform can't
+    // hold, which only come from broken files
+    pub fn to_system_time(&self) -> SystemTime {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days * 86400 +
+            (self.hour * 3600 + self.minute * 60 + self.second) as i64;
+
+        let time = if secs >= 0 {
+            SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs as u64))
+        } else {
+            SystemTime::UNIX_EPOCH.checked_sub(std::time::Duration::from_secs(secs.unsigned_abs()))
+        };
+        time.unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        const NAMES : [&str; 12] = [
+            "January", "February", "March", "April", "May", "June",
+            "July", "August", "September", "October", "November", "December",
+        ];
+
+        (self.month as usize)
+            .checked_sub(1)
+            .and_then(|idx| NAMES.get(idx))
+            .unwrap_or(&"")
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day,
+            self.hour, self.minute, self.second)
+    }
+}
+
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days : i64) -> (i32, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as i32, month as u32, day as u32)
+}
+
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year : i32, month : u32, day : u32) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+
+/// Metadata read from the image file, everything is optional since most
+/// files in the wild are missing some or all of it.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// When the photo was taken
+    pub date : Option<DateTime>,
+    /// Milliseconds after date's second, cameras shooting several frames a
+    /// second write them
+    pub date_millis : Option<u32>,
+    pub exposure : Exposure,
+    /// Latitude and longitude in degrees
+    pub gps : Option<(f64, f64)>,
+    /// Filled in from gps by the geocoder
+    pub location : Option<String>,
+    /// The light the camera says it was taken in
+    pub light_source : Option<Illuminant>,
+}
+
+impl Metadata {
+    pub fn read(byt : &[u8]) -> Self {
+        let exif = match exif::Reader::new().read_from_container(&mut std::io::Cursor::new(byt)) {
+            Ok(exif) => exif,
+            Err(_) => return Default::default(),
+        };
+
+        let date = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+            .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))
+            .and_then(|field| match field.value {
+                exif::Value::Ascii(ref v) => v.first(),
+                _ => None,
+            })
+            .and_then(|s| exif::DateTime::from_ascii(s).ok())
+            .map(|dt| DateTime{
+                year : dt.year as i32,
+                month : dt.month as u32,
+                day : dt.day as u32,
+                hour : dt.hour as u32,
+                minute : dt.minute as u32,
+                second : dt.second as u32,
+            });
+
+        // digits of a fraction of a second, "5" is 500ms
+        let date_millis = match exif.get_field(exif::Tag::SubSecTimeOriginal, exif::In::PRIMARY).map(|f| &f.value) {
+            Some(exif::Value::Ascii(v)) => v.first()
+                .and_then(|s| std::str::from_utf8(s).ok())
+                .map(|s| s.trim().chars().chain("000".chars()).take(3).collect::<String>())
+                .and_then(|ms| ms.parse().ok()),
+            _ => None,
+        };
+
+        let gps = read_coord(&exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, b'S')
+            .zip(read_coord(&exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, b'W'));
+
+        let light_source = exif.get_field(exif::Tag::LightSource, exif::In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0))
+            .and_then(white_balance::from_light_source);
+
+        Metadata{
+            date,
+            date_millis,
+            exposure : Exposure::read(&exif),
+            gps,
+            location : None,
+            light_source,
+        }
+    }
+}
+
+/// How the photo was exposed, from the exif.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Exposure {
+    /// In seconds
+    pub time : Option<f32>,
+    pub f_number : Option<f32>,
+    pub iso : Option<u32>,
+    /// Exposure compensation in stops
+    pub bias : Option<f32>,
+    /// In mm
+    pub focal_length : Option<f32>,
+    /// The camera says it was shot as part of an auto bracket
+    pub bracketed : bool,
+}
+
+impl Exposure {
+    fn read(exif : &exif::Exif) -> Self {
+        let field = |tag| exif.get_field(tag, exif::In::PRIMARY).map(|f| &f.value);
+        // a 0/0 rational is how some cameras say they don't know
+        let rational = |tag| match field(tag) {
+            Some(exif::Value::Rational(v)) => v.first().map(|r| r.to_f64() as f32),
+            Some(exif::Value::SRational(v)) => v.first().map(|r| r.to_f64() as f32),
+            _ => None,
+        }.filter(|v| v.is_finite());
+
+        Exposure{
+            time : rational(exif::Tag::ExposureTime).filter(|t| *t > 0.0),
+            f_number : rational(exif::Tag::FNumber).filter(|n| *n > 0.0),
+            iso : field(exif::Tag::PhotographicSensitivity).and_then(|v| v.get_uint(0)).filter(|iso| *iso > 0),
+            bias : rational(exif::Tag::ExposureBiasValue),
+            focal_length : rational(exif::Tag::FocalLength).filter(|f| *f > 0.0),
+            // 2 is auto bracket
+            bracketed : field(exif::Tag::ExposureMode).and_then(|v| v.get_uint(0)) == Some(2),
+        }
+    }
+
+    /// How bright the settings make the photo, in stops. Settings the file
+    /// doesn't have count as the same for every photo, and without an
+    /// exposure time the compensation stands in for them.
+    pub fn stops(&self) -> f32 {
+        let time = match self.time {
+            Some(time) => time.log2(),
+            None => return self.bias.unwrap_or(0.0),
+        };
+        let iso = self.iso.map_or(0.0, |iso| (iso as f32 / 100.0).log2());
+        let aperture = self.f_number.map_or(0.0, |n| 2.0 * n.log2());
+        time + iso - aperture
+    }
+}
+
+// degrees, minutes, seconds to degrees, negated if the ref tag is neg_ref
+fn read_coord(exif : &exif::Exif, tag : exif::Tag, ref_tag : exif::Tag, neg_ref : u8) -> Option<f64> {
+    let dms = match exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Rational(ref v) if v.len() >= 3 => {
+            v[0].to_f64() + v[1].to_f64() / 60.0 + v[2].to_f64() / 3600.0
+        },
+        _ => return None,
+    };
+
+    let negative = match exif.get_field(ref_tag, exif::In::PRIMARY).map(|f| &f.value) {
+        Some(exif::Value::Ascii(v)) => v.first().and_then(|s| s.first()) == Some(&neg_ref),
+        _ => false,
+    };
+
+    Some(if negative { -dms } else { dms })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_from_days_epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn civil_from_days_leap_days() {
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        // a century that isn't a leap year
+        assert_eq!(civil_from_days(-25509), (1900, 2, 28));
+        assert_eq!(civil_from_days(-25508), (1900, 3, 1));
+        assert_eq!(civil_from_days(-135081), (1600, 2, 29));
+    }
+
+    #[test]
+    fn civil_from_days_far() {
+        assert_eq!(civil_from_days(-719162), (1, 1, 1));
+        assert_eq!(civil_from_days(-719468), (0, 3, 1));
+        assert_eq!(civil_from_days(2932896), (9999, 12, 31));
+    }
+
+    // fuzz/regressions/metadata, and the years around it that do parse
+    #[test]
+    fn year_overflow() {
+        assert_eq!(DateTime::parse_iso(include_str!("../fuzz/regressions/metadata/year-overflow.txt")), None);
+        assert_eq!(DateTime::parse_iso("2147483648-01-01T00:00:00"), None);
+
+        let last = DateTime::parse_iso("2147483647-12-31T23:59:59").unwrap();
+        assert!(last.to_system_time() >= SystemTime::UNIX_EPOCH);
+
+        // the epoch if the platform can't hold it
+        let first = DateTime{ year : i32::MIN, month : 1, day : 1, hour : 0, minute : 0, second : 0 };
+        assert!(first.to_system_time() <= SystemTime::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn days_from_civil_round_trip() {
+        for days in (-800_000..3_000_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crate::App;
+use crate::MutexExt;
+
+/// Sees every message before it gets to `App::update`, in the order they
+/// were registered with `AppRunner::middleware`. Runs on the worker runtime,
+/// so it shouldn't block.
+pub trait Middleware<A : App> : Send + Sync {
+    /// Returns the message to pass on, or None to drop it.
+    fn on_msg(&self, msg : A::Msg) -> Option<A::Msg>;
+}
+
+// so a middleware can be registered and still be looked at from elsewhere
+impl<A : App, M : Middleware<A>> Middleware<A> for std::sync::Arc<M> {
+    fn on_msg(&self, msg : A::Msg) -> Option<A::Msg> {
+        (**self).on_msg(msg)
+    }
+}
+
+// the variant name from the Debug output, "Open" for Open{path : ..}
+crate fn variant_name<T : std::fmt::Debug>(msg : &T) -> String {
+    let s = format!("{:?}", msg);
+    let end = s.find(|c : char| !(c.is_alphanumeric() || c == '_')).unwrap_or(s.len());
+    s[..end].to_string()
+}
+
+/// Logs every message, at the info level.
+pub struct LogMsgs;
+
+impl<A : App> Middleware<A> for LogMsgs {
+    fn on_msg(&self, msg : A::Msg) -> Option<A::Msg> {
+        tracing::info!("msg: {:?}", msg);
+        Some(msg)
+    }
+}
+
+/// Counts messages by variant.
+#[derive(Default)]
+pub struct CountMsgs {
+    counts : Mutex<HashMap<String, usize>>,
+}
+
+impl CountMsgs {
+    pub fn counts(&self) -> HashMap<String, usize> {
+        self.counts.lock_or_recover().clone()
+    }
+}
+
+impl<A : App> Middleware<A> for CountMsgs {
+    fn on_msg(&self, msg : A::Msg) -> Option<A::Msg> {
+        *self.counts.lock_or_recover().entry(variant_name(&msg)).or_insert(0) += 1;
+        Some(msg)
+    }
+}
+
+/// Drops messages of a variant that arrive within `interval` of the last
+/// one that was let through.
+pub struct RateLimit {
+    interval : Duration,
+    last : Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(interval : Duration) -> Self {
+        RateLimit {
+            interval,
+            last : Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<A : App> Middleware<A> for RateLimit {
+    fn on_msg(&self, msg : A::Msg) -> Option<A::Msg> {
+        let now = Instant::now();
+        let name = variant_name(&msg);
+        let mut last = self.last.lock_or_recover();
+
+        match last.get(&name) {
+            Some(prev) if now.duration_since(*prev) < self.interval => return None,
+            _ => {},
+        }
+
+        last.insert(name, now);
+        Some(msg)
+    }
+}
+
+/// Keeps a copy of every message with the time since the first one, so a
+/// session can be replayed by sending them again.
+pub struct Record<M> {
+    start : Mutex<Option<Instant>>,
+    msgs : Mutex<Vec<(Duration, M)>>,
+}
+
+impl<M> Default for Record<M> {
+    fn default() -> Self {
+        Record {
+            start : Mutex::new(None),
+            msgs : Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<M> Record<M> {
+    pub fn take(&self) -> Vec<(Duration, M)> {
+        std::mem::take(&mut *self.msgs.lock_or_recover())
+    }
+}
+
+impl<A> Middleware<A> for Record<A::Msg>
+where
+    A : App,
+    A::Msg : Clone,
+{
+    fn on_msg(&self, msg : A::Msg) -> Option<A::Msg> {
+        let now = Instant::now();
+        let start = *self.start.lock_or_recover().get_or_insert(now);
+        self.msgs.lock_or_recover().push((now - start, msg.clone()));
+        Some(msg)
+    }
+}
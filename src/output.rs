@@ -0,0 +1,349 @@
+// the last steps of an export from the Export for... dialog, the "for the
+// web" ones: scaled down to a long edge, sharpened for the screen it'll be
+// seen on, converted to another color space, and saved as a jpeg with or
+// without the original's exif. run on a worker, they're slow
+
+use glam::f32::{
+    Mat3,
+    Vec3,
+};
+use image::imageops::FilterType;
+use image::RgbaImage;
+
+use photos1::{
+    matrix_profile,
+    srgb_to_xyz,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resample {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Lanczos3,
+}
+
+impl Resample {
+    pub const ALL : [Resample; 4] = [Resample::Nearest, Resample::Triangle, Resample::CatmullRom, Resample::Lanczos3];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resample::Nearest => "nearest (pixel art)",
+            Resample::Triangle => "bilinear (soft)",
+            Resample::CatmullRom => "bicubic",
+            Resample::Lanczos3 => "lanczos (sharpest)",
+        }
+    }
+
+    fn filter(&self) -> FilterType {
+        match self {
+            Resample::Nearest => FilterType::Nearest,
+            Resample::Triangle => FilterType::Triangle,
+            Resample::CatmullRom => FilterType::CatmullRom,
+            Resample::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+// an unsharp mask, made up for photos viewed at about 100%
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sharpen {
+    None,
+    Low,
+    Standard,
+    High,
+}
+
+// levels the mask leaves alone, so flat skies don't get noisier
+const SHARPEN_THRESHOLD : f32 = 2.;
+
+impl Sharpen {
+    pub const ALL : [Sharpen; 4] = [Sharpen::None, Sharpen::Low, Sharpen::Standard, Sharpen::High];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sharpen::None => "none",
+            Sharpen::Low => "low",
+            Sharpen::Standard => "standard",
+            Sharpen::High => "high",
+        }
+    }
+
+    // the blur's sigma and how much of the difference is added back
+    fn mask(&self) -> Option<(f32, f32)> {
+        match self {
+            Sharpen::None => None,
+            Sharpen::Low => Some((0.6, 0.4)),
+            Sharpen::Standard => Some((0.8, 0.7)),
+            Sharpen::High => Some((1.0, 1.0)),
+        }
+    }
+
+    fn apply(&self, img : &mut RgbaImage) {
+        let (sigma, amount) = match self.mask() {
+            Some(mask) => mask,
+            None => return,
+        };
+        let blurred = image::imageops::blur(img, sigma);
+
+        for (pixel, blur) in img.pixels_mut().zip(blurred.pixels()) {
+            for idx in 0..3 {
+                let (v, b) = (pixel.0[idx] as f32, blur.0[idx] as f32);
+                if (v - b).abs() > SHARPEN_THRESHOLD {
+                    pixel.0[idx] = (v + amount * (v - b)).round().clamp(0., 255.) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// What the jpeg's numbers mean, the photo is rendered in sRGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+}
+
+impl ColorSpace {
+    pub const ALL : [ColorSpace; 3] = [ColorSpace::Srgb, ColorSpace::DisplayP3, ColorSpace::AdobeRgb];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB (the web)",
+            ColorSpace::DisplayP3 => "Display P3",
+            ColorSpace::AdobeRgb => "Adobe RGB (1998)",
+        }
+    }
+
+    // the primaries adapted to D50, as columns, like an icc profile has
+    // them
+    fn to_xyz(&self) -> Mat3 {
+        match self {
+            ColorSpace::Srgb => srgb_to_xyz(),
+            ColorSpace::DisplayP3 => Mat3::from_cols_array_2d(&[
+                [0.515_102, 0.241_182, -0.001_05],
+                [0.291_965, 0.692_236, 0.041_882],
+                [0.157_153, 0.066_582, 0.784_378],
+            ]),
+            ColorSpace::AdobeRgb => Mat3::from_cols_array_2d(&[
+                [0.609_741, 0.311_111, 0.019_470],
+                [0.205_276, 0.625_671, 0.060_867],
+                [0.149_185, 0.063_217, 0.744_561],
+            ]),
+        }
+    }
+
+    // the tone curve as a power, None for the sRGB curve
+    fn gamma(&self) -> Option<f32> {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => None,
+            ColorSpace::AdobeRgb => Some(563. / 256.),
+        }
+    }
+
+    fn profile(&self) -> Vec<u8> {
+        matrix_profile(self.name(), self.to_xyz(), self.gamma())
+    }
+
+    // sRGB pixels into this space, out of gamut colors are clipped
+    fn convert(&self, img : &mut RgbaImage) {
+        if *self == ColorSpace::Srgb {
+            return
+        }
+
+        let matrix = self.to_xyz().inverse() * srgb_to_xyz();
+        let gamma = self.gamma();
+        // every value of a channel goes through the same curve
+        let linear : Vec<f32> = (0..=255u8).map(srgb_to_linear).collect();
+
+        for pixel in img.pixels_mut() {
+            let [r, g, b, _] = pixel.0;
+            let rgb = matrix * Vec3::new(linear[r as usize], linear[g as usize], linear[b as usize]);
+            for (idx, v) in rgb.to_array().iter().enumerate() {
+                let v = v.clamp(0., 1.);
+                let encoded = match gamma {
+                    Some(gamma) => v.powf(1. / gamma),
+                    None => linear_to_srgb(v),
+                };
+                pixel.0[idx] = (encoded * 255.).round() as u8;
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(v : u8) -> f32 {
+    let v = v as f32 / 255.;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v : f32) -> f32 {
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Everything after the preset, the defaults change nothing but the
+/// encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Output {
+    /// Photos longer than this are scaled down to it
+    pub long_edge : Option<u32>,
+    pub resample : Resample,
+    pub sharpen : Sharpen,
+    pub color_space : ColorSpace,
+    /// Copy the original's exif, and write the caption if the export
+    /// does
+    pub keep_metadata : bool,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            long_edge : None,
+            resample : Resample::Lanczos3,
+            sharpen : Sharpen::None,
+            color_space : ColorSpace::Srgb,
+            keep_metadata : true,
+        }
+    }
+}
+
+impl Output {
+    /// Resized, then sharpened, then converted.
+    pub fn apply(&self, img : RgbaImage) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        let mut img = match self.long_edge {
+            Some(edge) if width.max(height) > edge && edge > 0 => {
+                let scale = edge as f32 / width.max(height) as f32;
+                let (w, h) = (
+                    ((width as f32 * scale).round() as u32).max(1),
+                    ((height as f32 * scale).round() as u32).max(1),
+                );
+                image::imageops::resize(&img, w, h, self.resample.filter())
+            },
+            _ => img,
+        };
+
+        self.sharpen.apply(&mut img);
+        self.color_space.convert(&mut img);
+        img
+    }
+
+    /// The jpeg, with the color space's profile unless it's sRGB, and
+    /// original's exif if there is one and it's kept.
+    pub fn encode(&self, img : &RgbaImage, original : Option<&[u8]>) -> image::ImageResult<Vec<u8>> {
+        let rgb = image::DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+        let mut byt = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut byt).encode(
+            &rgb,
+            rgb.width(),
+            rgb.height(),
+            image::ColorType::Rgb8,
+        )?;
+
+        let mut segments = Vec::new();
+        if let (true, Some(exif)) = (self.keep_metadata, original.and_then(exif_of)) {
+            segments.push((0xE1, exif));
+        }
+        if self.color_space != ColorSpace::Srgb {
+            // the profile is small enough for one segment
+            let mut icc = b"ICC_PROFILE\0\x01\x01".to_vec();
+            icc.extend(self.color_space.profile());
+            segments.push((0xE2, icc));
+        }
+
+        Ok(with_segments(byt, &segments))
+    }
+}
+
+// the exif segment of a jpeg, with the orientation reset since the pixels
+// were already turned
+fn exif_of(jpeg : &[u8]) -> Option<Vec<u8>> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None
+    }
+
+    let mut at = 2;
+    loop {
+        let marker = *jpeg.get(at + 1)?;
+        // the image data starts, no more metadata
+        if *jpeg.get(at)? != 0xFF || marker == 0xDA {
+            return None
+        }
+
+        let len = u16::from_be_bytes([*jpeg.get(at + 2)?, *jpeg.get(at + 3)?]) as usize;
+        let payload = jpeg.get(at + 4..at + 2 + len)?;
+        if marker == 0xE1 && payload.starts_with(b"Exif\0\0") {
+            let mut exif = payload.to_vec();
+            reset_orientation(&mut exif[6..]);
+            return Some(exif)
+        }
+
+        at += 2 + len;
+    }
+}
+
+// sets the first IFD's orientation tag to 1, if it has one
+fn reset_orientation(tiff : &mut [u8]) -> Option<()> {
+    let little = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |byt : &[u8], at : usize| -> Option<u16> {
+        let b = [*byt.get(at)?, *byt.get(at + 1)?];
+        Some(if little { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let u32_at = |byt : &[u8], at : usize| -> Option<u32> {
+        let b = [*byt.get(at)?, *byt.get(at + 1)?, *byt.get(at + 2)?, *byt.get(at + 3)?];
+        Some(if little { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+
+    let ifd = u32_at(tiff, 4)? as usize;
+    let count = u16_at(tiff, ifd)? as usize;
+    for idx in 0..count {
+        let entry = ifd + 2 + idx * 12;
+        // a short, stored in the first two bytes of the value
+        if u16_at(tiff, entry)? == 0x0112 {
+            let one = if little { 1u16.to_le_bytes() } else { 1u16.to_be_bytes() };
+            tiff.get_mut(entry + 8..entry + 10)?.copy_from_slice(&one);
+            return Some(())
+        }
+    }
+    None
+}
+
+// the jpeg with the segments after SOI and JFIF's APP0, each payload has to
+// fit in one
+fn with_segments(jpeg : Vec<u8>, segments : &[(u8, Vec<u8>)]) -> Vec<u8> {
+    if segments.is_empty() || !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return jpeg
+    }
+
+    let mut at = 2;
+    if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) {
+        at += 2 + u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+    }
+
+    let mut out = Vec::with_capacity(jpeg.len() + segments.iter().map(|(_, s)| s.len() + 4).sum::<usize>());
+    out.extend_from_slice(&jpeg[..at]);
+    for (marker, payload) in segments {
+        // the length counts itself
+        let len = payload.len() + 2;
+        if len > u16::MAX as usize {
+            continue
+        }
+        out.extend_from_slice(&[0xFF, *marker]);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+        out.extend_from_slice(payload);
+    }
+    out.extend_from_slice(&jpeg[at..]);
+    out
+}
@@ -0,0 +1,123 @@
+// the photo screen's side panel is a column of cards, each collapsed and
+// moved up or down on its own, and the order and which are open are kept
+// in the settings
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Card {
+    Info,
+    Histogram,
+    Statistics,
+    Adjustments,
+    Crop,
+    History,
+}
+
+const ALL : [Card; 6] = [
+    Card::Info,
+    Card::Histogram,
+    Card::Statistics,
+    Card::Adjustments,
+    Card::Crop,
+    Card::History,
+];
+
+impl Card {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Card::Info => "info",
+            Card::Histogram => "histogram",
+            Card::Statistics => "statistics",
+            Card::Adjustments => "adjustments",
+            Card::Crop => "crop",
+            Card::History => "history",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct CardState {
+    card : Card,
+    open : bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanelLayout {
+    cards : Vec<CardState>,
+}
+
+impl Default for PanelLayout {
+    // how the panel was before it could be changed, with the histogram
+    // under the info
+    fn default() -> Self {
+        let open = |card| !matches!(card, Card::Info | Card::Statistics | Card::Crop | Card::History);
+
+        PanelLayout{
+            cards : ALL.iter().map(|&card| CardState{card, open : open(card)}).collect(),
+        }
+    }
+}
+
+impl PanelLayout {
+    /// Every card once, cards a newer version added at the end, for
+    /// layouts from the settings.
+    pub fn fixed(mut self) -> Self {
+        let mut seen = Vec::new();
+        self.cards.retain(|state| {
+            let first = !seen.contains(&state.card);
+            seen.push(state.card);
+            first
+        });
+
+        for &card in &ALL {
+            if !seen.contains(&card) {
+                self.cards.push(CardState{card, open : false});
+            }
+        }
+
+        self
+    }
+
+    pub fn is_open(&self, card : Card) -> bool {
+        self.cards.iter().any(|state| state.card == card && state.open)
+    }
+
+    /// Each card in order under a header which opens, closes and moves
+    /// it, body draws what's in the open ones.
+    pub fn show(&mut self, ui : &mut egui::Ui, mut body : impl FnMut(Card, &mut egui::Ui)) {
+        let count = self.cards.len();
+        let mut moved = None;
+
+        for (idx, state) in self.cards.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let arrow = if state.open { "⏷" } else { "⏵" };
+                if ui.selectable_label(false, format!("{} {}", arrow, state.card.name())).clicked() {
+                    state.open = !state.open;
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(), |ui| {
+                    if ui.add(egui::Button::new("⏷").small().enabled(idx + 1 < count)).clicked() {
+                        moved = Some((idx, idx + 1));
+                    }
+                    if ui.add(egui::Button::new("⏶").small().enabled(idx > 0)).clicked() {
+                        moved = Some((idx, idx - 1));
+                    }
+                });
+            });
+
+            if state.open {
+                body(state.card, ui);
+            }
+
+            ui.separator();
+        }
+
+        if let Some((a, b)) = moved {
+            self.cards.swap(a, b);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// just enough of PDF to put jpegs and lines of text on pages, for
+// printing. sizes are in points, 72 to the inch, from the bottom left of the
+// page
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+/// A jpeg added to a `Pdf`, to draw on its pages.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRef(usize);
+
+pub struct Page {
+    width : f32,
+    height : f32,
+    images : Vec<ImageRef>,
+    // bytes rather than a string, text is Latin-1
+    content : Vec<u8>,
+    has_text : bool,
+}
+
+// Helvetica's average width, for fitting text without its metrics
+const AVERAGE_WIDTH : f32 = 0.52;
+
+impl Page {
+    pub fn new(width : f32, height : f32) -> Self {
+        Page {
+            width,
+            height,
+            images : Vec::new(),
+            content : Vec::new(),
+            has_text : false,
+        }
+    }
+
+    /// A line in Helvetica from its baseline's left, cut short with ...
+    /// to about max_width. Characters outside Latin-1 are printed as ?.
+    pub fn text(&mut self, x : f32, y : f32, size : f32, max_width : f32, text : &str) {
+        let fits = (max_width / (size * AVERAGE_WIDTH)).max(1.) as usize;
+        let chars : Vec<char> = text.chars().collect();
+        let text : String = if chars.len() > fits {
+            chars[..fits.saturating_sub(3)].iter().chain(['.', '.', '.'].iter()).collect()
+        } else {
+            text.to_string()
+        };
+
+        self.has_text = true;
+        let _ = write!(self.content, "BT /F1 {:.1} Tf {:.2} {:.2} Td (", size, x, y);
+        for c in text.chars() {
+            match c {
+                '(' | ')' | '\\' => self.content.extend_from_slice(&[b'\\', c as u8]),
+                // WinAnsiEncoding is Latin-1 for these
+                ' '..='~' | '\u{a0}'..='\u{ff}' => self.content.push(c as u32 as u8),
+                _ => self.content.push(b'?'),
+            }
+        }
+        self.content.extend_from_slice(b") Tj ET\n");
+    }
+
+    /// Stretched over the rect.
+    pub fn image(&mut self, image : ImageRef, [x, y, w, h] : [f32; 4]) {
+        let name = self.images.len();
+        self.images.push(image);
+        let _ = writeln!(self.content, "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q", w, h, x, y, name);
+    }
+}
+
+pub struct Pdf {
+    // numbered from 1, the catalog and the page tree are filled in last
+    objects : Vec<Vec<u8>>,
+    pages : Vec<usize>,
+    // added with the first page with text
+    font : Option<usize>,
+}
+
+const CATALOG : usize = 1;
+const PAGES : usize = 2;
+
+impl Default for Pdf {
+    fn default() -> Self {
+        Pdf {
+            objects : vec![Vec::new(), Vec::new()],
+            pages : Vec::new(),
+            font : None,
+        }
+    }
+}
+
+impl Pdf {
+    fn add(&mut self, object : Vec<u8>) -> usize {
+        self.objects.push(object);
+        self.objects.len()
+    }
+
+    fn add_stream(&mut self, dict : &str, data : &[u8]) -> usize {
+        let mut object = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+        object.extend_from_slice(data);
+        object.extend_from_slice(b"\nendstream");
+        self.add(object)
+    }
+
+    /// An RGB baseline jpeg, width by height pixels.
+    pub fn add_jpeg(&mut self, jpeg : &[u8], width : u32, height : u32) -> ImageRef {
+        let dict = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode",
+            width,
+            height,
+        );
+        ImageRef(self.add_stream(&dict, jpeg))
+    }
+
+    pub fn add_page(&mut self, page : Page) {
+        let font = match (page.has_text, self.font) {
+            (false, _) => String::new(),
+            (true, Some(font)) => format!("/Font << /F1 {} 0 R >> ", font),
+            (true, None) => {
+                let object = b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>";
+                let font = self.add(object.to_vec());
+                self.font = Some(font);
+                format!("/Font << /F1 {} 0 R >> ", font)
+            },
+        };
+
+        let contents = self.add_stream("", &page.content);
+
+        let images : String = page.images.iter()
+            .enumerate()
+            .map(|(idx, ImageRef(id))| format!("/Im{} {} 0 R ", idx, id))
+            .collect();
+        let object = format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << {}/XObject << {}>> >> /Contents {} 0 R >>",
+            PAGES,
+            page.width,
+            page.height,
+            font,
+            images,
+            contents,
+        );
+        let id = self.add(object.into_bytes());
+        self.pages.push(id);
+    }
+
+    pub fn to_bytes(mut self) -> Vec<u8> {
+        let kids : String = self.pages.iter().map(|id| format!("{} 0 R ", id)).collect();
+        self.objects[CATALOG - 1] = format!("<< /Type /Catalog /Pages {} 0 R >>", PAGES).into_bytes();
+        self.objects[PAGES - 1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, self.pages.len()).into_bytes();
+
+        // the binary comment says it isn't text
+        let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (idx, object) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", idx + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref = out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = writeln!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF",
+            self.objects.len() + 1,
+            CATALOG,
+            xref,
+        );
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+}
@@ -0,0 +1,51 @@
+// vim: ft=c
+
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+
+uniform sampler2D texture;
+
+// the image's size, to step a pixel at a time
+uniform vec2 texture_size;
+
+// x, y, width, height in uv units, like the effects shader's
+uniform vec4 crop;
+
+// the gradient over which a pixel is marked, 0 to about 4
+uniform float threshold;
+
+float luma(vec2 at) {
+    vec3 color = texture2D(texture, at).rgb;
+    return 0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b;
+}
+
+// drawn over the photo, only the sharp edges are drawn and the rest is left
+// as is
+void main() {
+    if (any(lessThan(uv, crop.xy)) || any(greaterThan(uv, crop.xy + crop.zw))) {
+        discard;
+    }
+
+    vec2 px = 1. / texture_size;
+
+    float tl = luma(uv + vec2(-px.x,  px.y));
+    float t  = luma(uv + vec2(   0.,  px.y));
+    float tr = luma(uv + vec2( px.x,  px.y));
+    float l  = luma(uv + vec2(-px.x,    0.));
+    float r  = luma(uv + vec2( px.x,    0.));
+    float bl = luma(uv + vec2(-px.x, -px.y));
+    float b  = luma(uv + vec2(   0., -px.y));
+    float br = luma(uv + vec2( px.x, -px.y));
+
+    // sobel
+    float gx = (tr + 2. * r + br) - (tl + 2. * l + bl);
+    float gy = (tl + 2. * t + tr) - (bl + 2. * b + br);
+
+    if (length(vec2(gx, gy)) < threshold) {
+        discard;
+    }
+
+    gl_FragColor = vec4(.2, 1., .2, 1.);
+}
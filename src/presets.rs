@@ -0,0 +1,97 @@
+// exports sized for where they're going, like instagram or a print shop.
+// photos of another shape are either cropped where the detail is, or fit
+// inside and padded with a blurred copy of themselves
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+
+use crate::crop;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Preset {
+    pub name : &'static str,
+    // added to the file name
+    pub suffix : &'static str,
+    pub width : u32,
+    pub height : u32,
+}
+
+pub const PRESETS : &[Preset] = &[
+    Preset{ name : "Instagram portrait 4:5", suffix : "4x5", width : 1080, height : 1350 },
+    Preset{ name : "Instagram square 1:1", suffix : "1x1", width : 1080, height : 1080 },
+    Preset{ name : "Story 9:16", suffix : "9x16", width : 1080, height : 1920 },
+    // 6x4 inches at 300 dpi
+    Preset{ name : "Print 3:2", suffix : "3x2", width : 1800, height : 1200 },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    // the smart crop, see crop::best
+    Crop,
+    // the whole photo on a blurred copy of itself
+    Pad,
+}
+
+// the padding is blurred at this fraction of the output size, which is
+// much faster and looks the same
+const BLUR_SCALE : u32 = 8;
+const BLUR_SIGMA : f32 = 6.0;
+
+impl Preset {
+    fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    /// The image at the preset's size. Slow for large photos, run it off
+    /// the render thread.
+    pub fn apply(&self, img : &RgbaImage, fit : Fit) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return RgbaImage::new(self.width, self.height)
+        }
+
+        match fit {
+            Fit::Crop => {
+                let [x, y, w, h] = crop::best(img, self.aspect());
+                let cropped = image::imageops::crop_imm(
+                    img,
+                    (x * width as f32) as u32,
+                    (y * height as f32) as u32,
+                    ((w * width as f32) as u32).max(1),
+                    ((h * height as f32) as u32).max(1),
+                ).to_image();
+
+                image::imageops::resize(&cropped, self.width, self.height, FilterType::Lanczos3)
+            },
+            Fit::Pad => {
+                let (small_w, small_h) = ((self.width / BLUR_SCALE).max(1), (self.height / BLUR_SCALE).max(1));
+                let background = cover(img, small_w, small_h);
+                let background = image::imageops::blur(&background, BLUR_SIGMA);
+                let mut out = image::imageops::resize(&background, self.width, self.height, FilterType::Triangle);
+
+                let scale = (self.width as f32 / width as f32).min(self.height as f32 / height as f32);
+                let (fit_w, fit_h) = (
+                    ((width as f32 * scale).round() as u32).clamp(1, self.width),
+                    ((height as f32 * scale).round() as u32).clamp(1, self.height),
+                );
+                let fitted = image::imageops::resize(img, fit_w, fit_h, FilterType::Lanczos3);
+                image::imageops::overlay(&mut out, &fitted, (self.width - fit_w) / 2, (self.height - fit_h) / 2);
+
+                out
+            },
+        }
+    }
+}
+
+// scaled to cover width by height and cropped to it, centered
+fn cover(img : &RgbaImage, width : u32, height : u32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let scale = (width as f32 / w as f32).max(height as f32 / h as f32);
+    let (sw, sh) = (
+        ((w as f32 * scale).ceil() as u32).max(width),
+        ((h as f32 * scale).ceil() as u32).max(height),
+    );
+
+    let scaled = image::imageops::resize(img, sw, sh, FilterType::Triangle);
+    image::imageops::crop_imm(&scaled, (sw - width) / 2, (sh - height) / 2, width, height).to_image()
+}
@@ -0,0 +1,195 @@
+// a second window showing a photo fullscreen on another monitor, without
+// the ui, see RenderCtx::set_preview. its context shares textures with the
+// window's, but glium's programs and buffers belong to the context that made
+// them, so the photo is drawn into a texture by the window's context and the
+// preview's own context only copies that texture to its window
+
+use glium::glutin;
+use glium::{
+    GlObject,
+    Surface,
+};
+
+use crate::{
+    Error,
+    Result,
+    Vertex,
+};
+use crate::utils::{
+    create_preview_display,
+    WindowOptions,
+};
+
+const VERTEX : &str = "
+#version 100
+precision lowp float;
+
+attribute vec2 position;
+attribute vec2 texcoord;
+
+varying vec2 uv;
+
+void main() {
+    gl_Position = vec4(position, 0., 1.);
+    uv = texcoord;
+}
+";
+
+const FRAGMENT : &str = "
+#version 100
+precision lowp float;
+
+varying lowp vec2 uv;
+
+uniform sampler2D texture;
+
+void main() {
+    gl_FragColor = texture2D(texture, uv);
+}
+";
+
+crate struct Preview {
+    display : glium::Display,
+    // the index into RenderCtx::monitors it's on
+    crate monitor : usize,
+    program : glium::Program,
+    vertex_buffer : glium::VertexBuffer<Vertex>,
+    index_buffer : glium::IndexBuffer<u16>,
+    // drawn into by the window's context, kept while the size is the same
+    target : Option<glium::texture::SrgbTexture2d>,
+    // whether target has this frame's photo
+    drawn : bool,
+}
+
+impl Preview {
+    // None if the monitor is gone or the window can't be made, which is
+    // logged
+    crate fn new<T>(
+        shared : &glium::Display,
+        title : &str,
+        monitor : usize,
+        options : &WindowOptions,
+        event_loop : &glutin::event_loop::EventLoopWindowTarget<T>) -> Option<Self>
+    {
+        let handle = shared.gl_window().window().available_monitors().nth(monitor)?;
+        let display = match create_preview_display(shared, title, handle, options, event_loop) {
+            Ok(display) => display,
+            Err(err) => {
+                tracing::error!("could not open the preview window: {}", err);
+                return None
+            },
+        };
+
+        match Self::with_display(display, monitor) {
+            Ok(preview) => Some(preview),
+            Err(err) => {
+                tracing::error!("could not open the preview window: {}", err);
+                None
+            },
+        }
+    }
+
+    fn with_display(display : glium::Display, monitor : usize) -> Result<Self> {
+        let program = crate::cached_program(&display, "preview", VERTEX, FRAGMENT)?;
+
+        // render targets have their first row at the bottom, unlike the
+        // uploaded images GraphicsCtx's quad is for
+        let vertex_buffer = glium::VertexBuffer::new(&display,
+            &[
+                Vertex { position: [-1.0,  1.0], texcoord: [0.0, 1.0] },
+                Vertex { position: [-1.0, -1.0], texcoord: [0.0, 0.0] },
+                Vertex { position: [ 1.0, -1.0], texcoord: [1.0, 0.0] },
+                Vertex { position: [ 1.0,  1.0], texcoord: [1.0, 1.0] }
+            ]
+        )?;
+
+        let index_buffer = glium::IndexBuffer::new(
+            &display,
+            glium::index::PrimitiveType::TriangleStrip,
+            &[1 as u16, 2, 0, 3]
+        )?;
+
+        Ok(Preview{
+            display,
+            monitor,
+            program,
+            vertex_buffer,
+            index_buffer,
+            target : None,
+            drawn : false,
+        })
+    }
+
+    crate fn id(&self) -> glutin::window::WindowId {
+        self.display.gl_window().window().id()
+    }
+
+    crate fn dimensions(&self) -> (u32, u32) {
+        self.display.get_framebuffer_dimensions()
+    }
+
+    // the texture for this frame's photo, made in shared's context
+    crate fn target(&mut self, shared : &glium::Display) -> Result<&glium::texture::SrgbTexture2d> {
+        let (width, height) = self.dimensions();
+        let stale = self.target.as_ref().map_or(true, |target| {
+            target.get_width() != width || target.get_height() != Some(height)
+        });
+
+        if stale {
+            self.target = Some(glium::texture::SrgbTexture2d::empty_with_format(
+                shared,
+                glium::texture::SrgbFormat::U8U8U8U8,
+                glium::texture::MipmapsOption::NoMipmap,
+                width,
+                height,
+            )?);
+        }
+
+        self.drawn = true;
+        Ok(self.target.as_ref().unwrap())
+    }
+
+    // shows what was drawn into target this frame, black if nothing was
+    crate fn present(&mut self, shared : &glium::Display) -> Result<()> {
+        let mut frame = self.display.draw();
+        frame.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        let drawn = match (&self.target, self.drawn) {
+            (Some(target), true) => {
+                // the preview's context only sees what shared sent to the
+                // driver
+                shared.flush();
+
+                let (width, height) = (target.get_width(), target.get_height().unwrap_or(1));
+                let texture = unsafe {
+                    glium::texture::SrgbTexture2d::from_id(
+                        &self.display,
+                        glium::texture::SrgbFormat::U8U8U8U8,
+                        target.get_id(),
+                        false,
+                        glium::texture::MipmapsOption::NoMipmap,
+                        glium::texture::Dimensions::Texture2d{
+                            width,
+                            height,
+                        }
+                    )
+                };
+
+                frame.draw(
+                    &self.vertex_buffer,
+                    &self.index_buffer,
+                    &self.program,
+                    &glium::uniforms::UniformsStorage::new("texture", &texture),
+                    &Default::default(),
+                ).map_err(Error::from)
+            },
+            _ => Ok(()),
+        };
+        self.drawn = false;
+
+        // a frame has to be finished even if drawing into it failed
+        let finished = frame.finish();
+        drawn?;
+        Ok(finished?)
+    }
+}
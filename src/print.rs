@@ -0,0 +1,261 @@
+// a photo laid out on a sheet of paper, saved as a one page PDF or sent to
+// the printer through CUPS' lp, which takes PDFs. sizes are in points, 72
+// to the inch, like the PDF's
+
+use std::path::Path;
+
+use image::RgbaImage;
+
+use crate::pdf::{
+    Page,
+    Pdf,
+};
+
+const POINTS_PER_MM : f32 = 72. / 25.4;
+
+// the quality the photo is put in the PDF at
+const JPEG_QUALITY : u8 = 95;
+
+// the preview's page, the longer side
+const PREVIEW_SIZE : f32 = 280.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Paper {
+    pub name : &'static str,
+    // portrait, in mm
+    width : f32,
+    height : f32,
+}
+
+pub const PAPERS : &[Paper] = &[
+    Paper{ name : "A4", width : 210., height : 297. },
+    Paper{ name : "Letter", width : 215.9, height : 279.4 },
+    Paper{ name : "A3", width : 297., height : 420. },
+    Paper{ name : "A5", width : 148., height : 210. },
+    Paper{ name : "4x6 in", width : 101.6, height : 152.4 },
+    Paper{ name : "5x7 in", width : 127., height : 177.8 },
+    Paper{ name : "8x10 in", width : 203.2, height : 254. },
+];
+
+impl Paper {
+    /// Portrait, in points.
+    pub fn points(&self) -> (f32, f32) {
+        (self.width * POINTS_PER_MM, self.height * POINTS_PER_MM)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    // the photo's
+    Auto,
+    Portrait,
+    Landscape,
+}
+
+/// Where the photo goes on the page, from the bottom left.
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub page : (f32, f32),
+    pub rect : [f32; 4],
+    /// The pixels that make it there, x, y, width, height from the top
+    /// left, all of them unless the photo fills the page
+    pub crop : [u32; 4],
+}
+
+impl Placement {
+    /// The photo's resolution on paper.
+    pub fn dpi(&self) -> f32 {
+        self.crop[2] as f32 / (self.rect[2] / 72.)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintLayout {
+    /// In PAPERS
+    pub paper : usize,
+    pub orientation : Orientation,
+    /// Around the edges, in mm
+    pub margin : f32,
+    /// Covers the space inside the margins, cropped, instead of fitting
+    /// inside it
+    pub fill : bool,
+    /// At the top of the page instead of in the middle, for writing under
+    pub top : bool,
+}
+
+impl Default for PrintLayout {
+    fn default() -> Self {
+        PrintLayout {
+            paper : 0,
+            orientation : Orientation::Auto,
+            margin : 10.,
+            fill : false,
+            top : false,
+        }
+    }
+}
+
+impl PrintLayout {
+    fn page(&self, landscape_photo : bool) -> (f32, f32) {
+        let (w, h) = PAPERS[self.paper.min(PAPERS.len() - 1)].points();
+        let landscape = match self.orientation {
+            Orientation::Auto => landscape_photo,
+            Orientation::Portrait => false,
+            Orientation::Landscape => true,
+        };
+        if landscape { (h, w) } else { (w, h) }
+    }
+
+    /// For a photo of width by height pixels.
+    pub fn place(&self, width : u32, height : u32) -> Placement {
+        let (width, height) = (width.max(1), height.max(1));
+        let page = self.page(width > height);
+
+        // the margins are left whatever they're set to
+        let margin = (self.margin * POINTS_PER_MM).clamp(0., page.0.min(page.1) / 2. - 1.);
+        let (area_w, area_h) = (page.0 - 2. * margin, page.1 - 2. * margin);
+
+        let (img_w, img_h) = (width as f32, height as f32);
+        let (rect_w, rect_h, crop) = if self.fill {
+            // the middle of the photo in the area's shape
+            let scale = (area_w / img_w).max(area_h / img_h);
+            let (crop_w, crop_h) = (
+                ((area_w / scale).round() as u32).clamp(1, width),
+                ((area_h / scale).round() as u32).clamp(1, height),
+            );
+            (area_w, area_h, [(width - crop_w) / 2, (height - crop_h) / 2, crop_w, crop_h])
+        } else {
+            let scale = (area_w / img_w).min(area_h / img_h);
+            (img_w * scale, img_h * scale, [0, 0, width, height])
+        };
+
+        let x = margin + (area_w - rect_w) / 2.;
+        let y = match self.top {
+            true => page.1 - margin - rect_h,
+            false => margin + (area_h - rect_h) / 2.,
+        };
+
+        Placement{
+            page,
+            rect : [x, y, rect_w, rect_h],
+            crop,
+        }
+    }
+
+    /// A page with the photo on it. Slow for large photos, run it off the
+    /// render thread.
+    pub fn pdf(&self, img : &RgbaImage) -> image::ImageResult<Vec<u8>> {
+        let placement = self.place(img.width(), img.height());
+        let [x, y, w, h] = placement.crop;
+        let cropped = image::imageops::crop_imm(img, x, y, w, h).to_image();
+        let rgb = image::DynamicImage::ImageRgba8(cropped).to_rgb8();
+
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+            &rgb,
+            rgb.width(),
+            rgb.height(),
+            image::ColorType::Rgb8,
+        )?;
+
+        let mut pdf = Pdf::default();
+        let image = pdf.add_jpeg(&jpeg, rgb.width(), rgb.height());
+        let (page_w, page_h) = placement.page;
+        let mut page = Page::new(page_w, page_h);
+        page.image(image, placement.rect);
+        pdf.add_page(page);
+
+        Ok(pdf.to_bytes())
+    }
+
+    /// The paper, orientation, margin, and position.
+    pub fn show(&mut self, ui : &mut egui::Ui) {
+        let paper = &mut self.paper;
+        egui::ComboBox::from_label("Paper")
+            .selected_text(PAPERS.get(*paper).map_or("", |p| p.name))
+            .show_ui(ui, |ui| {
+                for (idx, p) in PAPERS.iter().enumerate() {
+                    ui.selectable_value(paper, idx, p.name);
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.orientation, Orientation::Auto, "like the photo");
+            ui.radio_value(&mut self.orientation, Orientation::Portrait, "portrait");
+            ui.radio_value(&mut self.orientation, Orientation::Landscape, "landscape");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Margins: ");
+            ui.add(egui::DragValue::new(&mut self.margin).clamp_range(0.0..=50.0).suffix(" mm"));
+        });
+
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.fill, false, "fit the photo");
+            ui.radio_value(&mut self.fill, true, "fill the page");
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.top, false, "centered");
+            ui.radio_value(&mut self.top, true, "at the top");
+        });
+    }
+
+    /// The page as it'll print, with the photo of width by height pixels
+    /// drawn from texture.
+    pub fn preview(
+        &self,
+        ui : &mut egui::Ui,
+        (width, height) : (u32, u32),
+        (texture_id, uv, tint) : (egui::TextureId, egui::Rect, egui::Color32),
+    ) {
+        let placement = self.place(width, height);
+        let (page_w, page_h) = placement.page;
+        let scale = PREVIEW_SIZE / page_w.max(page_h);
+
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(page_w * scale, page_h * scale), egui::Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 0.0, egui::Color32::WHITE);
+
+        // from the bottom left to egui's top left
+        let [x, y, w, h] = placement.rect;
+        let photo = egui::Rect::from_min_size(
+            egui::pos2(rect.left() + x * scale, rect.bottom() - (y + h) * scale),
+            egui::vec2(w * scale, h * scale),
+        );
+
+        let [cx, cy, cw, ch] = placement.crop;
+        let (fw, fh) = (width.max(1) as f32, height.max(1) as f32);
+        let at = |x : u32, y : u32| egui::pos2(
+            egui::lerp(uv.left()..=uv.right(), x as f32 / fw),
+            egui::lerp(uv.top()..=uv.bottom(), y as f32 / fh),
+        );
+        let uv_crop = egui::Rect::from_min_max(at(cx, cy), at(cx + cw, cy + ch));
+
+        let mut mesh = egui::epaint::Mesh::with_texture(texture_id);
+        mesh.add_rect_with_uv(photo, uv_crop, tint);
+        painter.add(egui::Shape::mesh(mesh));
+
+        ui.label(format!(
+            "{:.0} x {:.0} mm at {:.0} dpi",
+            w / POINTS_PER_MM,
+            h / POINTS_PER_MM,
+            placement.dpi(),
+        ));
+    }
+}
+
+/// Sends a PDF to the default printer.
+#[cfg(unix)]
+pub fn send(pdf : &Path) -> std::io::Result<()> {
+    let status = std::process::Command::new("lp").arg(pdf).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(std::io::ErrorKind::Other, format!("lp exited with {}", status)))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send(_pdf : &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "printing needs CUPS' lp, save a PDF and print that instead"))
+}
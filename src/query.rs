@@ -0,0 +1,110 @@
+// the searches smart albums are saved as, like "5 stars AND 2023 AND
+// tag:trip". every term has to match, "and" between them is optional, and
+// "not" or a - before one turns it around. the years are in the photos'
+// files, so they're checked once the photos are read, the rest against the
+// library
+
+use std::path::Path;
+
+use crate::library::PhotoRecord;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    // at least this many, 0 for none
+    Stars(i8),
+    Picked,
+    Rejected,
+    // a keyword, any case
+    Tag(String),
+    // a color label, any case
+    Label(String),
+    Year(i32),
+    // in the file name or the caption, any case
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    // and whether it's turned around
+    terms : Vec<(Term, bool)>,
+}
+
+impl Query {
+    /// Anything it doesn't know is searched for as text.
+    pub fn parse(text : &str) -> Self {
+        let mut terms = Vec::new();
+        let mut words = text.split_whitespace().peekable();
+        let mut negated = false;
+
+        while let Some(word) = words.next() {
+            let lower = word.to_lowercase();
+            if lower == "and" {
+                continue
+            }
+            if lower == "not" {
+                negated = !negated;
+                continue
+            }
+
+            let minus = lower.len() > 1 && lower.starts_with('-');
+            let lower = if minus { lower[1..].to_string() } else { lower };
+
+            let (key, value) = lower.split_once(':').unwrap_or(("", lower.as_str()));
+            let number = value.parse::<i32>().ok();
+
+            let term = match (key, number) {
+                ("stars" | "rating", Some(n)) => Term::Stars(n.clamp(0, 5) as i8),
+                ("year", Some(year)) => Term::Year(year),
+                ("tag" | "keyword", _) => Term::Tag(value.to_string()),
+                ("label", _) => Term::Label(value.to_string()),
+                ("", Some(n)) if matches!(words.peek().map(|w| w.to_lowercase()).as_deref(), Some("star" | "stars")) => {
+                    words.next();
+                    Term::Stars(n.clamp(0, 5) as i8)
+                },
+                ("", Some(year)) if value.len() == 4 => Term::Year(year),
+                ("", None) if value == "picked" => Term::Picked,
+                ("", None) if value == "rejected" => Term::Rejected,
+                _ => Term::Text(lower.clone()),
+            };
+
+            terms.push((term, negated != minus));
+            negated = false;
+        }
+
+        Query{terms}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Every term but the years, which aren't in the library.
+    pub fn matches_record(&self, path : &Path, record : &PhotoRecord) -> bool {
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let caption = record.description.as_ref().map(|d| d.to_lowercase()).unwrap_or_default();
+
+        self.terms.iter().all(|(term, negated)| {
+            let matched = match term {
+                Term::Stars(0) => record.rating.map_or(true, |rating| rating == 0),
+                Term::Stars(stars) => record.rating.map_or(false, |rating| rating >= *stars),
+                Term::Picked => record.picked,
+                Term::Rejected => record.rating == Some(-1),
+                Term::Tag(tag) => record.keywords.iter().any(|k| k.to_lowercase() == *tag),
+                Term::Label(label) => record.label.as_ref().map_or(false, |l| l.to_lowercase() == *label),
+                Term::Year(_) => return true,
+                Term::Text(text) => name.contains(text.as_str()) || caption.contains(text.as_str()),
+            };
+            matched != *negated
+        })
+    }
+
+    /// Only the years, a photo without a date is in none of them.
+    pub fn matches_year(&self, year : Option<i32>) -> bool {
+        self.terms.iter().all(|(term, negated)| match term {
+            Term::Year(wanted) => (year == Some(*wanted)) != *negated,
+            _ => true,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+// a panic in a worker shouldn't take the window down with it. worker
+// futures and model patches are run under catch_unwind and their panics
+// handed to App::handle_error, and the mutexes they shared are taken back
+// from them instead of poisoning everyone after
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    PoisonError,
+};
+use std::task::{
+    Context,
+    Poll,
+};
+
+/// A panic in `App::update`, a job or a patch, caught and handed to
+/// `App::handle_error`.
+#[derive(Debug, Clone)]
+pub struct Panic {
+    /// What was running, like "the job loading albums"
+    pub during : String,
+    pub message : String,
+}
+
+impl Panic {
+    crate fn new(during : impl Into<String>, payload : Box<dyn Any + Send>) -> Self {
+        // panic! with a format string gives a String, without one a &str
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>()
+                .map_or_else(|| "unknown panic".to_string(), |s| s.to_string()),
+        };
+
+        Panic {
+            during : during.into(),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Panic {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} crashed: {}", self.during, self.message)
+    }
+}
+
+/// For mutexes shared with workers, see `lock_or_recover`.
+pub trait MutexExt<T> {
+    /// Locks the mutex, and if a thread panicked while holding it, takes
+    /// the data back as that thread left it instead of panicking too.
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// resolves to Err with the payload if polling fut panics
+crate struct CatchUnwind<F> {
+    fut : Pin<Box<F>>,
+}
+
+crate fn catch_unwind<F : Future>(fut : F) -> CatchUnwind<F> {
+    CatchUnwind {
+        fut : Box::pin(fut),
+    }
+}
+
+impl<F : Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Self::Output> {
+        let fut = self.fut.as_mut();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(out)) => Poll::Ready(Ok(out)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+// where JobManager sends panics, App::handle_error once the app is made
+#[derive(Clone, Default)]
+crate struct PanicHandler {
+    f : Arc<Mutex<Option<Box<dyn Fn(Panic) + Send>>>>,
+}
+
+impl std::fmt::Debug for PanicHandler {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PanicHandler")
+    }
+}
+
+impl PanicHandler {
+    crate fn set(&self, f : impl Fn(Panic) + Send + 'static) {
+        *self.f.lock_or_recover() = Some(Box::new(f));
+    }
+
+    crate fn handle(&self, panic : Panic) {
+        match &*self.f.lock_or_recover() {
+            Some(f) => f(panic),
+            None => tracing::error!("{}", panic),
+        }
+    }
+}
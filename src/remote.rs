@@ -0,0 +1,517 @@
+// folders on other machines, opened without mounting them: sftp:// through
+// OpenSSH's sftp, with the keys and hosts in ~/.ssh, and webdav:// (or
+// webdavs:// for https) through curl, with passwords in ~/.netrc. both are
+// run as commands, like printing is, so every call blocks. photos are
+// downloaded into the data dir as the scan gets to them, everything after
+// that sees the copies. when the remote can't be reached those copies are
+// listed and opened instead, marked offline
+
+use std::io::Write as _;
+use std::path::{
+    Component,
+    Path,
+    PathBuf,
+};
+use std::process::{
+    Command,
+    Stdio,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::library;
+
+/// A file or folder on a remote.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// From the remote's root, folders without the trailing /
+    pub path : String,
+    pub is_dir : bool,
+    pub size : Option<u64>,
+}
+
+impl Entry {
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// Limits on downloads from remotes and links, so a big folder doesn't take
+/// the whole connection. Kept in the preferences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bandwidth {
+    /// Kilobytes a second for all of them together, None for no cap
+    pub max_rate : Option<u32>,
+    /// Downloads and listings running at once
+    pub max_requests : usize,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        Bandwidth {
+            max_rate : None,
+            max_requests : 4,
+        }
+    }
+}
+
+impl Bandwidth {
+    /// Each download's share of max_rate, when they all run at once.
+    pub fn per_download(&self) -> Option<u32> {
+        self.max_rate.map(|rate| (rate / self.max_requests.max(1) as u32).max(1))
+    }
+}
+
+/// curl capped to rate kilobytes a second.
+pub fn curl(rate : Option<u32>) -> Command {
+    let mut command = Command::new("curl");
+    if let Some(rate) = rate {
+        command.arg("--limit-rate").arg(format!("{}k", rate));
+    }
+    command
+}
+
+/// A listing or a downloaded photo, and whether it's the cached one because
+/// the remote couldn't be reached, which may be out of date.
+#[derive(Debug)]
+pub struct Fetched<T> {
+    pub value : T,
+    pub offline : bool,
+}
+
+/// Where a `PhotoSet::Folder` can be besides the disk.
+pub trait Remote : Send + Sync {
+    /// What's in dir, a path from the remote's root.
+    fn list(&self, dir : &str) -> std::io::Result<Vec<Entry>>;
+
+    /// Copies file, a path from the remote's root, to the local path to,
+    /// at most rate kilobytes a second.
+    fn download(&self, file : &str, to : &Path, rate : Option<u32>) -> std::io::Result<()>;
+}
+
+fn other(msg : String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, msg)
+}
+
+fn run(command : &mut Command, input : Option<&str>) -> std::io::Result<String> {
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(other(stderr.lines().last().unwrap_or("failed").to_string()))
+    }
+}
+
+struct Sftp {
+    // user@host, as ssh takes it
+    dest : String,
+    port : Option<String>,
+}
+
+// quoted for sftp's batch commands
+fn sftp_quote(path : &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// sftp's ls -la of dir, like ls -l the name is what's after the 8th column
+fn parse_ls(dir : &str, out : &str) -> Vec<Entry> {
+    out.lines()
+        .filter(|line| !line.starts_with("sftp>"))
+        .filter_map(|line| {
+            let mut rest = line;
+            let mut columns = Vec::with_capacity(8);
+            for _ in 0..8 {
+                rest = rest.trim_start();
+                let end = rest.find(char::is_whitespace)?;
+                columns.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+            let mut name = rest.trim_start();
+            // symlinks are name -> target
+            if columns[0].starts_with('l') {
+                name = name.split_once(" -> ").map_or(name, |(name, _)| name);
+            }
+            // and . and ..
+            if name.is_empty() || name.starts_with('.') {
+                return None
+            }
+
+            Some(Entry{
+                path : format!("{}/{}", dir.trim_end_matches('/'), name),
+                is_dir : columns[0].starts_with('d'),
+                size : columns[4].parse().ok(),
+            })
+        })
+        .collect()
+}
+
+impl Sftp {
+    fn batch(&self, commands : &str, rate : Option<u32>) -> std::io::Result<String> {
+        let mut command = Command::new("sftp");
+        // fail rather than ask for a password nobody can type
+        command.args(&["-q", "-o", "BatchMode=yes", "-b", "-"]);
+        if let Some(port) = &self.port {
+            command.args(&["-P", port]);
+        }
+        // in kilobits
+        if let Some(rate) = rate {
+            command.arg("-l").arg(rate.saturating_mul(8).to_string());
+        }
+        // a host starting with - isn't an option
+        command.arg("--").arg(&self.dest);
+        run(&mut command, Some(commands))
+    }
+}
+
+impl Remote for Sftp {
+    fn list(&self, dir : &str) -> std::io::Result<Vec<Entry>> {
+        let out = self.batch(&format!("ls -la {}\n", sftp_quote(dir)), None)?;
+        Ok(parse_ls(dir, &out))
+    }
+
+    fn download(&self, file : &str, to : &Path, rate : Option<u32>) -> std::io::Result<()> {
+        self.batch(&format!("get {} {}\n", sftp_quote(file), sftp_quote(&to.to_string_lossy())), rate)?;
+        Ok(())
+    }
+}
+
+struct WebDav {
+    // http(s)://host[:port], without the path
+    base : String,
+}
+
+// the unreserved characters and /, the rest percent encoded
+fn encode(path : &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => out.push(b as char),
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn decode(path : &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes.get(idx + 1..idx + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[idx], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                idx += 3;
+            },
+            (b, _) => {
+                out.push(b);
+                idx += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// the text of the first element named name, whatever its namespace prefix
+fn element<'a>(xml : &'a str, name : &str) -> Option<&'a str> {
+    let close = format!("{}>", name);
+    xml.match_indices(&close).find_map(|(idx, _)| {
+        let open = xml[..idx].rfind('<')?;
+        let prefix = &xml[open + 1..idx];
+        if prefix.starts_with('/') || !(prefix.is_empty() || prefix.ends_with(':')) {
+            return None
+        }
+        let text = &xml[idx + close.len()..];
+        Some(&text[..text.find('<').unwrap_or(text.len())])
+    })
+}
+
+impl Remote for WebDav {
+    fn list(&self, dir : &str) -> std::io::Result<Vec<Entry>> {
+        let dir = format!("{}/", dir.trim_end_matches('/'));
+        let url = format!("{}{}", self.base, encode(&dir));
+        let out = run(
+            Command::new("curl").args(&["-sSf", "--netrc-optional", "-X", "PROPFIND", "-H", "Depth: 1"]).arg(&url),
+            None,
+        )?;
+
+        // a response per entry, the first one is usually dir itself
+        let entries = out.split("response>")
+            .filter_map(|response| {
+                let href = decode(element(response, "href")?.trim());
+                // some servers answer with whole urls
+                let path = match href.find("://") {
+                    Some(idx) => href[idx + 3..].find('/').map_or("/".to_string(), |p| href[idx + 3 + p..].to_string()),
+                    None => href,
+                };
+                let path = path.trim_end_matches('/').to_string();
+                if path == dir.trim_end_matches('/') || path.is_empty() {
+                    return None
+                }
+
+                Some(Entry{
+                    is_dir : response.contains("collection"),
+                    size : element(response, "getcontentlength").and_then(|size| size.trim().parse().ok()),
+                    path,
+                })
+            })
+            .filter(|entry| !entry.name().starts_with('.'))
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn download(&self, file : &str, to : &Path, rate : Option<u32>) -> std::io::Result<()> {
+        let url = format!("{}{}", self.base, encode(file));
+        run(curl(rate).args(&["-sSf", "--netrc-optional", "-o"]).arg(to).arg(&url), None)?;
+        Ok(())
+    }
+}
+
+/// Whether a folder is on a remote rather than the disk.
+pub fn is_remote(folder : &str) -> bool {
+    ["sftp://", "webdav://", "webdavs://"].iter().any(|scheme| folder.starts_with(scheme))
+}
+
+/// A folder on a remote, and where its photos are downloaded to.
+pub struct Location {
+    remote : Box<dyn Remote>,
+    /// The folder, from the remote's root
+    pub root : String,
+    cache : PathBuf,
+}
+
+impl Location {
+    /// sftp://[user@]host[:port]/path, webdav://host[:port]/path, or
+    /// webdavs:// for the same over https.
+    pub fn parse(url : &str) -> std::io::Result<Self> {
+        let (scheme, rest) = url.split_at(url.find("://").ok_or_else(|| other(format!("not a url: {}", url)))?);
+        let rest = &rest[3..];
+        let (authority, root) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if authority.is_empty() {
+            return Err(other(format!("no host in {}", url)))
+        }
+        let root = if root.is_empty() { "/".to_string() } else { root.to_string() };
+
+        let remote : Box<dyn Remote> = match scheme {
+            "sftp" => {
+                let (dest, port) = match authority.rsplit_once(':') {
+                    Some((dest, port)) => (dest, Some(port.to_string())),
+                    None => (authority, None),
+                };
+                Box::new(Sftp{
+                    dest : dest.to_string(),
+                    port,
+                })
+            },
+            "webdav" => Box::new(WebDav{ base : format!("http://{}", authority) }),
+            "webdavs" => Box::new(WebDav{ base : format!("https://{}", authority) }),
+            _ => return Err(other(format!("can't open {} folders", scheme))),
+        };
+
+        // by host, without the user
+        let host = authority.rsplit('@').next().unwrap_or(authority).replace(':', "_");
+        let cache = library::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("remote")
+            .join(scheme)
+            .join(host);
+
+        Ok(Location{
+            remote,
+            root,
+            cache,
+        })
+    }
+
+    /// What's in dir, or what was downloaded from it before if the remote
+    /// can't be reached.
+    pub fn list(&self, dir : &str) -> std::io::Result<Fetched<Vec<Entry>>> {
+        let err = match self.remote.list(dir) {
+            Ok(entries) => return Ok(Fetched{ value : entries, offline : false }),
+            Err(err) => err,
+        };
+
+        match self.list_cached(dir) {
+            Ok(entries) => {
+                tracing::warn!("listing the copies from {}: {}", dir, err);
+                Ok(Fetched{ value : entries, offline : true })
+            },
+            // never downloaded from
+            Err(_) => Err(err),
+        }
+    }
+
+    // where path, from the remote's root, is copied to. the remote names
+    // its files, so a path that would lead out of the cache isn't taken
+    fn local(&self, path : &str) -> std::io::Result<PathBuf> {
+        let path = Path::new(path.trim_start_matches('/'));
+        if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} isn't in the folder", path.display()),
+            ))
+        }
+
+        Ok(self.cache.join(path))
+    }
+
+    // the cache's copy of dir, without downloads that didn't finish
+    fn list_cached(&self, dir : &str) -> std::io::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(self.local(dir)?)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || name.ends_with(".part") {
+                continue
+            }
+
+            let meta = entry.metadata()?;
+            entries.push(Entry{
+                path : format!("{}/{}", dir.trim_end_matches('/'), name),
+                is_dir : meta.is_dir(),
+                size : Some(meta.len()).filter(|_| meta.is_file()),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// The photo's downloaded copy, downloaded again only when its size
+    /// changed, at most rate kilobytes a second. If that fails the copy
+    /// from before is kept, offline.
+    pub fn fetch(&self, entry : &Entry, rate : Option<u32>) -> std::io::Result<Fetched<PathBuf>> {
+        let local = self.local(&entry.path)?;
+        let cached = std::fs::metadata(&local).ok().map(|meta| meta.len());
+        if cached.is_some() && (entry.size.is_none() || cached == entry.size) {
+            return Ok(Fetched{ value : local, offline : false })
+        }
+
+        if let Some(dir) = local.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // a download that didn't finish isn't mistaken for the photo
+        let part = local.with_file_name(format!("{}.part", entry.name()));
+        if let Err(err) = self.remote.download(&entry.path, &part, rate) {
+            let _ = std::fs::remove_file(&part);
+            if cached.is_none() {
+                return Err(err)
+            }
+            tracing::warn!("opening the copy of {}: {}", entry.path, err);
+            return Ok(Fetched{ value : local, offline : true })
+        }
+        std::fs::rename(&part, &local)?;
+        Ok(Fetched{ value : local, offline : false })
+    }
+
+    /// Fetches the photo at path, a path from the remote's root, with its
+    /// size listed again rather than from a listing that may have been
+    /// offline. For retrying.
+    pub fn refetch(&self, path : &str, rate : Option<u32>) -> std::io::Result<Fetched<PathBuf>> {
+        let dir = match path.rsplit_once('/') {
+            Some(("", _)) | None => "/",
+            Some((dir, _)) => dir,
+        };
+
+        let listed = self.list(dir)?;
+        let entry = listed.value.into_iter()
+            .find(|entry| entry.path == path)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} is gone", path)))?;
+        if listed.offline {
+            // the same copy, without trying again
+            return Ok(Fetched{ value : self.fetch(&entry, rate)?.value, offline : true })
+        }
+
+        self.fetch(&entry, rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_round_trip() {
+        let path = "/photos/2021 trip/caf\u{e9} #1?.jpg";
+        let encoded = encode(path);
+        assert_eq!(encoded, "/photos/2021%20trip/caf%C3%A9%20%231%3F.jpg");
+        assert_eq!(decode(&encoded), path);
+    }
+
+    #[test]
+    fn decode_malformed() {
+        // not escapes, kept as they are
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+        assert_eq!(decode("%41%62"), "Ab");
+        // bytes that aren't utf-8
+        assert_eq!(decode("%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn element_any_prefix() {
+        let xml = "<d:response><d:href>/a/b.jpg</d:href>\
+            <d:getcontentlength>1234</d:getcontentlength></d:response>";
+        assert_eq!(element(xml, "href"), Some("/a/b.jpg"));
+        assert_eq!(element(xml, "getcontentlength"), Some("1234"));
+        assert_eq!(element("<href>/c</href>", "href"), Some("/c"));
+        assert_eq!(element(xml, "collection"), None);
+        // a longer name ending the same isn't it
+        assert_eq!(element("<xhref>/d</xhref>", "href"), None);
+    }
+
+    #[test]
+    fn parse_locations() {
+        let location = Location::parse("sftp://me@nas:2222/volume1/photos").unwrap();
+        assert_eq!(location.root, "/volume1/photos");
+        assert!(location.cache.ends_with(Path::new("remote").join("sftp").join("nas_2222")));
+
+        let location = Location::parse("webdavs://cloud.example.com").unwrap();
+        assert_eq!(location.root, "/");
+        assert!(location.cache.ends_with(Path::new("webdavs").join("cloud.example.com")));
+
+        assert!(Location::parse("nas/photos").is_err());
+        assert!(Location::parse("sftp:///photos").is_err());
+        assert!(Location::parse("ftp://nas/photos").is_err());
+    }
+
+    #[test]
+    fn local_stays_in_cache() {
+        let location = Location::parse("webdav://nas/photos").unwrap();
+        assert_eq!(location.local("/photos/a.jpg").unwrap(), location.cache.join("photos").join("a.jpg"));
+        assert!(location.local("/photos/../../../.ssh/id_rsa").is_err());
+        assert!(location.local("/photos/./a.jpg").is_ok());
+        assert!(location.local("photos/..").is_err());
+    }
+
+    #[test]
+    fn ls_symlinks() {
+        let out = "sftp> ls -la \"/photos\"\n\
+            drwxr-xr-x    3 me  users     4096 Jan  1 12:00 .\n\
+            drwxr-xr-x    9 me  users     4096 Jan  1 12:00 ..\n\
+            -rw-r--r--    1 me  users   123456 Jan  1 12:00 a b.jpg\n\
+            lrwxrwxrwx    1 me  users       12 Jan  1 12:00 latest.jpg -> 2021/c.jpg\n\
+            drwxr-xr-x    2 me  users     4096 Jan  1 12:00 2021\n";
+        let entries = parse_ls("/photos", out);
+        let paths : Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["/photos/a b.jpg", "/photos/latest.jpg", "/photos/2021"]);
+        assert_eq!(entries[0].size, Some(123456));
+        assert!(entries[2].is_dir);
+    }
+}
@@ -0,0 +1,71 @@
+use std::future::Future;
+use std::time::Duration;
+
+/// Retries io operations which fail with errors that are likely to go away
+/// on their own, doubling the delay each time.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Total tries, including the first
+    pub attempts : u32,
+    pub initial : Duration,
+    pub max : Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            attempts : 4,
+            initial : Duration::from_millis(100),
+            max : Duration::from_secs(2),
+        }
+    }
+}
+
+impl Backoff {
+    pub async fn run<F, Fut, T>(&self, mut f : F) -> std::io::Result<T>
+    where
+        F : FnMut() -> Fut,
+        Fut : Future<Output = std::io::Result<T>>,
+    {
+        let mut delay = self.initial;
+
+        for _ in 1..self.attempts {
+            match f().await {
+                Err(err) if is_transient(&err) => {
+                    tracing::warn!("retrying in {:?} after: {}", delay, err);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(self.max);
+                },
+                res => return res,
+            }
+        }
+
+        f().await
+    }
+}
+
+/// Errors which are worth retrying: busy resources, timeouts on network
+/// mounts, and files locked by another process on windows.
+pub fn is_transient(err : &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    match err.kind() {
+        ErrorKind::WouldBlock |
+        ErrorKind::TimedOut |
+        ErrorKind::Interrupted => return true,
+        _ => {},
+    }
+
+    match err.raw_os_error() {
+        // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION, ERROR_NETNAME_DELETED,
+        // ERROR_SEM_TIMEOUT
+        #[cfg(windows)]
+        Some(32) | Some(33) | Some(64) | Some(121) => true,
+        // EAGAIN, EBUSY, ETIMEDOUT, EHOSTDOWN, ESTALE
+        #[cfg(target_os = "linux")]
+        Some(11) | Some(16) | Some(110) | Some(112) | Some(116) => true,
+        #[cfg(target_os = "macos")]
+        Some(35) | Some(16) | Some(60) | Some(64) | Some(70) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,107 @@
+// colors picked from photos this session, newest first, for copying into
+// other tools. they're of the photo as loaded, before its edits, and kept
+// until the app quits whichever photo is open
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+const KEPT : usize = 64;
+const SWATCH : f32 = 20.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub path : PathBuf,
+    /// The pixel, from the top left
+    pub x : u32,
+    pub y : u32,
+    pub rgba : [u8; 4],
+}
+
+impl Sample {
+    /// Like #1a2b3c, with the alpha only when it isn't opaque.
+    pub fn hex(&self) -> String {
+        let [r, g, b, a] = self.rgba;
+        if a == 255 {
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+
+    /// Like rgb(26, 43, 60), or rgba with the alpha from 0 to 1.
+    pub fn rgb(&self) -> String {
+        let [r, g, b, a] = self.rgba;
+        if a == 255 {
+            format!("rgb({}, {}, {})", r, g, b)
+        } else {
+            format!("rgba({}, {}, {}, {:.2})", r, g, b, a as f32 / 255.)
+        }
+    }
+
+    fn color(&self) -> egui::Color32 {
+        let [r, g, b, a] = self.rgba;
+        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Samples {
+    list : VecDeque<Sample>,
+}
+
+impl Samples {
+    /// Sampling the same pixel again moves it to the front.
+    pub fn push(&mut self, sample : Sample) {
+        self.list.retain(|s| *s != sample);
+        if self.list.len() >= KEPT {
+            self.list.pop_back();
+        }
+        self.list.push_front(sample);
+    }
+
+    /// A window with the samples, each with buttons to copy it.
+    pub fn show(&mut self, egui : &egui::CtxRef, open : &mut bool) {
+        let list = &mut self.list;
+
+        egui::Window::new("Color Samples").open(open).default_width(320.0).show(egui, |ui| {
+            if list.is_empty() {
+                ui.label("Alt and click on a photo to sample a color.");
+                return
+            }
+
+            if ui.button("clear").clicked() {
+                list.clear();
+            }
+            ui.separator();
+
+            let mut remove = None;
+            egui::ScrollArea::auto_sized().show(ui, |ui| {
+                for (idx, sample) in list.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let (rect, _) = ui.allocate_exact_size(egui::vec2(SWATCH, SWATCH), egui::Sense::hover());
+                        ui.painter().rect_filled(rect, 2.0, sample.color());
+
+                        ui.monospace(sample.hex());
+                        if ui.small_button("hex").on_hover_text("copy").clicked() {
+                            ui.output().copied_text = sample.hex();
+                        }
+                        if ui.small_button("rgb").on_hover_text(sample.rgb()).clicked() {
+                            ui.output().copied_text = sample.rgb();
+                        }
+                        if ui.small_button("x").clicked() {
+                            remove = Some(idx);
+                        }
+                    });
+
+                    let name = sample.path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.small(format!("{} at {}, {}", name, sample.x, sample.y))
+                        .on_hover_text(sample.path.display().to_string());
+                }
+            });
+
+            if let Some(idx) = remove {
+                list.remove(idx);
+            }
+        });
+    }
+}
@@ -0,0 +1,444 @@
+// end to end ui tests: a script of input and screenshots, played into the
+// real app one frame at a time. screenshots are compared with the ones
+// next to the script, missing ones are recorded as them on the first run. a
+// script looks like
+//
+//     # the window size has to match the references'
+//     size 1024 768
+//     wait idle
+//     screenshot gallery
+//     click on bars.png
+//     wait 30
+//     screenshot photo
+//
+// `lose context` has the next frame recreate the window and its context as
+// if the driver had been reset.
+//
+// coordinates are physical pixels from the top left. `click on` clicks the
+// middle of a widget the app named with RenderCtx::name_widget, waiting for
+// it to be drawn. run it on a virtual display like xvfb-run on machines
+// without one
+
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use glium::glutin;
+use glutin::event::{
+    DeviceId,
+    ElementState,
+    KeyboardInput,
+    ModifiersState,
+    MouseButton,
+    MouseScrollDelta,
+    TouchPhase,
+    VirtualKeyCode,
+    WindowEvent,
+};
+use image::RgbaImage;
+
+// a channel off by more than this is a different pixel
+const THRESHOLD : u8 = 16;
+// the fraction of pixels that can be different, by default
+const TOLERANCE : f32 = 0.001;
+// frames without any jobs before the app counts as idle
+const IDLE_FRAMES : u32 = 10;
+const IDLE_TIMEOUT : Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Size(u32, u32),
+    // frames
+    Wait(u32),
+    WaitIdle,
+    Move(f64, f64),
+    Click(f64, f64),
+    // a widget by the name the app gave it
+    ClickOn(String),
+    // the second half of a click, a frame after the first
+    Release,
+    Scroll(f32, f32),
+    Type(String),
+    Key(VirtualKeyCode),
+    Screenshot(String),
+    Tolerance(f32),
+    LoseContext,
+}
+
+/// Scripted input and screenshot comparisons, see `AppRunner::script`.
+#[derive(Debug)]
+pub struct Script {
+    path : PathBuf,
+    steps : VecDeque<Step>,
+    // the screenshots to compare with, named after the step
+    references : PathBuf,
+    tolerance : f32,
+    // frames into the current wait, or idle frames in a row
+    waited : u32,
+    wait_started : Option<Instant>,
+    pointer : (f64, f64),
+    capture : Option<String>,
+    lose_context : bool,
+    // the middles of the widgets named last frame, in physical pixels
+    widgets : HashMap<String, (f64, f64)>,
+    // whether the references are replaced by the screenshots, missing ones
+    // always are
+    record : bool,
+    failures : Vec<String>,
+}
+
+fn parse_step(line : &str) -> Result<Step, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let args : Vec<&str> = words.collect();
+
+    let number = |idx : usize| -> Result<f64, String> {
+        args.get(idx)
+            .ok_or_else(|| format!("{} needs {} numbers", command, idx + 1))?
+            .parse()
+            .map_err(|_| format!("{} is not a number", args[idx]))
+    };
+
+    let step = match command {
+        "size" => Step::Size(number(0)? as u32, number(1)? as u32),
+        "wait" if args.first() == Some(&"idle") => Step::WaitIdle,
+        "wait" => Step::Wait(number(0)? as u32),
+        "move" => Step::Move(number(0)?, number(1)?),
+        "click" if args.first() == Some(&"on") => {
+            let name = line.trim_start()["click".len()..].trim_start()["on".len()..].trim();
+            if name.is_empty() {
+                return Err("click on needs a widget's name".to_string())
+            }
+            Step::ClickOn(name.to_string())
+        },
+        "click" => Step::Click(number(0)?, number(1)?),
+        "scroll" => Step::Scroll(number(0)? as f32, number(1)? as f32),
+        "type" => Step::Type(line.trim_start()["type".len()..].trim().to_string()),
+        "key" => {
+            let name = args.first().ok_or("key needs a name")?;
+            Step::Key(key_code(name).ok_or_else(|| format!("unknown key {}", name))?)
+        },
+        "screenshot" => Step::Screenshot(args.first().ok_or("screenshot needs a name")?.to_string()),
+        "tolerance" => Step::Tolerance(number(0)? as f32),
+        "lose" if args.first() == Some(&"context") => Step::LoseContext,
+        _ => return Err(format!("unknown step {}", command)),
+    };
+
+    Ok(step)
+}
+
+fn key_code(name : &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+
+    let code = match name {
+        "Enter" => Return,
+        "Escape" => Escape,
+        "Tab" => Tab,
+        "Backspace" => Back,
+        "Delete" => Delete,
+        "Space" => Space,
+        "Left" => Left,
+        "Right" => Right,
+        "Up" => Up,
+        "Down" => Down,
+        "Home" => Home,
+        "End" => End,
+        "PageUp" => PageUp,
+        "PageDown" => PageDown,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+// every event is from the same made up mouse and keyboard
+#[allow(deprecated)]
+fn mouse_input(state : ElementState) -> WindowEvent<'static> {
+    WindowEvent::MouseInput{
+        device_id : unsafe { DeviceId::dummy() },
+        state,
+        button : MouseButton::Left,
+        modifiers : ModifiersState::empty(),
+    }
+}
+
+#[allow(deprecated)]
+fn cursor_moved((x, y) : (f64, f64)) -> WindowEvent<'static> {
+    WindowEvent::CursorMoved{
+        device_id : unsafe { DeviceId::dummy() },
+        position : glutin::dpi::PhysicalPosition::new(x, y),
+        modifiers : ModifiersState::empty(),
+    }
+}
+
+#[allow(deprecated)]
+fn key_input(key : VirtualKeyCode, state : ElementState) -> WindowEvent<'static> {
+    WindowEvent::KeyboardInput{
+        device_id : unsafe { DeviceId::dummy() },
+        input : KeyboardInput{
+            scancode : 0,
+            state,
+            virtual_keycode : Some(key),
+            modifiers : ModifiersState::empty(),
+        },
+        is_synthetic : true,
+    }
+}
+
+#[allow(deprecated)]
+fn mouse_wheel(dx : f32, dy : f32) -> WindowEvent<'static> {
+    WindowEvent::MouseWheel{
+        device_id : unsafe { DeviceId::dummy() },
+        delta : MouseScrollDelta::LineDelta(dx, dy),
+        phase : TouchPhase::Moved,
+        modifiers : ModifiersState::empty(),
+    }
+}
+
+/// What the window shows, after the frame was finished.
+crate fn capture(display : &glium::Display) -> Option<RgbaImage> {
+    let raw : glium::texture::RawImage2d<u8> = display.read_front_buffer().ok()?;
+    let img = RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())?;
+    // gl's rows go bottom up
+    Some(image::imageops::flip_vertical(&img))
+}
+
+impl Script {
+    /// Reads the script at path, the reference screenshots go in a folder
+    /// next to it with its name, like ui/gallery/ for ui/gallery.txt.
+    pub fn load(path : &Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+
+        let mut steps = VecDeque::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+
+            let step = parse_step(line).map_err(|err| std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), idx + 1, err),
+            ))?;
+            steps.push_back(step);
+        }
+
+        Ok(Script {
+            path : path.to_path_buf(),
+            steps,
+            references : path.with_extension(""),
+            tolerance : TOLERANCE,
+            waited : 0,
+            wait_started : None,
+            pointer : (0.0, 0.0),
+            capture : None,
+            lose_context : false,
+            widgets : HashMap::new(),
+            record : false,
+            failures : Vec::new(),
+        })
+    }
+
+    /// Records every screenshot as its reference, instead of comparing it.
+    /// Missing references are recorded either way.
+    pub fn record(mut self, record : bool) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// The input for this frame, None once the script is over. idle is
+    /// whether there are no jobs running.
+    crate fn frame(&mut self, window : &glutin::window::Window, idle : bool) -> Option<Vec<WindowEvent<'static>>> {
+        let mut events = Vec::new();
+
+        loop {
+            let step = match self.steps.front() {
+                Some(step) => step.clone(),
+                None => return if events.is_empty() { None } else { Some(events) },
+            };
+
+            match step {
+                Step::Wait(frames) => {
+                    if self.waited < frames {
+                        self.waited += 1;
+                        return Some(events)
+                    }
+                },
+                Step::WaitIdle => {
+                    let started = *self.wait_started.get_or_insert_with(Instant::now);
+                    self.waited = if idle { self.waited + 1 } else { 0 };
+
+                    if started.elapsed() > IDLE_TIMEOUT {
+                        self.failures.push("timed out waiting for the app to be idle".to_string());
+                    } else if self.waited < IDLE_FRAMES {
+                        return Some(events)
+                    }
+                },
+                Step::Screenshot(name) => {
+                    // of this frame, which has the input from before it
+                    self.capture = Some(name);
+                    self.steps.pop_front();
+                    return Some(events)
+                },
+                Step::Size(width, height) => {
+                    window.set_inner_size(glutin::dpi::PhysicalSize::new(width, height));
+                },
+                Step::Move(x, y) => {
+                    self.pointer = (x, y);
+                    events.push(cursor_moved(self.pointer));
+                },
+                Step::Click(x, y) => {
+                    self.pointer = (x, y);
+                    events.push(cursor_moved(self.pointer));
+                    events.push(mouse_input(ElementState::Pressed));
+
+                    self.steps.pop_front();
+                    self.steps.push_front(Step::Release);
+                    return Some(events)
+                },
+                Step::ClickOn(name) => match self.widgets.get(&name) {
+                    Some(&(x, y)) => {
+                        self.steps.pop_front();
+                        self.steps.push_front(Step::Click(x, y));
+                        continue
+                    },
+                    None => {
+                        // like a thumbnail that's still loading
+                        let started = *self.wait_started.get_or_insert_with(Instant::now);
+                        if started.elapsed() > IDLE_TIMEOUT {
+                            self.failures.push(format!("no widget named {}", name));
+                        } else {
+                            return Some(events)
+                        }
+                    },
+                },
+                Step::Release => events.push(mouse_input(ElementState::Released)),
+                Step::Scroll(dx, dy) => events.push(mouse_wheel(dx, dy)),
+                Step::Type(text) => events.extend(text.chars().map(WindowEvent::ReceivedCharacter)),
+                Step::Key(key) => {
+                    events.push(key_input(key, ElementState::Pressed));
+                    events.push(key_input(key, ElementState::Released));
+                },
+                Step::Tolerance(tolerance) => self.tolerance = tolerance,
+                Step::LoseContext => {
+                    // the next frame starts with a new context
+                    self.lose_context = true;
+                    self.steps.pop_front();
+                    return Some(events)
+                },
+            }
+
+            self.steps.pop_front();
+            self.waited = 0;
+            self.wait_started = None;
+        }
+    }
+
+    /// Where RenderCtx::name_widget puts this frame's widgets, emptied of
+    /// the last frame's.
+    crate fn name_widgets(&mut self) -> &mut HashMap<String, (f64, f64)> {
+        self.widgets.clear();
+        &mut self.widgets
+    }
+
+    /// Whether the context should be recreated as if it was lost.
+    crate fn take_lose_context(&mut self) -> bool {
+        std::mem::take(&mut self.lose_context)
+    }
+
+    /// The name of the screenshot to take of the frame just finished.
+    crate fn take_capture(&mut self) -> Option<String> {
+        self.capture.take()
+    }
+
+    crate fn compare(&mut self, name : &str, actual : Option<RgbaImage>) {
+        let actual = match actual {
+            Some(actual) => actual,
+            None => {
+                self.failures.push(format!("{}: could not read the window", name));
+                return
+            },
+        };
+
+        let reference_path = self.references.join(format!("{}.png", name));
+        let reference = match image::open(&reference_path) {
+            Ok(reference) if !self.record => reference.to_rgba8(),
+            // the first run on this machine, or recording again
+            _ => {
+                let res = std::fs::create_dir_all(&self.references)
+                    .map_err(image::ImageError::from)
+                    .and_then(|_| actual.save(&reference_path));
+                match res {
+                    Ok(()) => println!("recorded {}", reference_path.display()),
+                    Err(err) => self.failures.push(format!("{}: could not record: {}", name, err)),
+                }
+                return
+            },
+        };
+
+        if reference.dimensions() != actual.dimensions() {
+            self.failures.push(format!(
+                "{}: the window is {:?}, the reference {:?}",
+                name, actual.dimensions(), reference.dimensions(),
+            ));
+            self.save_failed(name, &actual, None);
+            return
+        }
+
+        // the different pixels in red over a faded copy of the reference
+        let mut diff = RgbaImage::new(actual.width(), actual.height());
+        let mut different = 0;
+        for ((a, r), d) in actual.pixels().zip(reference.pixels()).zip(diff.pixels_mut()) {
+            let off = a.0.iter().zip(r.0.iter()).any(|(a, r)| a.max(r) - a.min(r) > THRESHOLD);
+            *d = if off {
+                different += 1;
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([r[0] / 4, r[1] / 4, r[2] / 4, 255])
+            };
+        }
+
+        let fraction = different as f32 / (actual.width() * actual.height()).max(1) as f32;
+        if fraction > self.tolerance {
+            self.failures.push(format!("{}: {:.2}% of pixels are different", name, fraction * 100.));
+            self.save_failed(name, &actual, Some(&diff));
+        }
+    }
+
+    // next to the references, in failed/
+    fn save_failed(&mut self, name : &str, actual : &RgbaImage, diff : Option<&RgbaImage>) {
+        let dir = self.references.join("failed");
+        let res = std::fs::create_dir_all(&dir)
+            .map_err(image::ImageError::from)
+            .and_then(|_| actual.save(dir.join(format!("{}.png", name))))
+            .and_then(|_| match diff {
+                Some(diff) => diff.save(dir.join(format!("{}.diff.png", name))),
+                None => Ok(()),
+            });
+
+        if let Err(err) = res {
+            println!("could not save {} to {}: {}", name, dir.display(), err);
+        }
+    }
+
+    /// Prints what failed, returns whether everything passed.
+    crate fn report(&self) -> bool {
+        for failure in &self.failures {
+            println!("{}: {}", self.path.display(), failure);
+        }
+
+        if self.failures.is_empty() {
+            println!("{}: passed", self.path.display());
+        }
+        self.failures.is_empty()
+    }
+}
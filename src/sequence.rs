@@ -0,0 +1,81 @@
+// numbered image sequences, like render outputs (frame0001.png) and
+// timelapses (IMG_4021.JPG, IMG_4022.JPG, ...)
+
+use std::collections::HashMap;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+/// Fewer numbered files than this aren't worth offering as a flipbook.
+pub const MIN_FRAMES : usize = 8;
+
+// numbers further apart than this start a new sequence, so a couple of
+// deleted frames don't split a timelapse
+const MAX_GAP : u64 = 3;
+
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    /// Like frame#.png (1-240), with the numbers replaced
+    pub name : String,
+    pub frames : Vec<PathBuf>,
+}
+
+// the part of the file name before the number, the number, and the extension
+fn split(path : &Path) -> Option<(String, u64, String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let prefix = stem.trim_end_matches(|c : char| c.is_ascii_digit());
+    let digits = &stem[prefix.len()..];
+    let number = digits.parse().ok()?;
+
+    Some((prefix.to_string(), number, ext.to_lowercase()))
+}
+
+/// Runs of at least MIN_FRAMES files in the same folder which differ only
+/// in the number at the end of their name, in order.
+pub fn detect<'a, I>(paths : I) -> Vec<Sequence>
+where
+    I : IntoIterator<Item = &'a Path>,
+{
+    let mut groups : HashMap<(PathBuf, String, String), Vec<(u64, PathBuf)>> = HashMap::new();
+
+    for path in paths {
+        if let Some((prefix, number, ext)) = split(path) {
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            groups.entry((dir, prefix, ext))
+                .or_default()
+                .push((number, path.to_path_buf()));
+        }
+    }
+
+    let mut sequences = Vec::new();
+
+    for ((_, prefix, ext), mut numbered) in groups {
+        numbered.sort();
+
+        let mut run : Vec<(u64, PathBuf)> = Vec::new();
+        let mut runs = Vec::new();
+        for (number, path) in numbered {
+            if let Some((last, _)) = run.last() {
+                if number - last > MAX_GAP {
+                    runs.push(std::mem::take(&mut run));
+                }
+            }
+            run.push((number, path));
+        }
+        runs.push(run);
+
+        for run in runs.into_iter().filter(|r| r.len() >= MIN_FRAMES) {
+            let (first, last) = (run[0].0, run[run.len() - 1].0);
+            sequences.push(Sequence{
+                name : format!("{}#.{} ({}-{})", prefix, ext, first, last),
+                frames : run.into_iter().map(|(_, path)| path).collect(),
+            });
+        }
+    }
+
+    sequences.sort_by(|a, b| a.frames[0].cmp(&b.frames[0]));
+    sequences
+}
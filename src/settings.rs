@@ -0,0 +1,202 @@
+// what's kept between runs: where the window was and whatever ui state the
+// app wants back, like which panels were open. a toml file so it can be
+// fixed by hand
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use glium::glutin;
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+
+/// The window's size and position in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub width : u32,
+    pub height : u32,
+    /// None where windows can't be placed, like on wayland
+    #[serde(default)]
+    pub position : Option<(i32, i32)>,
+    #[serde(default)]
+    pub maximized : bool,
+}
+
+/// How egui looks, see `Preferences::theme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub fn visuals(&self) -> egui::Visuals {
+        match self {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        }
+    }
+}
+
+/// The framework's options which users get to pick, for the app's
+/// preferences window, see `RenderCtx::preferences_mut` and
+/// `Preferences::ui`. They win over the `AppRunner` ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Dark by default, photos look better on it
+    pub theme : Theme,
+    /// For selections and links, the theme's if None
+    pub accent : Option<[u8; 3]>,
+    /// Multiplies the size of the app's fonts, see `AppRunner::fonts`
+    pub text_scale : f32,
+    /// `AppRunner::worker_threads`, read at startup
+    pub worker_threads : Option<usize>,
+    /// `AppRunner::max_blocking_threads`, read at startup
+    pub max_blocking_threads : Option<usize>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            theme : Theme::Dark,
+            accent : None,
+            text_scale : 1.0,
+            worker_threads : None,
+            max_blocking_threads : None,
+        }
+    }
+}
+
+impl Preferences {
+    /// Widgets for each of them, for the app's preferences window.
+    pub fn ui(&mut self, ui : &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Theme: ");
+            ui.radio_value(&mut self.theme, Theme::Dark, "Dark");
+            ui.radio_value(&mut self.theme, Theme::Light, "Light");
+        });
+
+        ui.horizontal(|ui| {
+            let mut custom = self.accent.is_some();
+            ui.checkbox(&mut custom, "Accent color");
+            match (custom, self.accent.as_mut()) {
+                (true, Some(accent)) => {
+                    ui.color_edit_button_srgb(accent);
+                },
+                // egui's dark selection color
+                (true, None) => self.accent = Some([0, 92, 128]),
+                (false, _) => self.accent = None,
+            }
+        });
+
+        ui.add(egui::Slider::new(&mut self.text_scale, 0.75..=2.0).text("text size"));
+
+        // unchecked is the app's default
+        let threads = [
+            ("Worker threads", &mut self.worker_threads),
+            ("File and export threads", &mut self.max_blocking_threads),
+        ];
+        for (label, threads) in threads {
+            ui.horizontal(|ui| {
+                let mut set = threads.is_some();
+                ui.checkbox(&mut set, label);
+                match (set, threads.as_mut()) {
+                    (true, Some(n)) => {
+                        ui.add(egui::DragValue::new(n).clamp_range(1..=64));
+                    },
+                    (true, None) => *threads = Some(4),
+                    (false, _) => *threads = None,
+                }
+            });
+        }
+
+        ui.small("thread counts apply after a restart");
+    }
+}
+
+/// Loaded before the window is made and saved when it closes, see
+/// `AppRunner::settings_file` and `App::save_settings`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub window : Option<WindowGeometry>,
+    #[serde(default)]
+    pub prefs : Preferences,
+    /// The app's own, by key, see get and set.
+    #[serde(default)]
+    pub ui : toml::value::Table,
+}
+
+impl Settings {
+    /// $XDG_CONFIG_HOME/<app>/settings.toml or ~/.config/<app>/settings.toml
+    pub fn default_path(app : &str) -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(base.join(app).join("settings.toml"))
+    }
+
+    /// The defaults if the file isn't there or can't be read.
+    pub fn load(path : &Path) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => return Settings::default(),
+        };
+
+        toml::from_str(&text).unwrap_or_else(|err| {
+            tracing::warn!("ignoring settings in {}: {}", path.display(), err);
+            Settings::default()
+        })
+    }
+
+    /// Written next to path then moved over it, so a crash doesn't leave
+    /// half a file.
+    pub fn save(&self, path : &Path) -> std::io::Result<()> {
+        let text = toml::to_string(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// The value saved under key, None if there isn't one or it's not a T
+    /// anymore.
+    pub fn get<T : DeserializeOwned>(&self, key : &str) -> Option<T> {
+        self.ui.get(key)?.clone().try_into().ok()
+    }
+
+    pub fn set<T : Serialize>(&mut self, key : &str, value : &T) {
+        match toml::Value::try_from(value) {
+            Ok(value) => {
+                self.ui.insert(key.to_string(), value);
+            },
+            Err(err) => tracing::error!("could not save setting {}: {}", key, err),
+        }
+    }
+
+    crate fn capture(&mut self, window : &glutin::window::Window) {
+        // a minimized window has no size worth keeping
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return
+        }
+
+        self.window = Some(WindowGeometry {
+            width : size.width,
+            height : size.height,
+            position : window.outer_position().ok().map(|pos| (pos.x, pos.y)),
+            maximized : window.is_maximized(),
+        });
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{
+    Hash,
+    Hasher,
+};
+use std::path::PathBuf;
+
+use glium::program::{
+    Binary,
+    Program,
+    ProgramCreationError,
+};
+
+/// Compiles a program, going through an on disk cache of program binaries.
+/// The cache key covers the shader sources and the driver, so editing a
+/// shader or updating the driver just misses the cache. Failing to read or
+/// write the cache is not an error, it only costs a compile.
+pub fn program(
+    display : &glium::Display,
+    name : &str,
+    vertex : &str,
+    fragment : &str) -> Result<Program, ProgramCreationError>
+{
+    let dir = match cache_dir() {
+        Some(dir) => dir,
+        None => return Program::from_source(display, vertex, fragment, None),
+    };
+
+    let mut hasher = DefaultHasher::new();
+    vertex.hash(&mut hasher);
+    fragment.hash(&mut hasher);
+    display.get_opengl_vendor_string().hash(&mut hasher);
+    display.get_opengl_renderer_string().hash(&mut hasher);
+    display.get_opengl_version_string().hash(&mut hasher);
+
+    let prefix = format!("{}-", name);
+    let path = dir.join(format!("{}{:016x}.bin", prefix, hasher.finish()));
+
+    if let Some(binary) = read_binary(&path) {
+        match Program::new(display, binary) {
+            Ok(program) => return Ok(program),
+            Err(err) => tracing::debug!("discarding {}: {:?}", path.display(), err),
+        }
+    }
+
+    let program = Program::from_source(display, vertex, fragment, None)?;
+
+    match program.get_binary() {
+        Ok(binary) => {
+            // old entries for this program are stale now
+            if let Ok(entries) = std::fs::read_dir(&dir) {
+                for entry in entries.flatten() {
+                    let stale = entry.file_name()
+                        .to_str()
+                        .map_or(false, |s| s.starts_with(&prefix));
+
+                    if stale {
+                        let _ = std::fs::remove_file(entry.path());
+                    }
+                }
+            }
+
+            if let Err(err) = write_binary(&dir, &path, &binary) {
+                tracing::warn!("could not write {}: {:?}", path.display(), err);
+            }
+        },
+        Err(err) => tracing::debug!("no binary for {}: {:?}", name, err),
+    }
+
+    Ok(program)
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+    Some(base.join("photos1").join("shaders"))
+}
+
+// the file is the format as 4 little endian bytes followed by the content
+fn read_binary(path : &PathBuf) -> Option<Binary> {
+    let byt = std::fs::read(path).ok()?;
+    if byt.len() < 4 {
+        return None
+    }
+
+    let (format, content) = byt.split_at(4);
+    let mut format_byt = [0; 4];
+    format_byt.copy_from_slice(format);
+
+    Some(Binary{
+        format : u32::from_le_bytes(format_byt),
+        content : content.to_vec(),
+    })
+}
+
+fn write_binary(dir : &PathBuf, path : &PathBuf, binary : &Binary) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut byt = Vec::with_capacity(4 + binary.content.len());
+    byt.extend_from_slice(&binary.format.to_le_bytes());
+    byt.extend_from_slice(&binary.content);
+
+    std::fs::write(path, byt)
+}
@@ -1,19 +1,33 @@
 use crate::{
+    Color,
+    DisplayProfile,
     Error,
     ImageId,
     RenderCtx,
-};
-
-use glium::{
-    program,
+    Viewport,
+    shader_cache,
 };
 
 use glam::f32::{
     Mat4,
+    Vec3,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
 };
 
-#[derive(Debug, Clone)]
+/// The current processing version, bump this whenever the effects math
+/// changes in a way that would alter how existing edits render. The shader
+/// keeps the old code paths around, keyed on `Effects::process_version`.
+pub const PROCESS_VERSION : i32 = 1;
+
+// saved in the library, new fields need a serde default
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Effects {
+    pub process_version : i32,
     pub brightness : f32,
     pub contrast : f32,
     pub invert : i32,
@@ -22,12 +36,17 @@ pub struct Effects {
     pub white_pt : f32,
     pub black_pt : f32,
     pub temperature : f32,
+    /// Green to magenta, -150 to 150 like lightroom's
+    pub tint : f32,
     pub original : i32,
+    /// x, y, width, height as fractions of the image size
+    pub crop : [f32; 4],
 }
 
 impl Default for Effects {
     fn default() -> Effects {
         Effects {
+            process_version : PROCESS_VERSION,
             brightness: 0.,
             contrast: 0.5,
             invert : 0,
@@ -36,11 +55,51 @@ impl Default for Effects {
             white_pt : 1.0,
             black_pt : 0.0,
             temperature : 6500.,
+            tint : 0.,
             original : 0,
+            crop : [0., 0., 1., 1.],
         }
     }
 }
 
+impl Effects {
+    pub fn needs_upgrade(&self) -> bool {
+        self.process_version < PROCESS_VERSION
+    }
+
+    /// Move the edit to the current processing version. The slider values
+    /// are kept as is, so the photo may render differently afterwards.
+    pub fn upgrade_processing(&mut self) {
+        self.process_version = PROCESS_VERSION;
+    }
+
+    /// The crop in pixels, x, y, width, height, for an image of the given
+    /// size, at least a pixel wide for a non empty image.
+    pub fn crop_rect(&self, width : u32, height : u32) -> (u32, u32, u32, u32) {
+        let [x, y, w, h] = self.crop;
+        let x = ((x * width as f32) as u32).min(width.saturating_sub(1));
+        let y = ((y * height as f32) as u32).min(height.saturating_sub(1));
+        let w = ((w * width as f32).round() as u32).max(1).min(width - x);
+        let h = ((h * height as f32).round() as u32).max(1).min(height - y);
+        (x, y, w, h)
+    }
+}
+
+/// Screen pixels per image pixel from which `Overlays::pixel_grid` is drawn.
+pub const GRID_ZOOM : f32 = 8.;
+
+/// What's drawn over the photo on screen, none of it is part of the edit
+/// or of exports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Overlays {
+    /// The radius of a box blur as a fraction of the image size, 0 for none
+    pub blur : f32,
+    /// Lines between the image's pixels when zoomed in past GRID_ZOOM
+    pub pixel_grid : bool,
+    /// Clipped highlights in red and crushed shadows in blue, after the
+    /// effects
+    pub clipping : bool,
+}
 
 #[derive(Debug)]
 pub struct EffectsShader {
@@ -48,15 +107,15 @@ pub struct EffectsShader {
 }
 
 impl EffectsShader {
-    pub fn new(display : &glium::Display) -> Self {
-        let program = program!(display,
-            100 => {
-                vertex : include_str!("effects.vert"),
-                fragment : include_str!("effects.frag"),
-            }
-        ).unwrap();
+    pub fn new(display : &glium::Display) -> Result<Self, Error> {
+        let program = shader_cache::program(
+            display,
+            "effects",
+            include_str!("effects.vert"),
+            include_str!("effects.frag"),
+        )?;
 
-        Self{ program }
+        Ok(Self{ program })
     }
 
     pub fn draw_image_screen(
@@ -66,7 +125,143 @@ impl EffectsShader {
         trans : &Mat4,
         effects : &Effects
     ) -> Result<(), Error> {
+        self.draw_image_screen_blurred(ctx, img_id, trans, effects, 0.)
+    }
+
+    /// Like draw_image_screen with a box blur on top, blur is the radius as
+    /// a fraction of the image size.
+    pub fn draw_image_screen_blurred(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        trans : &Mat4,
+        effects : &Effects,
+        blur : f32,
+    ) -> Result<(), Error> {
+        let overlays = Overlays{
+            blur,
+            ..Default::default()
+        };
+        self.draw_image_screen_with(ctx, img_id, trans, effects, &overlays)
+    }
+
+    /// Like draw_image_screen with overlays on top.
+    pub fn draw_image_screen_with(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        trans : &Mat4,
+        effects : &Effects,
+        overlays : &Overlays,
+    ) -> Result<(), Error> {
+        self.draw(ctx, img_id, trans, effects, overlays, None)
+    }
+
+    /// Like draw_image_screen_with into a part of the window, see
+    /// RenderCtx::draw_image_viewport.
+    pub fn draw_image_viewport_with(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        trans : &Mat4,
+        effects : &Effects,
+        overlays : &Overlays,
+        viewport : Viewport,
+    ) -> Result<(), Error> {
+        self.draw(ctx, img_id, trans, effects, overlays, Some(viewport))
+    }
+
+    fn draw(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        trans : &Mat4,
+        effects : &Effects,
+        overlays : &Overlays,
+        viewport : Option<Viewport>,
+    ) -> Result<(), Error> {
+        // screen pixels per image pixel, the view is 1:1 at identity
+        let zoom = trans.transform_vector3(Vec3::X).length();
+        let grid = if overlays.pixel_grid && zoom >= GRID_ZOOM {
+            // a line a screen pixel wide, in image pixels
+            1. / zoom
+        } else {
+            0.
+        };
 
+        let (width, height) = ctx.image_dimensions(img_id)?;
+        let uniforms = Self::uniforms(
+            effects,
+            overlays.blur,
+            grid,
+            [width as f32, height as f32],
+            overlays.clipping,
+            ctx.display_profile(),
+        );
+
+        match viewport {
+            Some(viewport) => ctx.draw_image_viewport(img_id, trans, &self.program, uniforms, viewport),
+            None => ctx.draw_image_screen(img_id, trans, &self.program, uniforms),
+        }
+    }
+
+    /// The image fit to the preview window with the effects applied, see
+    /// RenderCtx::set_preview. Does nothing without one.
+    pub fn draw_image_preview(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        effects : &Effects,
+    ) -> Result<(), Error> {
+        let (win_width, win_height) = match ctx.preview_dimensions() {
+            Some(dims) => dims,
+            None => return Ok(()),
+        };
+
+        let (width, height) = ctx.image_dimensions(img_id)?;
+        let scale = (win_width / width as f32).min(win_height / height as f32);
+        let trans = Mat4::from_scale(Vec3::new(scale, scale, 1.0));
+
+        // its monitor's profile isn't loaded, it's shown as sRGB
+        let uniforms = Self::uniforms(effects, 0., 0., [width as f32, height as f32], false, &DisplayProfile::default());
+        ctx.draw_image_preview(img_id, &trans, &self.program, uniforms)
+    }
+
+    /// The image at full resolution with the effects applied.
+    pub fn render_image(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        effects : &Effects
+    ) -> Result<image::RgbaImage, Error> {
+        // exports are sRGB, whatever the monitor is
+        let uniforms = Self::uniforms(effects, 0., 0., [1., 1.], false, &DisplayProfile::default());
+        ctx.render_image(img_id, &self.program, uniforms)
+    }
+
+    /// The current view at another resolution, see RenderCtx::render_view.
+    pub fn render_view(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        trans : &Mat4,
+        effects : &Effects,
+        size : (u32, u32),
+        samples : u32,
+        clear : Color,
+    ) -> Result<image::RgbaImage, Error> {
+        let uniforms = Self::uniforms(effects, 0., 0., [1., 1.], false, &DisplayProfile::default());
+        ctx.render_view(img_id, trans, &self.program, uniforms, size, samples, clear)
+    }
+
+    fn uniforms(
+        effects : &Effects,
+        blur : f32,
+        grid : f32,
+        texture_size : [f32; 2],
+        clipping : bool,
+        display : &DisplayProfile,
+    ) -> impl glium::uniforms::Uniforms {
         macro_rules! effects_uniforms {
             ($val0:ident,$($val:ident),*,) => {
                 {
@@ -84,11 +279,57 @@ impl EffectsShader {
             };
         }
 
-        let uniforms = effects_uniforms!(
-            brightness, contrast, invert, original,
-            highlight, shadow, white_pt, black_pt, temperature,
-        );
+        effects_uniforms!(
+            process_version, brightness, contrast, invert, original,
+            highlight, shadow, white_pt, black_pt, temperature, tint, crop,
+        )
+        .add("blur", blur)
+        .add("grid", grid)
+        .add("texture_size", texture_size)
+        .add("clipping", if clipping { 1 } else { 0 })
+        .add("display", display.matrix.to_cols_array_2d())
+        // 0 for the sRGB curve
+        .add("display_gamma", display.gamma.unwrap_or(0.))
+    }
+}
+
+/// The gradient over which `PeakingShader` marks a pixel, of the sobel
+/// operator on luma from 0 to 1.
+pub const PEAKING_THRESHOLD : f32 = 0.3;
 
+/// Focus peaking, marks the sharp edges of an image in green. Drawn over
+/// the photo after `EffectsShader`, with the same transform.
+#[derive(Debug)]
+pub struct PeakingShader {
+    program : glium::Program,
+}
+
+impl PeakingShader {
+    pub fn new(display : &glium::Display) -> Result<Self, Error> {
+        let program = shader_cache::program(
+            display,
+            "peaking",
+            include_str!("effects.vert"),
+            include_str!("peaking.frag"),
+        )?;
+
+        Ok(Self{ program })
+    }
+
+    /// Edges of the image as loaded, the edits don't change what's in
+    /// focus. Only the crop is taken from effects.
+    pub fn draw_image_screen(
+        &self,
+        ctx : &mut RenderCtx,
+        img_id : ImageId,
+        trans : &Mat4,
+        effects : &Effects,
+        threshold : f32,
+    ) -> Result<(), Error> {
+        let (width, height) = ctx.image_dimensions(img_id)?;
+        let uniforms = glium::uniforms::UniformsStorage::new("texture_size", [width as f32, height as f32])
+            .add("crop", effects.crop)
+            .add("threshold", threshold);
 
         ctx.draw_image_screen(img_id, trans, &self.program, uniforms)
     }
@@ -0,0 +1,90 @@
+// plays a gallery's photos one after the other in the photo screen, with
+// music when the audio feature is on
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::Duration;
+
+#[cfg(feature = "audio")]
+use crate::audio::Music;
+
+// the music fades out over the last this much of the slideshow
+#[cfg(feature = "audio")]
+const FADE_OUT : Duration = Duration::from_secs(4);
+
+#[derive(Debug)]
+pub struct Slideshow {
+    paths : Vec<PathBuf>,
+    current : usize,
+    pub interval : Duration,
+    // how long the current photo has been on screen
+    shown_for : Duration,
+    #[cfg(feature = "audio")]
+    pub music : Option<Music>,
+}
+
+impl Slideshow {
+    pub fn new(paths : Vec<PathBuf>, interval : Duration) -> Option<Self> {
+        if paths.is_empty() {
+            return None
+        }
+
+        Some(Slideshow {
+            paths,
+            current : 0,
+            interval,
+            shown_for : Duration::ZERO,
+            #[cfg(feature = "audio")]
+            music : None,
+        })
+    }
+
+    pub fn current(&self) -> &Path {
+        &self.paths[self.current]
+    }
+
+    pub fn position(&self) -> (usize, usize) {
+        (self.current + 1, self.paths.len())
+    }
+
+    // time only counts while the current photo is on screen, not while it
+    // loads. returns the next photo to open when it's time
+    pub fn advance(&mut self, dt : Duration, shown : bool) -> Option<&Path> {
+        #[cfg(feature = "audio")]
+        {
+            let left = self.remaining();
+            if let Some(music) = &mut self.music {
+                if left <= FADE_OUT {
+                    music.fade_out(left);
+                }
+                music.update(dt);
+            }
+        }
+
+        if !shown {
+            return None
+        }
+
+        self.shown_for += dt;
+        if self.shown_for < self.interval || self.current + 1 >= self.paths.len() {
+            return None
+        }
+
+        self.shown_for = Duration::ZERO;
+        self.current += 1;
+        Some(self.current())
+    }
+
+    // until the last photo has been up for the interval
+    #[cfg(feature = "audio")]
+    fn remaining(&self) -> Duration {
+        let after = (self.paths.len() - self.current - 1) as u32;
+        (self.interval * (after + 1)).saturating_sub(self.shown_for)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.current + 1 >= self.paths.len() && self.shown_for >= self.interval
+    }
+}
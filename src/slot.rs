@@ -0,0 +1,50 @@
+// more models next to App::Model, each with its own buffer, so a worker
+// filling one in never replaces or waits on the others. like App::Model,
+// the render thread reads them and the workers write them through a
+// BufBufWrite, and they're swapped between frames
+
+use std::cell::{
+    RefCell,
+    RefMut,
+};
+use std::rc::Rc;
+
+use crate::BufBuf;
+use crate::Panic;
+
+/// The render thread's half of a model made with `UnrenderCtx::slot`, kept
+/// in the LocalModel. The other half is the `BufBufWrite` for workers.
+pub struct Slot<T> {
+    buf : Rc<RefCell<BufBuf<T>>>,
+}
+
+impl<T> Slot<T> {
+    /// The value for this frame, with the writes from before it.
+    pub fn get(&self) -> RefMut<'_, T> {
+        RefMut::map(self.buf.borrow_mut(), BufBuf::get_mut)
+    }
+}
+
+impl<T> std::fmt::Debug for Slot<T> {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Slot").finish_non_exhaustive()
+    }
+}
+
+// what the framework keeps of each slot to swap it
+crate trait SwapSlot {
+    fn swap(&self) -> Vec<Panic>;
+}
+
+impl<T> SwapSlot for RefCell<BufBuf<T>> {
+    fn swap(&self) -> Vec<Panic> {
+        // replaced values are just dropped, slots aren't for textures
+        self.borrow_mut().swap(|_, _| {})
+    }
+}
+
+crate fn new<T : 'static>(buf : BufBuf<T>) -> (Slot<T>, Rc<dyn SwapSlot>) {
+    let buf = Rc::new(RefCell::new(buf));
+    let swap : Rc<dyn SwapSlot> = buf.clone();
+    (Slot{buf}, swap)
+}
@@ -0,0 +1,212 @@
+// photos shot to be merged: exposure brackets, a few frames of the same
+// scene a stop or more apart for an hdr, and panoramas, frames of
+// neighbouring views with the same exposure. both are shot a frame right
+// after the other, which is what's looked for, with the exposure settings
+// and the difference hashes telling them apart
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use crate::metadata::Exposure;
+
+// frames of a bracket are taken within this many seconds of each other,
+// panorama frames within the other
+const BRACKET_GAP : f64 = 2.0;
+const PANORAMA_GAP : f64 = 10.0;
+
+// exposures closer than this many stops are the same
+const SAME_STOPS : f32 = 0.3;
+// a bracket that doesn't cover at least this many stops is more likely the
+// camera adjusting between shots
+const MIN_BRACKET_STOPS : f32 = 1.0;
+
+// difference hashes of one scene exposed differently differ in at most
+// this many bits, and neighbouring views in more. looser than
+// dupes::MAX_DISTANCE since clipped highlights move edges around
+const SAME_SCENE : u32 = 16;
+
+const MIN_PANORAMA : usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackKind {
+    Bracket,
+    Panorama,
+}
+
+impl StackKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StackKind::Bracket => "bracket",
+            StackKind::Panorama => "panorama",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Stack {
+    pub kind : StackKind,
+    /// In the order they were shot
+    pub frames : Vec<PathBuf>,
+}
+
+/// What detect needs to know about each photo.
+#[derive(Debug, Clone, Copy)]
+pub struct Shot<'a> {
+    pub path : &'a Path,
+    /// Seconds since the epoch, None for photos that don't say
+    pub time : Option<f64>,
+    pub exposure : &'a Exposure,
+    pub dims : (u32, u32),
+    pub dhash : u64,
+}
+
+impl Shot<'_> {
+    // same camera setup, so could be from one stack
+    fn matches(&self, other : &Shot) -> bool {
+        self.dims == other.dims &&
+            self.exposure.focal_length == other.exposure.focal_length &&
+            self.path.parent() == other.path.parent()
+    }
+
+    fn same_scene(&self, other : &Shot) -> bool {
+        (self.dhash ^ other.dhash).count_ones() <= SAME_SCENE
+    }
+
+    fn gap(&self, other : &Shot) -> f64 {
+        (other.time.unwrap_or(0.0) - self.time.unwrap_or(0.0)).abs()
+    }
+}
+
+/// Probable brackets and panoramas among shots, in the order they were
+/// shot. A photo is in at most one of them.
+pub fn detect(shots : &[Shot]) -> Vec<Stack> {
+    let mut shots : Vec<&Shot> = shots.iter().filter(|shot| shot.time.is_some()).collect();
+    shots.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut stacks = Vec::new();
+    let mut start = 0;
+    while start < shots.len() {
+        let found = bracket(&shots[start..])
+            .map(|len| (StackKind::Bracket, len))
+            .or_else(|| panorama(&shots[start..]).map(|len| (StackKind::Panorama, len)));
+
+        match found {
+            Some((kind, len)) => {
+                stacks.push(Stack{
+                    kind,
+                    frames : shots[start..start + len].iter().map(|shot| shot.path.to_path_buf()).collect(),
+                });
+                start += len;
+            },
+            None => start += 1,
+        }
+    }
+
+    stacks
+}
+
+// how many of the shots from the first make a bracket, if they do
+fn bracket(shots : &[&Shot]) -> Option<usize> {
+    let first = shots[0];
+    let mut stops = vec![first.exposure.stops()];
+
+    let mut len = 1;
+    for pair in shots.windows(2) {
+        let (prev, shot) = (pair[0], pair[1]);
+        let shot_stops = shot.exposure.stops();
+
+        let fits = prev.gap(shot) <= BRACKET_GAP &&
+            first.matches(shot) &&
+            first.same_scene(shot) &&
+            // the next bracket of the same scene starts over
+            stops.iter().all(|s| (s - shot_stops).abs() >= SAME_STOPS);
+        if !fits {
+            break
+        }
+
+        stops.push(shot_stops);
+        len += 1;
+    }
+
+    let min = stops.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = stops.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let marked = shots[..len].iter().all(|shot| shot.exposure.bracketed);
+
+    if len >= 2 && (max - min >= MIN_BRACKET_STOPS || marked) {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+// how many of the shots from the first make a panorama, if they do
+fn panorama(shots : &[&Shot]) -> Option<usize> {
+    let first = shots[0];
+    let stops = first.exposure.stops();
+
+    let mut len = 1;
+    for pair in shots.windows(2) {
+        let (prev, shot) = (pair[0], pair[1]);
+
+        let fits = prev.gap(shot) <= PANORAMA_GAP &&
+            first.matches(shot) &&
+            // panoramas are shot with the exposure locked
+            (shot.exposure.stops() - stops).abs() < SAME_STOPS &&
+            // and each frame shows something else, unlike a burst
+            !prev.same_scene(shot);
+        if !fits {
+            break
+        }
+
+        len += 1;
+    }
+
+    Some(len).filter(|len| *len >= MIN_PANORAMA)
+}
+
+/// Exposure fusion of a bracket, each pixel a mix of the frames weighted by
+/// how well exposed and colorful they are there. None if the frames aren't
+/// all the same size.
+pub fn fuse(frames : &[image::RgbImage]) -> Option<image::RgbImage> {
+    let (width, height) = frames.first()?.dimensions();
+    if frames.iter().any(|frame| frame.dimensions() != (width, height)) {
+        return None
+    }
+
+    let mut out = image::RgbImage::new(width, height);
+    for (idx, pixel) in out.chunks_exact_mut(3).enumerate() {
+        let mut sum = [0.0; 3];
+        let mut total = 0.0;
+
+        for frame in frames {
+            let p = &frame.as_raw()[idx * 3..idx * 3 + 3];
+            let rgb = [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0];
+            let weight = weight(rgb);
+
+            for (s, v) in sum.iter_mut().zip(rgb.iter()) {
+                *s += v * weight;
+            }
+            total += weight;
+        }
+
+        for (p, s) in pixel.iter_mut().zip(sum.iter()) {
+            *p = (s / total * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    Some(out)
+}
+
+// mertens et al., well exposed is near the middle of the range in every
+// channel, colorful is far apart channels. never 0 so a pixel blown out in
+// every frame is still their average
+fn weight(rgb : [f32; 3]) -> f32 {
+    let exposed : f32 = rgb.iter().map(|v| (-(v - 0.5).powi(2) / (2.0 * 0.2 * 0.2)).exp()).product();
+
+    let mean = (rgb[0] + rgb[1] + rgb[2]) / 3.0;
+    let saturation = (rgb.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / 3.0).sqrt();
+
+    exposed * (saturation + 0.1) + 1e-6
+}
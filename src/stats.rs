@@ -0,0 +1,101 @@
+// numbers about the framework for a performance overlay, gathered by run
+// before every frame from the render loop, the GraphicsCtx, the message
+// lanes and the model's buffer
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// frame times kept for the graph
+const FRAMES : usize = 120;
+const GRAPH_HEIGHT : f32 = 60.0;
+// the graph's top, 30 fps
+const GRAPH_MAX : Duration = Duration::from_millis(33);
+
+/// From `RenderCtx::stats`, as of the start of the frame.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// The time between each of the last frames and the one before,
+    /// oldest first
+    pub frame_times : VecDeque<Duration>,
+    /// Images uploaded with add_image
+    pub textures : usize,
+    /// Of the textures, estimated at 4 bytes a pixel
+    pub texture_bytes : u64,
+    /// Messages waiting for a worker in the interactive and background
+    /// lanes
+    pub queued : (usize, usize),
+    /// Jobs running, see `JobManager`
+    pub jobs : usize,
+    /// Times `App::Model` was replaced with `set_next`
+    pub swaps : u64,
+    /// Patches applied to it
+    pub patches : u64,
+}
+
+impl Stats {
+    crate fn push_frame(&mut self, dt : Duration) {
+        if self.frame_times.len() >= FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+    }
+
+    /// The average of the last frame times.
+    pub fn frame_time(&self) -> Duration {
+        let count = self.frame_times.len().max(1) as u32;
+        self.frame_times.iter().sum::<Duration>() / count
+    }
+
+    /// A small window in the corner with the numbers and a graph of frame
+    /// times.
+    pub fn show(&self, egui : &egui::CtxRef, open : &mut bool) {
+        if !*open {
+            return
+        }
+
+        egui::Window::new("Performance")
+            .open(open)
+            .resizable(false)
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 32.0])
+            .show(egui, |ui| {
+                let frame_time = self.frame_time();
+                let fps = if frame_time > Duration::ZERO { 1.0 / frame_time.as_secs_f32() } else { 0.0 };
+                ui.label(format!("{:.0} fps, {:.1} ms a frame", fps, frame_time.as_secs_f32() * 1000.0));
+
+                self.graph(ui);
+
+                ui.label(format!(
+                    "{} textures, about {:.1} MB",
+                    self.textures,
+                    self.texture_bytes as f64 / (1024.0 * 1024.0),
+                ));
+                ui.label(format!("{} interactive and {} background messages queued", self.queued.0, self.queued.1));
+                ui.label(format!("{} jobs running", self.jobs));
+                ui.label(format!("{} model swaps, {} patches", self.swaps, self.patches));
+            });
+    }
+
+    fn graph(&self, ui : &mut egui::Ui) {
+        let size = egui::vec2(FRAMES as f32 * 2.0, GRAPH_HEIGHT);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 2.0, egui::Color32::from_gray(16));
+
+        let y = |dt : Duration| {
+            let t = (dt.as_secs_f32() / GRAPH_MAX.as_secs_f32()).min(1.0);
+            rect.bottom() - rect.height() * t
+        };
+
+        // 60 fps
+        let line = y(Duration::from_micros(16_667));
+        painter.line_segment(
+            [egui::pos2(rect.left(), line), egui::pos2(rect.right(), line)],
+            (1.0, egui::Color32::from_gray(80)),
+        );
+
+        let points = self.frame_times.iter().enumerate()
+            .map(|(idx, dt)| egui::pos2(rect.left() + idx as f32 * 2.0, y(*dt)))
+            .collect();
+        painter.add(egui::Shape::line(points, (1.0, egui::Color32::from_rgb(90, 200, 90))));
+    }
+}
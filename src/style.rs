@@ -0,0 +1,53 @@
+// how egui looks: the theme and accent from the preferences, the app's
+// fonts scaled by the text size, and whatever else the app changes in
+// App::customize_visuals. set again whenever the preferences change
+
+use crate::{
+    App,
+    Preferences,
+};
+
+crate struct Style {
+    fonts : egui::FontDefinitions,
+    // what egui was last given
+    shown : Option<Preferences>,
+}
+
+impl Style {
+    crate fn new(fonts : Option<egui::FontDefinitions>) -> Self {
+        Style {
+            fonts : fonts.unwrap_or_default(),
+            shown : None,
+        }
+    }
+
+    crate fn update<A : App>(&mut self, egui : &egui::CtxRef, prefs : &Preferences, app : &A) {
+        let shown = self.shown.as_ref();
+        let visuals_changed = shown.map_or(true, |s| s.theme != prefs.theme || s.accent != prefs.accent);
+        let fonts_changed = shown.map_or(true, |s| s.text_scale != prefs.text_scale);
+
+        if visuals_changed {
+            let mut visuals = prefs.theme.visuals();
+            if let Some([r, g, b]) = prefs.accent {
+                let accent = egui::Color32::from_rgb(r, g, b);
+                visuals.selection.bg_fill = accent;
+                visuals.hyperlink_color = accent;
+            }
+
+            app.customize_visuals(&mut visuals);
+            egui.set_visuals(visuals);
+        }
+
+        if fonts_changed {
+            let mut fonts = self.fonts.clone();
+            for (_, size) in fonts.family_and_size.values_mut() {
+                *size *= prefs.text_scale;
+            }
+            egui.set_fonts(fonts);
+        }
+
+        if visuals_changed || fonts_changed {
+            self.shown = Some(prefs.clone());
+        }
+    }
+}
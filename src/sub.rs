@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use glium::glutin::event_loop::EventLoopProxy;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+use crate::wake::LoopEvent;
+
+/// A message sent every period for as long as `App::subscriptions` keeps
+/// returning it. Subscriptions are told apart by key, so a slideshow can
+/// be `Sub::every("slideshow", interval, || Msg::Next)` while it plays and
+/// left out when it's paused.
+pub struct Sub<M> {
+    key : String,
+    period : Duration,
+    msg : Arc<dyn Fn() -> M + Send + Sync>,
+}
+
+impl<M> std::fmt::Debug for Sub<M> {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sub")
+            .field("key", &self.key)
+            .field("period", &self.period)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M> Sub<M> {
+    /// The first message is sent one period from when the subscription
+    /// starts. Changing the period restarts it.
+    pub fn every<F>(key : impl Into<String>, period : Duration, msg : F) -> Self
+    where
+        F : Fn() -> M + Send + Sync + 'static,
+    {
+        Sub {
+            key : key.into(),
+            // tokio's intervals can't be 0
+            period : period.max(Duration::from_millis(1)),
+            msg : Arc::new(msg),
+        }
+    }
+}
+
+struct Running {
+    period : Duration,
+    task : JoinHandle<()>,
+}
+
+// the timers for the subscriptions returned last frame
+crate struct Subscriptions<M> {
+    running : HashMap<String, Running>,
+    _msg : std::marker::PhantomData<fn() -> M>,
+}
+
+impl<M : Send + 'static> Subscriptions<M> {
+    crate fn new() -> Self {
+        Subscriptions {
+            running : HashMap::new(),
+            _msg : std::marker::PhantomData,
+        }
+    }
+
+    // starts the new ones and stops the ones which aren't in subs anymore,
+    // ticks go through proxy to the render thread
+    crate fn update(&mut self, subs : Vec<Sub<M>>, handle : &Handle, proxy : &EventLoopProxy<LoopEvent<M>>) {
+        let mut running = HashMap::with_capacity(subs.len());
+
+        for sub in subs {
+            match self.running.remove(&sub.key) {
+                Some(r) if r.period == sub.period => {
+                    running.insert(sub.key, r);
+                    continue
+                },
+                Some(r) => r.task.abort(),
+                None => {},
+            }
+
+            let proxy = proxy.clone();
+            let Sub{key, period, msg} = sub;
+            let task = handle.spawn(async move {
+                let start = tokio::time::Instant::now() + period;
+                let mut interval = tokio::time::interval_at(start, period);
+
+                loop {
+                    interval.tick().await;
+                    if proxy.send_event(LoopEvent::Msg(msg())).is_err() {
+                        // the window closed
+                        break;
+                    }
+                }
+            });
+
+            running.insert(key, Running{period, task});
+        }
+
+        for r in self.running.values() {
+            r.task.abort();
+        }
+
+        self.running = running;
+    }
+}
@@ -1,50 +1,553 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::Duration;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicU64,
+    AtomicUsize,
+    Ordering,
+};
 
-use tokio::runtime::Runtime;
+use tokio::runtime::{
+    Handle,
+    Runtime,
+};
+use tokio::sync::Notify;
+use tracing::Instrument;
+
+use glium::glutin::event_loop::EventLoopProxy;
 
 use crate::App;
 use crate::BufBufWrite;
+use crate::Cmd;
+use crate::Generation;
+use crate::Middleware;
+use crate::middleware::variant_name;
+use crate::MutexExt;
+use crate::Panic;
+use crate::recover::{
+    catch_unwind,
+    PanicHandler,
+};
+use crate::Sub;
+use crate::sub::Subscriptions;
+use crate::wake::LoopEvent;
+use crate::Waker;
 
+// how often a paused job looks again, in case it missed the notify
+const PAUSE_POLL : Duration = Duration::from_secs(1);
 
-crate struct TaskChannel<A : App> {
-    // TODO: unbounded sender or increase bound size
-    sender : tokio::sync::mpsc::Sender<A::Msg>,
-    _rt : Runtime,
+
+/// Messages are handled in two lanes, each in order, so that a slow
+/// background message never holds up something the user is waiting on.
+/// See App::priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Background,
 }
 
-impl <A : App> TaskChannel<A> {
-    crate fn new(app : &'static A, model : BufBufWrite<A::Model>) -> Self {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(4)
+// one queue per lane
+struct Lane<M> {
+    // unbounded, so sending never blocks the render thread. see App::coalesce
+    // for keeping it short
+    queue : Mutex<VecDeque<M>>,
+    notify : Notify,
+}
+
+// see the AppRunner methods of the same names
+#[derive(Debug, Clone)]
+crate struct RuntimeOptions {
+    crate worker_threads : usize,
+    crate max_blocking_threads : Option<usize>,
+    crate current_thread : bool,
+    crate handle : Option<Handle>,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        RuntimeOptions {
+            worker_threads : 4,
+            max_blocking_threads : None,
+            current_thread : false,
+            handle : None,
+        }
+    }
+}
+
+impl RuntimeOptions {
+    // None when the caller's runtime is used, otherwise the runtime which
+    // has to be kept alive for as long as tasks run on the handle
+    fn build(self) -> (Handle, Option<Runtime>) {
+        if let Some(handle) = self.handle {
+            return (handle, None)
+        }
+
+        let mut builder = if self.current_thread {
+            tokio::runtime::Builder::new_current_thread()
+        } else {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            builder.worker_threads(self.worker_threads);
+            builder
+        };
+
+        if let Some(n) = self.max_blocking_threads {
+            builder.max_blocking_threads(n);
+        }
+
+        let rt = builder
             .thread_name("photos-workers")
+            .enable_all()
             .build()
             .unwrap();
 
-        let (sender, mut recv) = tokio::sync::mpsc::channel(1);
+        if self.current_thread {
+            // a current thread runtime only runs tasks inside block_on, and
+            // the render thread is busy with the event loop
+            let handle = rt.handle().clone();
+            std::thread::Builder::new()
+                .name("photos-workers".to_string())
+                .spawn(move || rt.block_on(std::future::pending::<()>()))
+                .unwrap();
 
-        rt.spawn(async move {
-            loop {
-                println!("waiting for message");
-                let msg = if let Some(msg) = recv.recv().await {
-                    msg
-                } else {
-                    break
-                };
+            return (handle, None)
+        }
 
-                println!("got msg : {:?}", msg);
+        (rt.handle().clone(), Some(rt))
+    }
+}
+
+crate struct TaskChannel<A : App> {
+    app : &'static A,
+    model : BufBufWrite<A::Model>,
+    interactive : Arc<Lane<A::Msg>>,
+    background : Arc<Lane<A::Msg>>,
+    handle : Handle,
+    // messages from commands run off the render thread come back through
+    // the event loop
+    proxy : EventLoopProxy<LoopEvent<A::Msg>>,
+    subscriptions : Subscriptions<A::Msg>,
+    _rt : Option<Runtime>,
+}
+
+impl <A : App> TaskChannel<A> {
+    crate fn new(
+        app : &'static A,
+        model : BufBufWrite<A::Model>,
+        middleware : Vec<Box<dyn Middleware<A>>>,
+        runtime : RuntimeOptions,
+        proxy : EventLoopProxy<LoopEvent<A::Msg>>) -> Self
+    {
+        let (handle, rt) = runtime.build();
+
+        let middleware = Arc::new(middleware);
+        let new_lane = || Arc::new(Lane{
+            queue : Mutex::new(VecDeque::new()),
+            notify : Notify::new(),
+        });
+        let interactive = new_lane();
+        let background = new_lane();
+
+        for lane in [interactive.clone(), background.clone()].iter().cloned() {
+            let model = model.clone();
+            let middleware = middleware.clone();
+            let proxy = proxy.clone();
+
+            handle.spawn(async move {
+                loop {
+                    let msg = loop {
+                        if let Some(msg) = lane.queue.lock_or_recover().pop_front() {
+                            break msg
+                        }
+                        lane.notify.notified().await;
+                    };
+
+                    let span = tracing::debug_span!("update", msg = %variant_name(&msg));
+                    span.in_scope(|| tracing::trace!("{:?}", msg));
 
-                if let Err(err) = app.update(&model, msg).await {
-                    app.handle_error(err)
+                    let msg = middleware.iter().try_fold(msg, |msg, m| m.on_msg(msg));
+                    let msg = match msg {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
+
+                    // a panic in update is the message's error, the lane
+                    // keeps going
+                    let res = catch_unwind(app.update(&model, msg)).instrument(span.clone()).await
+                        .unwrap_or_else(|payload| Err(Panic::new("handling a message", payload).into()));
+                    model.wake();
+
+                    span.in_scope(|| match res {
+                        Ok(cmd) => {
+                            let send = |msg| { let _ = proxy.send_event(LoopEvent::Msg(msg)); };
+                            cmd.run(send, &Handle::current(), &proxy)
+                        },
+                        Err(err) => app.handle_error(err),
+                    });
                 }
-            }
+            });
+        }
+
+        Self{
+            app,
+            model,
+            interactive,
+            background,
+            handle,
+            proxy,
+            subscriptions : Subscriptions::new(),
+            _rt : rt,
+        }
+    }
+
+    crate fn subscribe(&mut self, subs : Vec<Sub<A::Msg>>) {
+        self.subscriptions.update(subs, &self.handle, &self.proxy)
+    }
+
+    // stops the subscriptions and runs App::shutdown on the runtime, waits
+    // for it for at most timeout. returns whether it finished
+    crate fn shutdown(&mut self, timeout : Duration) -> bool {
+        self.subscriptions.update(Vec::new(), &self.handle, &self.proxy);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let app = self.app;
+        let model = self.model.clone();
+        self.handle.spawn(async move {
+            app.shutdown(&model).await;
+            let _ = tx.send(());
         });
 
-        Self{sender, _rt : rt}
+        rx.recv_timeout(timeout).is_ok()
+    }
+
+    // messages waiting in the interactive and background lanes
+    crate fn queued(&self) -> (usize, usize) {
+        (
+            self.interactive.queue.lock_or_recover().len(),
+            self.background.queue.lock_or_recover().len(),
+        )
+    }
+
+    // on the render thread, messages are sent right away
+    crate fn run(&self, cmd : Cmd<A::Msg>) {
+        cmd.run(|msg| self.send(msg), &self.handle, &self.proxy)
     }
 
     crate fn send(&self, msg : A::Msg) {
-        println!("sending msg : {:?}", msg);
-        self.sender
-            .blocking_send(msg)
-            .unwrap();
+        tracing::debug!("sending {}", variant_name(&msg));
+
+        let lane = match self.app.priority(&msg) {
+            Priority::Interactive => &self.interactive,
+            Priority::Background => &self.background,
+        };
+
+        let mut queue = lane.queue.lock_or_recover();
+        let msg = match queue.back_mut() {
+            Some(last) => self.app.coalesce(last, msg),
+            None => Some(msg),
+        };
+
+        if let Some(msg) = msg {
+            queue.push_back(msg);
+        }
+
+        drop(queue);
+        lane.notify.notify_one();
+    }
+}
+
+/// A handle to a job for the task running it, to report progress and check
+/// for cancellation.
+#[derive(Debug, Clone)]
+pub struct Job {
+    state : Arc<JobState>,
+    waker : Waker,
+    pause : Arc<Pause>,
+}
+
+// set while the window is minimized or the system is suspended
+#[derive(Debug, Default)]
+struct Pause {
+    paused : AtomicBool,
+    resumed : Notify,
+}
+
+#[derive(Debug)]
+struct JobState {
+    id : u64,
+    name : String,
+    group : Option<String>,
+    // the model it fills in, see spawn_for
+    generation : Option<Generation>,
+    done : AtomicUsize,
+    // 0 while unknown
+    total : AtomicUsize,
+    cancelled : AtomicBool,
+}
+
+impl Job {
+    pub fn id(&self) -> u64 {
+        self.state.id
+    }
+
+    /// Cancellation is cooperative, the job should check this between items
+    /// and return early.
+    pub fn cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Waits while the window is minimized or the system is suspended, so
+    /// work nobody can see yet, like loading thumbnails, doesn't keep the
+    /// machine busy. Returns right away once the job is cancelled. Jobs the
+    /// user is waiting on, like copying files, shouldn't call it.
+    pub async fn resumed(&self) {
+        while self.pause.paused.load(Ordering::Acquire) && !self.cancelled() {
+            let _ = tokio::time::timeout(PAUSE_POLL, self.pause.resumed.notified()).await;
+        }
+    }
+
+    pub fn set_total(&self, total : usize) {
+        self.state.total.store(total, Ordering::Relaxed);
+        self.waker.wake();
+    }
+
+    /// For jobs which find more work as they go, like folder scans.
+    pub fn add_total(&self, n : usize) {
+        self.state.total.fetch_add(n, Ordering::Relaxed);
+        self.waker.wake();
+    }
+
+    /// Marks one more item as done, and asks for a frame to show it along
+    /// with whatever the job changed in the model.
+    pub fn inc(&self) {
+        self.state.done.fetch_add(1, Ordering::Relaxed);
+        self.waker.wake();
+    }
+}
+
+/// A snapshot of a running job, see JobManager::jobs.
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub id : u64,
+    pub name : String,
+    pub done : usize,
+    pub total : Option<usize>,
+    pub cancelled : bool,
+}
+
+/// Keeps track of long running tasks spawned on the worker runtime, so the ui
+/// can show what's going on in the background and cancel it. Cheap to clone,
+/// apps get one from `InitCtx::jobs` or `RenderCtx::jobs`.
+#[derive(Debug, Clone, Default)]
+pub struct JobManager {
+    jobs : Arc<Mutex<Vec<Arc<JobState>>>>,
+    next_id : Arc<AtomicU64>,
+    waker : Waker,
+    pause : Arc<Pause>,
+    panics : PanicHandler,
+}
+
+impl JobManager {
+    crate fn new(waker : Waker) -> Self {
+        JobManager {
+            waker,
+            ..Default::default()
+        }
+    }
+
+    // where jobs that panic go, App::handle_error
+    crate fn on_panic(&self, f : impl Fn(Panic) + Send + 'static) {
+        self.panics.set(f)
+    }
+
+    /// Runs f on the worker runtime as a named job. It's listed until the
+    /// future returns. Has to be called from the worker runtime, for example
+    /// in `App::update`.
+    pub fn spawn<F, Fut>(&self, name : impl Into<String>, f : F) -> u64
+    where
+        F : FnOnce(Job) -> Fut,
+        Fut : Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_with_group(None, None, name.into(), f)
+    }
+
+    /// Like spawn, for work on the model of generation, like loading a
+    /// screen's thumbnails. The job is cancelled when that model is
+    /// replaced on screen by a newer one from `set_next`, so whatever an
+    /// update starts for a screen stops with it without keeping track.
+    pub fn spawn_for<F, Fut>(&self, generation : Generation, name : impl Into<String>, f : F) -> u64
+    where
+        F : FnOnce(Job) -> Fut,
+        Fut : Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_with_group(None, Some(generation), name.into(), f)
+    }
+
+    /// Like spawn, but first cancels the jobs in the same group. For work
+    /// that a newer job makes pointless, like loading a screen which is
+    /// being replaced.
+    pub fn spawn_in<F, Fut>(&self, group : &str, name : impl Into<String>, f : F) -> u64
+    where
+        F : FnOnce(Job) -> Fut,
+        Fut : Future<Output = ()> + Send + 'static,
+    {
+        self.cancel_group(group);
+        self.spawn_with_group(Some(group.to_string()), None, name.into(), f)
+    }
+
+    fn spawn_with_group<F, Fut>(
+        &self,
+        group : Option<String>,
+        generation : Option<Generation>,
+        name : String,
+        f : F,
+    ) -> u64
+    where
+        F : FnOnce(Job) -> Fut,
+        Fut : Future<Output = ()> + Send + 'static,
+    {
+        let during = format!("the job {}", name);
+        let state = Arc::new(JobState{
+            id : self.next_id.fetch_add(1, Ordering::Relaxed),
+            name,
+            group,
+            generation,
+            done : AtomicUsize::new(0),
+            total : AtomicUsize::new(0),
+            cancelled : AtomicBool::new(false),
+        });
+
+        let id = state.id;
+        self.jobs.lock_or_recover().push(state.clone());
+
+        let span = tracing::debug_span!("job", id, name = %state.name);
+        let waker = self.waker.clone();
+        let fut = f(Job{ state, waker : waker.clone(), pause : self.pause.clone() });
+        let jobs = self.jobs.clone();
+        let panics = self.panics.clone();
+        tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            tracing::debug!("started");
+            let res = catch_unwind(fut).await;
+            // a job that panicked isn't running anymore either
+            jobs.lock_or_recover().retain(|job| job.id != id);
+            waker.wake();
+            tracing::debug!(secs = start.elapsed().as_secs_f64(), "done");
+
+            if let Err(payload) = res {
+                panics.handle(Panic::new(during, payload));
+            }
+        }.instrument(span));
+
+        id
+    }
+
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        self.jobs.lock_or_recover().iter().map(|job| {
+            let total = job.total.load(Ordering::Relaxed);
+
+            JobStatus{
+                id : job.id,
+                name : job.name.clone(),
+                done : job.done.load(Ordering::Relaxed),
+                total : if total == 0 { None } else { Some(total) },
+                cancelled : job.cancelled.load(Ordering::Relaxed),
+            }
+        }).collect()
+    }
+
+    pub fn cancel(&self, id : u64) {
+        for job in self.jobs.lock_or_recover().iter().filter(|job| job.id == id) {
+            tracing::debug!(id, name = %job.name, "cancelled");
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn cancel_group(&self, group : &str) {
+        let jobs = self.jobs.lock_or_recover();
+        for job in jobs.iter().filter(|job| job.group.as_deref() == Some(group)) {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // the model on screen is now current, the jobs for the ones it replaced
+    // are cancelled
+    crate fn cancel_replaced(&self, current : Generation) {
+        let jobs = self.jobs.lock_or_recover();
+        let replaced = jobs.iter()
+            .filter(|job| job.generation.map_or(false, |g| g < current))
+            .filter(|job| !job.cancelled.load(Ordering::Relaxed));
+
+        for job in replaced {
+            tracing::debug!(id = job.id, name = %job.name, "cancelled with its model");
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn cancel_all(&self) {
+        for job in self.jobs.lock_or_recover().iter() {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// True while the window is minimized or the system is suspended, see
+    /// `Job::resumed`.
+    pub fn paused(&self) -> bool {
+        self.pause.paused.load(Ordering::Acquire)
+    }
+
+    crate fn set_paused(&self, paused : bool) {
+        self.pause.paused.store(paused, Ordering::Release);
+        if !paused {
+            self.pause.resumed.notify_waiters();
+        }
+    }
+
+    /// A window listing the running jobs with their progress.
+    pub fn show(&self, egui : &egui::CtxRef, open : &mut bool) {
+        let jobs = self.jobs();
+
+        egui::Window::new("Background Activity")
+            .open(open)
+            .default_width(300.0)
+            .show(egui, |ui| {
+                if jobs.is_empty() {
+                    ui.label("nothing running");
+                }
+
+                for job in jobs {
+                    ui.horizontal(|ui| {
+                        ui.label(&job.name);
+
+                        match job.total {
+                            Some(total) => ui.label(format!("{} / {}", job.done, total)),
+                            None => ui.label(format!("{}", job.done)),
+                        };
+
+                        if job.cancelled {
+                            ui.label("cancelling");
+                        } else if ui.small_button("cancel").clicked() {
+                            self.cancel(job.id);
+                        }
+                    });
+
+                    if let Some(total) = job.total {
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::Vec2{ x : ui.available_width(), y : 4.0 },
+                            egui::Sense::hover(),
+                        );
+                        let fraction = (job.done as f32 / total as f32).min(1.0);
+                        let mut filled = rect;
+                        filled.set_width(rect.width() * fraction);
+
+                        ui.painter().rect_filled(rect, 0.0, egui::Color32::from_gray(60));
+                        ui.painter().rect_filled(filled, 0.0, egui::Color32::LIGHT_BLUE);
+                    }
+                }
+            });
     }
 }
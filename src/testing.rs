@@ -0,0 +1,645 @@
+//! Runs an app's `update` without a window, for unit tests of message
+//! handlers and the model changes they make.
+//!
+//! `Harness::new` takes the app and its first model from the test, and
+//! `Harness::on_swap` stands in for `App::swap`. `Harness::init` instead
+//! runs `App::init` with a hidden window, whose GL context `render` and
+//! `swap` get, for tests of whole update and render cycles. Those need a
+//! display server, xvfb is enough.
+//!
+//! ```ignore
+//! let mut harness = Harness::new(app, Model::default());
+//! harness.send(Msg::Open(path));
+//! harness.pump();
+//! assert!(harness.take_errors().is_empty());
+//! assert_eq!(harness.model().photos.len(), 1);
+//!
+//! let mut harness = Harness::<Photos>::init()?;
+//! harness.pump();
+//! harness.render();
+//! assert!(harness.local_model().gallery.is_some());
+//! ```
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::rc::Rc;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use glium::glutin;
+use glium::glutin::event_loop::EventLoop;
+use tokio::runtime::Runtime;
+
+use crate::App;
+use crate::BufBuf;
+use crate::BufBufWrite;
+use crate::Cmd;
+use crate::DisplayProfile;
+use crate::Error;
+use crate::Formats;
+use crate::GraphicsCtx;
+use crate::InitCtx;
+use crate::JobManager;
+use crate::Logs;
+use crate::Middleware;
+use crate::Panic;
+use crate::RenderCtx;
+use crate::Settings;
+use crate::Stats;
+use crate::SwapCtx;
+use crate::Toasts;
+use crate::Waker;
+use crate::preview::Preview;
+use crate::recover::catch_unwind;
+use crate::slot::SwapSlot;
+use crate::utils::{
+    create_display,
+    WindowOptions,
+};
+
+// a message handler that keeps sending more is a bug, not a test that
+// should hang
+const MAX_STEPS : usize = 10_000;
+
+type Every<M> = Box<dyn FnMut() -> Option<M> + Send>;
+
+// what the run loop keeps around its window for init, render and swap
+struct Window<A : App> {
+    display : glium::Display,
+    egui_gl : egui_glium::EguiGlium,
+    gfx : GraphicsCtx,
+    local_model : A::LocalModel,
+    toasts : Toasts,
+    logs : Logs,
+    stats : Stats,
+    jobs : JobManager,
+    settings : Settings,
+    waker : Waker,
+    formats : Formats,
+    slots : Vec<Rc<dyn SwapSlot>>,
+    display_profile : DisplayProfile,
+    title : String,
+    icon : Option<glutin::window::Icon>,
+    // RenderCtx::set_preview's, never opened here
+    preview : Option<Preview>,
+    preview_monitor : Option<usize>,
+    exit_code : i32,
+    frame_count : u64,
+    start : Instant,
+    last_frame : Option<Instant>,
+    // after the display made with it
+    _event_loop : EventLoop<()>,
+}
+
+// tests run off the main thread, which winit only allows when asked
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn event_loop() -> EventLoop<()> {
+    use glutin::platform::unix::EventLoopExtUnix;
+
+    EventLoop::new_any_thread()
+}
+
+#[cfg(windows)]
+fn event_loop() -> EventLoop<()> {
+    use glutin::platform::windows::EventLoopExtWindows;
+
+    EventLoop::new_any_thread()
+}
+
+#[cfg(not(any(
+    windows,
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn event_loop() -> EventLoop<()> {
+    EventLoop::new()
+}
+
+/// A message pump like the framework's, on one thread. Messages are handled
+/// one at a time in the order they were sent, whatever their `Priority`,
+/// and patches are applied after each, so every run of a test is the same.
+pub struct Harness<A : App> {
+    app : &'static A,
+    buf : BufBuf<A::Model>,
+    write : BufBufWrite<A::Model>,
+    queue : VecDeque<A::Msg>,
+    // from Cmd::every, called by tick
+    polls : Vec<Every<A::Msg>>,
+    middleware : Vec<Box<dyn Middleware<A>>>,
+    swap : Box<dyn FnMut(&mut A::Model, &mut A::Model)>,
+    errors : Vec<A::Error>,
+    rt : Runtime,
+    // from init, None from new. last so the model's images go first
+    window : Option<Box<Window<A>>>,
+}
+
+impl<A : App + 'static> Harness<A> {
+    /// update takes a `&'static self`, so app is leaked, which is fine for
+    /// a test.
+    pub fn new(app : A, model : A::Model) -> Self {
+        Self::with_runtime(app, model, runtime())
+    }
+
+    /// Runs `App::init` with a hidden window, and queues its command.
+    /// `render` draws a frame into the window, and models replaced by
+    /// `set_next` go through `App::swap` instead of `on_swap`. Fails if
+    /// init does, or if there's no display to make the window on.
+    pub fn init() -> Result<Self, A::Error> {
+        Self::init_with(None)
+    }
+
+    /// Like init, with args for `InitCtx::args`, see `AppRunner::args`.
+    pub fn init_with_args<T : 'static>(args : T) -> Result<Self, A::Error> {
+        Self::init_with(Some(Box::new(args)))
+    }
+
+    fn init_with(args : Option<Box<dyn Any>>) -> Result<Self, A::Error> {
+        let event_loop = event_loop();
+        let options = WindowOptions{
+            app_id : A::name().to_string(),
+            drag_and_drop : false,
+            headless : true,
+        };
+        let display = create_display(A::name(), &options, None, &event_loop)
+            .map_err(|err| Error::from(std::io::Error::new(std::io::ErrorKind::Other, err.to_string())))?;
+
+        let mut egui_gl = egui_glium::EguiGlium::new(&display);
+        let mut gfx = GraphicsCtx::new(&display)?;
+        let toasts = Toasts::default();
+        let logs = Logs::default();
+        let waker = Waker::default();
+        let jobs = JobManager::new(waker.clone());
+        let settings = Settings::default();
+        let formats = Formats::builtin();
+        let mut slots = Vec::new();
+
+        let rt = runtime();
+        let (app, local_model, model, cmd) = {
+            let _guard = rt.enter();
+            let mut init_ctx = InitCtx{
+                gfx : &mut gfx,
+                display : &display,
+                egui_glium : egui_gl.ctx_and_painter_mut().1,
+                toasts : &toasts,
+                logs : &logs,
+                jobs : &jobs,
+                settings : &settings,
+                waker : &waker,
+                formats : &formats,
+                slots : &mut slots,
+                args,
+            };
+            A::init(&mut init_ctx)?
+        };
+
+        let mut harness = Self::with_runtime(app, model, rt);
+        harness.window = Some(Box::new(Window{
+            display,
+            egui_gl,
+            gfx,
+            local_model,
+            toasts,
+            logs,
+            stats : Stats::default(),
+            jobs,
+            settings,
+            waker,
+            formats,
+            slots,
+            display_profile : DisplayProfile::default(),
+            title : A::name().to_string(),
+            icon : None,
+            preview : None,
+            preview_monitor : None,
+            exit_code : 0,
+            frame_count : 0,
+            start : Instant::now(),
+            last_frame : None,
+            _event_loop : event_loop,
+        }));
+        harness.run(cmd);
+
+        Ok(harness)
+    }
+
+    fn with_runtime(app : A, model : A::Model, rt : Runtime) -> Self {
+        let buf = BufBuf::new(model);
+        let write = buf.new_write();
+
+        Harness{
+            app : Box::leak(Box::new(app)),
+            buf,
+            write,
+            queue : VecDeque::new(),
+            polls : Vec::new(),
+            middleware : Vec::new(),
+            swap : Box::new(|_, _| {}),
+            errors : Vec::new(),
+            rt,
+            window : None,
+        }
+    }
+
+    /// Runs on every message before update, like `AppRunner::middleware`.
+    pub fn middleware<M : Middleware<A> + 'static>(mut self, middleware : M) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Called with the old and the new model when update replaces it with
+    /// `set_next`, instead of `App::swap`. By default the old one is
+    /// dropped. Not used after `init`, which has `App::swap`.
+    pub fn on_swap<F>(mut self, f : F) -> Self
+    where F : FnMut(&mut A::Model, &mut A::Model) + 'static
+    {
+        self.swap = Box::new(f);
+        self
+    }
+
+    pub fn app(&self) -> &'static A {
+        self.app
+    }
+
+    /// What update gets, to replace or patch the model from the test.
+    pub fn write(&self) -> &BufBufWrite<A::Model> {
+        &self.write
+    }
+
+    /// The model with every change so far applied.
+    pub fn model(&mut self) -> &mut A::Model {
+        self.apply();
+        self.buf.get_mut()
+    }
+
+    /// What `App::init` made, and `render` changed since. Panics after
+    /// `new`, which has no window.
+    pub fn local_model(&mut self) -> &mut A::LocalModel {
+        let window = self.window.as_mut().expect("no window, see Harness::init");
+        &mut window.local_model
+    }
+
+    /// Queues msg, folded into the last queued message of its lane by
+    /// `App::coalesce` like the framework does.
+    pub fn send(&mut self, msg : A::Msg) {
+        let app = self.app;
+        let priority = app.priority(&msg);
+
+        let last = self.queue.iter_mut().rev().find(|queued| app.priority(queued) == priority);
+        let msg = match last {
+            Some(last) => app.coalesce(last, msg),
+            None => Some(msg),
+        };
+
+        if let Some(msg) = msg {
+            self.queue.push_back(msg);
+        }
+    }
+
+    /// Queues the messages of cmd, like one returned from `App::init` or
+    /// `App::render`. Its futures are run right away, `Cmd::after` waits
+    /// for real, and `Cmd::every` is only called by tick.
+    pub fn run(&mut self, cmd : Cmd<A::Msg>) {
+        let mut sent = Vec::new();
+        let polls = cmd.run_blocking(|msg| sent.push(msg), &self.rt);
+        self.polls.extend(polls);
+
+        for msg in sent {
+            self.send(msg);
+        }
+    }
+
+    /// The messages waiting for update, in the order they'll be handled.
+    pub fn queued(&self) -> impl Iterator<Item = &A::Msg> {
+        self.queue.iter()
+    }
+
+    /// Handles the next message, returns false if there wasn't one.
+    pub fn step(&mut self) -> bool {
+        let msg = match self.queue.pop_front() {
+            Some(msg) => msg,
+            None => return false,
+        };
+
+        let msg = match self.middleware.iter().try_fold(msg, |msg, m| m.on_msg(msg)) {
+            Some(msg) => msg,
+            None => return true,
+        };
+
+        let res = self.rt.block_on(catch_unwind(self.app.update(&self.write, msg)))
+            .unwrap_or_else(|payload| Err(Panic::new("handling a message", payload).into()));
+        self.apply();
+
+        match res {
+            Ok(cmd) => self.run(cmd),
+            Err(err) => self.errors.push(err),
+        }
+
+        true
+    }
+
+    /// Handles messages until there are none left, including the ones
+    /// they send. Returns how many were handled. Panics if they keep
+    /// coming, a loop of messages would otherwise hang the test.
+    pub fn pump(&mut self) -> usize {
+        let mut steps = 0;
+        while self.step() {
+            steps += 1;
+            assert!(steps < MAX_STEPS, "still handling messages after {} steps", MAX_STEPS);
+        }
+
+        steps
+    }
+
+    /// Calls each `Cmd::every` function once, as if its period passed, and
+    /// queues what they return. The ones that returned None are dropped.
+    pub fn tick(&mut self) {
+        let mut sent = Vec::new();
+        for mut poll in std::mem::take(&mut self.polls) {
+            if let Some(msg) = poll() {
+                sent.push(msg);
+                self.polls.push(poll);
+            }
+        }
+
+        for msg in sent {
+            self.send(msg);
+        }
+    }
+
+    /// Runs fut on the harness's runtime, which is also where jobs spawned
+    /// by update make progress, so awaiting something they send lets them
+    /// run.
+    pub fn block_on<F : Future>(&self, fut : F) -> F::Output {
+        self.rt.block_on(fut)
+    }
+
+    /// Applies the changes so far, then draws a frame with `App::render`
+    /// into the hidden window and queues what it returns. Subscriptions
+    /// aren't run, `tick` stands in for them. Panics after `new`, which
+    /// has no window.
+    pub fn render(&mut self) {
+        self.apply();
+
+        let window = self.window.as_mut().expect("no window to render in, see Harness::init");
+        let Window{
+            display, egui_gl, gfx, local_model, toasts, logs, stats, jobs, settings,
+            display_profile, title, icon, preview, preview_monitor, exit_code,
+            frame_count, start, last_frame, ..
+        } = &mut **window;
+
+        let now = Instant::now();
+        let dt = last_frame.map_or(Duration::from_secs(0), |last| now - last);
+        *last_frame = Some(now);
+
+        egui_gl.begin_frame(display);
+        let mut frame = display.draw();
+        let mut quit = false;
+        let mut renew = false;
+        let (egui_ctx, egui_painter) = egui_gl.ctx_and_painter_mut();
+
+        let mut render_ctx = RenderCtx {
+            egui : egui_ctx,
+            egui_glium : egui_painter,
+            gfx,
+            display,
+            frame : &mut frame,
+            quit : &mut quit,
+            renew : &mut renew,
+            exit_code,
+            background_input : None,
+            resized : false,
+            focused : true,
+            dropped_files : &[],
+            toasts,
+            logs,
+            stats,
+            jobs,
+            dt,
+            elapsed : now - *start,
+            frame_count : *frame_count,
+            display_profile,
+            scale_factor : display.gl_window().window().scale_factor() as f32,
+            title,
+            icon,
+            prefs : &mut settings.prefs,
+            preview,
+            preview_monitor,
+            widgets : None,
+        };
+
+        let cmd = {
+            let _guard = self.rt.enter();
+            self.app.render(&mut render_ctx, local_model, self.buf.get_mut())
+        };
+        if renew {
+            self.buf.renew();
+            jobs.cancel_replaced(self.buf.generation());
+        }
+        toasts.show(egui_ctx);
+        *frame_count += 1;
+
+        let (_, shapes) = egui_gl.end_frame(display);
+        egui_gl.paint(display, &mut frame, shapes);
+        if let Err(err) = frame.finish() {
+            self.errors.push(Error::from(err).into());
+        }
+
+        self.run(cmd);
+    }
+
+    /// The errors from update and from patches since the last call, which
+    /// the framework would have given to `App::handle_error`.
+    pub fn take_errors(&mut self) -> Vec<A::Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    // what the render thread does between frames
+    fn apply(&mut self) {
+        let window = match &mut self.window {
+            Some(window) => window,
+            None => {
+                let swap = &mut self.swap;
+                let panics = self.buf.swap(|old, new| swap(old, new));
+                self.errors.extend(panics.into_iter().map(A::Error::from));
+                return
+            },
+        };
+
+        let Window{ display, egui_gl, gfx, toasts, logs, jobs, settings, waker, formats, slots, .. } = &mut **window;
+        let app = self.app;
+        let swaps = self.buf.swap_count();
+        let panics = {
+            let _guard = self.rt.enter();
+            self.buf.swap(|old, new| {
+                let mut swap_ctx = SwapCtx{
+                    gfx,
+                    display,
+                    egui_glium : egui_gl.ctx_and_painter_mut().1,
+                    toasts,
+                    logs,
+                    jobs : &*jobs,
+                    settings,
+                    waker,
+                    formats,
+                    slots : &mut *slots,
+                    args : None,
+                };
+                app.swap(&mut swap_ctx, old, new)
+            })
+        };
+        if self.buf.swap_count() != swaps {
+            jobs.cancel_replaced(self.buf.generation());
+        }
+
+        let slot_panics = slots.iter().flat_map(|slot| slot.swap()).collect::<Vec<_>>();
+        self.errors.extend(panics.into_iter().chain(slot_panics).map(A::Error::from));
+    }
+}
+
+impl<A : App> std::fmt::Debug for Harness<A> {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Harness")
+            .field("queue", &self.queue)
+            .field("polls", &self.polls.len())
+            .field("errors", &self.errors)
+            .field("window", &self.window.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quick_from::QuickFrom;
+
+    use crate::async_trait;
+
+    #[derive(Debug)]
+    enum Msg {
+        Add(u32),
+        // replaces the model, for App::swap
+        Reset,
+        // sent by render
+        Rendered,
+    }
+
+    #[derive(Debug, Default)]
+    struct Model {
+        count : u32,
+        renders : u32,
+        swaps : u32,
+    }
+
+    #[derive(Debug, QuickFrom)]
+    enum TestError {
+        #[quick_from]
+        Panic(Panic),
+        #[quick_from]
+        Framework(Error),
+    }
+
+    // what each frame showed
+    #[derive(Debug, Default)]
+    struct Shown {
+        counts : Vec<u32>,
+    }
+
+    struct Counter;
+
+    #[async_trait]
+    impl App for Counter {
+        type Model = Model;
+        type LocalModel = Shown;
+        type Msg = Msg;
+        type Error = TestError;
+
+        fn name() -> &'static str {
+            "counter"
+        }
+
+        fn init(ctx : &mut InitCtx) -> Result<(Self, Shown, Model, Cmd<Msg>), TestError> {
+            let count = ctx.args().unwrap_or(0);
+            Ok((Counter, Shown::default(), Model{ count, ..Model::default() }, Cmd::msg(Msg::Add(1))))
+        }
+
+        fn render(&self, ctx : &mut RenderCtx, shown : &mut Shown, model : &mut Model) -> Cmd<Msg> {
+            egui::CentralPanel::default().show(ctx.egui, |ui| {
+                ui.label(model.count.to_string());
+            });
+            shown.counts.push(model.count);
+            Cmd::msg(Msg::Rendered)
+        }
+
+        fn swap(&self, _ctx : &mut SwapCtx, old : &mut Model, new : &mut Model) {
+            new.swaps = old.swaps + 1;
+        }
+
+        async fn update(&'static self, model : &BufBufWrite<Model>, msg : Msg) -> Result<Cmd<Msg>, TestError> {
+            match msg {
+                Msg::Add(n) => model.patch_current(move |model| model.count += n),
+                Msg::Reset => { model.set_next(Model::default()); },
+                Msg::Rendered => model.patch_current(|model| model.renders += 1),
+            }
+            Ok(Cmd::none())
+        }
+    }
+
+    #[test]
+    fn update_cycle() {
+        let mut harness = Harness::new(Counter, Model::default());
+        harness.send(Msg::Add(2));
+        harness.send(Msg::Add(3));
+        assert_eq!(harness.pump(), 2);
+        assert_eq!(harness.model().count, 5);
+
+        harness.send(Msg::Reset);
+        harness.pump();
+        assert!(harness.take_errors().is_empty());
+        // on_swap's default doesn't call App::swap
+        assert_eq!(harness.model().count, 0);
+        assert_eq!(harness.model().swaps, 0);
+    }
+
+    // needs a display, xvfb-run cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn render_cycle() {
+        let mut harness = Harness::<Counter>::init_with_args(4u32).unwrap();
+        // init's Add(1)
+        harness.pump();
+        harness.render();
+
+        harness.send(Msg::Reset);
+        harness.pump();
+        harness.render();
+        harness.pump();
+
+        assert!(harness.take_errors().is_empty());
+        assert_eq!(harness.local_model().counts, [5, 0]);
+        let model = harness.model();
+        assert_eq!(model.swaps, 1);
+        // the first frame's Rendered went with the reset, the second's
+        // is the one counted
+        assert_eq!(model.renders, 1);
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crate::MutexExt;
+
+/// How many toasts the activity log keeps.
+pub const LOG_LEN : usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// How long a toast stays up when it isn't given a timeout, errors stay
+    /// until dismissed.
+    pub fn timeout(&self) -> Option<Duration> {
+        match self {
+            Level::Info => Some(Duration::from_secs(5)),
+            Level::Warn => Some(Duration::from_secs(10)),
+            Level::Error => None,
+        }
+    }
+
+    fn color(&self) -> egui::Color32 {
+        match self {
+            Level::Info => egui::Color32::LIGHT_GRAY,
+            Level::Warn => egui::Color32::YELLOW,
+            Level::Error => egui::Color32::RED,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub level : Level,
+    pub text : String,
+    pub time : Instant,
+    /// None to stay up until dismissed
+    pub timeout : Option<Duration>,
+}
+
+impl Toast {
+    fn expired(&self) -> bool {
+        self.timeout.map_or(false, |timeout| self.time.elapsed() >= timeout)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    shown : Vec<Toast>,
+    log : VecDeque<Toast>,
+}
+
+/// Short messages for the user, drawn in the corner of the window after
+/// `App::render`. Cheap to clone and safe to push to from any thread, so an
+/// app can keep one from `InitCtx::toasts` and use it in `handle_error` and
+/// background jobs. Every toast also goes to an activity log, see `show_log`.
+#[derive(Debug, Clone, Default)]
+pub struct Toasts {
+    inner : Arc<Mutex<Inner>>,
+}
+
+impl Toasts {
+    pub fn push(&self, level : Level, text : impl Into<String>) {
+        self.push_for(level, text, level.timeout())
+    }
+
+    pub fn push_for(&self, level : Level, text : impl Into<String>, timeout : Option<Duration>) {
+        let toast = Toast{
+            level,
+            text : text.into(),
+            time : Instant::now(),
+            timeout,
+        };
+
+        let mut inner = self.inner.lock_or_recover();
+        if inner.log.len() >= LOG_LEN {
+            inner.log.pop_front();
+        }
+        inner.log.push_back(toast.clone());
+        inner.shown.push(toast);
+    }
+
+    pub fn info(&self, text : impl Into<String>) {
+        self.push(Level::Info, text)
+    }
+
+    pub fn warn(&self, text : impl Into<String>) {
+        self.push(Level::Warn, text)
+    }
+
+    pub fn error(&self, text : impl Into<String>) {
+        self.push(Level::Error, text)
+    }
+
+    /// Every toast still in the log, oldest first.
+    pub fn log(&self) -> Vec<Toast> {
+        self.inner.lock_or_recover().log.iter().cloned().collect()
+    }
+
+    pub fn clear_log(&self) {
+        self.inner.lock_or_recover().log.clear();
+    }
+
+    /// A window with the activity log, newest first.
+    pub fn show_log(&self, egui : &egui::CtxRef, open : &mut bool) {
+        let mut clear = false;
+
+        egui::Window::new("Activity")
+            .open(open)
+            .default_width(400.0)
+            .show(egui, |ui| {
+                clear = ui.button("clear").clicked();
+                ui.separator();
+
+                egui::ScrollArea::auto_sized().show(ui, |ui| {
+                    let inner = self.inner.lock_or_recover();
+                    for toast in inner.log.iter().rev() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}s ago", toast.time.elapsed().as_secs()));
+                            ui.colored_label(toast.level.color(), &toast.text);
+                        });
+                    }
+                });
+            });
+
+        if clear {
+            self.clear_log();
+        }
+    }
+
+    crate fn show(&self, egui : &egui::CtxRef) {
+        let mut inner = self.inner.lock_or_recover();
+        inner.shown.retain(|t| !t.expired());
+
+        if inner.shown.is_empty() {
+            return
+        }
+
+        let mut dismissed = None;
+
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+            .order(egui::Order::Foreground)
+            .show(egui, |ui| {
+                for (idx, toast) in inner.shown.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(toast.level.color(), &toast.text);
+
+                            if ui.small_button("x").clicked() {
+                                dismissed = Some(idx);
+                            }
+                        });
+                    });
+                }
+            });
+
+        if let Some(idx) = dismissed {
+            inner.shown.remove(idx);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use glium::glutin;
 
+use crate::WindowGeometry;
+
 crate struct UniformsCons<'a, X, Xs> {
     crate name : &'a str,
     crate value : X,
@@ -19,20 +21,181 @@ where
 
 
 
-crate fn create_display(title : &str, event_loop: &glutin::event_loop::EventLoop<()>) -> glium::Display {
+// desktop integration, see AppRunner
+crate struct WindowOptions {
+    // wayland app_id and x11 WM_CLASS, should match the .desktop file
+    #[cfg_attr(any(windows, target_os = "macos"), allow(dead_code))]
+    crate app_id : String,
+    // only changes anything on windows, where it has to be off for the
+    // window to use OLE, for example from a native clipboard library
+    #[cfg_attr(not(windows), allow(dead_code))]
+    crate drag_and_drop : bool,
+    // the window is never shown, and the context can be a software one
+    // for machines without a gpu, see AppRunner::headless
+    crate headless : bool,
+}
+
+// geometry is where the window was last run, see Settings. without a window
+// there's nowhere to show the error, the caller prints it
+crate fn create_display<T>(
+    title : &str,
+    options : &WindowOptions,
+    geometry : Option<&WindowGeometry>,
+    event_loop: &glutin::event_loop::EventLoopWindowTarget<T>)
+    -> Result<glium::Display, glium::backend::glutin::DisplayCreationError>
+{
+    let window_builder = window_builder(title, options);
+    let window_builder = match geometry {
+        Some(geometry) => {
+            let builder = window_builder
+                .with_inner_size(glutin::dpi::PhysicalSize::new(geometry.width, geometry.height))
+                .with_maximized(geometry.maximized);
+
+            match geometry.position {
+                Some((x, y)) => builder.with_position(glutin::dpi::PhysicalPosition::new(x, y)),
+                None => builder,
+            }
+        },
+        None => {
+            window_builder.with_inner_size(glutin::dpi::LogicalSize {
+                width: 800.0,
+                height: 600.0,
+            })
+        },
+    };
+
+    let display = glium::Display::new(window_builder, context_builder(options), event_loop)?;
+    // the monitor it was on may be gone
+    keep_on_screen(display.gl_window().window());
+    Ok(display)
+}
+
+// a new window and context where old's window was, for when the context is
+// lost. None if it can't be made either
+crate fn recreate_display<T>(
+    old : &glium::Display,
+    title : &str,
+    options : &WindowOptions,
+    event_loop: &glutin::event_loop::EventLoopWindowTarget<T>) -> Option<glium::Display>
+{
+    let builder = {
+        let gl_window = old.gl_window();
+        let window = gl_window.window();
+
+        let builder = window_builder(title, options)
+            .with_inner_size(window.inner_size())
+            .with_fullscreen(window.fullscreen());
+
+        match window.outer_position() {
+            Ok(pos) => builder.with_position(pos),
+            Err(_) => builder,
+        }
+    };
+
+    match glium::Display::new(builder, context_builder(options), event_loop) {
+        Ok(display) => Some(display),
+        Err(err) => {
+            tracing::error!("could not recreate the window: {}", err);
+            None
+        },
+    }
+}
+
+// a borderless fullscreen window on monitor whose context shares textures
+// with shared's, see Preview
+crate fn create_preview_display<T>(
+    shared : &glium::Display,
+    title : &str,
+    monitor : glutin::monitor::MonitorHandle,
+    options : &WindowOptions,
+    event_loop: &glutin::event_loop::EventLoopWindowTarget<T>)
+    -> Result<glium::Display, glium::backend::glutin::DisplayCreationError>
+{
+    let builder = window_builder(title, options)
+        .with_decorations(false)
+        .with_fullscreen(Some(glutin::window::Fullscreen::Borderless(Some(monitor))));
+
+    let gl_window = shared.gl_window();
+    let context = context_builder(options).with_shared_lists(gl_window.context());
+    glium::Display::new(builder, context, event_loop)
+}
+
+fn window_builder(title : &str, options : &WindowOptions) -> glutin::window::WindowBuilder {
     let window_builder = glutin::window::WindowBuilder::new()
         .with_resizable(true)
-        .with_inner_size(glutin::dpi::LogicalSize {
-            width: 800.0,
-            height: 600.0,
-        })
+        .with_visible(!options.headless)
         .with_title(title);
 
-    let context_builder = glutin::ContextBuilder::new()
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    let window_builder = {
+        use glutin::platform::unix::WindowBuilderExtUnix;
+
+        window_builder
+            .with_app_id(options.app_id.clone())
+            .with_class(options.app_id.clone(), options.app_id.clone())
+    };
+
+    #[cfg(windows)]
+    let window_builder = {
+        use glutin::platform::windows::WindowBuilderExtWindows;
+
+        window_builder.with_drag_and_drop(options.drag_and_drop)
+    };
+
+    window_builder
+}
+
+fn context_builder(options : &WindowOptions) -> glutin::ContextBuilder<'static, glutin::NotCurrent> {
+    // without robustness a driver reset can leave the context unusable
+    // without it reporting itself lost, see AppRunner's recreate_display
+    let builder = glutin::ContextBuilder::new()
         .with_depth_buffer(0)
         .with_srgb(true)
         .with_stencil_buffer(0)
-        .with_vsync(true);
+        .with_gl_robustness(glutin::Robustness::TryRobustLoseContextOnReset);
 
-    glium::Display::new(window_builder, context_builder, event_loop).unwrap()
+    if options.headless {
+        // nothing is shown so there's nothing to wait for, and llvmpipe
+        // and the like are fine
+        builder
+            .with_vsync(false)
+            .with_hardware_acceleration(None)
+    } else {
+        builder.with_vsync(true)
+    }
+}
+
+/// Moves the window to the primary monitor if its center is not on any
+/// monitor, which happens when the display it was on is unplugged.
+crate fn keep_on_screen(window : &glutin::window::Window) {
+    let pos = match window.outer_position() {
+        Ok(pos) => pos,
+        Err(_) => return,
+    };
+
+    let size = window.outer_size();
+    let (cx, cy) = (
+        pos.x + size.width as i32 / 2,
+        pos.y + size.height as i32 / 2,
+    );
+
+    let on_screen = window.available_monitors().any(|monitor| {
+        let mpos = monitor.position();
+        let msize = monitor.size();
+
+        cx >= mpos.x && cx < mpos.x + msize.width as i32 &&
+            cy >= mpos.y && cy < mpos.y + msize.height as i32
+    });
+
+    if !on_screen {
+        if let Some(monitor) = window.primary_monitor() {
+            window.set_outer_position(monitor.position());
+        }
+    }
 }
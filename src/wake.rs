@@ -0,0 +1,72 @@
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use glium::glutin::event_loop::EventLoopProxy;
+
+use crate::MutexExt;
+
+// what the event loop gets from other threads
+crate enum LoopEvent<M> {
+    Msg(M),
+    // the model changed, see Waker
+    Wake,
+}
+
+struct Inner {
+    // EventLoopProxy isn't Sync everywhere
+    send : Mutex<Box<dyn Fn() + Send>>,
+    // a wake was sent and the frame for it hasn't started
+    pending : AtomicBool,
+}
+
+/// Asks the render thread for a frame, from any thread, like for a new
+/// model or a patch from `BufBufWrite`. Wakes before the next frame starts
+/// are folded into one. The default one does nothing.
+#[derive(Clone, Default)]
+pub struct Waker {
+    inner : Option<Arc<Inner>>,
+}
+
+impl std::fmt::Debug for Waker {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Waker")
+    }
+}
+
+impl Waker {
+    crate fn new<M : Send + 'static>(proxy : EventLoopProxy<LoopEvent<M>>) -> Self {
+        let send = move || {
+            // the window closed
+            let _ = proxy.send_event(LoopEvent::Wake);
+        };
+
+        Waker {
+            inner : Some(Arc::new(Inner{
+                send : Mutex::new(Box::new(send)),
+                pending : AtomicBool::new(false),
+            })),
+        }
+    }
+
+    pub fn wake(&self) {
+        if let Some(inner) = &self.inner {
+            if !inner.pending.swap(true, Ordering::AcqRel) {
+                (*inner.send.lock_or_recover())();
+            }
+        }
+    }
+
+    // on the render thread as a frame starts, changes after this need
+    // another one
+    crate fn frame_started(&self) {
+        if let Some(inner) = &self.inner {
+            inner.pending.store(false, Ordering::Release);
+        }
+    }
+}
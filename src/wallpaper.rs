@@ -0,0 +1,144 @@
+// setting the desktop's wallpaper, through each platform's own tool since
+// none of them has a library for it. the image has to stay where it is, the
+// desktop reads it from there
+
+use std::path::Path;
+use std::process::Command;
+
+pub trait Wallpaper : Send + Sync {
+    /// For errors, like "gnome".
+    fn name(&self) -> &'static str;
+
+    /// Blocks until it's set, run it on a worker.
+    fn set(&self, path : &Path) -> std::io::Result<()>;
+}
+
+fn run(name : &str, command : &mut Command) -> std::io::Result<()> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("setting the {} wallpaper failed with {}", name, status),
+        ))
+    }
+}
+
+/// Every desktop, through System Events.
+struct MacOs;
+
+impl Wallpaper for MacOs {
+    fn name(&self) -> &'static str {
+        "macOS"
+    }
+
+    fn set(&self, path : &Path) -> std::io::Result<()> {
+        let script = format!(
+            "tell application \"System Events\" to tell every desktop to set picture to \"{}\"",
+            path.display().to_string().replace('\\', "\\\\").replace('"', "\\\""),
+        );
+        run(self.name(), Command::new("osascript").arg("-e").arg(script))
+    }
+}
+
+/// SystemParametersInfo, from PowerShell so there's no winapi to build.
+struct Windows;
+
+impl Wallpaper for Windows {
+    fn name(&self) -> &'static str {
+        "Windows"
+    }
+
+    fn set(&self, path : &Path) -> std::io::Result<()> {
+        // SPI_SETDESKWALLPAPER, saved and broadcast
+        let script = format!(
+            concat!(
+                "Add-Type -TypeDefinition 'using System.Runtime.InteropServices; public class Wallpaper {{ ",
+                "[DllImport(\"user32.dll\", CharSet = CharSet.Unicode)] ",
+                "public static extern int SystemParametersInfo(int action, int param, string file, int flags); }}'; ",
+                "exit [int]([Wallpaper]::SystemParametersInfo(20, 0, '{}', 3) -eq 0)",
+            ),
+            path.display().to_string().replace('\'', "''"),
+        );
+        run(self.name(), Command::new("powershell").args(&["-NoProfile", "-Command", &script]))
+    }
+}
+
+/// GNOME and the desktops built on it, for the light and dark styles.
+struct Gnome;
+
+impl Wallpaper for Gnome {
+    fn name(&self) -> &'static str {
+        "GNOME"
+    }
+
+    fn set(&self, path : &Path) -> std::io::Result<()> {
+        let uri = format!("file://{}", path.display());
+        run(self.name(), Command::new("gsettings").args(&["set", "org.gnome.desktop.background", "picture-uri", &uri]))?;
+        // only there since GNOME 42, so it may fail
+        let _ = Command::new("gsettings")
+            .args(&["set", "org.gnome.desktop.background", "picture-uri-dark", &uri])
+            .status();
+        Ok(())
+    }
+}
+
+/// Plasma, every desktop, through its scripting interface.
+struct Kde;
+
+impl Wallpaper for Kde {
+    fn name(&self) -> &'static str {
+        "KDE"
+    }
+
+    fn set(&self, path : &Path) -> std::io::Result<()> {
+        let script = format!(
+            concat!(
+                "desktops().forEach(d => {{ d.wallpaperPlugin = 'org.kde.image'; ",
+                "d.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General']; ",
+                "d.writeConfig('Image', 'file://{}'); }})",
+            ),
+            path.display().to_string().replace('\\', "\\\\").replace('\'', "\\'"),
+        );
+        run(self.name(), Command::new("qdbus").args(&[
+            "org.kde.plasmashell",
+            "/PlasmaShell",
+            "org.kde.PlasmaShell.evaluateScript",
+            &script,
+        ]))
+    }
+}
+
+/// Window managers without a desktop of their own, with feh.
+struct Feh;
+
+impl Wallpaper for Feh {
+    fn name(&self) -> &'static str {
+        "feh"
+    }
+
+    fn set(&self, path : &Path) -> std::io::Result<()> {
+        run(self.name(), Command::new("feh").arg("--bg-fill").arg(path))
+    }
+}
+
+/// The one for this platform and, on Linux and the BSDs, the desktop
+/// running.
+pub fn backend() -> Box<dyn Wallpaper> {
+    if cfg!(target_os = "macos") {
+        return Box::new(MacOs)
+    }
+    if cfg!(target_os = "windows") {
+        return Box::new(Windows)
+    }
+
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().to_lowercase();
+    if desktop.contains("kde") {
+        Box::new(Kde)
+    } else if ["gnome", "unity", "budgie", "pantheon"].iter().any(|name| desktop.contains(name)) {
+        Box::new(Gnome)
+    } else {
+        Box::new(Feh)
+    }
+}
@@ -0,0 +1,81 @@
+// notices when the render thread is stuck in the driver, which otherwise
+// looks like a frozen window with nothing in the logs
+
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crate::MutexExt;
+
+struct State {
+    // what the render thread is doing and since when, None between frames
+    stage : Mutex<Option<(&'static str, Instant)>>,
+    // set by the watchdog thread once per stuck stage
+    stalled : Mutex<Option<&'static str>>,
+}
+
+crate struct Watchdog {
+    state : Arc<State>,
+}
+
+impl Watchdog {
+    // driver is logged along with stalls, like the GL renderer string
+    crate fn new(threshold : Duration, driver : String) -> Self {
+        let state = Arc::new(State{
+            stage : Mutex::new(None),
+            stalled : Mutex::new(None),
+        });
+
+        let weak = Arc::downgrade(&state);
+        std::thread::Builder::new()
+            .name("render watchdog".to_string())
+            .spawn(move || {
+                // the stage last reported, to only log it once
+                let mut reported = None;
+
+                loop {
+                    std::thread::sleep(threshold / 4);
+
+                    let state = match weak.upgrade() {
+                        Some(state) => state,
+                        None => return,
+                    };
+
+                    let stage = *state.stage.lock_or_recover();
+                    match stage {
+                        Some((name, since)) if since.elapsed() > threshold => {
+                            if reported != Some(since) {
+                                tracing::warn!(
+                                    "render thread stuck in {} for {:?} on {}",
+                                    name, since.elapsed(), driver,
+                                );
+                                reported = Some(since);
+                                *state.stalled.lock_or_recover() = Some(name);
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            })
+            .unwrap();
+
+        Watchdog {
+            state,
+        }
+    }
+
+    crate fn enter(&self, stage : &'static str) {
+        *self.state.stage.lock_or_recover() = Some((stage, Instant::now()));
+    }
+
+    // the stage which got stuck since the last call, if one did
+    crate fn leave(&self) -> Option<&'static str> {
+        *self.state.stage.lock_or_recover() = None;
+        self.state.stalled.lock_or_recover().take()
+    }
+}
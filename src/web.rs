@@ -0,0 +1,161 @@
+// photos opened from http(s) links, pasted, typed into Open, or dropped as
+// the shortcut files browsers and desktops make of links. they're downloaded
+// with curl, like remote folders are, into the data dir and opened from
+// there, so a link opened again is only downloaded again if it changed, and
+// the copy is opened, offline, if the link can't be reached
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use crate::library;
+use crate::remote::{
+    curl,
+    Fetched,
+};
+
+pub fn is_url(text : &str) -> bool {
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+/// A Windows .url or a freedesktop .desktop link, by its name.
+pub fn is_shortcut(path : &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "url" || ext == "desktop")
+}
+
+/// The link in a shortcut's URL= line.
+pub fn shortcut_url(contents : &str) -> Option<String> {
+    contents.lines()
+        .filter_map(|line| line.trim().strip_prefix("URL="))
+        .map(str::trim)
+        .find(|url| is_url(url))
+        .map(str::to_string)
+}
+
+fn cache_dir() -> PathBuf {
+    library::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("web")
+}
+
+// 64 bit FNV-1a, for the cache's names. unlike DefaultHasher it's the same
+// in every build, so links keep their copies across updates
+fn fnv1a(byt : &[u8]) -> u64 {
+    const OFFSET : u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME : u64 = 0x0000_0100_0000_01b3;
+
+    byt.iter().fold(OFFSET, |hash, b| (hash ^ *b as u64).wrapping_mul(PRIME))
+}
+
+// the extension of the url's last path segment, without the query
+fn url_extension(url : &str) -> Option<String> {
+    let path = url.split(|c| c == '?' || c == '#').next()?;
+    let (_, name) = path.rsplit_once('/')?;
+    let (_, ext) = name.rsplit_once('.')?;
+    Some(ext.to_lowercase()).filter(|ext| !ext.is_empty() && ext.len() <= 5)
+}
+
+/// The photo at url, downloaded unless the copy from before is as new, at
+/// most rate kilobytes a second. Files over max_size bytes aren't
+/// downloaded. If the download fails the copy is used, offline. Blocks, run
+/// it on a worker.
+pub fn fetch(url : &str, max_size : u64, rate : Option<u32>) -> std::io::Result<Fetched<PathBuf>> {
+    let key = format!("{:016x}", fnv1a(url.as_bytes()));
+
+    // the extension is only known after the download, so it's looked for
+    let dir = cache_dir();
+    let cached = std::fs::read_dir(&dir).ok().and_then(|entries| {
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                let stem = path.file_stem().map_or(false, |stem| stem == key.as_str());
+                stem && path.extension().map_or(false, |ext| ext != "part")
+            })
+    });
+
+    std::fs::create_dir_all(&dir)?;
+    let part = dir.join(format!("{}.part", key));
+    // from a download that didn't finish, it'd be taken for a new one
+    let _ = std::fs::remove_file(&part);
+    let mut command = curl(rate);
+    // -R keeps the server's time on the copy for -z
+    command.arg("-sSfLR");
+    // unlimited is u64::MAX, more than curl takes
+    if max_size < i64::MAX as u64 {
+        command.args(&["--max-filesize", &max_size.to_string()]);
+    }
+    // only downloaded if it changed since the copy, otherwise curl doesn't
+    // make part
+    if let Some(cached) = &cached {
+        command.arg("-z").arg(cached);
+    }
+    let res = command.arg("-o").arg(&part).arg(url).output().and_then(|output| {
+        if output.status.success() {
+            return Ok(())
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("downloading {}: {}", url, stderr.lines().last().unwrap_or("failed")),
+        ))
+    });
+
+    let replaced = match (res, cached) {
+        (Err(err), Some(cached)) => {
+            let _ = std::fs::remove_file(&part);
+            tracing::warn!("opening the copy of {}: {}", url, err);
+            return Ok(Fetched{ value : cached, offline : true })
+        },
+        (Err(err), None) => {
+            let _ = std::fs::remove_file(&part);
+            return Err(err)
+        },
+        (Ok(()), Some(cached)) if !part.exists() => return Ok(Fetched{ value : cached, offline : false }),
+        (Ok(()), cached) => cached,
+    };
+
+    // links often don't say what they are, like a cdn's ?format=jpg, so the
+    // file does
+    let mut head = vec![0; 64];
+    let read = std::io::Read::read(&mut std::fs::File::open(&part)?, &mut head)?;
+    let ext = image::guess_format(&head[..read])
+        .ok()
+        .and_then(|format| format.extensions_str().first().map(|ext| ext.to_string()))
+        .or_else(|| url_extension(url));
+    let ext = match ext {
+        Some(ext) => ext,
+        None => {
+            let _ = std::fs::remove_file(&part);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} isn't a photo", url)))
+        },
+    };
+
+    let path = dir.join(format!("{}.{}", key, ext));
+    std::fs::rename(&part, &path)?;
+    // the copy from before, if the link is another format now
+    if let Some(replaced) = replaced.filter(|replaced| *replaced != path) {
+        let _ = std::fs::remove_file(replaced);
+    }
+    Ok(Fetched{ value : path, offline : false })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_known() {
+        // from the FNV reference's test vectors
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn url_extensions() {
+        assert_eq!(url_extension("https://example.com/a/photo.JPG?w=800#top").as_deref(), Some("jpg"));
+        assert_eq!(url_extension("https://example.com/image?format=png"), None);
+        assert_eq!(url_extension("https://example.com/archive.tar.gzipped"), None);
+    }
+}
@@ -0,0 +1,44 @@
+// white balance presets for the light a photo was taken in, with the
+// temperature and tint lightroom uses for them so they match imported
+// sidecars, and which of them the camera recorded
+
+use std::ops::RangeInclusive;
+
+/// Of the temperature slider, in Kelvin.
+pub const TEMPERATURE : RangeInclusive<f32> = 2000.0..=10000.0;
+/// Of the tint slider, in lightroom's units, positive is magenta.
+pub const TINT : RangeInclusive<f32> = -150.0..=150.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Illuminant {
+    pub name : &'static str,
+    pub temperature : f32,
+    pub tint : f32,
+}
+
+pub const DAYLIGHT : Illuminant = Illuminant{ name : "daylight", temperature : 5500., tint : 10. };
+pub const CLOUDY : Illuminant = Illuminant{ name : "cloudy", temperature : 6500., tint : 10. };
+pub const SHADE : Illuminant = Illuminant{ name : "shade", temperature : 7500., tint : 10. };
+pub const TUNGSTEN : Illuminant = Illuminant{ name : "tungsten", temperature : 2850., tint : 0. };
+pub const FLUORESCENT : Illuminant = Illuminant{ name : "fluorescent", temperature : 3800., tint : 21. };
+pub const FLASH : Illuminant = Illuminant{ name : "flash", temperature : 5500., tint : 0. };
+
+pub const ILLUMINANTS : &[Illuminant] = &[DAYLIGHT, CLOUDY, SHADE, TUNGSTEN, FLUORESCENT, FLASH];
+
+/// The preset for an exif LightSource, None for unknown and other.
+pub fn from_light_source(light_source : u32) -> Option<Illuminant> {
+    match light_source {
+        // fine weather and the D50 and D55 standard illuminants
+        1 | 9 | 20 | 23 => Some(DAYLIGHT),
+        // cloudy weather and D65
+        10 | 21 => Some(CLOUDY),
+        // shade and D75
+        11 | 22 => Some(SHADE),
+        // tungsten, standard illuminant A and iso studio tungsten
+        3 | 17 | 24 => Some(TUNGSTEN),
+        // fluorescent and its daylight, white and cool white kinds
+        2 | 12..=15 => Some(FLUORESCENT),
+        4 => Some(FLASH),
+        _ => None,
+    }
+}
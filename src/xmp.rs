@@ -0,0 +1,398 @@
+// .xmp sidecars from lightroom, darktable, and apple photos' exports.
+// there's no xml parser here, the few fields we want are found by name,
+// both written as attributes (xmp:Rating="3") and as elements
+// (<xmp:Rating>3</xmp:Rating>)
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use photos1::Effects;
+
+use crate::white_balance;
+
+// darktable's color labels, by number
+const DARKTABLE_LABELS : &[&str] = &["Red", "Yellow", "Green", "Blue", "Purple"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sidecar {
+    /// 1 to 5, -1 for rejected
+    pub rating : Option<i8>,
+    /// Like Red or Green
+    pub label : Option<String>,
+    pub keywords : Vec<String>,
+    /// The caption, or the title if there's no caption
+    pub description : Option<String>,
+    /// When the photo was taken, as written, like 2021-06-03T14:22:10
+    pub date : Option<String>,
+    /// Lightroom's basic panel, as close as the effects get. Darktable's
+    /// history is binary per module and isn't read.
+    pub effects : Option<Effects>,
+}
+
+/// The sidecar next to path. Darktable writes photo.jpg.xmp, most
+/// everything else photo.xmp.
+pub async fn find(path : &Path) -> Option<PathBuf> {
+    let mut long = path.as_os_str().to_os_string();
+    long.push(".xmp");
+
+    for candidate in [PathBuf::from(long), path.with_extension("xmp")] {
+        if tokio::fs::metadata(&candidate).await.is_ok() {
+            return Some(candidate)
+        }
+    }
+
+    None
+}
+
+impl Sidecar {
+    pub fn parse(text : &str) -> Self {
+        let rating = value(text, "xmp:Rating")
+            .and_then(|r| r.parse::<f32>().ok())
+            .map(|r| r.round().max(-1.).min(5.) as i8)
+            .filter(|r| *r != 0);
+
+        let label = value(text, "xmp:Label")
+            .filter(|l| !l.is_empty())
+            .or_else(|| {
+                list(text, "darktable:colorlabels")
+                    .first()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .and_then(|n| DARKTABLE_LABELS.get(n))
+                    .map(|l| l.to_string())
+            });
+
+        // lightroom's hierarchical keywords are also flattened into
+        // dc:subject, so that's all that's read
+        let mut keywords = list(text, "dc:subject");
+        keywords.dedup();
+
+        // both are language alternatives, the first is the default
+        let description = list(text, "dc:description").into_iter().next()
+            .or_else(|| list(text, "dc:title").into_iter().next());
+
+        // apple photos writes photoshop:DateCreated
+        let date = ["photoshop:DateCreated", "exif:DateTimeOriginal", "xmp:CreateDate"]
+            .iter()
+            .find_map(|name| value(text, name))
+            .filter(|d| !d.is_empty());
+
+        Sidecar {
+            rating,
+            label,
+            keywords,
+            description,
+            date,
+            effects : lightroom_effects(text),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Sidecar::default()
+    }
+}
+
+fn number(text : &str, name : &str) -> Option<f32> {
+    // lightroom writes +0.50 for positive values
+    value(text, name)?.trim_start_matches('+').parse().ok()
+}
+
+// the sliders are -100 to 100, centered on 0.5 here
+fn slider(text : &str, name : &str) -> Option<f32> {
+    number(text, name).map(|v| (0.5 + v / 200.).max(0.).min(1.))
+}
+
+fn lightroom_effects(text : &str) -> Option<Effects> {
+    let exposure = number(text, "crs:Exposure2012");
+    let contrast = slider(text, "crs:Contrast2012");
+    let highlight = slider(text, "crs:Highlights2012");
+    let shadow = slider(text, "crs:Shadows2012");
+    let whites = number(text, "crs:Whites2012");
+    let blacks = number(text, "crs:Blacks2012");
+    let temperature = number(text, "crs:Temperature");
+    let tint = number(text, "crs:Tint");
+    let crop = if value(text, "crs:HasCrop").as_deref() == Some("True") {
+        let side = |name| number(text, name);
+        match (side("crs:CropLeft"), side("crs:CropTop"), side("crs:CropRight"), side("crs:CropBottom")) {
+            // the crop angle isn't supported
+            (Some(l), Some(t), Some(r), Some(b)) if r > l && b > t => Some([l, t, r - l, b - t]),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let found = [exposure, contrast, highlight, shadow, whites, blacks, temperature, tint]
+        .iter()
+        .any(Option::is_some);
+    if !found && crop.is_none() {
+        return None
+    }
+
+    let mut effects = Effects::default();
+    if let Some(ev) = exposure {
+        effects.brightness = (ev / 10.).max(-0.5).min(0.5);
+    }
+    effects.contrast = contrast.unwrap_or(effects.contrast);
+    effects.highlight = highlight.unwrap_or(effects.highlight);
+    effects.shadow = shadow.unwrap_or(effects.shadow);
+    // brighter whites pull the white point down, darker blacks push the
+    // black point up
+    if let Some(w) = whites {
+        effects.white_pt = (1. - w / 200.).max(0.5).min(1.);
+    }
+    if let Some(b) = blacks {
+        effects.black_pt = (-b / 200.).max(0.).min(0.5);
+    }
+    if let Some(k) = temperature {
+        effects.temperature = k.max(*white_balance::TEMPERATURE.start()).min(*white_balance::TEMPERATURE.end());
+    }
+    if let Some(t) = tint {
+        effects.tint = t.max(*white_balance::TINT.start()).min(*white_balance::TINT.end());
+    }
+    effects.crop = crop.unwrap_or(effects.crop);
+
+    Some(effects)
+}
+
+// the value of an attribute or of a single element
+fn value(text : &str, name : &str) -> Option<String> {
+    let attr = format!("{}=\"", name);
+    if let Some(start) = text.find(&attr).map(|i| i + attr.len()) {
+        let end = text[start..].find('"')? + start;
+        return Some(unescape(&text[start..end]))
+    }
+
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let start = text.find(&open)? + open.len();
+    let end = text[start..].find(&close)? + start;
+    Some(unescape(text[start..end].trim()))
+}
+
+// the <rdf:li> items of a bag or seq element
+fn list(text : &str, name : &str) -> Vec<String> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let inner = match text.find(&open) {
+        Some(i) => {
+            let start = i + open.len();
+            match text[start..].find(&close) {
+                Some(end) => &text[start..start + end],
+                None => return Vec::new(),
+            }
+        },
+        None => return Vec::new(),
+    };
+
+    inner.split("<rdf:li")
+        .skip(1)
+        .filter_map(|item| {
+            // skip the rest of the tag, like xml:lang="x-default"
+            let start = item.find('>')? + 1;
+            let end = item.find("</rdf:li>")?;
+            Some(unescape(item.get(start..end)?.trim()))
+        })
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn unescape(s : &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(s : &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// the jpeg segment xmp goes in starts with this
+const XMP_NAMESPACE : &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+// a packet with only the caption, which Sidecar::parse reads back
+fn description_packet(description : &str) -> String {
+    format!(concat!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+        " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+        "  <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n",
+        "   <dc:description>\n",
+        "    <rdf:Alt>\n",
+        "     <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n",
+        "    </rdf:Alt>\n",
+        "   </dc:description>\n",
+        "  </rdf:Description>\n",
+        " </rdf:RDF>\n",
+        "</x:xmpmeta>\n",
+        "<?xpacket end=\"w\"?>"),
+        escape(description))
+}
+
+// the jpeg with an xmp segment after SOI and JFIF's APP0, None if it isn't
+// a jpeg or the packet doesn't fit in a segment
+fn embed_jpeg(byt : &[u8], packet : &str) -> Option<Vec<u8>> {
+    if !byt.starts_with(&[0xFF, 0xD8]) {
+        return None
+    }
+
+    // the length counts itself
+    let len = 2 + XMP_NAMESPACE.len() + packet.len();
+    if len > u16::MAX as usize {
+        return None
+    }
+
+    let mut at = 2;
+    if byt.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let app0 = u16::from_be_bytes([*byt.get(4)?, *byt.get(5)?]) as usize;
+        at += 2 + app0;
+    }
+
+    let mut out = Vec::with_capacity(byt.len() + len + 2);
+    out.extend_from_slice(byt.get(..at)?);
+    out.extend_from_slice(&[0xFF, 0xE1]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    out.extend_from_slice(XMP_NAMESPACE);
+    out.extend_from_slice(packet.as_bytes());
+    out.extend_from_slice(&byt[at..]);
+    Some(out)
+}
+
+/// Writes the caption into an exported jpeg, or to a photo.xmp sidecar for
+/// other formats.
+pub async fn write_description(path : &Path, description : &str) -> std::io::Result<()> {
+    let packet = description_packet(description);
+
+    let is_jpeg = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"));
+    if is_jpeg {
+        let byt = tokio::fs::read(path).await?;
+        if let Some(byt) = embed_jpeg(&byt, &packet) {
+            return tokio::fs::write(path, byt).await
+        }
+    }
+
+    tokio::fs::write(path.with_extension("xmp"), packet).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a : f32, b : f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn parse_lightroom_attributes() {
+        let text = concat!(
+            "<x:xmpmeta><rdf:RDF><rdf:Description rdf:about=\"\" ",
+            "xmp:Rating=\"4\" xmp:Label=\"Red\" ",
+            "crs:Exposure2012=\"+1.00\" crs:Contrast2012=\"-20\" crs:Temperature=\"5200\" ",
+            "crs:HasCrop=\"True\" crs:CropLeft=\"0.1\" crs:CropTop=\"0.2\" crs:CropRight=\"0.9\" crs:CropBottom=\"0.8\">",
+            "<dc:subject><rdf:Bag><rdf:li>beach</rdf:li><rdf:li>family &amp; friends</rdf:li></rdf:Bag></dc:subject>",
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">At the beach</rdf:li></rdf:Alt></dc:description>",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>");
+
+        let sidecar = Sidecar::parse(text);
+        assert_eq!(sidecar.rating, Some(4));
+        assert_eq!(sidecar.label.as_deref(), Some("Red"));
+        assert_eq!(sidecar.keywords, ["beach", "family & friends"]);
+        assert_eq!(sidecar.description.as_deref(), Some("At the beach"));
+        assert_eq!(sidecar.date, None);
+
+        let effects = sidecar.effects.unwrap();
+        assert!(close(effects.brightness, 0.1));
+        assert!(close(effects.contrast, 0.4));
+        assert!(close(effects.temperature, 5200.));
+        // not in the sidecar
+        assert!(close(effects.highlight, Effects::default().highlight));
+        let crop = [0.1, 0.2, 0.8, 0.6];
+        assert!(effects.crop.iter().zip(&crop).all(|(a, b)| close(*a, *b)));
+    }
+
+    #[test]
+    fn parse_elements() {
+        // apple photos writes elements, and the title without a caption
+        let text = concat!(
+            "<rdf:Description>\n",
+            "  <xmp:Rating>-1</xmp:Rating>\n",
+            "  <photoshop:DateCreated>2021-06-03T14:22:10</photoshop:DateCreated>\n",
+            "  <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Sunset</rdf:li></rdf:Alt></dc:title>\n",
+            "</rdf:Description>");
+
+        let sidecar = Sidecar::parse(text);
+        assert_eq!(sidecar.rating, Some(-1));
+        assert_eq!(sidecar.date.as_deref(), Some("2021-06-03T14:22:10"));
+        assert_eq!(sidecar.description.as_deref(), Some("Sunset"));
+        assert!(sidecar.effects.is_none());
+    }
+
+    #[test]
+    fn parse_darktable() {
+        let text = concat!(
+            "<rdf:Description xmp:Rating=\"1\">",
+            "<darktable:colorlabels><rdf:Seq><rdf:li>2</rdf:li><rdf:li>0</rdf:li></rdf:Seq></darktable:colorlabels>",
+            "<dc:subject><rdf:Bag><rdf:li>trip</rdf:li><rdf:li>trip</rdf:li><rdf:li> </rdf:li></rdf:Bag></dc:subject>",
+            "</rdf:Description>");
+
+        let sidecar = Sidecar::parse(text);
+        assert_eq!(sidecar.rating, Some(1));
+        assert_eq!(sidecar.label.as_deref(), Some("Green"));
+        assert_eq!(sidecar.keywords, ["trip"]);
+    }
+
+    #[test]
+    fn parse_ratings() {
+        let rating = |r : &str| Sidecar::parse(&format!("xmp:Rating=\"{}\"", r)).rating;
+        assert_eq!(rating("0"), None);
+        assert_eq!(rating("3.6"), Some(4));
+        assert_eq!(rating("9"), Some(5));
+        assert_eq!(rating("-5"), Some(-1));
+        assert_eq!(rating("three"), None);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert!(Sidecar::parse("").is_empty());
+        assert!(Sidecar::parse("not xml at all").is_empty());
+        // unterminated attributes and elements
+        assert!(Sidecar::parse("<rdf:Description xmp:Rating=\"4").is_empty());
+        assert!(Sidecar::parse("<xmp:Rating>4").is_empty());
+        assert!(Sidecar::parse("<dc:subject><rdf:Bag><rdf:li>beach</rdf:li>").is_empty());
+        // an item without its end is skipped
+        let sidecar = Sidecar::parse("<dc:subject><rdf:li>a<rdf:li>b</rdf:li></dc:subject>");
+        assert_eq!(sidecar.keywords, ["b"]);
+        // a darktable label past the ones there are
+        assert!(Sidecar::parse("<darktable:colorlabels><rdf:li>7</rdf:li></darktable:colorlabels>").is_empty());
+        // a crop that's inside out, and nothing else
+        let text = "crs:HasCrop=\"True\" crs:CropLeft=\"0.9\" crs:CropTop=\"0.1\" crs:CropRight=\"0.1\" crs:CropBottom=\"0.9\"";
+        assert!(Sidecar::parse(text).effects.is_none());
+    }
+
+    #[test]
+    fn description_round_trip() {
+        let description = "Tom & Jerry's <first> \"trip\"";
+        let sidecar = Sidecar::parse(&description_packet(description));
+        assert_eq!(sidecar.description.as_deref(), Some(description));
+    }
+
+    #[test]
+    fn embed_after_app0() {
+        assert_eq!(embed_jpeg(b"\x89PNG", "packet"), None);
+
+        // SOI, a JFIF APP0 of 4 bytes, EOI
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0xAA, 0xBB, 0xFF, 0xD9];
+        let out = embed_jpeg(&jpeg, "packet").unwrap();
+        assert_eq!(&out[..8], &jpeg[..8]);
+        assert_eq!(&out[8..10], &[0xFF, 0xE1]);
+        let len = u16::from_be_bytes([out[10], out[11]]) as usize;
+        assert_eq!(len, 2 + XMP_NAMESPACE.len() + "packet".len());
+        assert_eq!(&out[out.len() - 2..], &[0xFF, 0xD9]);
+    }
+}
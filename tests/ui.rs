@@ -0,0 +1,38 @@
+// plays every script in tests/ui into the app, see the ui tests in the
+// README. they need a display, and the references are only right on the
+// machine they were recorded on, so the first run records them. they only
+// run when asked for:
+//
+//     xvfb-run cargo test --test ui -- --ignored
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+#[ignore]
+fn scripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui");
+    let mut scripts : Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "txt"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    // each prints what failed
+    let failed : Vec<String> = scripts.iter()
+        .filter(|script| {
+            let status = Command::new(env!("CARGO_BIN_EXE_photos1"))
+                .arg("--headless")
+                .arg("--open").arg(dir.join("fixtures"))
+                .arg("--script").arg(script)
+                .status()
+                .expect("could not start photos1");
+            !status.success()
+        })
+        .map(|script| script.display().to_string())
+        .collect();
+
+    assert!(failed.is_empty(), "failed: {}", failed.join(", "));
+}